@@ -0,0 +1,67 @@
+//! Benchmarks of serializing logs to tokens and deserializing them back.
+//! Run with `cargo bench --bench dictionary`.
+//!
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use scribe::dictionary::Module;
+use scribe::token::Token;
+use scribe::trie::BiTrie;
+
+const QUOTES: &str = include_str!("../assets/quotes.txt");
+
+fn serialize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("serialize");
+    group.bench_function("new_words", |b| {
+        b.iter_batched(
+            || Module::new(BiTrie::new()),
+            |mut m| {
+                for log in QUOTES.lines() {
+                    black_box(m.serialize(log).unwrap());
+                }
+                m
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    let mut m = Module::new(BiTrie::new());
+    for log in QUOTES.lines() {
+        m.serialize(log).unwrap();
+    }
+    group.bench_function("known_words", |b| {
+        b.iter(|| {
+            for log in QUOTES.lines() {
+                black_box(m.serialize(black_box(log)).unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+fn deserialize(c: &mut Criterion) {
+    let mut m = Module::new(BiTrie::new());
+    let buffers: Vec<Vec<Token>> = QUOTES
+        .lines()
+        .map(|log| m.serialize(log).unwrap())
+        .collect();
+    let mut group = c.benchmark_group("deserialize");
+    group.bench_function("string", |b| {
+        b.iter(|| {
+            for buffer in buffers.iter() {
+                black_box(m.deserialize(black_box(buffer)));
+            }
+        })
+    });
+    group.bench_function("into", |b| {
+        let mut output = String::new();
+        b.iter(|| {
+            for buffer in buffers.iter() {
+                m.deserialize_into(black_box(buffer), &mut output);
+                black_box(&output);
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, serialize, deserialize);
+criterion_main!(benches);
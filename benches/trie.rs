@@ -0,0 +1,68 @@
+//! Benchmarks of pushing words to and finding prefixes in the radix trees.
+//! Run with `cargo bench --bench trie`.
+//!
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use rand::distributions::Alphanumeric;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use scribe::dictionary::Filter;
+use scribe::token::Token;
+use scribe::trie::{BiTrie, Trie};
+
+const WORDS: usize = 100000;
+const WORD_SIZE: usize = 12;
+const SEED: u64 = 42;
+
+/// Creates words of random alphanumeric chars, the same for every run.
+///
+fn words() -> Vec<String> {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    (0..WORDS)
+        .map(|_| {
+            (&mut rng)
+                .sample_iter(&Alphanumeric)
+                .take(WORD_SIZE)
+                .map(char::from)
+                .collect()
+        })
+        .collect()
+}
+
+fn filled<F: Filter>(mut f: F, words: &[String]) -> F {
+    for (i, w) in words.iter().enumerate() {
+        f.push(w, Token::from(i as u32));
+    }
+    f
+}
+
+fn push(c: &mut Criterion) {
+    let words = words();
+    let mut group = c.benchmark_group("push");
+    group.bench_function("trie", |b| {
+        b.iter_batched(Trie::new, |t| filled(t, &words), BatchSize::LargeInput)
+    });
+    group.bench_function("bitrie", |b| {
+        b.iter_batched(BiTrie::new, |t| filled(t, &words), BatchSize::LargeInput)
+    });
+    group.finish();
+}
+
+fn find_prefix(c: &mut Criterion) {
+    let words = words();
+    let trie = filled(Trie::new(), &words);
+    let bitrie = filled(BiTrie::new(), &words);
+    let mut group = c.benchmark_group("find_prefix");
+    for prefix in ["a", "ab", "abc"] {
+        group.bench_function(format!("trie/{prefix}"), |b| {
+            b.iter(|| trie.find_prefix(black_box(prefix)))
+        });
+        group.bench_function(format!("bitrie/{prefix}"), |b| {
+            b.iter(|| bitrie.find_prefix(black_box(prefix)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, push, find_prefix);
+criterion_main!(benches);
@@ -0,0 +1,35 @@
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Writes the commit the binary is built from and the time of the build in to the environment of the compiler,
+/// read back with `env!` as `SCRIBE_COMMIT` and `SCRIBE_BUILT_AT`.
+/// The time is taken from `SOURCE_DATE_EPOCH` if it is set, so reproducible builds stay reproducible.
+///
+fn main() {
+    let commit = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .map(|commit| commit.trim().to_string())
+        .filter(|commit| !commit.is_empty())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let built_at = match std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+    {
+        Some(secs) => UNIX_EPOCH + Duration::from_secs(secs),
+        None => SystemTime::now(),
+    };
+
+    println!("cargo:rustc-env=SCRIBE_COMMIT={}", commit);
+    println!(
+        "cargo:rustc-env=SCRIBE_BUILT_AT={}",
+        humantime::format_rfc3339_seconds(built_at)
+    );
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+}
@@ -0,0 +1,322 @@
+use crate::repository::error::RepositoryError;
+use actix_web::error::{JsonPayloadError, PathError, QueryPayloadError};
+use actix_web::http::header::WWW_AUTHENTICATE;
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt::{self, Display};
+use std::time::Duration;
+
+/// ErrorCode names the kind of a failed request, so clients branch on it instead of on the message.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The body, query or path of the request cannot be parsed.
+    BadRequest,
+    /// The request is well formed but holds values out of range or in conflict with each other.
+    InvalidInput,
+    /// The log or the query breaks a rule of what is saved or read, named in the details.
+    ValidationFailed,
+    /// The request lacks the token the endpoint requires or holds a wrong one.
+    Unauthorized,
+    /// The request body is larger than allowed.
+    PayloadTooLarge,
+    /// The asked for log or word does not exist.
+    NotFound,
+    /// The request conflicts with the state of the server.
+    Conflict,
+    /// The write queue cannot take more logs until it is flushed.
+    QueueFull,
+    /// The database cannot be reached.
+    RepositoryDown,
+    /// The database did not answer in time.
+    RepositoryTimeout,
+    /// The query did not finish in time, the details tell how far it got.
+    QueryTimeout,
+    /// The repository does not support the operation.
+    Unsupported,
+    /// The token id space of the dictionary is exhausted.
+    StorageFull,
+    /// The server failed to serve a valid request.
+    Internal,
+}
+
+impl ErrorCode {
+    fn status(self) -> StatusCode {
+        match self {
+            Self::BadRequest | Self::ValidationFailed => StatusCode::BAD_REQUEST,
+            Self::InvalidInput => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::Conflict => StatusCode::CONFLICT,
+            Self::QueueFull | Self::RepositoryDown => StatusCode::SERVICE_UNAVAILABLE,
+            Self::RepositoryTimeout | Self::QueryTimeout => StatusCode::GATEWAY_TIMEOUT,
+            Self::Unsupported => StatusCode::NOT_IMPLEMENTED,
+            Self::StorageFull => StatusCode::INSUFFICIENT_STORAGE,
+            Self::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// ApiError is the response of a failed request, sent as a JSON `{code, message, details, request_id}` envelope
+/// with the status of the code. Details are left out when there are none, the request id outside of a request.
+///
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiError {
+    code: ErrorCode,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<Value>,
+}
+
+/// Envelope is the JSON body of an ApiError together with the id of the failed request.
+///
+#[derive(Serialize)]
+struct Envelope<'a> {
+    #[serde(flatten)]
+    error: &'a ApiError,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    /// Adds details to the error, like the limit a value is over.
+    ///
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    /// Returns the error of the dictionary lock poisoned by a panicking request.
+    ///
+    pub fn dictionary() -> Self {
+        Self::new(ErrorCode::Internal, "Dictionary is not responding.")
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.code.status()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut res = HttpResponse::build(self.status_code());
+        if self.code == ErrorCode::Unauthorized {
+            res.insert_header((WWW_AUTHENTICATE, "Bearer"));
+        }
+        res.json(Envelope {
+            error: self,
+            request_id: crate::logging::request_id(),
+        })
+    }
+}
+
+/// Tells clients a lost or slow database from a failed query, without exposing the address of the database.
+/// Failures of the database are logged whole, in the span of the failed request.
+///
+impl From<RepositoryError> for ApiError {
+    fn from(e: RepositoryError) -> Self {
+        if !matches!(
+            e,
+            RepositoryError::InvalidInput(_) | RepositoryError::Unsupported(_)
+        ) {
+            tracing::error!(error = %e, "repository operation failed");
+        }
+        match e {
+            RepositoryError::NotConnected(_) => {
+                Self::new(ErrorCode::RepositoryDown, "Database not responding.")
+            }
+            RepositoryError::Timeout(_) => {
+                Self::new(ErrorCode::RepositoryTimeout, "Database timed out.")
+            }
+            RepositoryError::InvalidInput(_) => Self::new(ErrorCode::InvalidInput, e.to_string()),
+            RepositoryError::Unsupported(_) => Self::new(ErrorCode::Unsupported, e.to_string()),
+            RepositoryError::Corrupt(_) | RepositoryError::QueryFailed { .. } => {
+                Self::new(ErrorCode::Internal, e.to_string())
+            }
+        }
+    }
+}
+
+/// Answers a JSON body that cannot be read with the envelope, 413 if it is too large and 400 otherwise.
+///
+pub fn json_error(e: JsonPayloadError, _: &HttpRequest) -> actix_web::Error {
+    let code = match e {
+        JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } => {
+            ErrorCode::PayloadTooLarge
+        }
+        _ => ErrorCode::BadRequest,
+    };
+    ApiError::new(code, "Cannot read the JSON body.")
+        .with_details(serde_json::json!({ "cause": e.to_string() }))
+        .into()
+}
+
+/// Answers a query string that cannot be parsed with the envelope and 400.
+///
+pub fn query_error(e: QueryPayloadError, _: &HttpRequest) -> actix_web::Error {
+    ApiError::new(ErrorCode::BadRequest, "Cannot parse the query string.")
+        .with_details(serde_json::json!({ "cause": e.to_string() }))
+        .into()
+}
+
+/// Answers a path that cannot be parsed with the envelope and 400.
+///
+pub fn path_error(e: PathError, _: &HttpRequest) -> actix_web::Error {
+    ApiError::new(ErrorCode::BadRequest, "Cannot parse the path.")
+        .with_details(serde_json::json!({ "cause": e.to_string() }))
+        .into()
+}
+
+/// Returns an error if the log holds no words or more than max_tokens of them.
+/// Counting stops past the limit, so a huge log is not split whole.
+///
+pub fn validate_log(log: &str, max_tokens: Option<usize>) -> Result<(), ApiError> {
+    if log.split_whitespace().next().is_none() {
+        return Err(
+            ApiError::new(ErrorCode::ValidationFailed, "Log holds no words.")
+                .with_details(serde_json::json!({ "violation": "empty_log" })),
+        );
+    }
+    match max_tokens {
+        Some(max) if log.split_whitespace().nth(max).is_some() => Err(ApiError::new(
+            ErrorCode::ValidationFailed,
+            format!("Log holds more than {} words.", max),
+        )
+        .with_details(serde_json::json!({ "violation": "too_many_tokens", "max": max }))),
+        _ => Ok(()),
+    }
+}
+
+/// Returns an error if the time range starts after it ends or spans longer than max_span.
+///
+pub fn validate_range(
+    from: &Duration,
+    to: &Duration,
+    max_span: Option<Duration>,
+) -> Result<(), ApiError> {
+    if from > to {
+        return Err(
+            ApiError::new(ErrorCode::ValidationFailed, "Range starts after it ends.")
+                .with_details(serde_json::json!({ "violation": "from_after_to" })),
+        );
+    }
+    match max_span {
+        Some(max) if *to - *from > max => Err(ApiError::new(
+            ErrorCode::ValidationFailed,
+            format!("Range spans more than {} seconds.", max.as_secs()),
+        )
+        .with_details(
+            serde_json::json!({ "violation": "span_too_long", "max_seconds": max.as_secs() }),
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+
+    #[actix_web::test]
+    async fn on_error_response_should_send_the_envelope_with_the_status_of_the_code() {
+        let e = ApiError::new(ErrorCode::InvalidInput, "Fuzzy distance is limited to 2.")
+            .with_details(serde_json::json!({ "max": 2 }));
+        let res = e.error_response();
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body = to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(
+            &body[..],
+            br#"{"code":"invalid_input","message":"Fuzzy distance is limited to 2.","details":{"max":2}}"#
+        );
+
+        let res = ApiError::new(ErrorCode::NotFound, "Unknown log 1.").error_response();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let body = to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(
+            &body[..],
+            br#"{"code":"not_found","message":"Unknown log 1."}"#
+        );
+    }
+
+    #[test]
+    fn on_from_repository_error_should_tell_a_lost_database_from_bad_input() {
+        let e = ApiError::from(RepositoryError::NotConnected("10.0.0.1:27017".to_string()));
+        assert_eq!(e.status_code(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(!e.to_string().contains("10.0.0.1"));
+
+        let e = ApiError::from(RepositoryError::InvalidInput(
+            "malformed cursor".to_string(),
+        ));
+        assert_eq!(e.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[test]
+    fn on_validate_log_should_reject_empty_logs_and_logs_with_too_many_tokens() {
+        assert!(validate_log("one two three", None).is_ok());
+        assert!(validate_log("one two three", Some(3)).is_ok());
+
+        let Err(e) = validate_log(" \t ", Some(3)) else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(e.status_code(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            e.details,
+            Some(serde_json::json!({ "violation": "empty_log" }))
+        );
+
+        let Err(e) = validate_log("one two three four", Some(3)) else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(
+            e.details,
+            Some(serde_json::json!({ "violation": "too_many_tokens", "max": 3 }))
+        );
+    }
+
+    #[test]
+    fn on_validate_range_should_reject_reversed_and_too_long_ranges() {
+        let hour = Duration::from_secs(3600);
+        assert!(validate_range(&Duration::ZERO, &hour, Some(hour)).is_ok());
+        assert!(validate_range(&hour, &hour, None).is_ok());
+
+        let Err(e) = validate_range(&hour, &Duration::ZERO, None) else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(e.status_code(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            e.details,
+            Some(serde_json::json!({ "violation": "from_after_to" }))
+        );
+
+        let Err(e) = validate_range(&Duration::ZERO, &(hour * 2), Some(hour)) else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(
+            e.details,
+            Some(serde_json::json!({ "violation": "span_too_long", "max_seconds": 3600 }))
+        );
+    }
+}
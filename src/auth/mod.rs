@@ -0,0 +1,106 @@
+use crate::api::{ApiError, ErrorCode};
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderValue, AUTHORIZATION};
+use actix_web::Error;
+use std::future::Future;
+
+const BEARER: &str = "Bearer ";
+
+/// Serves the request only if its `Authorization: Bearer <token>` header holds the token,
+/// answering `401 Unauthorized` otherwise. Without a token every request is served.
+///
+pub fn authorize<S, B>(
+    req: ServiceRequest,
+    srv: &S,
+    token: Option<&str>,
+) -> impl Future<Output = Result<ServiceResponse<BoxBody>, Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    let authorized = match token {
+        Some(token) => holds_token(req.headers().get(AUTHORIZATION), BEARER, token),
+        None => true,
+    };
+    let served = if authorized {
+        Ok(srv.call(req))
+    } else {
+        Err(req.error_response(ApiError::new(
+            ErrorCode::Unauthorized,
+            "Admin token is missing or wrong.",
+        )))
+    };
+
+    async move {
+        match served {
+            Ok(fut) => Ok(fut.await?.map_into_boxed_body()),
+            Err(rejected) => Ok(rejected),
+        }
+    }
+}
+
+/// Compares the token of the header in the scheme, like `Bearer `, with the token in time not depending on where
+/// they differ, so the token cannot be guessed byte by byte from response times.
+///
+pub fn holds_token(header: Option<&HeaderValue>, scheme: &str, token: &str) -> bool {
+    let Some(given) = header.and_then(|h| h.as_bytes().strip_prefix(scheme.as_bytes())) else {
+        return false;
+    };
+    given.len() == token.len()
+        && given
+            .iter()
+            .zip(token.as_bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App, HttpResponse};
+
+    async fn hello() -> HttpResponse {
+        HttpResponse::Ok().body("hello")
+    }
+
+    #[actix_web::test]
+    async fn on_authorize_should_serve_only_requests_holding_the_token() {
+        let app = test::init_service(
+            App::new()
+                .wrap_fn(|req, srv| authorize(req, srv, Some("s3cr3t")))
+                .route("/", web::get().to(hello)),
+        )
+        .await;
+
+        for (header, status) in [
+            (Some("Bearer s3cr3t"), StatusCode::OK),
+            (Some("Bearer s3cr3"), StatusCode::UNAUTHORIZED),
+            (Some("Bearer s3cr3T"), StatusCode::UNAUTHORIZED),
+            (Some("s3cr3t"), StatusCode::UNAUTHORIZED),
+            (None, StatusCode::UNAUTHORIZED),
+        ] {
+            let mut req = test::TestRequest::get().uri("/");
+            if let Some(header) = header {
+                req = req.insert_header((AUTHORIZATION, header));
+            }
+            let res = test::call_service(&app, req.to_request()).await;
+            assert_eq!(res.status(), status, "{:?}", header);
+        }
+    }
+
+    #[actix_web::test]
+    async fn on_authorize_without_token_should_serve_every_request() {
+        let app = test::init_service(
+            App::new()
+                .wrap_fn(|req, srv| authorize(req, srv, None))
+                .route("/", web::get().to(hello)),
+        )
+        .await;
+
+        let res = test::call_service(&app, test::TestRequest::get().uri("/").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,284 @@
+use crate::repository;
+use crate::repository::error::RepositoryError;
+use crate::repository::interface::{Page, RepositoryProvider};
+use crate::server::new_dictionary;
+use crate::{settings, snapshot};
+use scribe::dictionary;
+use scribe::token::Token;
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// Exports logs of the repository and words of the dictionary to the snapshot file at the path,
+/// returning the number of exported logs and words.
+///
+pub async fn backup_to(
+    repo: &impl RepositoryProvider,
+    dict: &RwLock<dictionary::Module>,
+    file: tokio::fs::File,
+) -> repository::error::Result<(u64, usize)> {
+    let mut file = tokio::io::BufWriter::new(file);
+    let mut words = 0;
+    let logs = repo
+        .export(&mut file, || {
+            let Ok(dict) = dict.read() else {
+                return Err(std::io::Error::other("dictionary is not responding"));
+            };
+            let mut schema = Vec::new();
+            dict.write_schema_binary(&mut schema)?;
+            words = dict.len();
+            Ok(schema)
+        })
+        .await?;
+
+    Ok((logs, words))
+}
+
+/// Imports logs and the dictionary of the snapshot file at the path in to the empty repository,
+/// returning the number of imported logs and words.
+/// Words are saved and checked first, so no log is imported in to a repository not keeping them.
+///
+pub async fn restore_from(
+    repo: &impl RepositoryProvider,
+    setup: &settings::Setup,
+    path: &str,
+) -> std::io::Result<(u64, usize)> {
+    ensure_empty(repo, "restore").await?;
+    let (snapshot, schema) = snapshot::Snapshot::open(std::fs::File::open(path)?)?;
+    let (dict, _) = new_dictionary(
+        setup.get_filter(),
+        setup.get_fold_case(),
+        Some(&mut &schema[..]),
+    )?;
+    let words: Vec<(String, Token)> = dict.iter().map(|(w, n)| (w.clone(), *n)).collect();
+    save_words(repo, &words).await?;
+    let logs = repo
+        .import(snapshot, &[])
+        .await
+        .map_err(std::io::Error::other)?;
+
+    Ok((logs, words.len()))
+}
+
+/// Returns an error naming the repository of the setup if it cannot stream logs,
+/// so no snapshot file is created for a backup failing once logs are read.
+///
+pub async fn ensure_streaming(
+    repo: &impl RepositoryProvider,
+    setup: &settings::Setup,
+) -> std::io::Result<()> {
+    match snapshot::check_streaming(repo).await {
+        Err(RepositoryError::Unsupported(_)) => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!(
+                "the {} repository cannot stream logs, it cannot be backed up",
+                repository::backend(setup)
+            ),
+        )),
+        Err(e) => Err(std::io::Error::other(e)),
+        Ok(()) => Ok(()),
+    }
+}
+
+/// Returns an error if the repository holds words or logs, naming the command that needs an empty one.
+///
+async fn ensure_empty(repo: &impl RepositoryProvider, command: &str) -> std::io::Result<()> {
+    let holding = |what: &str| {
+        std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!(
+                "repository holds {} already, {} in to an empty repository",
+                what, command
+            ),
+        )
+    };
+    if !repo
+        .find_words()
+        .await
+        .map_err(std::io::Error::other)?
+        .is_empty()
+    {
+        return Err(holding("words"));
+    }
+    let page = repo
+        .find_logs_page(
+            &Duration::ZERO,
+            &Duration::from_nanos(i64::MAX as u64),
+            1,
+            &Page::Offset(0),
+        )
+        .await
+        .map_err(std::io::Error::other)?;
+    if !page.logs.is_empty() {
+        return Err(holding("logs"));
+    }
+
+    Ok(())
+}
+
+/// Saves the words in to the repository and checks they are found back,
+/// failing with `Unsupported` if the repository does not keep them.
+///
+async fn save_words(
+    repo: &impl RepositoryProvider,
+    words: &[(String, Token)],
+) -> std::io::Result<()> {
+    repo.insert_logs_with_words(&[], words)
+        .await
+        .map_err(std::io::Error::other)?;
+    if repo
+        .find_words()
+        .await
+        .map_err(std::io::Error::other)?
+        .len()
+        != words.len()
+    {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "repository does not keep the dictionary",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Saves words of the imported dictionary in to the empty repository and marks them saved,
+/// returning the number of saved words, so the dictionary loaded on restart numbers words
+/// as the imported one does.
+///
+pub async fn save_imported(
+    repo: &impl RepositoryProvider,
+    dict: &mut dictionary::Module,
+) -> std::io::Result<usize> {
+    ensure_empty(repo, "import").await?;
+    let words: Vec<(String, Token)> = dict.iter().map(|(w, n)| (w.clone(), *n)).collect();
+    save_words(repo, &words).await?;
+    dict.mark_saved(&words);
+
+    Ok(words.len())
+}
+
+/// Saves words of the dictionary schema file at the path in to the empty repository,
+/// returning the number of saved words.
+///
+pub async fn import_schema(
+    repo: &impl RepositoryProvider,
+    setup: &settings::Setup,
+    path: &str,
+) -> std::io::Result<usize> {
+    let mut file = std::io::BufReader::new(std::fs::File::open(path)?);
+    let (mut dict, _) = new_dictionary(setup.get_filter(), setup.get_fold_case(), Some(&mut file))?;
+    save_imported(repo, &mut dict).await
+}
+
+#[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
+mod tests {
+    use super::*;
+    use crate::repository::kv::WarehouseKv;
+    use crate::server::load_dictionary;
+    use scribe::trie;
+    use std::env;
+
+    #[tokio::test]
+    async fn on_import_should_load_imported_words_with_their_numbers_after_restart() {
+        let dir = env::temp_dir().join("scribe_backup_import");
+        let _ = std::fs::remove_dir_all(&dir);
+        let setup = settings::Setup::default();
+        let mut source = dictionary::Module::new(trie::Trie::new());
+        let Ok(_) = source.serialize("imported words keep numbers") else {
+            assert!(false);
+            return;
+        };
+        let mut schema = Vec::new();
+        let Ok(()) = source.write_schema_binary(&mut schema) else {
+            assert!(false);
+            return;
+        };
+
+        let Ok(repo) = WarehouseKv::new(&dir).await else {
+            assert!(false);
+            return;
+        };
+        let Ok((mut imported, _)) = new_dictionary(
+            setup.get_filter(),
+            setup.get_fold_case(),
+            Some(&mut &schema[..]),
+        ) else {
+            assert!(false);
+            return;
+        };
+        let Ok(words) = save_imported(&repo, &mut imported).await else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(words, 4);
+        assert!(imported.unsaved_words().is_empty());
+        repo.close().await;
+        drop(repo);
+
+        let Ok(repo) = WarehouseKv::new(&dir).await else {
+            assert!(false);
+            return;
+        };
+        let Ok((loaded, _)) = load_dictionary(&repo, &setup).await else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(loaded.len(), imported.len());
+        for (word, num) in imported.iter() {
+            assert_eq!(loaded.num(word), Some(*num));
+        }
+
+        let Ok((mut again, _)) = new_dictionary(
+            setup.get_filter(),
+            setup.get_fold_case(),
+            Some(&mut &schema[..]),
+        ) else {
+            assert!(false);
+            return;
+        };
+        let Err(e) = save_imported(&repo, &mut again).await else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(e.kind(), std::io::ErrorKind::AlreadyExists);
+
+        repo.close().await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn on_import_schema_should_save_words_of_v1_fixture() {
+        let dir = env::temp_dir().join("scribe_backup_import_v1");
+        let _ = std::fs::remove_dir_all(&dir);
+        let setup = settings::Setup::default();
+        let Ok(repo) = WarehouseKv::new(&dir).await else {
+            assert!(false);
+            return;
+        };
+
+        let Ok(words) =
+            import_schema(&repo, &setup, "./assets/fixtures/v1/dictionary.schema").await
+        else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(words, 7);
+        let Ok((dict, _)) = load_dictionary(&repo, &setup).await else {
+            assert!(false);
+            return;
+        };
+        let Ok(data) = std::fs::read("./assets/fixtures/v1/log.bin") else {
+            assert!(false);
+            return;
+        };
+        let Ok(buffer) = Token::decode(&data) else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(dict.deserialize(&buffer), "connection refused by peer");
+
+        repo.close().await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,631 @@
+//! Load test harness for a running scribe server.
+//!
+//! Writers save logs mixed from a corpus with synthetic ids, readers query recent logs by word and prefix.
+//! At the end latency percentiles and throughput of both are reported.
+//!
+//! ```sh
+//! cargo run --release --bin scribe-bench -- --target http://localhost:8000 --writers 8 --readers 2 --duration 30
+//! ```
+//!
+//! In soak mode every log is tagged with the run and a sequence number, and invariants are verified periodically
+//! while the load goes on: the number of stored logs of the run matches the number of acknowledged saves,
+//! and sampled logs are found by their sequence number and decode exactly to what was saved.
+//! Any violation makes the run fail.
+//!
+//! ```sh
+//! cargo run --release --bin scribe-bench -- --mode soak --duration 14400 --verify-every 60
+//! ```
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::process::ExitCode;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const USAGE: &str = "Usage: scribe-bench [--mode bench|soak] [--target URL] [--writers N] [--readers N] [--duration SECS] [--corpus PATH] [--words N] [--seed N] [--verify-every SECS]";
+const LEVELS: [&str; 4] = ["INFO", "WARN", "ERROR", "DEBUG"];
+const SERVICES: [&str; 5] = ["auth", "billing", "gateway", "search", "storage"];
+const SAMPLES: usize = 10_000;
+const SAMPLE_CHECKS: usize = 20;
+const TOP_WORDS: usize = 10;
+const QUERY_AHEAD: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Serialize)]
+struct LogInput {
+    log: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Query {
+    prefix: Option<String>,
+    words: Option<Vec<String>>,
+    from: u64,
+    to: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct TopQuery {
+    n: usize,
+    from: Option<u64>,
+    to: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LogsOutput {
+    logs: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WordCount {
+    word: String,
+    count: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TopWordsOutput {
+    words: Vec<WordCount>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Bench,
+    Soak,
+}
+
+impl std::str::FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "bench" => Ok(Self::Bench),
+            "soak" => Ok(Self::Soak),
+            _ => Err(format!("unknown mode {}", s)),
+        }
+    }
+}
+
+/// Options of a bench run.
+///
+#[derive(Debug, Clone, PartialEq)]
+struct Options {
+    mode: Mode,
+    target: String,
+    writers: usize,
+    readers: usize,
+    duration: Duration,
+    corpus: String,
+    words: usize,
+    seed: u64,
+    verify_every: Duration,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Bench,
+            target: "http://localhost:8000".to_string(),
+            writers: 4,
+            readers: 1,
+            duration: Duration::from_secs(10),
+            corpus: "assets/quotes.txt".to_string(),
+            words: 12,
+            seed: 0,
+            verify_every: Duration::from_secs(60),
+        }
+    }
+}
+
+impl Options {
+    /// Parses options from command line arguments, skipping the program name.
+    ///
+    fn from_args(args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut o = Self::default();
+        let mut args = args.skip(1);
+        while let Some(flag) = args.next() {
+            let Some(value) = args.next() else {
+                return Err(invalid(format!("missing value of {}", flag)));
+            };
+            match flag.as_str() {
+                "--mode" => o.mode = parse(&flag, &value)?,
+                "--target" => o.target = value.trim_end_matches('/').to_string(),
+                "--writers" => o.writers = parse(&flag, &value)?,
+                "--readers" => o.readers = parse(&flag, &value)?,
+                "--duration" => o.duration = Duration::from_secs(parse(&flag, &value)?),
+                "--corpus" => o.corpus = value,
+                "--words" => o.words = parse(&flag, &value)?,
+                "--seed" => o.seed = parse(&flag, &value)?,
+                "--verify-every" => o.verify_every = Duration::from_secs(parse(&flag, &value)?),
+                _ => return Err(invalid(format!("unknown flag {}", flag))),
+            }
+        }
+        if o.writers + o.readers == 0 {
+            return Err(invalid(
+                "at least one writer or reader is needed".to_string(),
+            ));
+        }
+        if o.mode == Mode::Soak && (o.writers == 0 || o.verify_every.is_zero()) {
+            return Err(invalid(
+                "soak mode needs writers and a verification period".to_string(),
+            ));
+        }
+
+        Ok(o)
+    }
+}
+
+fn parse<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T> {
+    match value.parse() {
+        Ok(v) => Ok(v),
+        Err(_) => Err(invalid(format!("cannot parse {} value {}", flag, value))),
+    }
+}
+
+fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, msg)
+}
+
+/// Generator mixes corpus words with levels, services and synthetic ids the way real logs do.
+///
+struct Generator {
+    words: Vec<String>,
+    rng: StdRng,
+}
+
+impl Generator {
+    fn new(corpus: &str, seed: u64) -> Self {
+        Self {
+            words: corpus.split_whitespace().map(|w| w.to_string()).collect(),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    fn log(&mut self, words: usize) -> String {
+        let mut log = format!(
+            "{} service={} request_id={:016x} user={}",
+            LEVELS[self.rng.gen_range(0..LEVELS.len())],
+            SERVICES[self.rng.gen_range(0..SERVICES.len())],
+            self.rng.gen::<u64>(),
+            self.rng.gen_range(0..10_000),
+        );
+        for _ in 0..words {
+            log.push(' ');
+            log.push_str(self.word());
+        }
+        log
+    }
+
+    fn word(&mut self) -> &str {
+        match self.words.is_empty() {
+            true => "empty",
+            false => &self.words[self.rng.gen_range(0..self.words.len())],
+        }
+    }
+}
+
+/// Stats collects latencies of a single kind of request.
+///
+#[derive(Debug, Default)]
+struct Stats {
+    latencies: Vec<Duration>,
+    errors: usize,
+}
+
+impl Stats {
+    fn merge(&mut self, other: Stats) {
+        self.latencies.extend(other.latencies);
+        self.errors += other.errors;
+    }
+
+    /// Returns latency below which given percent of requests completed.
+    ///
+    fn percentile(&self, p: f64) -> Duration {
+        percentile(&self.latencies, p)
+    }
+
+    fn report(&mut self, name: &str, elapsed: Duration) {
+        self.latencies.sort_unstable();
+        let ok = self.latencies.len();
+        println!(
+            "{:<6} requests: {:>8}  errors: {:>6}  throughput: {:>9.1} req/s",
+            name,
+            ok + self.errors,
+            self.errors,
+            ok as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+        );
+        if ok == 0 {
+            return;
+        }
+        println!(
+            "{:<6} p50: {:?}  p90: {:?}  p99: {:?}  max: {:?}",
+            name,
+            self.percentile(50.0),
+            self.percentile(90.0),
+            self.percentile(99.0),
+            self.latencies[ok - 1],
+        );
+    }
+}
+
+/// Returns the nearest rank percentile of sorted latencies.
+///
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Ledger tracks logs saved in soak mode and keeps a uniform sample of them for verification.
+///
+struct Ledger {
+    run: String,
+    started: u64,
+    attempted: AtomicU64,
+    acked: AtomicU64,
+    samples: Mutex<Vec<(String, String)>>,
+}
+
+impl Ledger {
+    fn new(seed: u64) -> Self {
+        Self {
+            run: format!(
+                "run={:016x}",
+                StdRng::seed_from_u64(seed ^ now()).gen::<u64>()
+            ),
+            started: now(),
+            attempted: AtomicU64::new(0),
+            acked: AtomicU64::new(0),
+            samples: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Tags the log with the run and a new sequence number, returning the sequence word.
+    ///
+    fn tag(&self, log: &mut String) -> String {
+        let seq = self.attempted.fetch_add(1, Ordering::SeqCst);
+        let word = format!("seq={}-{}", &self.run[4..], seq);
+        log.push(' ');
+        log.push_str(&self.run);
+        log.push(' ');
+        log.push_str(&word);
+        word
+    }
+
+    /// Records an acknowledged save, sampling it with reservoir sampling.
+    ///
+    fn ack(&self, word: String, log: String, rng: &mut StdRng) {
+        let n = self.acked.fetch_add(1, Ordering::SeqCst) + 1;
+        let Ok(mut samples) = self.samples.lock() else {
+            return;
+        };
+        if samples.len() < SAMPLES {
+            samples.push((word, log));
+            return;
+        }
+        let i = rng.gen_range(0..n) as usize;
+        if i < SAMPLES {
+            samples[i] = (word, log);
+        }
+    }
+
+    fn pick(&self, n: usize, rng: &mut StdRng) -> Vec<(String, String)> {
+        let Ok(samples) = self.samples.lock() else {
+            return Vec::new();
+        };
+        (0..n.min(samples.len()))
+            .map(|_| samples[rng.gen_range(0..samples.len())].clone())
+            .collect()
+    }
+}
+
+/// Verifies soak invariants, returning a description of every violation found.
+///
+fn verify(o: &Options, ledger: &Ledger, rng: &mut StdRng) -> Result<Vec<String>> {
+    let agent = ureq::agent();
+    let to = now() + QUERY_AHEAD.as_nanos() as u64;
+    let mut violations = Vec::new();
+
+    let acked = ledger.acked.load(Ordering::SeqCst);
+    let top: TopWordsOutput = agent
+        .post(&format!("{}/top", o.target))
+        .send_json(&TopQuery {
+            n: TOP_WORDS,
+            from: Some(ledger.started),
+            to: Some(to),
+        })
+        .map_err(Error::other)?
+        .into_json()?;
+    let attempted = ledger.attempted.load(Ordering::SeqCst);
+    let stored = top
+        .words
+        .iter()
+        .find(|w| w.word == ledger.run)
+        .map(|w| w.count)
+        .unwrap_or_default();
+    if stored < acked || stored > attempted {
+        violations.push(format!(
+            "{} logs stored, expected between {} acknowledged and {} attempted",
+            stored, acked, attempted
+        ));
+    }
+
+    for (word, log) in ledger.pick(SAMPLE_CHECKS, rng) {
+        let found: LogsOutput = agent
+            .post(&format!("{}/read", o.target))
+            .send_json(&Query {
+                prefix: None,
+                words: Some(vec![word.clone()]),
+                from: ledger.started,
+                to,
+            })
+            .map_err(Error::other)?
+            .into_json()?;
+        if found.logs != [log.as_str()] {
+            violations.push(format!(
+                "{} expected [ {} ], found {:?}",
+                word, log, found.logs
+            ));
+        }
+    }
+
+    Ok(violations)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64
+}
+
+fn write(
+    o: &Options,
+    corpus: &str,
+    seed: u64,
+    running: &AtomicBool,
+    ledger: Option<&Ledger>,
+) -> Stats {
+    let mut generator = Generator::new(corpus, seed);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let agent = ureq::agent();
+    let path = format!("{}/save", o.target);
+    let mut stats = Stats::default();
+    while running.load(Ordering::Relaxed) {
+        let mut log = generator.log(o.words);
+        let word = ledger.map(|l| l.tag(&mut log));
+        let input = LogInput { log };
+        let start = Instant::now();
+        match agent.post(&path).send_json(&input) {
+            Ok(_) => {
+                stats.latencies.push(start.elapsed());
+                if let (Some(ledger), Some(word)) = (ledger, word) {
+                    ledger.ack(word, input.log, &mut rng);
+                }
+            }
+            Err(_) => stats.errors += 1,
+        }
+    }
+    stats
+}
+
+fn read(o: &Options, corpus: &str, seed: u64, running: &AtomicBool) -> Stats {
+    let mut generator = Generator::new(corpus, seed);
+    let agent = ureq::agent();
+    let path = format!("{}/read", o.target);
+    let mut stats = Stats::default();
+    while running.load(Ordering::Relaxed) {
+        let word = generator.word().to_string();
+        let prefix: String = word.chars().take(3).collect();
+        let to = now();
+        let query = Query {
+            prefix: Some(prefix),
+            words: Some(vec![word]),
+            from: to.saturating_sub(Duration::from_secs(60).as_nanos() as u64),
+            to,
+        };
+        let start = Instant::now();
+        match agent.post(&path).send_json(&query) {
+            Ok(_) => stats.latencies.push(start.elapsed()),
+            Err(_) => stats.errors += 1,
+        }
+    }
+    stats
+}
+
+fn run(o: Options) -> Result<()> {
+    let corpus = Arc::new(fs::read_to_string(&o.corpus)?);
+    let o = Arc::new(o);
+    let running = Arc::new(AtomicBool::new(true));
+    let ledger = match o.mode {
+        Mode::Bench => None,
+        Mode::Soak => Some(Arc::new(Ledger::new(o.seed))),
+    };
+
+    println!(
+        "Running {:?} against [ {} ] with {} writers and {} readers for {:?}",
+        o.mode, o.target, o.writers, o.readers, o.duration
+    );
+
+    let mut writers = Vec::new();
+    let mut readers = Vec::new();
+    for i in 0..o.writers + o.readers {
+        let (o, corpus, running, ledger) =
+            (o.clone(), corpus.clone(), running.clone(), ledger.clone());
+        let seed = o.seed.wrapping_add(i as u64);
+        match i < o.writers {
+            true => writers.push(thread::spawn(move || {
+                write(&o, &corpus, seed, &running, ledger.as_deref())
+            })),
+            false => readers.push(thread::spawn(move || read(&o, &corpus, seed, &running))),
+        }
+    }
+
+    let start = Instant::now();
+    let mut violations = 0;
+    match ledger.as_ref() {
+        None => thread::sleep(o.duration),
+        Some(ledger) => {
+            let mut rng = StdRng::seed_from_u64(o.seed);
+            while start.elapsed() < o.duration {
+                thread::sleep(
+                    o.verify_every
+                        .min(o.duration.saturating_sub(start.elapsed())),
+                );
+                let found = verify(&o, ledger, &mut rng)?;
+                println!(
+                    "[ {:?} ] acknowledged: {}  violations: {}",
+                    start.elapsed(),
+                    ledger.acked.load(Ordering::SeqCst),
+                    found.len()
+                );
+                for v in found.iter() {
+                    println!("  violation: {}", v);
+                }
+                violations += found.len();
+            }
+        }
+    }
+    running.store(false, Ordering::Relaxed);
+
+    let mut ingest = Stats::default();
+    for w in writers {
+        ingest.merge(w.join().unwrap_or_default());
+    }
+    let mut query = Stats::default();
+    for r in readers {
+        query.merge(r.join().unwrap_or_default());
+    }
+    let elapsed = start.elapsed();
+
+    ingest.report("ingest", elapsed);
+    query.report("query", elapsed);
+
+    if let Some(ledger) = ledger.as_ref() {
+        let found = verify(&o, ledger, &mut StdRng::seed_from_u64(o.seed))?;
+        violations += found.len();
+        for v in found.iter() {
+            println!("  violation: {}", v);
+        }
+    }
+    if violations > 0 {
+        return Err(Error::other(format!(
+            "{} invariant violations found",
+            violations
+        )));
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let o = match Options::from_args(env::args()) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(e) = run(o) {
+        eprintln!("Cannot run the bench due to: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> impl Iterator<Item = String> + '_ {
+        std::iter::once("scribe-bench")
+            .chain(s.split_whitespace())
+            .map(String::from)
+    }
+
+    #[test]
+    fn on_from_args_should_override_defaults() {
+        let o = Options::from_args(args("--target http://host:9000/ --writers 2 --duration 3"))
+            .unwrap();
+
+        assert_eq!(o.target, "http://host:9000");
+        assert_eq!(o.writers, 2);
+        assert_eq!(o.readers, Options::default().readers);
+        assert_eq!(o.duration, Duration::from_secs(3));
+    }
+
+    #[test]
+    fn on_from_args_should_reject_bad_input() {
+        assert!(Options::from_args(args("--writers")).is_err());
+        assert!(Options::from_args(args("--writers many")).is_err());
+        assert!(Options::from_args(args("--unknown 1")).is_err());
+        assert!(Options::from_args(args("--writers 0 --readers 0")).is_err());
+    }
+
+    #[test]
+    fn on_from_args_should_read_soak_mode() {
+        let o = Options::from_args(args("--mode soak --verify-every 5")).unwrap();
+        assert_eq!(o.mode, Mode::Soak);
+        assert_eq!(o.verify_every, Duration::from_secs(5));
+
+        assert!(Options::from_args(args("--mode soak --verify-every 0")).is_err());
+        assert!(Options::from_args(args("--mode soak --writers 0")).is_err());
+        assert!(Options::from_args(args("--mode fast")).is_err());
+    }
+
+    #[test]
+    fn on_ledger_tag_should_give_every_log_a_unique_sequence_word() {
+        let ledger = Ledger::new(1);
+        let mut first = "some log".to_string();
+        let mut second = "some log".to_string();
+
+        let a = ledger.tag(&mut first);
+        let b = ledger.tag(&mut second);
+
+        assert_ne!(a, b);
+        assert!(first.ends_with(&format!(" {} {}", ledger.run, a)));
+        assert_eq!(ledger.attempted.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn on_ledger_ack_should_keep_sample_bounded() {
+        let ledger = Ledger::new(1);
+        let mut rng = StdRng::seed_from_u64(1);
+        for i in 0..SAMPLES + 100 {
+            ledger.ack(i.to_string(), i.to_string(), &mut rng);
+        }
+
+        assert_eq!(ledger.acked.load(Ordering::SeqCst), (SAMPLES + 100) as u64);
+        assert_eq!(ledger.samples.lock().unwrap().len(), SAMPLES);
+        assert_eq!(ledger.pick(SAMPLE_CHECKS, &mut rng).len(), SAMPLE_CHECKS);
+    }
+
+    #[test]
+    fn on_percentile_should_return_nearest_rank() {
+        let sorted: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+
+        assert_eq!(percentile(&sorted, 50.0), Duration::from_millis(50));
+        assert_eq!(percentile(&sorted, 99.0), Duration::from_millis(99));
+        assert_eq!(percentile(&sorted, 100.0), Duration::from_millis(100));
+        assert_eq!(percentile(&sorted, 0.0), Duration::from_millis(1));
+        assert_eq!(percentile(&[], 50.0), Duration::ZERO);
+    }
+
+    #[test]
+    fn on_generator_log_should_repeat_for_the_same_seed() {
+        let mut a = Generator::new("one two three", 7);
+        let mut b = Generator::new("one two three", 7);
+
+        let log = a.log(5);
+        assert_eq!(log, b.log(5));
+        assert_eq!(log.split_whitespace().count(), 9);
+    }
+}
@@ -0,0 +1,536 @@
+use crate::dictionary::{Filter, Stats};
+use crate::token::Token;
+use crate::trie::fold;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+/// Longest prefix in chars that has its own bloom filter, longer prefixes are probed
+/// with their first LEVELS chars and then checked against the word.
+const LEVELS: usize = 16;
+/// Bits per entry of a bloom filter, which with HASHES probes gives about 1% false positives.
+const BITS_PER_ENTRY: usize = 10;
+const HASHES: u64 = 7;
+const MIN_CAPACITY: usize = 64;
+
+/// Bloom is a filter for memory constrained deployments, keeping words in a plain map
+/// and one bloom filter per prefix length instead of a tree.
+/// The bloom filter of length n holds every pair of a prefix of n chars with the num of the word,
+/// so a prefix query probes a couple of bits per word without touching words.
+/// Probes give false positives, so `find_prefix` may return about 1% of the words
+/// that do not start with the prefix. All other queries scan the words and are exact.
+///
+/// Bits cannot be removed from a bloom filter, removed words only leave the map
+/// and their bits are dropped when the filter is rebuilt to grow or on `shrink_to_fit`.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Bloom {
+    words: HashMap<Box<str>, Entry>,
+    levels: Vec<Level>,
+}
+
+/// Entry is the num of a word and the count of its occurrences.
+///
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    num: Token,
+    count: u64,
+}
+
+/// Level is a bloom filter of prefixes of one length paired with nums,
+/// sized for capacity entries and rebuilt twice as large once it holds more.
+///
+#[derive(Debug, Clone)]
+struct Level {
+    bits: Vec<u64>,
+    len: usize,
+    capacity: usize,
+}
+
+impl Level {
+    fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(MIN_CAPACITY);
+        Self {
+            bits: vec![0; (capacity * BITS_PER_ENTRY).div_ceil(64)],
+            len: 0,
+            capacity,
+        }
+    }
+
+    #[inline(always)]
+    fn insert(&mut self, key: u64) {
+        let m = (self.bits.len() * 64) as u64;
+        for bit in probes(key, m) {
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+        self.len += 1;
+    }
+
+    #[inline(always)]
+    fn contains(&self, key: u64) -> bool {
+        let m = (self.bits.len() * 64) as u64;
+        probes(key, m).all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+}
+
+/// Returns bit positions of the key in a filter of m bits, using double hashing of two halves of a mixed key.
+///
+#[inline(always)]
+fn probes(key: u64, m: u64) -> impl Iterator<Item = u64> {
+    let h = mix(key);
+    let (h1, h2) = (h & 0xffff_ffff, (h >> 32) | 1);
+    (0..HASHES).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % m)
+}
+
+/// Finalizer of splitmix64, spreads a key over all bits.
+///
+#[inline(always)]
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Hasher of the prefix, the num of each word is hashed in to a clone of it.
+///
+fn prefix_hasher(prefix: &str) -> DefaultHasher {
+    let mut hasher = DefaultHasher::new();
+    prefix.hash(&mut hasher);
+    hasher
+}
+
+#[inline(always)]
+fn key(prefix: &DefaultHasher, num: Token) -> u64 {
+    let mut hasher = prefix.clone();
+    num.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Returns the first n chars of the string, or the whole string if it is shorter.
+///
+fn first_chars(s: &str, n: usize) -> &str {
+    match s.char_indices().nth(n) {
+        Some((i, _)) => &s[..i],
+        None => s,
+    }
+}
+
+impl Bloom {
+    /// Creates an empty filter.
+    ///
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns an estimate of bytes allocated on the heap by the words and the bloom filters.
+    ///
+    pub fn heap_size(&self) -> usize {
+        self.words.capacity() * (std::mem::size_of::<(Box<str>, Entry)>() + 1)
+            + self.words.keys().map(|w| w.len()).sum::<usize>()
+            + self
+                .levels
+                .iter()
+                .map(|l| l.bits.capacity() * std::mem::size_of::<u64>())
+                .sum::<usize>()
+    }
+
+    /// Sets the prefixes of the word in to the bloom filters, growing any filter that is full.
+    ///
+    fn insert_prefixes(&mut self, s: &str, num: Token) {
+        for (i, (end, c)) in s.char_indices().take(LEVELS).enumerate() {
+            if self.levels.len() <= i {
+                self.levels.push(Level::new(MIN_CAPACITY));
+            }
+            if self.levels[i].len >= self.levels[i].capacity {
+                self.rebuild_level(i, self.levels[i].capacity * 2);
+                continue;
+            }
+            self.levels[i].insert(key(&prefix_hasher(&s[..end + c.len_utf8()]), num));
+        }
+    }
+
+    /// Builds the filter of prefixes of i + 1 chars anew from the words.
+    /// The word being inserted is already in the map, so it gets in to the new filter too.
+    ///
+    fn rebuild_level(&mut self, i: usize, capacity: usize) {
+        let mut level = Level::new(capacity);
+        for (word, entry) in self.words.iter() {
+            if let Some((end, c)) = word.char_indices().nth(i) {
+                level.insert(key(&prefix_hasher(&word[..end + c.len_utf8()]), entry.num));
+            }
+        }
+        self.levels[i] = level;
+    }
+
+    /// Finds all index nums of words within given edit distance of the string,
+    /// or of words starting with a string within the distance if whole is not set.
+    ///
+    fn scan_fuzzy(&self, s: &str, distance: u8, whole: bool) -> HashSet<Token> {
+        let word: Vec<char> = s.chars().collect();
+        self.words
+            .iter()
+            .filter(|(w, _)| within_distance(&word, w, distance as usize, whole))
+            .map(|(_, e)| e.num)
+            .collect()
+    }
+}
+
+/// Checks if the candidate is within given edit distance of the word,
+/// or if it starts with a string within the distance if whole is not set.
+/// The candidate is given up once no prefix of the word is within the distance.
+///
+pub(crate) fn within_distance(
+    word: &[char],
+    candidate: &str,
+    distance: usize,
+    whole: bool,
+) -> bool {
+    let mut row: Vec<usize> = (0..=word.len()).collect();
+    if !whole && row[word.len()] <= distance {
+        return true;
+    }
+    for c in candidate.chars() {
+        let mut curr = Vec::with_capacity(row.len());
+        curr.push(row[0] + 1);
+        for i in 1..row.len() {
+            let substitution = row[i - 1] + usize::from(word[i - 1] != c);
+            curr.push((curr[i - 1] + 1).min(row[i] + 1).min(substitution));
+        }
+        if curr.iter().min().is_none_or(|d| *d > distance) {
+            return false;
+        }
+        row = curr;
+        if !whole && row[word.len()] <= distance {
+            return true;
+        }
+    }
+    row[word.len()] <= distance
+}
+
+/// Matches the word against the glob pattern, where `*` matches any run of characters
+/// and `?` matches a single character.
+///
+pub(crate) fn glob(pattern: &[char], word: &[char]) -> bool {
+    let (mut p, mut w) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while w < word.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, w));
+                p += 1;
+            }
+            Some('?') => {
+                p += 1;
+                w += 1;
+            }
+            Some(c) if *c == word[w] => {
+                p += 1;
+                w += 1;
+            }
+            _ => match star {
+                Some((sp, sw)) => {
+                    star = Some((sp, sw + 1));
+                    p = sp + 1;
+                    w = sw + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+impl Filter for Bloom {
+    /// Pushes the word to the map and its prefixes to the bloom filters.
+    /// A word pushed again under another num takes the new num.
+    ///
+    fn push(&mut self, s: &str, num: Token) {
+        match self.words.get_mut(s) {
+            Some(entry) if entry.num == num => return,
+            Some(entry) => entry.num = num,
+            None => {
+                self.words.insert(s.into(), Entry { num, count: 0 });
+            }
+        }
+        self.insert_prefixes(s, num);
+    }
+
+    /// Removes the word from the map, its bits stay set until the filters are rebuilt.
+    /// Returns false if the string is not held under that num.
+    ///
+    fn remove(&mut self, s: &str, num: Token) -> bool {
+        if self.words.get(s).is_none_or(|entry| entry.num != num) {
+            return false;
+        }
+        self.words.remove(s);
+        true
+    }
+
+    /// Returns the size of the words and the bloom filters, the filters have no graph to describe.
+    ///
+    fn stats(&self) -> Option<Stats> {
+        Some(Stats {
+            bytes: self.heap_size(),
+            ..Stats::default()
+        })
+    }
+
+    fn count(&mut self, s: &str, by: u64) {
+        if let Some(entry) = self.words.get_mut(s) {
+            entry.count += by;
+        }
+    }
+
+    /// Finds up to k index nums of words starting with the prefix together with their counts, most frequent first.
+    /// Candidates are checked against the words, so the result is exact.
+    ///
+    fn find_prefix_top(&self, s: &str, k: usize) -> Vec<(Token, u64)> {
+        let mut top: Vec<(Token, u64)> = self
+            .words
+            .iter()
+            .filter(|(w, _)| w.starts_with(s))
+            .map(|(_, e)| (e.num, e.count))
+            .collect();
+        top.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        top.truncate(k);
+        top
+    }
+
+    /// Finds index nums of words with matching string prefix by probing the bloom filter of the prefix length,
+    /// so about 1% of the returned nums may belong to words that do not start with the prefix.
+    ///
+    fn find_prefix(&self, s: &str) -> HashSet<Token> {
+        if s.is_empty() {
+            return self.words.values().map(|e| e.num).collect();
+        }
+        let probed = first_chars(s, LEVELS);
+        let Some(level) = self.levels.get(probed.chars().count() - 1) else {
+            return HashSet::new();
+        };
+        let prefix = prefix_hasher(probed);
+        self.words
+            .iter()
+            .filter(|(w, e)| {
+                level.contains(key(&prefix, e.num)) && (probed.len() == s.len() || w.starts_with(s))
+            })
+            .map(|(_, e)| e.num)
+            .collect()
+    }
+
+    /// Finds all words with matching prefix in lexicographic order.
+    /// Words are compared as they are, so unlike `find_prefix` there are no false positives.
+    ///
+    fn find_prefix_words(&self, s: &str) -> Vec<(String, Token)> {
+        let mut words: Vec<(String, Token)> = self
+            .words
+            .iter()
+            .filter(|(w, _)| w.starts_with(s))
+            .map(|(w, e)| (w.to_string(), e.num))
+            .collect();
+        words.sort_unstable();
+        words
+    }
+
+    fn find_prefix_case_insensitive(&self, s: &str) -> HashSet<Token> {
+        let folded: Vec<char> = s.chars().flat_map(fold).collect();
+        self.words
+            .iter()
+            .filter(|(w, _)| {
+                let mut chars = w.chars().flat_map(fold);
+                folded.iter().all(|c| chars.next() == Some(*c))
+            })
+            .map(|(_, e)| e.num)
+            .collect()
+    }
+
+    fn find_fuzzy(&self, s: &str, distance: u8) -> HashSet<Token> {
+        self.scan_fuzzy(s, distance, true)
+    }
+
+    fn find_prefix_fuzzy(&self, s: &str, distance: u8) -> HashSet<Token> {
+        self.scan_fuzzy(s, distance, false)
+    }
+
+    fn find_glob(&self, pattern: &str) -> HashSet<Token> {
+        let pattern: Vec<char> = pattern.chars().collect();
+        self.words
+            .iter()
+            .filter(|(w, _)| glob(&pattern, &w.chars().collect::<Vec<char>>()))
+            .map(|(_, e)| e.num)
+            .collect()
+    }
+
+    fn find_suffix(&self, s: &str) -> HashSet<Token> {
+        self.words
+            .iter()
+            .filter(|(w, _)| w.ends_with(s))
+            .map(|(_, e)| e.num)
+            .collect()
+    }
+
+    /// Rebuilds the bloom filters sized for the words held, dropping bits of removed words.
+    ///
+    fn shrink_to_fit(&mut self) {
+        self.words.shrink_to_fit();
+        let levels = self.levels.len();
+        for i in 0..levels {
+            let len = self
+                .words
+                .keys()
+                .filter(|w| w.chars().nth(i).is_some())
+                .count();
+            self.rebuild_level(i, len);
+        }
+        while self.levels.last().is_some_and(|l| l.len == 0) {
+            self.levels.pop();
+        }
+        self.levels.shrink_to_fit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trie::Trie;
+    use rand::{distributions::Alphanumeric, thread_rng, Rng};
+
+    const WORDS: [&str; 10] = [
+        "connection",
+        "connections",
+        "connected",
+        "collected",
+        "refused",
+        "refusal",
+        "Straße",
+        "config.json",
+        "data.json",
+        "żółw",
+    ];
+
+    fn filters() -> (Trie, Bloom) {
+        let mut trie = Trie::new();
+        let mut bloom = Bloom::new();
+        for (i, w) in WORDS.iter().enumerate() {
+            trie.push(w, Token::from(i as u32));
+            bloom.push(w, Token::from(i as u32));
+        }
+        (trie, bloom)
+    }
+
+    #[test]
+    fn on_find_should_match_the_trie() {
+        let (trie, bloom) = filters();
+
+        for prefix in [
+            "",
+            "con",
+            "conn",
+            "connection",
+            "ref",
+            "ż",
+            "x",
+            "connectionsandmore",
+        ] {
+            assert_eq!(
+                bloom.find_prefix(prefix),
+                trie.find_prefix(prefix),
+                "{}",
+                prefix
+            );
+            assert_eq!(
+                bloom.find_prefix_words(prefix),
+                trie.find_prefix_words(prefix)
+            );
+            assert_eq!(
+                bloom.find_prefix_case_insensitive(prefix),
+                trie.find_prefix_case_insensitive(prefix)
+            );
+        }
+        for (word, distance) in [("conected", 1), ("refusd", 1), ("zolw", 3), ("data", 2)] {
+            assert_eq!(
+                bloom.find_fuzzy(word, distance),
+                trie.find_fuzzy(word, distance)
+            );
+            assert_eq!(
+                bloom.find_prefix_fuzzy(word, distance),
+                trie.find_prefix_fuzzy(word, distance)
+            );
+        }
+        for pattern in ["con*", "*.json", "re?us*", "*", "c*d", "?"] {
+            assert_eq!(
+                bloom.find_glob(pattern),
+                trie.find_glob(pattern),
+                "{}",
+                pattern
+            );
+        }
+        for suffix in ["ed", ".json", "w"] {
+            assert_eq!(bloom.find_suffix(suffix), trie.find_suffix(suffix));
+        }
+    }
+
+    #[test]
+    fn on_push_and_remove_should_keep_words_after_growing_and_shrinking() {
+        let mut bloom = Bloom::new();
+        let words: Vec<String> = (0..MIN_CAPACITY * 20)
+            .map(|i| format!("word{}", i))
+            .collect();
+        for (i, w) in words.iter().enumerate() {
+            bloom.push(w, Token::from(i as u32));
+        }
+        assert!(bloom.remove("word1", Token::from(1)));
+        assert!(!bloom.remove("word1", Token::from(1)));
+        assert!(!bloom.remove("word2", Token::from(3)));
+
+        let found = bloom.find_prefix("word1");
+        for (i, w) in words.iter().enumerate().skip(2) {
+            if w.starts_with("word1") {
+                assert!(found.contains(&Token::from(i as u32)));
+            }
+        }
+        assert!(!found.contains(&Token::from(1)));
+
+        bloom.shrink_to_fit();
+        assert_eq!(bloom.find_prefix("word").len(), words.len() - 1);
+        assert_eq!(bloom.levels[0].capacity, words.len() - 1);
+    }
+
+    #[test]
+    fn on_find_prefix_should_return_few_false_positives() {
+        let mut bloom = Bloom::new();
+        let mut trie = Trie::new();
+        for i in 0..10000 {
+            let w: String = thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(8)
+                .map(char::from)
+                .collect();
+            bloom.push(&w, Token::from(i));
+            trie.push(&w, Token::from(i));
+        }
+
+        let mut false_positives = 0;
+        for prefix in ["a", "ab", "abc", "Xy1", "q2Rt"] {
+            let found = bloom.find_prefix(prefix);
+            let exact = trie.find_prefix(prefix);
+            assert!(found.is_superset(&exact));
+            false_positives += found.len() - exact.len();
+        }
+        assert!(false_positives < 5 * 10000 * 3 / 100);
+
+        assert!(bloom.heap_size() < trie.heap_size());
+    }
+
+    #[test]
+    fn on_find_prefix_top_should_rank_by_count() {
+        let (_, mut bloom) = filters();
+        bloom.count("connected", 5);
+        bloom.count("connection", 3);
+        bloom.count("collected", 9);
+
+        assert_eq!(
+            bloom.find_prefix_top("con", 2),
+            vec![(Token::from(2), 5), (Token::from(0), 3)]
+        );
+    }
+}
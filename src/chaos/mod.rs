@@ -0,0 +1,111 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::io::{Error, ErrorKind, Result};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+static CHAOS: OnceLock<Chaos> = OnceLock::new();
+
+/// Faults configures failures injected by the `chaos` feature.
+/// Random faults are drawn from a generator seeded with `seed`,
+/// so a sequential test run gets the same faults every time.
+///
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Faults {
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default)]
+    pub repository_latency_ms: u64,
+    #[serde(default)]
+    pub lock_delay_ms: u64,
+    #[serde(default)]
+    pub insert_failure_rate: f64,
+}
+
+struct Chaos {
+    faults: Faults,
+    rng: Mutex<StdRng>,
+}
+
+/// Installs faults for the lifetime of the process.
+/// Only the first call has an effect, hooks do nothing until faults are installed.
+///
+pub fn install(faults: Faults) {
+    let rng = Mutex::new(StdRng::seed_from_u64(faults.seed));
+    let _ = CHAOS.set(Chaos { faults, rng });
+}
+
+/// Delays a repository call by the configured latency.
+///
+pub async fn repository_latency() {
+    let Some(chaos) = CHAOS.get() else {
+        return;
+    };
+    if chaos.faults.repository_latency_ms > 0 {
+        tokio::time::sleep(Duration::from_millis(chaos.faults.repository_latency_ms)).await;
+    }
+}
+
+/// Holds the calling thread for the configured delay, meant to be called with the dictionary lock taken.
+///
+pub fn lock_delay() {
+    let Some(chaos) = CHAOS.get() else {
+        return;
+    };
+    if chaos.faults.lock_delay_ms > 0 {
+        std::thread::sleep(Duration::from_millis(chaos.faults.lock_delay_ms));
+    }
+}
+
+/// Fails with the configured rate, returning an error that looks like a dropped connection.
+///
+pub fn insert_failure() -> Result<()> {
+    let Some(chaos) = CHAOS.get() else {
+        return Ok(());
+    };
+    if draw(&chaos.rng, chaos.faults.insert_failure_rate) {
+        return Err(Error::new(
+            ErrorKind::ConnectionAborted,
+            "chaos: injected insert failure",
+        ));
+    }
+    Ok(())
+}
+
+fn draw(rng: &Mutex<StdRng>, rate: f64) -> bool {
+    if rate <= 0.0 {
+        return false;
+    }
+    let Ok(mut rng) = rng.lock() else {
+        return false;
+    };
+    rng.gen_bool(rate.min(1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn on_draw_should_repeat_the_same_faults_for_the_same_seed() {
+        let first = Mutex::new(StdRng::seed_from_u64(7));
+        let second = Mutex::new(StdRng::seed_from_u64(7));
+
+        let a: Vec<bool> = (0..100).map(|_| draw(&first, 0.3)).collect();
+        let b: Vec<bool> = (0..100).map(|_| draw(&second, 0.3)).collect();
+
+        assert_eq!(a, b);
+        assert!(a.iter().any(|f| *f));
+        assert!(a.iter().any(|f| !*f));
+    }
+
+    #[test]
+    fn on_draw_should_respect_rate_bounds() {
+        let rng = Mutex::new(StdRng::seed_from_u64(1));
+
+        assert!((0..100).all(|_| !draw(&rng, 0.0)));
+        assert!((0..100).all(|_| draw(&rng, 1.0)));
+        assert!((0..100).all(|_| draw(&rng, 2.0)));
+    }
+}
@@ -0,0 +1,176 @@
+use clap::{Parser, Subcommand};
+
+/// Cli holds the command scribe runs and where its setup comes from.
+/// Setup parameters are read from the config file, overridden by `SCRIBE_` environment variables,
+/// overridden in turn by `--set` flags.
+///
+#[derive(Debug, Parser)]
+#[command(
+    name = "scribe",
+    version,
+    about = "Stores logs as tokens of a shared dictionary."
+)]
+pub struct Cli {
+    /// Path of the YAML setup file, defaults are used without one.
+    #[arg(short, long, global = true, value_name = "PATH")]
+    pub config: Option<String>,
+    /// Sets the setup parameter over the file and environment variables, like `--set port=9000`.
+    #[arg(long = "set", global = true, value_name = "PARAM=VALUE", value_parser = parse_override)]
+    pub overrides: Vec<(String, String)>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Command is run against the repository of the setup, serving the API when none is given.
+///
+#[derive(Debug, Clone, PartialEq, Subcommand)]
+pub enum Command {
+    /// Serves the public and the admin API.
+    Serve,
+    /// Serves the API and saves entries of the systemd journal, read after the cursor of the checkpoint file.
+    Journald {
+        /// File the cursor of the last saved entry is kept in.
+        #[arg(long, value_name = "PATH", default_value = "scribe.journal.cursor")]
+        cursor: String,
+        /// Reads entries of the unit only, may be given many times, all units are read without one.
+        #[arg(long, value_name = "UNIT")]
+        unit: Vec<String>,
+    },
+    /// Serves the API and saves lines appended to the files, following their rotation.
+    Tail {
+        /// File to follow, `*` and `?` in its name match many files, may be given many times.
+        #[arg(long, value_name = "PATH", required = true, num_args = 1..)]
+        path: Vec<String>,
+        /// File the position of each followed file is kept in.
+        #[arg(long, value_name = "PATH", default_value = "scribe.tail.positions")]
+        positions: String,
+    },
+    /// Migrates the schema of the repository and exits.
+    Migrate,
+    /// Writes the dictionary of words saved in the repository to the file in the binary schema format.
+    Export { path: String },
+    /// Saves words of the dictionary schema file in to the repository holding none.
+    ImportSchema { path: String },
+    /// Reclaims space of the repository left by expired logs.
+    Compact,
+    /// Writes every log and the dictionary to the snapshot file.
+    Backup { path: String },
+    /// Loads the snapshot file in to the empty repository.
+    Restore { path: String },
+    /// Moves logs and words to the empty repository of the other setup file.
+    Copy { to: String },
+}
+
+impl Command {
+    /// Returns true if the command serves the API instead of exiting once done.
+    ///
+    pub fn serves(&self) -> bool {
+        matches!(
+            self,
+            Command::Serve | Command::Journald { .. } | Command::Tail { .. }
+        )
+    }
+}
+
+/// Parses the `param=value` pair of a `--set` flag.
+///
+fn parse_override(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((param, value)) if !param.trim().is_empty() => {
+            Ok((param.trim().to_string(), value.to_string()))
+        }
+        _ => Err(format!("[ {} ] is to be written as param=value", s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn on_parse_should_read_the_command_config_and_overrides_in_any_order() {
+        let cli = Cli::try_parse_from([
+            "scribe",
+            "serve",
+            "--config",
+            "setup.yaml",
+            "--set",
+            "port=9000",
+            "--set",
+            "log_level=warn,scribe=debug",
+        ])
+        .unwrap();
+        assert_eq!(cli.command, Some(Command::Serve));
+        assert_eq!(cli.config.as_deref(), Some("setup.yaml"));
+        assert_eq!(
+            cli.overrides,
+            [
+                ("port".to_string(), "9000".to_string()),
+                ("log_level".to_string(), "warn,scribe=debug".to_string())
+            ]
+        );
+
+        let cli = Cli::try_parse_from(["scribe", "-c", "setup.yaml", "import-schema", "words.bin"])
+            .unwrap();
+        assert_eq!(
+            cli.command,
+            Some(Command::ImportSchema {
+                path: "words.bin".to_string()
+            })
+        );
+
+        let cli = Cli::try_parse_from([
+            "scribe",
+            "journald",
+            "--unit",
+            "nginx.service",
+            "--unit",
+            "sshd.service",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.command,
+            Some(Command::Journald {
+                cursor: "scribe.journal.cursor".to_string(),
+                unit: vec!["nginx.service".to_string(), "sshd.service".to_string()]
+            })
+        );
+        assert!(cli.command.is_some_and(|command| command.serves()));
+
+        let cli = Cli::try_parse_from([
+            "scribe",
+            "tail",
+            "--path",
+            "/var/log/nginx/*.log",
+            "/var/log/syslog",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.command,
+            Some(Command::Tail {
+                path: vec![
+                    "/var/log/nginx/*.log".to_string(),
+                    "/var/log/syslog".to_string()
+                ],
+                positions: "scribe.tail.positions".to_string()
+            })
+        );
+
+        let cli = Cli::try_parse_from(["scribe"]).unwrap();
+        assert_eq!(cli.command, None);
+        assert_eq!(cli.config, None);
+    }
+
+    #[test]
+    fn on_parse_should_reject_malformed_overrides_and_missing_paths() {
+        for args in [
+            &["scribe", "--set", "port"][..],
+            &["scribe", "--set", "=9000"],
+            &["scribe", "export"],
+            &["scribe", "tail"],
+            &["scribe", "setup.yaml"],
+        ] {
+            assert!(Cli::try_parse_from(args).is_err(), "{:?}", args);
+        }
+    }
+}
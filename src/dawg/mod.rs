@@ -0,0 +1,844 @@
+use crate::dictionary::{Filter, Stats};
+use crate::token::Token;
+use crate::trie::Trie;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+const ROOT: u32 = 0;
+const NONE: u32 = u32::MAX;
+
+/// Dawg is a minimized directed acyclic word graph, words sharing a suffix share the states of it,
+/// so large vocabularies of similar words take a fraction of the memory of a trie.
+/// A minimized graph cannot hold a number per word in its states, instead every state counts the words
+/// below it and a word is numbered by its rank in lexicographic order, which indexes the tokens and counts.
+///
+/// The graph is frozen, words pushed afterwards go to a small trie overlay and removed words are
+/// hidden until `shrink_to_fit` builds a new graph of all the words, which suits read mostly deployments
+/// where the housekeeper shrinks the dictionary on idle.
+/// The graph holds up to u32::MAX words.
+///
+#[derive(Debug, Clone)]
+pub struct Dawg {
+    states: Vec<State>,
+    transitions: Vec<Transition>,
+    tokens: Vec<Token>,
+    counts: Vec<u64>,
+    overlay: Trie,
+    removed: HashSet<Token>,
+}
+
+/// State of the graph, its transitions are the slice of `len` transitions from `start`
+/// sorted by character and `count` is the number of words accepted from the state.
+///
+#[derive(Debug, Clone, Copy, Default)]
+struct State {
+    terminal: bool,
+    count: u32,
+    start: u32,
+    len: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Transition {
+    c: char,
+    to: u32,
+}
+
+/// Builder builds the minimized graph from words in lexicographic order,
+/// minimizing states of the previous word as soon as the next word leaves them.
+///
+#[derive(Default)]
+struct Builder {
+    states: Vec<BuildState>,
+    register: HashMap<BuildState, u32>,
+    path: Vec<u32>,
+    prev: Vec<char>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+struct BuildState {
+    terminal: bool,
+    edges: Vec<(char, u32)>,
+}
+
+impl Builder {
+    fn new() -> Self {
+        Self {
+            states: vec![BuildState::default()],
+            path: vec![ROOT],
+            ..Default::default()
+        }
+    }
+
+    /// Inserts a word that is greater than the previously inserted one.
+    ///
+    fn insert(&mut self, word: &str) {
+        let word: Vec<char> = word.chars().collect();
+        let common = word
+            .iter()
+            .zip(self.prev.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        self.minimize(common);
+        for c in word[common..].iter() {
+            self.states.push(BuildState::default());
+            let next = (self.states.len() - 1) as u32;
+            let last = self.path[self.path.len() - 1];
+            self.states[last as usize].edges.push((*c, next));
+            self.path.push(next);
+        }
+        let last = self.path[self.path.len() - 1];
+        self.states[last as usize].terminal = true;
+        self.prev = word;
+    }
+
+    /// Replaces states of the path below the depth with equal registered states or registers them.
+    ///
+    fn minimize(&mut self, depth: usize) {
+        while self.path.len() > depth + 1 {
+            let Some(child) = self.path.pop() else {
+                return;
+            };
+            let parent = self.path[self.path.len() - 1];
+            match self.register.get(&self.states[child as usize]) {
+                Some(equal) => {
+                    if let Some(edge) = self.states[parent as usize].edges.last_mut() {
+                        edge.1 = *equal;
+                    }
+                }
+                None => {
+                    self.register
+                        .insert(self.states[child as usize].clone(), child);
+                }
+            }
+        }
+    }
+
+    /// Minimizes the last word and lays out states reachable from the root, counting words below them.
+    ///
+    fn finish(mut self, tokens: Vec<Token>, counts: Vec<u64>) -> Dawg {
+        self.minimize(0);
+        let mut dawg = Dawg {
+            states: Vec::with_capacity(self.register.len() + 1),
+            transitions: Vec::with_capacity(self.register.len()),
+            tokens,
+            counts,
+            overlay: Trie::new(),
+            removed: HashSet::new(),
+        };
+        let mut ids = vec![NONE; self.states.len()];
+        self.lay_out(ROOT, &mut ids, &mut dawg);
+        dawg
+    }
+
+    fn lay_out(&self, state: u32, ids: &mut [u32], dawg: &mut Dawg) -> u32 {
+        if ids[state as usize] != NONE {
+            return ids[state as usize];
+        }
+        let src = &self.states[state as usize];
+        let id = dawg.states.len() as u32;
+        ids[state as usize] = id;
+        dawg.states.push(State::default());
+
+        let mut count = u32::from(src.terminal);
+        let mut transitions = Vec::with_capacity(src.edges.len());
+        for (c, to) in src.edges.iter() {
+            let to = self.lay_out(*to, ids, dawg);
+            count += dawg.states[to as usize].count;
+            transitions.push(Transition { c: *c, to });
+        }
+        dawg.states[id as usize] = State {
+            terminal: src.terminal,
+            count,
+            start: dawg.transitions.len() as u32,
+            len: transitions.len() as u32,
+        };
+        dawg.transitions.extend(transitions);
+        id
+    }
+}
+
+impl Default for Dawg {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dawg {
+    /// Creates an empty graph, words pushed to it are held by the overlay until `shrink_to_fit`.
+    ///
+    pub fn new() -> Self {
+        Builder::new().finish(Vec::new(), Vec::new())
+    }
+
+    fn build(mut words: Vec<(String, Token, u64)>) -> Self {
+        words.reverse();
+        words.sort_by(|a, b| a.0.cmp(&b.0));
+        words.dedup_by(|a, b| a.0 == b.0);
+
+        let mut builder = Builder::new();
+        for (word, _, _) in words.iter() {
+            builder.insert(word);
+        }
+        let (tokens, counts) = words.into_iter().map(|(_, t, c)| (t, c)).unzip();
+        builder.finish(tokens, counts)
+    }
+
+    /// Returns an estimate of bytes allocated on the heap by the graph and the overlay.
+    ///
+    pub fn heap_size(&self) -> usize {
+        self.states.capacity() * std::mem::size_of::<State>()
+            + self.transitions.capacity() * std::mem::size_of::<Transition>()
+            + self.tokens.capacity() * std::mem::size_of::<Token>()
+            + self.counts.capacity() * std::mem::size_of::<u64>()
+            + self.overlay.heap_size()
+            + self.removed.capacity() * std::mem::size_of::<Token>()
+    }
+
+    /// Returns the length of the longest path from the state, remembering lengths of shared states.
+    ///
+    fn height(&self, state: u32, heights: &mut [Option<usize>]) -> usize {
+        if let Some(h) = heights[state as usize] {
+            return h;
+        }
+        let s = self.states[state as usize];
+        let h = self.transitions[s.start as usize..(s.start + s.len) as usize]
+            .iter()
+            .map(|t| self.height(t.to, heights) + 1)
+            .max()
+            .unwrap_or(0);
+        heights[state as usize] = Some(h);
+        h
+    }
+
+    /// Returns all words of the graph that are not removed together with their tokens and counts in lexicographic order.
+    ///
+    fn words(&self) -> Vec<(String, Token, u64)> {
+        let mut words = Vec::with_capacity(self.tokens.len());
+        self.collect_words(ROOT, 0, &mut String::new(), &mut words);
+        words
+    }
+
+    fn collect_words(
+        &self,
+        state: u32,
+        rank: u32,
+        path: &mut String,
+        words: &mut Vec<(String, Token, u64)>,
+    ) {
+        if self.states[state as usize].terminal {
+            let num = self.tokens[rank as usize];
+            if !self.removed.contains(&num) {
+                words.push((path.clone(), num, self.counts[rank as usize]));
+            }
+        }
+        for (c, to, rank) in self.children(state, rank) {
+            path.push(c);
+            self.collect_words(to, rank, path, words);
+            path.pop();
+        }
+    }
+
+    /// Returns transitions of the state with the rank of the first word below each of them.
+    ///
+    #[inline(always)]
+    fn children(&self, state: u32, rank: u32) -> impl Iterator<Item = (char, u32, u32)> + '_ {
+        let s = self.states[state as usize];
+        let mut rank = rank + u32::from(s.terminal);
+        self.transitions[s.start as usize..(s.start + s.len) as usize]
+            .iter()
+            .map(move |t| {
+                let r = rank;
+                rank += self.states[t.to as usize].count;
+                (t.c, t.to, r)
+            })
+    }
+
+    /// Walks the string returning the state it ends in and the rank of the first word below it.
+    ///
+    fn walk(&self, s: &str) -> Option<(u32, u32)> {
+        let mut state = ROOT;
+        let mut rank = 0;
+        for c in s.chars() {
+            (_, state, rank) = self.children(state, rank).find(|(t, _, _)| *t == c)?;
+        }
+        Some((state, rank))
+    }
+
+    #[inline(always)]
+    fn emit(&self, rank: u32, nums: &mut HashSet<Token>) {
+        let num = self.tokens[rank as usize];
+        if !self.removed.contains(&num) {
+            nums.insert(num);
+        }
+    }
+
+    /// Keeps the k most frequent words below the state in a min heap bounded to k entries.
+    ///
+    fn append_top(
+        &self,
+        state: u32,
+        rank: u32,
+        k: usize,
+        top: &mut BinaryHeap<Reverse<(u64, Reverse<Token>)>>,
+    ) {
+        if self.states[state as usize].terminal {
+            let num = self.tokens[rank as usize];
+            if !self.removed.contains(&num) {
+                top.push(Reverse((self.counts[rank as usize], Reverse(num))));
+                if top.len() > k {
+                    top.pop();
+                }
+            }
+        }
+        for (_, to, rank) in self.children(state, rank) {
+            self.append_top(to, rank, k, top);
+        }
+    }
+
+    fn append(&self, state: u32, rank: u32, nums: &mut HashSet<Token>) {
+        if self.states[state as usize].terminal {
+            self.emit(rank, nums);
+        }
+        for (_, to, rank) in self.children(state, rank) {
+            self.append(to, rank, nums);
+        }
+    }
+
+    /// Walks the graph simulating a Levenshtein automaton of the word,
+    /// where row holds edit distances of the path so far to every prefix of the word.
+    ///
+    fn walk_fuzzy(
+        &self,
+        state: u32,
+        rank: u32,
+        word: &[char],
+        row: &[usize],
+        distance: usize,
+        nums: &mut HashSet<Token>,
+    ) {
+        for (c, to, rank) in self.children(state, rank) {
+            let mut curr = Vec::with_capacity(row.len());
+            curr.push(row[0] + 1);
+            for i in 1..row.len() {
+                let substitution = row[i - 1] + usize::from(word[i - 1] != c);
+                curr.push((curr[i - 1] + 1).min(row[i] + 1).min(substitution));
+            }
+            if curr.iter().min().is_none_or(|d| *d > distance) {
+                continue;
+            }
+            if curr[word.len()] <= distance && self.states[to as usize].terminal {
+                self.emit(rank, nums);
+            }
+            self.walk_fuzzy(to, rank, word, &curr, distance, nums);
+        }
+    }
+
+    /// Walks the graph simulating a Levenshtein automaton of the word like `walk_fuzzy`,
+    /// collecting every word below a state once the path is within the distance of the whole word.
+    ///
+    fn walk_prefix_fuzzy(
+        &self,
+        state: u32,
+        rank: u32,
+        word: &[char],
+        row: &[usize],
+        distance: usize,
+        nums: &mut HashSet<Token>,
+    ) {
+        for (c, to, rank) in self.children(state, rank) {
+            let mut curr = Vec::with_capacity(row.len());
+            curr.push(row[0] + 1);
+            for i in 1..row.len() {
+                let substitution = row[i - 1] + usize::from(word[i - 1] != c);
+                curr.push((curr[i - 1] + 1).min(row[i] + 1).min(substitution));
+            }
+            if curr[word.len()] <= distance {
+                self.append(to, rank, nums);
+                continue;
+            }
+            if curr.iter().min().is_none_or(|d| *d > distance) {
+                continue;
+            }
+            self.walk_prefix_fuzzy(to, rank, word, &curr, distance, nums);
+        }
+    }
+
+    /// Collects words whose case folded form starts with the folded chars.
+    ///
+    fn walk_case_insensitive(
+        &self,
+        state: u32,
+        rank: u32,
+        folded: &[char],
+        nums: &mut HashSet<Token>,
+    ) {
+        if folded.is_empty() {
+            self.append(state, rank, nums);
+            return;
+        }
+        'transitions: for (c, to, rank) in self.children(state, rank) {
+            let mut rest = folded;
+            for c in crate::trie::fold(c) {
+                let Some((first, r)) = rest.split_first() else {
+                    self.append(to, rank, nums);
+                    continue 'transitions;
+                };
+                if c != *first {
+                    continue 'transitions;
+                }
+                rest = r;
+            }
+            self.walk_case_insensitive(to, rank, rest, nums);
+        }
+    }
+
+    /// Collects words matching the glob pattern,
+    /// where `*` matches any run of characters and `?` matches a single character.
+    ///
+    fn walk_glob(&self, state: u32, rank: u32, pattern: &[char], nums: &mut HashSet<Token>) {
+        match pattern.first() {
+            None => {
+                if self.states[state as usize].terminal {
+                    self.emit(rank, nums);
+                }
+            }
+            Some('*') => {
+                self.walk_glob(state, rank, &pattern[1..], nums);
+                for (_, to, rank) in self.children(state, rank) {
+                    self.walk_glob(to, rank, pattern, nums);
+                }
+            }
+            Some('?') => {
+                for (_, to, rank) in self.children(state, rank) {
+                    self.walk_glob(to, rank, &pattern[1..], nums);
+                }
+            }
+            Some(p) => {
+                if let Some((_, to, rank)) = self.children(state, rank).find(|(c, _, _)| c == p) {
+                    self.walk_glob(to, rank, &pattern[1..], nums);
+                }
+            }
+        }
+    }
+
+    /// Collects words ending with the suffix, rebuilding each word on the path.
+    ///
+    fn walk_suffix(
+        &self,
+        state: u32,
+        rank: u32,
+        path: &mut String,
+        suffix: &str,
+        nums: &mut HashSet<Token>,
+    ) {
+        if self.states[state as usize].terminal && path.ends_with(suffix) {
+            self.emit(rank, nums);
+        }
+        for (c, to, rank) in self.children(state, rank) {
+            path.push(c);
+            self.walk_suffix(to, rank, path, suffix, nums);
+            path.pop();
+        }
+    }
+}
+
+impl Filter for Dawg {
+    /// Pushes the word to the overlay, hiding the word in the graph if it had another token.
+    ///
+    fn push(&mut self, s: &str, num: Token) {
+        if let Some((state, rank)) = self.walk(s) {
+            if self.states[state as usize].terminal && self.tokens[rank as usize] != num {
+                self.removed.insert(self.tokens[rank as usize]);
+            }
+        }
+        self.overlay.push(s, num);
+    }
+
+    /// Removes the word from the overlay or hides it in the graph until it is rebuilt.
+    /// Returns false if the string is not held under that num.
+    ///
+    fn remove(&mut self, s: &str, num: Token) -> bool {
+        if self.overlay.remove(s, num) {
+            return true;
+        }
+        let Some((state, rank)) = self.walk(s) else {
+            return false;
+        };
+        self.states[state as usize].terminal
+            && self.tokens[rank as usize] == num
+            && self.removed.insert(num)
+    }
+
+    /// Adds occurrences to the count of the word in the overlay or in the graph.
+    ///
+    fn count(&mut self, s: &str, by: u64) {
+        if self.overlay.find_match(s).is_some() {
+            self.overlay.count(s, by);
+            return;
+        }
+        if let Some((state, rank)) = self.walk(s) {
+            if self.states[state as usize].terminal {
+                self.counts[rank as usize] += by;
+            }
+        }
+    }
+
+    /// Finds up to k most frequent words with matching prefix.
+    /// The graph keeps no counts of its states, so every word under the prefix is visited,
+    /// holding no more than k of them at a time.
+    ///
+    fn find_prefix_top(&self, s: &str, k: usize) -> Vec<(Token, u64)> {
+        if k == 0 {
+            return Vec::new();
+        }
+        let mut top = BinaryHeap::with_capacity(k + 1);
+        for (num, count) in self.overlay.find_prefix_top(s, k) {
+            top.push(Reverse((count, Reverse(num))));
+        }
+        if let Some((state, rank)) = self.walk(s) {
+            self.append_top(state, rank, k, &mut top);
+        }
+        top.into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((count, Reverse(num)))| (num, count))
+            .collect()
+    }
+
+    fn find_prefix(&self, s: &str) -> HashSet<Token> {
+        let mut nums = self.overlay.find_prefix(s);
+        if let Some((state, rank)) = self.walk(s) {
+            self.append(state, rank, &mut nums);
+        }
+        nums
+    }
+
+    /// Returns the shape of the graph, the overlay is counted in bytes only.
+    ///
+    fn stats(&self) -> Option<Stats> {
+        let inner = self.states.iter().filter(|s| s.len > 0).count();
+        Some(Stats {
+            nodes: self.states.len(),
+            max_depth: self.height(ROOT, &mut vec![None; self.states.len()]),
+            branching: match inner {
+                0 => 0.0,
+                _ => self.transitions.len() as f64 / inner as f64,
+            },
+            bytes: self.heap_size(),
+        })
+    }
+
+    /// Finds index nums with matching prefix unless there are more than max of them.
+    /// States count the words below them, so a prefix with too many words is turned down without a walk.
+    ///
+    fn find_prefix_limited(&self, s: &str, max: usize) -> Option<HashSet<Token>> {
+        let mut nums = self.overlay.find_prefix_limited(s, max)?;
+        if let Some((state, rank)) = self.walk(s) {
+            let count = self.states[state as usize].count as usize;
+            if count.saturating_sub(self.removed.len()) + nums.len() > max {
+                return None;
+            }
+            self.append(state, rank, &mut nums);
+        }
+        (nums.len() <= max).then_some(nums)
+    }
+
+    /// Finds all words with matching prefix of the graph and the overlay in lexicographic order.
+    ///
+    fn find_prefix_words(&self, s: &str) -> Vec<(String, Token)> {
+        let mut words = Vec::new();
+        if let Some((state, rank)) = self.walk(s) {
+            self.collect_words(state, rank, &mut s.to_string(), &mut words);
+        }
+        let mut words: Vec<(String, Token)> = words
+            .into_iter()
+            .map(|(word, num, _)| (word, num))
+            .chain(self.overlay.find_prefix_words(s))
+            .collect();
+        words.sort_unstable();
+        words
+    }
+
+    fn find_prefix_case_insensitive(&self, s: &str) -> HashSet<Token> {
+        let folded: Vec<char> = s.chars().flat_map(crate::trie::fold).collect();
+        let mut nums = self.overlay.find_prefix_case_insensitive(s);
+        self.walk_case_insensitive(ROOT, 0, &folded, &mut nums);
+        nums
+    }
+
+    fn find_fuzzy(&self, s: &str, distance: u8) -> HashSet<Token> {
+        let word: Vec<char> = s.chars().collect();
+        let row: Vec<usize> = (0..=word.len()).collect();
+        let mut nums = self.overlay.find_fuzzy(s, distance);
+        if word.len() <= distance as usize && self.states[ROOT as usize].terminal {
+            self.emit(0, &mut nums);
+        }
+        self.walk_fuzzy(ROOT, 0, &word, &row, distance as usize, &mut nums);
+        nums
+    }
+
+    fn find_prefix_fuzzy(&self, s: &str, distance: u8) -> HashSet<Token> {
+        let word: Vec<char> = s.chars().collect();
+        let row: Vec<usize> = (0..=word.len()).collect();
+        let mut nums = self.overlay.find_prefix_fuzzy(s, distance);
+        if word.len() <= distance as usize {
+            self.append(ROOT, 0, &mut nums);
+            return nums;
+        }
+        self.walk_prefix_fuzzy(ROOT, 0, &word, &row, distance as usize, &mut nums);
+        nums
+    }
+
+    fn find_glob(&self, pattern: &str) -> HashSet<Token> {
+        let mut chars: Vec<char> = Vec::with_capacity(pattern.len());
+        for c in pattern.chars() {
+            if !(c == '*' && chars.last() == Some(&'*')) {
+                chars.push(c);
+            }
+        }
+        let mut nums = self.overlay.find_glob(pattern);
+        self.walk_glob(ROOT, 0, &chars, &mut nums);
+        nums
+    }
+
+    fn find_suffix(&self, s: &str) -> HashSet<Token> {
+        let mut nums = self.overlay.find_suffix(s);
+        self.walk_suffix(ROOT, 0, &mut String::new(), s, &mut nums);
+        nums
+    }
+
+    /// Builds a new minimized graph of the graph and the overlay words, leaving out removed words.
+    ///
+    fn shrink_to_fit(&mut self) {
+        if self.overlay.iter_words().next().is_none() && self.removed.is_empty() {
+            return;
+        }
+        let mut words = self.words();
+        for (word, num) in self.overlay.iter_words() {
+            let count = self.overlay.find_count(&word);
+            words.push((word, num, count));
+        }
+        let mut dawg = Self::build(words);
+        dawg.states.shrink_to_fit();
+        dawg.transitions.shrink_to_fit();
+        dawg.tokens.shrink_to_fit();
+        dawg.counts.shrink_to_fit();
+        *self = dawg;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl Dawg {
+        /// Builds the minimized graph of the words, a repeated word keeps its last token.
+        ///
+        fn from_words<S: Into<String>>(words: impl IntoIterator<Item = (S, Token)>) -> Self {
+            Self::build(words.into_iter().map(|(w, t)| (w.into(), t, 0)).collect())
+        }
+
+        /// Find matching string in the graph or the overlay returning it index num if found or None otherwise.
+        ///
+        fn find_match(&self, s: &str) -> Option<Token> {
+            if let Some(num) = self.overlay.find_match(s) {
+                return Some(num);
+            }
+            let (state, rank) = self.walk(s)?;
+            if !self.states[state as usize].terminal {
+                return None;
+            }
+            let num = self.tokens[rank as usize];
+            match self.removed.contains(&num) {
+                true => None,
+                false => Some(num),
+            }
+        }
+    }
+
+    const WORDS: [&str; 10] = [
+        "connection",
+        "connections",
+        "connected",
+        "collected",
+        "refused",
+        "refusal",
+        "Straße",
+        "config.json",
+        "data.json",
+        "żółw",
+    ];
+
+    fn filters() -> (Trie, Dawg) {
+        let mut trie = Trie::new();
+        let mut words = Vec::new();
+        for (i, w) in WORDS.iter().enumerate() {
+            trie.push(w, Token::from(i as u32));
+            words.push((*w, Token::from(i as u32)));
+        }
+        (trie, Dawg::from_words(words))
+    }
+
+    #[test]
+    fn on_find_match_should_number_words_by_rank() {
+        let (_, dawg) = filters();
+
+        for (i, w) in WORDS.iter().enumerate() {
+            assert_eq!(dawg.find_match(w), Some(Token::from(i as u32)));
+        }
+        for w in ["connect", "connectionss", "", "x"] {
+            assert_eq!(dawg.find_match(w), None);
+        }
+    }
+
+    #[test]
+    fn on_queries_should_match_the_trie() {
+        let (trie, dawg) = filters();
+
+        for prefix in ["con", "re", "", "x", "żó"] {
+            assert_eq!(dawg.find_prefix(prefix), trie.find_prefix(prefix));
+            assert_eq!(
+                dawg.find_prefix_words(prefix),
+                trie.find_prefix_words(prefix)
+            );
+        }
+        for prefix in ["STRASS", "CON", "Żó"] {
+            assert_eq!(
+                dawg.find_prefix_case_insensitive(prefix),
+                trie.find_prefix_case_insensitive(prefix)
+            );
+        }
+        for (word, distance) in [("conection", 1), ("refuse", 2), ("żółw", 0)] {
+            assert_eq!(
+                dawg.find_fuzzy(word, distance),
+                trie.find_fuzzy(word, distance)
+            );
+            assert_eq!(
+                dawg.find_prefix_fuzzy(word, distance),
+                trie.find_prefix_fuzzy(word, distance)
+            );
+        }
+        for pattern in ["con*ed", "refus??", "*.json", "*"] {
+            assert_eq!(dawg.find_glob(pattern), trie.find_glob(pattern));
+        }
+        for suffix in ["ed", ".json", "w"] {
+            assert_eq!(dawg.find_suffix(suffix), trie.find_suffix(suffix));
+        }
+    }
+
+    #[test]
+    fn on_push_and_remove_should_use_the_overlay_until_shrink() {
+        let (_, mut dawg) = filters();
+        let states = dawg.states.len();
+
+        dawg.push("connecting", Token::from(100));
+        assert!(dawg.remove("refused", Token::from(4)));
+        assert!(!dawg.remove("refused", Token::from(4)));
+        assert!(!dawg.remove("refusal", Token::from(0)));
+
+        assert_eq!(dawg.states.len(), states);
+        assert_eq!(dawg.find_match("connecting"), Some(Token::from(100)));
+        assert_eq!(dawg.find_match("refused"), None);
+        assert_eq!(dawg.find_prefix("refus"), HashSet::from([Token::from(5)]));
+
+        dawg.shrink_to_fit();
+
+        assert!(dawg.overlay.iter_words().next().is_none() && dawg.removed.is_empty());
+        assert_eq!(dawg.find_match("connecting"), Some(Token::from(100)));
+        assert_eq!(dawg.find_match("refused"), None);
+        assert_eq!(dawg.find_match("żółw"), Some(Token::from(9)));
+        assert_eq!(dawg.tokens.len(), WORDS.len());
+    }
+
+    #[test]
+    fn on_find_prefix_limited_should_count_overlay_and_removed_words() {
+        let (_, mut dawg) = filters();
+        dawg.push("connecting", Token::from(100));
+        assert!(dawg.remove("connected", Token::from(2)));
+
+        assert_eq!(
+            dawg.find_prefix_limited("conn", 3),
+            Some(HashSet::from_iter([0, 1, 100].map(Token::from)))
+        );
+        assert_eq!(dawg.find_prefix_limited("conn", 2), None);
+        assert_eq!(dawg.find_prefix_limited("", 3), None);
+    }
+
+    #[test]
+    fn on_stats_should_count_shared_states_once() {
+        let dawg = Dawg::from_words([("tapped", Token::from(1)), ("tipped", Token::from(2))]);
+
+        let stats = dawg.stats().unwrap();
+        assert_eq!(stats.nodes, 7);
+        assert_eq!(stats.max_depth, 6);
+        assert_eq!(stats.branching, 7.0 / 6.0);
+        assert_eq!(stats.bytes, dawg.heap_size());
+    }
+
+    #[test]
+    fn on_find_prefix_top_should_rank_graph_and_overlay_words() {
+        let (trie, mut dawg) = filters();
+        let mut counted = trie.clone();
+        for (i, w) in WORDS.iter().enumerate() {
+            dawg.count(w, i as u64 % 4);
+            counted.count(w, i as u64 % 4);
+        }
+        let counts = |top: Vec<(Token, u64)>| top.into_iter().map(|(_, c)| c).collect::<Vec<_>>();
+        for prefix in ["con", "", "re", "x"] {
+            assert_eq!(
+                counts(dawg.find_prefix_top(prefix, 3)),
+                counts(counted.find_prefix_top(prefix, 3))
+            );
+        }
+
+        dawg.push("connector", Token::from(50));
+        dawg.count("connector", 7);
+        dawg.remove("connected", Token::from(2));
+        assert_eq!(
+            dawg.find_prefix_top("conn", 2),
+            vec![(Token::from(50), 7), (Token::from(1), 1)]
+        );
+
+        dawg.shrink_to_fit();
+        assert_eq!(
+            dawg.find_prefix_top("conn", 2),
+            vec![(Token::from(50), 7), (Token::from(1), 1)]
+        );
+        dawg.count("connector", 1);
+        assert_eq!(
+            dawg.find_prefix_top("connecto", 1),
+            vec![(Token::from(50), 8)]
+        );
+    }
+
+    #[test]
+    fn on_from_words_should_share_suffixes() {
+        let services = ["auth", "billing", "gateway", "search", "storage"];
+        let mut words = Vec::new();
+        for s in services {
+            for n in 0..200 {
+                for ext in ["log", "json", "trace"] {
+                    words.push(format!("{}-{:03}.{}", s, n, ext));
+                }
+            }
+        }
+
+        let mut trie = Trie::new();
+        for (i, w) in words.iter().enumerate() {
+            trie.push(w, Token::from(i as u32));
+        }
+        trie.shrink_to_fit();
+        let dawg = Dawg::from_words(
+            words
+                .iter()
+                .enumerate()
+                .map(|(i, w)| (w.as_str(), Token::from(i as u32))),
+        );
+
+        for (i, w) in words.iter().enumerate() {
+            assert_eq!(dawg.find_match(w), Some(Token::from(i as u32)));
+        }
+        assert!(dawg.states.len() < 100);
+        assert!(dawg.heap_size() * 2 < trie.heap_size());
+    }
+}
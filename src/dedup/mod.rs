@@ -0,0 +1,155 @@
+use crate::settings::DedupMode;
+use scribe::token::Token;
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Deduplicator remembers logs inserted within the window by the hash of their tokens,
+/// so identical logs repeated within the window are skipped or counted instead of stored again.
+/// Two different logs sharing a hash are taken for identical, a chance of about one in 2^64.
+///
+#[derive(Debug)]
+pub struct Deduplicator {
+    mode: DedupMode,
+    window: Duration,
+    seen: Mutex<Seen>,
+}
+
+#[derive(Debug, Default)]
+struct Seen {
+    first: HashMap<u64, Duration>,
+    order: VecDeque<(Duration, u64)>,
+}
+
+impl Seen {
+    /// Forgets logs first seen before given time, oldest first.
+    ///
+    fn expire(&mut self, before: Duration) {
+        while let Some((at, hash)) = self.order.front().copied() {
+            if at >= before {
+                break;
+            }
+            self.order.pop_front();
+            if self.first.get(&hash) == Some(&at) {
+                self.first.remove(&hash);
+            }
+        }
+    }
+}
+
+#[inline(always)]
+fn hash(input: &[Token]) -> u64 {
+    let mut h = DefaultHasher::new();
+    input.hash(&mut h);
+    h.finish()
+}
+
+impl Deduplicator {
+    /// Creates a new Deduplicator handling logs repeated within the window as the mode says.
+    ///
+    pub fn new(mode: DedupMode, window: Duration) -> Self {
+        Self {
+            mode,
+            window,
+            seen: Mutex::new(Seen::default()),
+        }
+    }
+
+    /// Returns how repeated logs are handled.
+    ///
+    pub fn mode(&self) -> DedupMode {
+        self.mode
+    }
+
+    /// Returns when an identical log was first seen if it was within the window before now,
+    /// otherwise remembers the log as first seen now and returns None.
+    ///
+    pub fn check(&self, input: &[Token], now: Duration) -> Option<Duration> {
+        let Ok(mut seen) = self.seen.lock() else {
+            return None;
+        };
+        seen.expire(now.saturating_sub(self.window));
+        let h = hash(input);
+        match seen.first.entry(h) {
+            Entry::Occupied(e) => Some(*e.get()),
+            Entry::Vacant(e) => {
+                e.insert(now);
+                seen.order.push_back((now, h));
+                None
+            }
+        }
+    }
+
+    /// Forgets the log, so the next identical one is stored, for example after its insert failed.
+    ///
+    pub fn forget(&self, input: &[Token]) {
+        if let Ok(mut seen) = self.seen.lock() {
+            seen.first.remove(&hash(input));
+        }
+    }
+
+    /// Forgets logs seen before the window ending now and releases memory held for them,
+    /// so a burst of distinct logs does not keep its fingerprints allocated once the server is idle.
+    ///
+    pub fn shrink(&self, now: Duration) {
+        if let Ok(mut seen) = self.seen.lock() {
+            seen.expire(now.saturating_sub(self.window));
+            seen.first.shrink_to_fit();
+            seen.order.shrink_to_fit();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn on_check_should_find_identical_logs_within_the_window_only() {
+        let d = Deduplicator::new(DedupMode::Skip, WINDOW);
+        let log = [Token::from(1), Token::from(2)];
+        let other = [Token::from(2), Token::from(1)];
+        let start = Duration::from_secs(1000);
+
+        assert_eq!(d.check(&log, start), None);
+        assert_eq!(d.check(&other, start), None);
+        assert_eq!(d.check(&log, start + WINDOW / 2), Some(start));
+        assert_eq!(d.check(&log, start + WINDOW), Some(start));
+
+        let later = start + WINDOW + Duration::from_secs(1);
+        assert_eq!(d.check(&log, later), None);
+        assert_eq!(d.check(&log, later), Some(later));
+    }
+
+    #[test]
+    fn on_shrink_should_drop_logs_seen_before_the_window() {
+        let d = Deduplicator::new(DedupMode::Skip, WINDOW);
+        let start = Duration::from_secs(1000);
+        for i in 0..1000 {
+            d.check(&[Token::from(i)], start);
+        }
+        d.check(&[Token::from(1000)], start + WINDOW);
+
+        d.shrink(start + WINDOW + Duration::from_secs(1));
+        let seen = d.seen.lock().unwrap();
+        assert_eq!(seen.first.len(), 1);
+        assert!(seen.first.capacity() < 1000);
+        assert_eq!(seen.order.len(), 1);
+    }
+
+    #[test]
+    fn on_forget_should_let_the_next_identical_log_through() {
+        let d = Deduplicator::new(DedupMode::Count, WINDOW);
+        let log = [Token::from(7)];
+        let now = Duration::from_secs(1000);
+
+        assert_eq!(d.check(&log, now), None);
+        d.forget(&log);
+        assert_eq!(d.check(&log, now), None);
+        assert_eq!(d.check(&log, now), Some(now));
+    }
+}
@@ -1,24 +1,126 @@
-use scanf::sscanf;
+use crate::token::Token;
+use serde::Serialize;
 use std::collections::{HashMap, HashSet};
-use std::fs::File;
-use std::io::prelude::*;
-use std::io::BufReader;
-use std::io::{LineWriter, Result as ResultStd, Write};
+use std::io::{Error, ErrorKind, Read, Result as ResultStd, Write};
+use std::time::Instant;
+
+const SCHEMA_MAGIC: &[u8; 4] = b"SCRD";
+const SCHEMA_VERSION: u8 = 1;
+
+/// Number of buffers filtered between checks of the deadline, so the clock is not read for every buffer.
+const DEADLINE_CHECK_INTERVAL: usize = 256;
+
+/// Tells if a buffer matches a query, so buffers are scanned once for all queries of a request.
+///
+pub type Matcher<'a> = Box<dyn Fn(&[Token]) -> bool + 'a>;
+
+/// Filtered holds buffers kept by a counting filter, the number of all matching buffers
+/// and the number of scanned ones, fewer than given if the deadline passed first.
+///
+#[derive(Debug, PartialEq)]
+pub struct Filtered<B> {
+    pub buffers: Vec<B>,
+    pub matched: usize,
+    pub scanned: usize,
+}
 
 /// Offers finding mechanism for matching words with numeric representation.
 ///
 pub trait Filter: Send + Sync {
-    fn push(&mut self, s: &str, num: u32);
-    fn find_prefix(&self, s: &str) -> HashSet<u32>;
-    fn find_prefix_case_insensitive(&self, s: &str) -> HashSet<u32>;
+    fn push(&mut self, s: &str, num: Token);
+    /// Removes the word held under the num, returning false if there is no such word.
+    /// Filters prune what the word alone held, or hide it until they are rebuilt.
+    ///
+    fn remove(&mut self, s: &str, num: Token) -> bool;
+    fn count(&mut self, s: &str, by: u64);
+    fn find_prefix_top(&self, s: &str, k: usize) -> Vec<(Token, u64)>;
+    fn find_prefix(&self, s: &str) -> HashSet<Token>;
+    fn find_prefix_words(&self, s: &str) -> Vec<(String, Token)>;
+    /// Pushes a word that may not be UTF-8, filters of strings only take it if it is UTF-8.
+    ///
+    fn push_bytes(&mut self, s: &[u8], num: Token) {
+        if let Ok(s) = std::str::from_utf8(s) {
+            self.push(s, num);
+        }
+    }
+    /// Removes a word that may not be UTF-8, filters of strings only hold it if it is UTF-8.
+    ///
+    fn remove_bytes(&mut self, s: &[u8], num: Token) -> bool {
+        std::str::from_utf8(s).is_ok_and(|s| self.remove(s, num))
+    }
+    /// Finds all index nums of words starting with the bytes, filters of strings only find UTF-8 prefixes.
+    ///
+    fn find_prefix_bytes(&self, s: &[u8]) -> HashSet<Token> {
+        match std::str::from_utf8(s) {
+            Ok(s) => self.find_prefix(s),
+            Err(_) => HashSet::new(),
+        }
+    }
+    fn find_prefix_case_insensitive(&self, s: &str) -> HashSet<Token>;
+    fn find_fuzzy(&self, s: &str, distance: u8) -> HashSet<Token>;
+    fn find_prefix_fuzzy(&self, s: &str, distance: u8) -> HashSet<Token>;
+    fn find_glob(&self, pattern: &str) -> HashSet<Token>;
+    fn find_suffix(&self, s: &str) -> HashSet<Token>;
+
+    /// Finds index nums with matching string prefix unless there are more than max of them,
+    /// so a caller can give up on a set too large to be worth building.
+    ///
+    fn find_prefix_limited(&self, s: &str, max: usize) -> Option<HashSet<Token>> {
+        let nums = self.find_prefix(s);
+        (nums.len() <= max).then_some(nums)
+    }
+
+    /// Finds all index nums of words from the lower bound inclusive up to the upper bound exclusive
+    /// in lexicographic order. Words between the bounds start with the prefix the bounds share,
+    /// so the default visits only words of that prefix, filters keeping words in order should override it.
+    ///
+    fn find_range(&self, from: &str, to: &str) -> HashSet<Token> {
+        let shared = from
+            .char_indices()
+            .zip(to.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(from.len().min(to.len()), |((at, _), _)| at);
+        self.find_prefix_words(&from[..shared])
+            .into_iter()
+            .filter(|(w, _)| w.as_str() >= from && w.as_str() < to)
+            .map(|(_, num)| num)
+            .collect()
+    }
+
+    /// Returns statistics of the graph of the filter, or None if the filter keeps none.
+    /// Filters that are not graphs report their size in bytes only.
+    ///
+    fn stats(&self) -> Option<Stats> {
+        None
+    }
+
+    /// Releases spare capacity held by the filter, if any.
+    ///
+    fn shrink_to_fit(&mut self) {}
+}
+
+/// Stats describe the shape of a filter graph, to guide capacity planning.
+/// Depth counts edges from the root to the deepest node and branching is the average number
+/// of edges of nodes that have any.
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Stats {
+    pub nodes: usize,
+    pub max_depth: usize,
+    pub branching: f64,
+    pub bytes: usize,
 }
 
 /// Serializer serialize the log in to the binary format.
 ///
 pub struct Module {
-    words_to_numbers: HashMap<String, u32>,
-    nums_to_words: HashMap<u32, String>,
-    last_available_number: u32,
+    words_to_numbers: HashMap<String, Token>,
+    nums_to_words: HashMap<Token, String>,
+    last_available_number: Token,
+    frequencies: HashMap<Token, u64>,
+    unsaved: Vec<(String, Token)>,
+    pruned: HashSet<Token>,
+    max_log_bytes: Option<usize>,
     filter: Box<dyn Filter>,
 }
 
@@ -30,16 +132,33 @@ impl Module {
         Self {
             words_to_numbers: HashMap::new(),
             nums_to_words: HashMap::new(),
-            last_available_number: 0,
+            last_available_number: Token::default(),
+            frequencies: HashMap::new(),
+            unsaved: Vec::new(),
+            pruned: HashSet::new(),
+            max_log_bytes: None,
             filter: Box::new(f),
         }
     }
 
+    /// Creates new Book holding given words, the filter is expected to already hold them,
+    /// so the maps can be rebuilt from a loaded filter, for example from `Trie::iter_words`.
+    ///
+    #[inline]
+    pub fn with_words(
+        f: impl Filter + 'static,
+        words: impl IntoIterator<Item = (String, Token)>,
+    ) -> Self {
+        let mut m = Self::new(f);
+        m.set_map_from(words.into_iter().collect());
+        m
+    }
+
     /// Sets a new words to numbers map from given dataset.
     ///
     #[inline(always)]
-    pub fn set_map_from(&mut self, m: HashMap<String, u32>) {
-        self.last_available_number = 0;
+    pub fn set_map_from(&mut self, m: HashMap<String, Token>) {
+        self.last_available_number = Token::default();
         self.words_to_numbers = m;
         self.words_to_numbers.iter().for_each(|(_, n)| {
             if *n > self.last_available_number {
@@ -49,127 +168,637 @@ impl Module {
         self.nums_from_words();
     }
 
+    /// Adds words saved earlier with their numbers to the dictionary and the filter,
+    /// so logs saved by a previous run are read and new words get numbers not taken yet.
+    /// Words already known are skipped, added words are not marked as unsaved.
+    ///
+    pub fn load_words(&mut self, words: impl IntoIterator<Item = (String, Token)>) {
+        for (word, num) in words {
+            if self.words_to_numbers.contains_key(&word) || self.nums_to_words.contains_key(&num) {
+                continue;
+            }
+            if self.last_available_number < num {
+                self.last_available_number = num;
+            }
+            self.filter.push(&word, num);
+            self.nums_to_words.insert(num, word.clone());
+            self.words_to_numbers.insert(word, num);
+        }
+    }
+
+    /// Moves words of the dictionary in to the empty one, so its filter is built anew without leftovers of growth.
+    /// Words keep their numbers and unsaved words stay unsaved, frequencies start over,
+    /// so top words and suggestions rank words by use since the rotation.
+    /// Pruned words stay out of the new filter.
+    ///
+    pub fn rotated(&self, mut empty: Module) -> Module {
+        empty.load_words(
+            self.iter()
+                .filter(|(_, num)| !self.pruned.contains(num))
+                .map(|(word, num)| (word.clone(), *num)),
+        );
+        for num in self.pruned.iter() {
+            if let Some(word) = self.nums_to_words.get(num) {
+                empty.nums_to_words.insert(*num, word.clone());
+                empty.words_to_numbers.insert(word.clone(), *num);
+            }
+        }
+        empty.last_available_number = self.last_available_number;
+        empty.unsaved = self.unsaved.clone();
+        empty.pruned = self.pruned.clone();
+        empty.max_log_bytes = self.max_log_bytes;
+        empty
+    }
+
+    /// Removes words no stored log refers to from the filter, returning the number of pruned words.
+    /// Words keep their numbers, so logs holding them are still read and a pruned word
+    /// is pushed to the filter again once a log holds it. Unsaved words and words serialized
+    /// since the rotation are kept, as logs holding them may not be stored yet.
+    ///
+    pub fn prune(&mut self, referenced: &HashSet<Token>) -> usize {
+        let unsaved: HashSet<Token> = self.unsaved.iter().map(|(_, num)| *num).collect();
+        let mut pruned = 0;
+        for (word, num) in self.words_to_numbers.iter() {
+            if referenced.contains(num)
+                || unsaved.contains(num)
+                || self.frequencies.contains_key(num)
+                || self.pruned.contains(num)
+            {
+                continue;
+            }
+            if self.filter.remove(word, *num) {
+                self.pruned.insert(*num);
+                pruned += 1;
+            }
+        }
+        pruned
+    }
+
+    /// Sets the longest log in bytes the dictionary serializes, None means logs of any length.
+    ///
+    #[inline(always)]
+    pub fn set_max_log_bytes(&mut self, max: Option<usize>) {
+        self.max_log_bytes = max;
+    }
+
+    /// Returns the longest log in bytes the dictionary serializes, None means logs of any length.
+    ///
+    #[inline(always)]
+    pub fn max_log_bytes(&self) -> Option<usize> {
+        self.max_log_bytes
+    }
+
+    /// Returns an error of kind FileTooLarge if the log is longer than the dictionary serializes,
+    /// so a batch can be checked before any of its words are added.
+    ///
+    #[inline(always)]
+    pub fn check_log(&self, log: &str) -> ResultStd<()> {
+        match self.max_log_bytes {
+            Some(max) if log.len() > max => Err(Error::new(
+                ErrorKind::FileTooLarge,
+                format!("log of {} bytes is longer than {} bytes", log.len(), max),
+            )),
+            _ => Ok(()),
+        }
+    }
+
     /// Serializes the value in to the numeric representation of data.
+    /// Counts every occurrence of each token.
+    /// Returns an error if the log is too long, see check_log,
+    /// or once the token id space is exhausted, new words of the log met before that stay in the dictionary.
     ///
     #[inline]
-    pub fn serialize(&mut self, log: &str) -> Vec<u32> {
+    pub fn serialize(&mut self, log: &str) -> ResultStd<Vec<Token>> {
+        self.check_log(log)?;
         log.split_whitespace()
-            .into_iter()
             .map(|token| {
-                let Some(num) = self.words_to_numbers.get(token) else {
-                    self.last_available_number += 1;
-                    self.words_to_numbers
-                        .insert(token.to_string(), self.last_available_number);
-                    self.nums_to_words
-                        .insert(self.last_available_number, token.to_string());
-                    self.filter.push(token, self.last_available_number);
-                    return self.last_available_number;
+                let num = match self.words_to_numbers.get(token) {
+                    Some(num) => {
+                        let num = *num;
+                        if !self.pruned.is_empty() && self.pruned.remove(&num) {
+                            self.filter.push(token, num);
+                        }
+                        num
+                    }
+                    None => {
+                        let num = self.last_available_number.next()?;
+                        self.last_available_number = num;
+                        self.words_to_numbers.insert(token.to_string(), num);
+                        self.nums_to_words.insert(num, token.to_string());
+                        self.unsaved.push((token.to_string(), num));
+                        self.filter.push(token, num);
+                        num
+                    }
                 };
-                *num
+                *self.frequencies.entry(num).or_insert(0) += 1;
+                self.filter.count(token, 1);
+                Ok(num)
             })
             .collect()
     }
 
+    /// Returns words created by serialized logs or read from a schema that are not marked as saved yet.
+    /// Logs are saved together with them, so no saved log refers to a word that was never saved.
+    ///
+    #[inline]
+    pub fn unsaved_words(&self) -> Vec<(String, Token)> {
+        self.unsaved.clone()
+    }
+
+    /// Marks the words as saved, words created since they were taken stay unsaved.
+    ///
+    #[inline]
+    pub fn mark_saved(&mut self, words: &[(String, Token)]) {
+        if words.is_empty() {
+            return;
+        }
+        let saved: HashSet<Token> = words.iter().map(|(_, num)| *num).collect();
+        self.unsaved.retain(|(_, num)| !saved.contains(num));
+    }
+
     /// Deserializes numeric representation of data to String.
     ///
     #[inline(always)]
-    pub fn deserialize(&self, buffer: &[u32]) -> String {
-        let mut msg = "".to_string();
-        for candidate in buffer.into_iter() {
-            match self.nums_to_words.get(candidate) {
-                Some(w) => msg.push_str(w),
-                None => msg.push_str("[?]"),
+    pub fn deserialize(&self, buffer: &[Token]) -> String {
+        let mut msg = String::new();
+        self.deserialize_into(buffer, &mut msg);
+        msg
+    }
+
+    /// Deserializes numeric representation of data in to the given String.
+    /// The output is cleared first, so the same String can be reused for many logs.
+    ///
+    #[inline(always)]
+    pub fn deserialize_into(&self, buffer: &[Token], output: &mut String) {
+        output.clear();
+        for (i, word) in self.deserialize_words(buffer).enumerate() {
+            if i > 0 {
+                output.push(' ');
+            }
+            output.push_str(word);
+        }
+    }
+
+    /// Returns an iterator over words of the numeric representation of data.
+    /// Unknown numbers are yielded as `[?]`.
+    ///
+    #[inline(always)]
+    pub fn deserialize_words<'a>(&'a self, buffer: &'a [Token]) -> impl Iterator<Item = &'a str> {
+        buffer
+            .iter()
+            .map(|candidate| match self.nums_to_words.get(candidate) {
+                Some(w) => w.as_str(),
+                None => "[?]",
+            })
+    }
+
+    /// Returns the word represented by given number.
+    ///
+    #[inline(always)]
+    pub fn word(&self, num: Token) -> Option<&str> {
+        self.nums_to_words.get(&num).map(|w| w.as_str())
+    }
+
+    /// Returns the number representing given word.
+    ///
+    #[inline(always)]
+    pub fn num(&self, word: &str) -> Option<Token> {
+        self.words_to_numbers.get(word).copied()
+    }
+
+    /// Returns numbers of words starting with any of the prefixes,
+    /// or None if there are more than max of them.
+    ///
+    #[inline(always)]
+    pub fn prefixed_nums(&self, words: &[&str], max: usize) -> Option<HashSet<Token>> {
+        let mut set: HashSet<Token> = HashSet::new();
+        for w in words.iter() {
+            set.extend(
+                self.filter
+                    .find_prefix_limited(w, max - set.len().min(max))?,
+            );
+        }
+        Some(set)
+    }
+
+    /// Returns numbers of words starting with any of the prefixes regardless of case.
+    ///
+    #[inline(always)]
+    pub fn prefixed_nums_ignoring_case(&self, words: &[&str]) -> HashSet<Token> {
+        words
+            .iter()
+            .flat_map(|w| self.filter.find_prefix_case_insensitive(w))
+            .collect()
+    }
+
+    /// Returns statistics of the graph of the filter, or None if the filter keeps none.
+    /// Filters that are not graphs report their size in bytes only.
+    ///
+    #[inline(always)]
+    pub fn stats(&self) -> Option<Stats> {
+        self.filter.stats()
+    }
+
+    /// Filters buffers matching every matcher, keeping their order,
+    /// and stops scanning buffers once limit of them matched.
+    ///
+    #[inline(always)]
+    pub fn filter_all<B: AsRef<[Token]>>(
+        buffers: Vec<B>,
+        matchers: &[Matcher],
+        limit: Option<usize>,
+    ) -> Vec<B> {
+        buffers
+            .into_iter()
+            .filter(|buf| matchers.iter().all(|m| m(buf.as_ref())))
+            .take(limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+
+    /// Filters buffers matching every matcher like filter_all, keeping at most limit of them,
+    /// but scans every buffer to count all matching ones too.
+    /// Scanning stops once the deadline passes, leaving the rest of buffers unscanned.
+    ///
+    #[inline(always)]
+    pub fn filter_all_counted<B: AsRef<[Token]>>(
+        buffers: Vec<B>,
+        matchers: &[Matcher],
+        limit: Option<usize>,
+        deadline: Option<Instant>,
+    ) -> Filtered<B> {
+        let limit = limit.unwrap_or(usize::MAX);
+        let mut filtered = Filtered {
+            buffers: Vec::new(),
+            matched: 0,
+            scanned: 0,
+        };
+        for buf in buffers.into_iter() {
+            if filtered.scanned % DEADLINE_CHECK_INTERVAL == 0
+                && deadline.is_some_and(|d| Instant::now() >= d)
+            {
+                break;
+            }
+            filtered.scanned += 1;
+            if !matchers.iter().all(|m| m(buf.as_ref())) {
+                continue;
+            }
+            filtered.matched += 1;
+            if filtered.buffers.len() < limit {
+                filtered.buffers.push(buf);
             }
-            msg.push(' ');
         }
+        filtered
+    }
 
-        msg.trim().to_string()
+    /// Matches buffers holding any of the numbers.
+    ///
+    #[inline(always)]
+    pub fn any_of_matcher(set: HashSet<Token>) -> Matcher<'static> {
+        Box::new(move |buf| buf.iter().any(|num| set.contains(num)))
     }
 
-    /// Filters buffers based on matching prefix.
+    /// Matches buffers holding a word starting with any of the prefixes.
+    /// Matching numbers of all prefixes are collected once before buffers are scanned,
+    /// unless there are more of them than max, the number of tokens in the buffers to scan,
+    /// as for a short prefix in a large dictionary.
+    /// Then words of the buffers are checked against the prefixes instead, which costs less than building the set.
     ///
     #[inline(always)]
-    pub fn filter_prefixed(&self, word: &str, buffers: Vec<Vec<u32>>) -> Vec<Vec<u32>> {
-        let mut filtered = Vec::new();
-        let set = self.filter.find_prefix(word);
-        'outer: for buf in buffers.iter() {
-            for member in buf.iter() {
-                if set.contains(member) {
-                    filtered.push(buf.to_vec());
-                    continue 'outer;
+    pub fn prefixed_any_matcher<'a>(&'a self, words: &'a [&'a str], max: usize) -> Matcher<'a> {
+        let mut set: HashSet<Token> = HashSet::new();
+        for w in words.iter() {
+            match self.filter.find_prefix_limited(w, max - set.len().min(max)) {
+                Some(nums) => set.extend(nums),
+                None => {
+                    return Box::new(move |buf| {
+                        buf.iter().any(|num| {
+                            self.nums_to_words
+                                .get(num)
+                                .is_some_and(|w| words.iter().any(|p| w.starts_with(p)))
+                        })
+                    })
                 }
             }
         }
 
-        filtered
+        Self::any_of_matcher(set)
+    }
+
+    /// Matches buffers holding any word starting with a string within given edit distance of any of the prefixes.
+    ///
+    #[inline(always)]
+    pub fn prefixed_fuzzy_matcher(&self, words: &[&str], distance: u8) -> Matcher<'static> {
+        let mut set: HashSet<Token> = HashSet::new();
+        for w in words.iter() {
+            set.extend(self.filter.find_prefix_fuzzy(w, distance));
+        }
+
+        Self::any_of_matcher(set)
+    }
+
+    /// Matches buffers holding a word matching the glob pattern,
+    /// where `*` matches any run of characters and `?` matches a single character.
+    ///
+    #[inline(always)]
+    pub fn pattern_matcher(&self, pattern: &str) -> Matcher<'static> {
+        Self::any_of_matcher(self.filter.find_glob(pattern))
+    }
+
+    /// Matches buffers holding a word ending with the suffix.
+    ///
+    #[inline(always)]
+    pub fn suffix_matcher(&self, suffix: &str) -> Matcher<'static> {
+        Self::any_of_matcher(self.filter.find_suffix(suffix))
+    }
+
+    /// Matches buffers holding a word from the lower bound inclusive up to the upper bound exclusive
+    /// in lexicographic order.
+    ///
+    #[inline(always)]
+    pub fn range_matcher(&self, from: &str, to: &str) -> Matcher<'static> {
+        Self::any_of_matcher(self.filter.find_range(from, to))
+    }
+
+    /// Matches buffers holding any full word from slice of words.
+    ///
+    #[inline(always)]
+    pub fn word_matcher(&self, words: &[String]) -> Matcher<'static> {
+        Self::any_of_matcher(
+            words
+                .iter()
+                .filter_map(|w| self.words_to_numbers.get(w).copied())
+                .collect(),
+        )
+    }
+
+    /// Matches buffers holding every word from slice of words.
+    /// No buffer matches if any of the words is not known to the dictionary.
+    ///
+    #[inline(always)]
+    pub fn words_all_matcher(&self, words: &[String]) -> Matcher<'static> {
+        let mut nums: Vec<Token> = Vec::with_capacity(words.len());
+        for w in words.iter() {
+            let Some(num) = self.words_to_numbers.get(w) else {
+                return Box::new(|_| false);
+            };
+            nums.push(*num);
+        }
+
+        Box::new(move |buf| nums.iter().all(|num| buf.contains(num)))
+    }
+
+    /// Matches buffers holding any word within given edit distance of any of the words.
+    ///
+    #[inline(always)]
+    pub fn word_fuzzy_matcher(&self, words: &[String], distance: u8) -> Matcher<'static> {
+        let mut set: HashSet<Token> = HashSet::new();
+        for w in words.iter() {
+            set.extend(self.filter.find_fuzzy(w, distance));
+        }
+
+        Self::any_of_matcher(set)
     }
 
-    /// Filters buffers based on matching full word from slice of words.
+    /// Matches buffers holding, for every word, a word within given edit distance of it.
     ///
     #[inline(always)]
-    pub fn filter_word(&self, words: &[String], buffers: Vec<Vec<u32>>) -> Vec<Vec<u32>> {
-        let mut filtered = Vec::new();
-        let mut set: HashSet<u32> = HashSet::new();
+    pub fn words_all_fuzzy_matcher(&self, words: &[String], distance: u8) -> Matcher<'static> {
+        let mut sets: Vec<HashSet<Token>> = Vec::with_capacity(words.len());
         for w in words.iter() {
-            if let Some(num) = self.words_to_numbers.get(w) {
-                set.insert(*num);
+            let set = self.filter.find_fuzzy(w, distance);
+            if set.is_empty() {
+                return Box::new(|_| false);
             }
+            sets.push(set);
         }
-        'outer: for buf in buffers.iter() {
-            for member in buf.iter() {
-                if set.contains(member) {
-                    filtered.push(buf.to_vec());
-                    continue 'outer;
-                }
+
+        Box::new(move |buf| {
+            sets.iter()
+                .all(|set| buf.iter().any(|num| set.contains(num)))
+        })
+    }
+
+    /// Matches buffers holding every word with at most window positions
+    /// between the first and the last of them, in any order.
+    /// No buffer matches if any of the words is not known to the dictionary.
+    ///
+    #[inline(always)]
+    pub fn near_matcher(&self, words: &[String], window: usize) -> Matcher<'static> {
+        let mut nums: Vec<Token> = Vec::with_capacity(words.len());
+        for w in words.iter() {
+            let Some(num) = self.words_to_numbers.get(w) else {
+                return Box::new(|_| false);
+            };
+            if !nums.contains(num) {
+                nums.push(*num);
             }
         }
+        if nums.is_empty() {
+            return Box::new(|_| true);
+        }
 
-        filtered
+        Box::new(move |buf| within_window(buf, &nums, window))
+    }
+
+    /// Matches buffers holding the phrase as a contiguous sequence of words.
+    /// The phrase is split on whitespace the same way logs are, so it matches whole words only.
+    /// No buffer matches if any of the phrase words is not known to the dictionary.
+    ///
+    #[inline(always)]
+    pub fn phrase_matcher(&self, phrase: &str) -> Matcher<'static> {
+        let mut nums: Vec<Token> = Vec::new();
+        for w in phrase.split_whitespace() {
+            let Some(num) = self.words_to_numbers.get(w) else {
+                return Box::new(|_| false);
+            };
+            nums.push(*num);
+        }
+        if nums.is_empty() {
+            return Box::new(|_| true);
+        }
+
+        Box::new(move |buf| buf.windows(nums.len()).any(|w| w == nums.as_slice()))
+    }
+
+    /// Returns up to n most frequent words with the number of their occurrences
+    /// in all logs serialized by this dictionary, most frequent first.
+    ///
+    #[inline]
+    pub fn top_words(&self, n: usize) -> Vec<(&str, u64)> {
+        self.rank(
+            self.frequencies.iter().map(|(num, count)| (*num, *count)),
+            n,
+        )
+    }
+
+    /// Returns words starting with the prefix with their numbers in lexicographic order,
+    /// which are the words the prefix expands to in queries.
+    ///
+    #[inline(always)]
+    pub fn expand_prefix(&self, prefix: &str) -> Vec<(String, Token)> {
+        self.filter.find_prefix_words(prefix)
+    }
+
+    /// Returns up to k most frequent words starting with the prefix
+    /// with the number of their occurrences, most frequent first.
+    ///
+    #[inline]
+    pub fn suggest(&self, prefix: &str, k: usize) -> Vec<(&str, u64)> {
+        let mut suggested: Vec<(&str, u64)> = self
+            .filter
+            .find_prefix_top(prefix, k)
+            .into_iter()
+            .filter_map(|(num, count)| Some((self.nums_to_words.get(&num)?.as_str(), count)))
+            .collect();
+        suggested.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        suggested
+    }
+
+    /// Returns up to n most frequent words with the number of their occurrences
+    /// in given buffers, most frequent first.
+    ///
+    #[inline]
+    pub fn top_words_in<B: AsRef<[Token]>>(&self, n: usize, buffers: &[B]) -> Vec<(&str, u64)> {
+        let mut counts: HashMap<Token, u64> = HashMap::new();
+        for buf in buffers.iter() {
+            for num in buf.as_ref().iter() {
+                *counts.entry(*num).or_insert(0) += 1;
+            }
+        }
+        self.rank(counts.into_iter(), n)
+    }
+
+    #[inline(always)]
+    fn rank(&self, counts: impl Iterator<Item = (Token, u64)>, n: usize) -> Vec<(&str, u64)> {
+        let mut ranked: Vec<(&str, u64)> = counts
+            .filter_map(|(num, count)| Some((self.nums_to_words.get(&num)?.as_str(), count)))
+            .collect();
+        ranked.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        ranked.truncate(n);
+        ranked
+    }
+
+    /// Returns the number of words in the dictionary.
+    ///
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.words_to_numbers.len()
+    }
+
+    /// Returns true if the dictionary holds no words.
+    ///
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.words_to_numbers.is_empty()
     }
 
     /// Allows to iterate over inner words to num collection.
     ///
     #[inline(always)]
-    pub fn iter(&self) -> impl Iterator<Item = (&String, &u32)> {
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Token)> {
         self.words_to_numbers.iter()
     }
 
-    /// Saves schema to a file.
+    /// Writes schema in the binary format.
+    /// The format starts with `SCRD` magic, format version and token width,
+    /// followed by the number of words and then every word as little endian token,
+    /// little endian u32 length and UTF-8 bytes of the word.
     ///
     #[inline]
-    pub fn save_schema_to_file(&self, path: &str) -> ResultStd<()> {
-        let file = File::create(path)?;
-        let mut file = LineWriter::new(file);
-        for (w, n) in self.words_to_numbers.iter() {
-            file.write_all(format!("{} : {}\n", *w, *n).as_bytes())?;
+    pub fn write_schema_binary(&self, w: &mut impl Write) -> ResultStd<()> {
+        w.write_all(SCHEMA_MAGIC)?;
+        w.write_all(&[SCHEMA_VERSION, Token::WIDTH as u8])?;
+        w.write_all(&(self.words_to_numbers.len() as u64).to_le_bytes())?;
+        for (word, num) in self.words_to_numbers.iter() {
+            w.write_all(&num.to_le_bytes())?;
+            w.write_all(&(word.len() as u32).to_le_bytes())?;
+            w.write_all(word.as_bytes())?;
         }
-        file.flush()?;
 
         Ok(())
     }
 
-    /// Reads schema from a file.
+    /// Reads schema in the binary format written by `write_schema_binary`.
+    /// Fails on unknown version, token width other than the compiled one and repeated words or numbers.
     ///
     #[inline]
-    pub fn read_schema_from_file(path: &str, f: impl Filter + 'static) -> ResultStd<Self> {
-        let mut serializer = Self::new(f);
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
+    pub fn read_schema_binary(r: &mut impl Read, f: impl Filter + 'static) -> ResultStd<Self> {
+        let mut header = [0; 6];
+        r.read_exact(&mut header)?;
+        if &header[..4] != SCHEMA_MAGIC {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "not a dictionary schema",
+            ));
+        }
+        if header[4] != SCHEMA_VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unsupported schema version {}", header[4]),
+            ));
+        }
+        if header[5] as usize != Token::WIDTH {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "schema token width {} does not match {}",
+                    header[5],
+                    Token::WIDTH
+                ),
+            ));
+        }
+        let mut count = [0; 8];
+        r.read_exact(&mut count)?;
 
-        for line in reader.lines() {
-            let mut n = 0;
-            let mut w = String::new();
-            sscanf!(&line?, "{} : {}", w, n)?;
-            if serializer.last_available_number < n {
-                serializer.last_available_number = n;
+        let mut serializer = Self::new(f);
+        for _ in 0..u64::from_le_bytes(count) {
+            let mut num = [0; Token::WIDTH];
+            r.read_exact(&mut num)?;
+            let num = Token::from_le_bytes(num);
+            let mut len = [0; 4];
+            r.read_exact(&mut len)?;
+            let mut word = Vec::new();
+            r.take(u32::from_le_bytes(len) as u64)
+                .read_to_end(&mut word)?;
+            if word.len() != u32::from_le_bytes(len) as usize {
+                return Err(Error::new(ErrorKind::UnexpectedEof, "schema is truncated"));
+            }
+            let Ok(word) = String::from_utf8(word) else {
+                return Err(Error::new(ErrorKind::InvalidData, "word is not UTF-8"));
+            };
+            if serializer.nums_to_words.contains_key(&num)
+                || serializer.words_to_numbers.contains_key(&word)
+            {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("word {} or number {} repeats", word, num),
+                ));
+            }
+            if serializer.last_available_number < num {
+                serializer.last_available_number = num;
             }
-            serializer.words_to_numbers.insert(w.to_string(), n);
+            serializer.filter.push(&word, num);
+            serializer.unsaved.push((word.clone(), num));
+            serializer.nums_to_words.insert(num, word.clone());
+            serializer.words_to_numbers.insert(word, num);
         }
-        serializer.nums_from_words();
 
         Ok(serializer)
     }
 
+    /// Releases spare capacity of inner maps and the filter.
+    /// Worth calling after a burst of new words, as maps never shrink on their own.
+    ///
+    #[inline]
+    pub fn shrink_to_fit(&mut self) {
+        self.words_to_numbers.shrink_to_fit();
+        self.nums_to_words.shrink_to_fit();
+        self.frequencies.shrink_to_fit();
+        self.unsaved.shrink_to_fit();
+        self.pruned.shrink_to_fit();
+        self.filter.shrink_to_fit();
+    }
+
     #[inline(always)]
     fn nums_from_words(&mut self) {
         for (k, v) in self.words_to_numbers.iter() {
@@ -178,15 +807,131 @@ impl Module {
     }
 }
 
+/// Schema in the text format of v1, a `word : number` line per word, with no header,
+/// read so dictionaries saved by v1 are imported.
+///
+impl Module {
+    /// Reads schema in the binary format of `write_schema_binary`, or in the text format of v1
+    /// if it does not start with the magic bytes of the binary format.
+    ///
+    #[inline]
+    pub fn read_schema(r: &mut impl Read, f: impl Filter + 'static) -> ResultStd<Self> {
+        let mut magic = Vec::with_capacity(SCHEMA_MAGIC.len());
+        r.by_ref()
+            .take(SCHEMA_MAGIC.len() as u64)
+            .read_to_end(&mut magic)?;
+        let mut r = magic.as_slice().chain(r);
+        if magic == SCHEMA_MAGIC {
+            return Self::read_schema_binary(&mut r, f);
+        }
+        Self::read_schema_text(&mut r, f)
+    }
+
+    /// Reads schema in the text format of v1, words are unsaved as words of `read_schema_binary`.
+    /// Fails on malformed lines and repeated words or numbers.
+    ///
+    #[inline]
+    pub fn read_schema_text(r: &mut impl Read, f: impl Filter + 'static) -> ResultStd<Self> {
+        use scanf::sscanf;
+        use std::io::{BufRead, BufReader};
+
+        let mut serializer = Self::new(f);
+        for line in BufReader::new(r).lines() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let mut num = Token::default();
+            let mut word = String::new();
+            if sscanf!(&line, "{} : {}", word, num).is_err() || word.is_empty() {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("malformed schema line {}", line),
+                ));
+            }
+            if serializer.nums_to_words.contains_key(&num)
+                || serializer.words_to_numbers.contains_key(&word)
+            {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("word {} or number {} repeats", word, num),
+                ));
+            }
+            if serializer.last_available_number < num {
+                serializer.last_available_number = num;
+            }
+            serializer.filter.push(&word, num);
+            serializer.unsaved.push((word.clone(), num));
+            serializer.nums_to_words.insert(num, word.clone());
+            serializer.words_to_numbers.insert(word, num);
+        }
+
+        Ok(serializer)
+    }
+}
+
+/// Checks if buffer holds all nums within a span of window positions,
+/// sliding the smallest window that covers every num over their occurrences.
+///
+#[inline(always)]
+fn within_window(buf: &[Token], nums: &[Token], window: usize) -> bool {
+    let hits: Vec<(usize, usize)> = buf
+        .iter()
+        .enumerate()
+        .filter_map(|(pos, t)| Some((pos, nums.iter().position(|n| n == t)?)))
+        .collect();
+    let mut counts = vec![0usize; nums.len()];
+    let mut covered = 0;
+    let mut left = 0;
+    for &(pos, i) in hits.iter() {
+        if counts[i] == 0 {
+            covered += 1;
+        }
+        counts[i] += 1;
+        while covered == nums.len() {
+            let (first, j) = hits[left];
+            if pos - first <= window {
+                return true;
+            }
+            counts[j] -= 1;
+            if counts[j] == 0 {
+                covered -= 1;
+            }
+            left += 1;
+        }
+    }
+
+    false
+}
+
 #[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
 mod tests {
     use std::error::Error;
 
     use super::*;
-    use std::time::Instant;
+
+    /// Writes schema in the text format of v1 for tests of the reader.
+    ///
+    impl Module {
+        /// Saves schema to a file.
+        ///
+        #[inline]
+        fn save_schema_to_file(&self, path: &str) -> ResultStd<()> {
+            use std::io::LineWriter;
+
+            let file = std::fs::File::create(path)?;
+            let mut file = LineWriter::new(file);
+            for (w, n) in self.words_to_numbers.iter() {
+                file.write_all(format!("{} : {}\n", *w, *n).as_bytes())?;
+            }
+            file.flush()?;
+
+            Ok(())
+        }
+    }
 
     const TEXT: &str = "Sed ut perspiciatis unde omnis iste natus error sit voluptatem accusantium doloremque laudantium, totam rem aperiam, eaque ipsa quae ab illo inventore veritatis et quasi architecto beatae vitae dicta sunt explicabo. Nemo enim ipsam voluptatem quia voluptas sit aspernatur aut odit aut fugit, sed quia consequuntur magni dolores eos qui ratione voluptatem sequi nesciunt. Neque porro quisquam est, qui dolorem ipsum quia dolor sit amet, consectetur, adipisci velit, sed quia non numquam eius modi tempora incidunt ut labore et dolore magnam aliquam quaerat voluptatem. Ut enim ad minima veniam, quis nostrum exercitationem ullam corporis suscipit laboriosam, nisi ut aliquid ex ea commodi consequatur? Quis autem vel eum iure reprehenderit qui in ea voluptate velit esse quam nihil molestiae consequatur, vel illum qui dolorem eum fugiat quo voluptas nulla pariatur";
-    const BENCH_LOOP: usize = 10000;
 
     struct MyFilterMock {}
     impl MyFilterMock {
@@ -196,13 +941,35 @@ mod tests {
     }
 
     impl Filter for MyFilterMock {
-        fn push(&mut self, _: &str, _: u32) {}
-        fn find_prefix(&self, _: &str) -> HashSet<u32> {
+        fn push(&mut self, _: &str, _: Token) {}
+        fn find_prefix(&self, _: &str) -> HashSet<Token> {
+            HashSet::new()
+        }
+        fn find_prefix_words(&self, _: &str) -> Vec<(String, Token)> {
+            Vec::new()
+        }
+        fn find_prefix_case_insensitive(&self, _: &str) -> HashSet<Token> {
+            HashSet::new()
+        }
+        fn find_fuzzy(&self, _: &str, _: u8) -> HashSet<Token> {
+            HashSet::new()
+        }
+        fn find_prefix_fuzzy(&self, _: &str, _: u8) -> HashSet<Token> {
             HashSet::new()
         }
-        fn find_prefix_case_insensitive(&self, _: &str) -> HashSet<u32> {
+        fn find_glob(&self, _: &str) -> HashSet<Token> {
             HashSet::new()
         }
+        fn find_suffix(&self, _: &str) -> HashSet<Token> {
+            HashSet::new()
+        }
+        fn remove(&mut self, _: &str, _: Token) -> bool {
+            false
+        }
+        fn count(&mut self, _: &str, _: u64) {}
+        fn find_prefix_top(&self, _: &str, _: usize) -> Vec<(Token, u64)> {
+            Vec::new()
+        }
     }
 
     #[test]
@@ -210,25 +977,25 @@ mod tests {
         let mock = MyFilterMock::new();
         let mut serialize = Module::new(mock);
 
-        let buffer = serialize.serialize(TEXT);
+        let buffer = serialize.serialize(TEXT).unwrap();
 
-        assert!(buffer.len() > 0);
+        assert!(!buffer.is_empty());
     }
 
     #[test]
-    fn test_serialize_bench() {
-        let mock = MyFilterMock::new();
-        let mut serialize = Module::new(mock);
-        let start = Instant::now();
-        for _ in 0..BENCH_LOOP {
-            serialize.serialize(TEXT);
-        }
-        let duration = start.elapsed();
+    fn test_serialize_exhausted_token_space() {
+        let mut serialize = Module::new(MyFilterMock::new());
+        let known = serialize.serialize("known").unwrap();
+        let last = Token::from_le_bytes([u8::MAX; Token::WIDTH]);
+        serialize.last_available_number = last;
 
-        println!(
-            "Time elapsed in test_serialize_bench is: {:?}",
-            duration / BENCH_LOOP as u32
+        assert_eq!(
+            serialize.serialize("known known").unwrap(),
+            vec![known[0]; 2]
         );
+        let err = serialize.serialize("known unknown").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::StorageFull);
+        assert_eq!(serialize.last_available_number, last);
     }
 
     #[test]
@@ -236,18 +1003,183 @@ mod tests {
         let mock = MyFilterMock::new();
 
         let mut serialize = Module::new(mock);
-        let buffer = serialize.serialize(TEXT);
+        let buffer = serialize.serialize(TEXT).unwrap();
         let log = serialize.deserialize(&buffer);
         assert_eq!(TEXT, log);
     }
 
+    #[test]
+    fn test_deserialize_into_reuses_buffer() {
+        let mut serialize = Module::new(MyFilterMock::new());
+        let first = serialize.serialize(TEXT).unwrap();
+        let second = serialize.serialize("short log").unwrap();
+
+        let mut output = String::new();
+        serialize.deserialize_into(&first, &mut output);
+        assert_eq!(TEXT, output);
+        let capacity = output.capacity();
+
+        serialize.deserialize_into(&second, &mut output);
+        assert_eq!("short log", output);
+        assert_eq!(capacity, output.capacity());
+    }
+
+    #[test]
+    fn test_deserialize_words() {
+        let mut serialize = Module::new(MyFilterMock::new());
+        let mut buffer = serialize.serialize("one two three").unwrap();
+        buffer.push(Token::from(11111));
+
+        let words: Vec<&str> = serialize.deserialize_words(&buffer).collect();
+
+        assert_eq!(words, vec!["one", "two", "three", "[?]"]);
+    }
+
+    #[test]
+    fn test_with_words_from_loaded_trie() {
+        let mut trie = crate::trie::Trie::new();
+        for (i, w) in ["error", "errors", "warning"].iter().enumerate() {
+            trie.push(w, Token::from(i as u32 + 1));
+        }
+        let mut buf = Vec::new();
+        trie.write_to(&mut buf).unwrap();
+        let trie = crate::trie::Trie::read_from(&mut &buf[..]).unwrap();
+
+        let words: Vec<(String, Token)> = trie.iter_words().collect();
+        let mut m = Module::with_words(trie, words);
+
+        assert_eq!(m.len(), 3);
+        assert_eq!(m.word(Token::from(2)), Some("errors"));
+        assert_eq!(m.num("warning"), Some(Token::from(3)));
+        let buffers = vec![
+            m.serialize("errors found").unwrap(),
+            m.serialize("all fine").unwrap(),
+        ];
+        assert_eq!(
+            Module::filter_all(
+                buffers,
+                &[m.prefixed_any_matcher(&["err"], usize::MAX)],
+                None
+            )
+            .len(),
+            1
+        );
+        assert_eq!(m.num("found"), Some(Token::from(4)));
+    }
+
+    #[test]
+    fn test_unsaved_words_until_marked_saved() {
+        let mut serialize = Module::new(MyFilterMock::new());
+        let first = serialize.serialize("one two one").unwrap();
+        let taken = serialize.unsaved_words();
+        assert_eq!(
+            taken,
+            vec![("one".to_string(), first[0]), ("two".to_string(), first[1])]
+        );
+
+        let second = serialize.serialize("two three").unwrap();
+        serialize.mark_saved(&taken);
+
+        assert_eq!(
+            serialize.unsaved_words(),
+            vec![("three".to_string(), second[1])]
+        );
+    }
+
+    #[test]
+    fn test_serialize_rejects_logs_longer_than_max_bytes() {
+        let mut serialize = Module::new(MyFilterMock::new());
+        serialize.set_max_log_bytes(Some(7));
+        assert!(serialize.serialize("one two").is_ok());
+
+        let Err(e) = serialize.serialize("one two three") else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(e.kind(), ErrorKind::FileTooLarge);
+        assert_eq!(serialize.len(), 2);
+    }
+
+    #[test]
+    fn test_load_words_keeps_numbers_and_continues_after_them() {
+        let mut serialize = Module::new(MyFilterMock::new());
+        serialize.load_words(vec![
+            ("one".to_string(), Token::from(3)),
+            ("two".to_string(), Token::from(7)),
+        ]);
+
+        assert_eq!(serialize.num("two"), Some(Token::from(7)));
+        assert!(serialize.unsaved_words().is_empty());
+        let buffer = serialize.serialize("one three").unwrap();
+        assert_eq!(buffer, vec![Token::from(3), Token::from(8)]);
+    }
+
+    #[test]
+    fn test_rotated_keeps_numbers_and_unsaved_words_and_resets_frequencies() {
+        let mut serialize = Module::new(MyFilterMock::new());
+        serialize.set_max_log_bytes(Some(64));
+        let buffer = serialize.serialize("one two two").unwrap();
+        serialize.mark_saved(&[("one".to_string(), buffer[0])]);
+
+        let mut rotated = serialize.rotated(Module::new(MyFilterMock::new()));
+        assert_eq!(rotated.len(), 2);
+        assert_eq!(rotated.num("two"), Some(buffer[1]));
+        assert_eq!(
+            rotated.unsaved_words(),
+            vec![("two".to_string(), buffer[1])]
+        );
+        assert_eq!(rotated.max_log_bytes(), Some(64));
+        assert!(rotated.top_words(10).is_empty());
+        let buffer = rotated.serialize("three").unwrap();
+        assert_eq!(buffer, vec![Token::from(3)]);
+    }
+
+    #[test]
+    fn test_prune_removes_words_no_log_refers_to_from_the_filter_until_used_again() {
+        let mut serialize = Module::new(crate::trie::Trie::new());
+        let buffer = serialize.serialize("alpha beta gamma").unwrap();
+        let words = serialize.unsaved_words();
+        serialize.mark_saved(&words);
+        let fresh = serialize.serialize("delta").unwrap();
+
+        let mut rotated = serialize.rotated(Module::new(crate::trie::Trie::new()));
+        let referenced = HashSet::from([buffer[0]]);
+        assert_eq!(rotated.prune(&referenced), 2);
+        assert_eq!(rotated.prune(&referenced), 0);
+
+        assert!(!rotated.filter.find_prefix("al").is_empty());
+        assert!(rotated.filter.find_prefix("be").is_empty());
+        assert!(rotated.filter.find_prefix("ga").is_empty());
+        assert!(!rotated.filter.find_prefix("de").is_empty());
+        assert_eq!(rotated.deserialize(&buffer), "alpha beta gamma");
+        assert_eq!(rotated.deserialize(&fresh), "delta");
+
+        let mut rotated = rotated.rotated(Module::new(crate::trie::Trie::new()));
+        assert!(rotated.filter.find_prefix("ga").is_empty());
+        assert_eq!(rotated.len(), 4);
+        assert_eq!(rotated.serialize("beta").unwrap(), vec![buffer[1]]);
+        assert_eq!(rotated.filter.find_prefix("be"), HashSet::from([buffer[1]]));
+        assert_eq!(rotated.serialize("epsilon").unwrap(), vec![Token::from(5)]);
+    }
+
+    #[test]
+    fn test_word_and_num_lookup() {
+        let mut serialize = Module::new(MyFilterMock::new());
+        let buffer = serialize.serialize("one two three").unwrap();
+
+        assert_eq!(serialize.word(buffer[1]), Some("two"));
+        assert_eq!(serialize.num("three"), Some(buffer[2]));
+        assert_eq!(serialize.word(Token::from(11111)), None);
+        assert_eq!(serialize.num("four"), None);
+    }
+
     #[test]
     fn test_serialize_save_read() -> Result<(), Box<dyn Error>> {
         let path = "./save_read.schema";
         let expected = Module::new(MyFilterMock::new());
         expected.save_schema_to_file(path)?;
 
-        let actual = Module::read_schema_from_file(path, MyFilterMock::new())?;
+        let actual = Module::read_schema(&mut std::fs::File::open(path)?, MyFilterMock::new())?;
 
         let result = expected
             .words_to_numbers
@@ -258,65 +1190,486 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_read_schema_v1_fixture() -> Result<(), Box<dyn Error>> {
+        let serialize = Module::read_schema(
+            &mut std::fs::File::open("./assets/fixtures/v1/dictionary.schema")?,
+            crate::trie::Trie::new(),
+        )?;
+        let buffer = Token::decode(&std::fs::read("./assets/fixtures/v1/log.bin")?)?;
+
+        assert_eq!(serialize.words_to_numbers.len(), 7);
+        assert_eq!(serialize.last_available_number, Token::from(7));
+        assert_eq!(serialize.deserialize(&buffer), "connection refused by peer");
+        assert_eq!(serialize.unsaved_words().len(), 7);
+        assert!(!serialize.filter.find_prefix("refu").is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_binary_round_trip() -> Result<(), Box<dyn Error>> {
+        let mut expected = Module::new(MyFilterMock::new());
+        let buffer = expected.serialize(TEXT)?;
+        let mut schema = Vec::new();
+        expected.write_schema_binary(&mut schema)?;
+
+        let actual = Module::read_schema_binary(&mut schema.as_slice(), crate::trie::Trie::new())?;
+
+        assert_eq!(actual.words_to_numbers, expected.words_to_numbers);
+        assert_eq!(actual.last_available_number, expected.last_available_number);
+        assert_eq!(actual.deserialize(&buffer), TEXT);
+        assert!(!actual.filter.find_prefix("perspic").is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_binary_rejects_malformed_input() {
+        let mut serialize = Module::new(MyFilterMock::new());
+        serialize.serialize("one two three").unwrap();
+        let mut schema = Vec::new();
+        serialize.write_schema_binary(&mut schema).unwrap();
+
+        let mut width = schema.clone();
+        width[5] += 1;
+        let mut truncated = schema.clone();
+        truncated.pop();
+        for malformed in [&b"not a schema"[..], &width, &truncated] {
+            assert!(Module::read_schema_binary(&mut &malformed[..], MyFilterMock::new()).is_err());
+        }
+    }
+
     #[test]
     fn test_find_prefixes() {
         let path = "./test.schema";
         struct MyFindPrefixTestFilterMock {
-            h: HashSet<u32>,
+            h: HashSet<Token>,
         }
         impl MyFindPrefixTestFilterMock {
-            fn new(h: HashSet<u32>) -> Self {
+            fn new(h: HashSet<Token>) -> Self {
                 Self { h }
             }
         }
 
         impl Filter for MyFindPrefixTestFilterMock {
-            fn push(&mut self, _: &str, num: u32) {}
-            fn find_prefix(&self, _: &str) -> HashSet<u32> {
+            fn push(&mut self, _: &str, _num: Token) {}
+            fn find_prefix(&self, _: &str) -> HashSet<Token> {
                 self.h.clone()
             }
-            fn find_prefix_case_insensitive(&self, _: &str) -> HashSet<u32> {
+            fn find_prefix_words(&self, _: &str) -> Vec<(String, Token)> {
+                Vec::new()
+            }
+            fn find_prefix_case_insensitive(&self, _: &str) -> HashSet<Token> {
+                HashSet::new()
+            }
+            fn find_fuzzy(&self, _: &str, _: u8) -> HashSet<Token> {
                 HashSet::new()
             }
+            fn find_prefix_fuzzy(&self, _: &str, _: u8) -> HashSet<Token> {
+                HashSet::new()
+            }
+            fn find_glob(&self, _: &str) -> HashSet<Token> {
+                HashSet::new()
+            }
+            fn find_suffix(&self, _: &str) -> HashSet<Token> {
+                HashSet::new()
+            }
+            fn remove(&mut self, _: &str, _: Token) -> bool {
+                false
+            }
+            fn count(&mut self, _: &str, _: u64) {}
+            fn find_prefix_top(&self, _: &str, _: usize) -> Vec<(Token, u64)> {
+                Vec::new()
+            }
         }
 
         let mut hs = HashSet::new();
-        hs.insert(1);
+        hs.insert(Token::from(1));
 
         let mock = MyFindPrefixTestFilterMock::new(hs);
 
         let mut serialize = Module::new(mock);
-        let buffer = serialize.serialize(TEXT);
-        if let Err(_) = serialize.save_schema_to_file(path) {
+        let buffer = serialize.serialize(TEXT).unwrap();
+        if serialize.save_schema_to_file(path).is_err() {
             assert!(false);
         }
 
-        let mut buffers = Vec::new();
-        buffers.push(buffer.clone());
-        buffers.push(vec![11111]);
+        let buffers = vec![buffer.clone(), vec![Token::from(11111)]];
 
-        let result = serialize.filter_prefixed("Se", buffers);
+        let result = Module::filter_all(
+            buffers,
+            &[serialize.prefixed_any_matcher(&["Se"], usize::MAX)],
+            None,
+        );
 
         assert_eq!(result.len(), 1);
         assert_eq!(result[0].clone(), buffer);
     }
 
     #[test]
-    fn test_deserialize_bench() {
-        let mock = MyFilterMock::new();
+    fn test_filter_prefixed_any() {
+        let mut serialize = Module::new(crate::trie::Trie::new());
+        let connection = serialize.serialize("connection refused by peer").unwrap();
+        let timeout = serialize.serialize("request timeout reached").unwrap();
+        let other = serialize.serialize("all good here").unwrap();
 
-        let mut serialize = Module::new(mock);
-        let buffer = serialize.serialize(TEXT);
-        let start = Instant::now();
-        for _ in 0..BENCH_LOOP {
-            let _ = serialize.deserialize(&buffer);
+        let result = Module::filter_all(
+            vec![connection.clone(), timeout.clone(), other],
+            &[serialize.prefixed_any_matcher(&["conn", "time"], usize::MAX)],
+            None,
+        );
+
+        assert_eq!(result, vec![connection, timeout]);
+    }
+
+    #[test]
+    fn test_filter_prefixed_any_falls_back_to_words_of_buffers() {
+        let mut serialize = Module::new(crate::trie::Trie::new());
+        for i in 0..100 {
+            serialize.serialize(&format!("connection{}", i)).unwrap();
         }
+        let connection = serialize.serialize("connection refused").unwrap();
+        let timeout = serialize.serialize("request timeout").unwrap();
+        let buffers = vec![connection.clone(), timeout.clone()];
+        assert!(serialize.filter.find_prefix_limited("conn", 4).is_none());
+
+        let result = Module::filter_all(
+            buffers.clone(),
+            &[serialize.prefixed_any_matcher(&["conn"], usize::MAX)],
+            None,
+        );
+        assert_eq!(result, vec![connection.clone()]);
+        let result = Module::filter_all(
+            buffers,
+            &[serialize.prefixed_any_matcher(&["time", "conn"], usize::MAX)],
+            None,
+        );
+        assert_eq!(result, vec![connection, timeout]);
+    }
+
+    #[test]
+    fn test_prefixed_nums_ignoring_case() {
+        let mut serialize = Module::new(crate::trie::Folded::new(crate::trie::Trie::new()));
+        let buffer = serialize
+            .serialize("Connection CONNECTED connect timeout")
+            .unwrap();
+
+        let nums = serialize.prefixed_nums_ignoring_case(&["conn", "TIME"]);
+
+        assert_eq!(nums, buffer.into_iter().collect());
+        assert!(serialize
+            .prefixed_nums_ignoring_case(&["refused"])
+            .is_empty());
+    }
+
+    #[test]
+    fn test_filter_words_all() {
+        let mut serialize = Module::new(MyFilterMock::new());
+        let both = serialize.serialize("connection to db refused").unwrap();
+        let one = serialize.serialize("connection established").unwrap();
+        let reversed = serialize.serialize("refused incoming connection").unwrap();
+
+        let words = vec!["connection".to_string(), "refused".to_string()];
+        let result = Module::filter_all(
+            vec![both.clone(), one.clone(), reversed.clone()],
+            &[serialize.words_all_matcher(&words)],
+            None,
+        );
+        assert_eq!(result, vec![both.clone(), reversed]);
+
+        let words = vec!["connection".to_string(), "unknown".to_string()];
+        let result = Module::filter_all(
+            vec![both, one],
+            &[serialize.words_all_matcher(&words)],
+            None,
+        );
+        assert!(result.is_empty());
+    }
+
+    #[test]
+    fn test_filter_words_fuzzy() {
+        let mut serialize = Module::new(crate::trie::Trie::new());
+        let pod = serialize.serialize("kubernetes pod restarted").unwrap();
+        let node = serialize.serialize("kubernetes node drained").unwrap();
+        let other = serialize.serialize("docker daemon restarted").unwrap();
+        let buffers = vec![pod.clone(), node.clone(), other.clone()];
+
+        let words = vec!["kubranetes".to_string()];
+        let result = Module::filter_all(
+            buffers.clone(),
+            &[serialize.word_fuzzy_matcher(&words, 1)],
+            None,
+        );
+        assert!(result.is_empty());
+        let result = Module::filter_all(
+            buffers.clone(),
+            &[serialize.word_fuzzy_matcher(&words, 2)],
+            None,
+        );
+        assert_eq!(result, vec![pod.clone(), node]);
+
+        let words = vec!["kubranetes".to_string(), "restart".to_string()];
+        let result = Module::filter_all(
+            buffers.clone(),
+            &[serialize.words_all_fuzzy_matcher(&words, 2)],
+            None,
+        );
+        assert_eq!(result, vec![pod]);
+        let result = Module::filter_all(
+            buffers,
+            &[serialize.words_all_fuzzy_matcher(&words, 1)],
+            None,
+        );
+        assert!(result.is_empty());
+    }
+
+    #[test]
+    fn test_filter_prefixed_fuzzy() {
+        let mut serialize = Module::new(crate::trie::Trie::new());
+        let pod = serialize.serialize("kubernetes pod restarted").unwrap();
+        let other = serialize.serialize("docker daemon restarted").unwrap();
+        let buffers = vec![pod.clone(), other.clone()];
+
+        let result = Module::filter_all(
+            buffers.clone(),
+            &[serialize.prefixed_fuzzy_matcher(&["kubr"], 0)],
+            None,
+        );
+        assert!(result.is_empty());
+        let result = Module::filter_all(
+            buffers.clone(),
+            &[serialize.prefixed_fuzzy_matcher(&["kubr"], 1)],
+            None,
+        );
+        assert_eq!(result, vec![pod.clone()]);
+        let result = Module::filter_all(
+            buffers,
+            &[serialize.prefixed_fuzzy_matcher(&["kubr", "dokc"], 2)],
+            None,
+        );
+        assert_eq!(result, vec![pod, other]);
+    }
+
+    #[test]
+    fn test_filter_near() {
+        let mut serialize = Module::new(MyFilterMock::new());
+        let close = serialize.serialize("disk error on node one").unwrap();
+        let reversed = serialize.serialize("error full disk").unwrap();
+        let far = serialize
+            .serialize("disk check passed, later network error")
+            .unwrap();
+        let twice = serialize.serialize("disk ok, error then disk").unwrap();
+        let buffers = vec![close.clone(), reversed.clone(), far.clone(), twice.clone()];
+
+        let words = vec!["disk".to_string(), "error".to_string()];
+        let result =
+            Module::filter_all(buffers.clone(), &[serialize.near_matcher(&words, 1)], None);
+        assert_eq!(result, vec![close.clone()]);
+
+        let result =
+            Module::filter_all(buffers.clone(), &[serialize.near_matcher(&words, 2)], None);
+        assert_eq!(result, vec![close, reversed, twice]);
+
+        let result =
+            Module::filter_all(buffers.clone(), &[serialize.near_matcher(&words, 5)], None);
+        assert_eq!(result.len(), 4);
+
+        let words = vec!["disk".to_string(), "unknown".to_string()];
+        assert!(Module::filter_all(buffers, &[serialize.near_matcher(&words, 5)], None).is_empty());
+    }
+
+    #[test]
+    fn test_filter_pattern() {
+        let mut serialize = Module::new(crate::trie::Trie::new());
+        let refused = serialize.serialize("conn-refused on port 80").unwrap();
+        let closed = serialize.serialize("connection closed").unwrap();
+        let typo = serialize.serialize("errar in handler").unwrap();
+        let buffers = vec![refused.clone(), closed, typo.clone()];
+
+        assert_eq!(
+            Module::filter_all(
+                buffers.clone(),
+                &[serialize.pattern_matcher("conn*refused")],
+                None
+            ),
+            vec![refused]
+        );
+        assert_eq!(
+            Module::filter_all(buffers.clone(), &[serialize.pattern_matcher("err?r")], None),
+            vec![typo]
+        );
+        assert!(Module::filter_all(buffers, &[serialize.pattern_matcher("x*")], None).is_empty());
+    }
+
+    #[test]
+    fn test_filter_suffix() {
+        let mut serialize = Module::new(crate::trie::BiTrie::new());
+        let json = serialize.serialize("loaded config.json").unwrap();
+        let yaml = serialize.serialize("loaded config.yaml").unwrap();
+        let buffers = vec![json.clone(), yaml];
+
+        assert_eq!(
+            Module::filter_all(buffers.clone(), &[serialize.suffix_matcher(".json")], None),
+            vec![json]
+        );
+        assert!(Module::filter_all(buffers, &[serialize.suffix_matcher(".toml")], None).is_empty());
+    }
+
+    #[test]
+    fn test_filter_range() {
+        let mut serialize = Module::new(crate::transducer::Fst::new());
+        let timeout = serialize.serialize("request timeout").unwrap();
+        let refused = serialize.serialize("connection refused").unwrap();
+        serialize.filter.shrink_to_fit();
+        let reset = serialize.serialize("connection reset").unwrap();
+        let buffers = vec![timeout, refused.clone(), reset.clone()];
+
+        assert_eq!(
+            Module::filter_all(
+                buffers.clone(),
+                &[serialize.range_matcher("conn", "cono")],
+                None
+            ),
+            vec![refused.clone(), reset]
+        );
+        assert_eq!(
+            Module::filter_all(
+                buffers.clone(),
+                &[serialize.range_matcher("refused", "request")],
+                None
+            ),
+            vec![refused]
+        );
+        assert!(Module::filter_all(buffers, &[serialize.range_matcher("x", "y")], None).is_empty());
+    }
+
+    #[test]
+    fn test_suggest() {
+        let mut serialize = Module::new(crate::trie::BiTrie::new());
+        serialize.serialize("connection refused").unwrap();
+        serialize.serialize("connection reset").unwrap();
+        serialize.serialize("connection reset by peer").unwrap();
+        serialize.serialize("config loaded").unwrap();
+
+        assert_eq!(
+            serialize.suggest("con", 2),
+            vec![("connection", 3), ("config", 1)]
+        );
+        assert_eq!(
+            serialize.suggest("re", 5),
+            vec![("reset", 2), ("refused", 1)]
+        );
+        assert!(serialize.suggest("x", 5).is_empty());
+    }
+
+    #[test]
+    fn test_filter_phrase() {
+        let mut serialize = Module::new(MyFilterMock::new());
+        let exact = serialize
+            .serialize("error: connection refused by peer")
+            .unwrap();
+        let split = serialize.serialize("connection to db refused").unwrap();
+        let reversed = serialize.serialize("refused connection").unwrap();
+        let buffers = vec![exact.clone(), split.clone(), reversed.clone()];
+
+        let result = Module::filter_all(
+            buffers.clone(),
+            &[serialize.phrase_matcher("connection refused")],
+            None,
+        );
+        assert_eq!(result, vec![exact.clone()]);
+
+        let result = Module::filter_all(
+            buffers.clone(),
+            &[serialize.phrase_matcher("connection")],
+            None,
+        );
+        assert_eq!(result, vec![exact, split, reversed]);
+
+        let result = Module::filter_all(
+            buffers.clone(),
+            &[serialize.phrase_matcher("connection unknown")],
+            None,
+        );
+        assert!(result.is_empty());
+
+        let result = Module::filter_all(buffers.clone(), &[serialize.phrase_matcher("  ")], None);
+        assert_eq!(result, buffers);
+    }
+
+    #[test]
+    fn test_filter_all_stops_at_limit() {
+        let mut serialize = Module::new(MyFilterMock::new());
+        let first = serialize.serialize("error connection refused").unwrap();
+        let skipped = serialize.serialize("warning connection slow").unwrap();
+        let second = serialize.serialize("error connection reset").unwrap();
+        let third = serialize.serialize("error disk full").unwrap();
+        let buffers = vec![first.clone(), skipped, second.clone(), third.clone()];
+        let words = vec!["error".to_string()];
+
+        let matchers = [serialize.word_matcher(&words)];
+        let result = Module::filter_all(buffers.clone(), &matchers, Some(2));
+        assert_eq!(result, vec![first.clone(), second.clone()]);
 
-        let duration = start.elapsed();
+        let result = Module::filter_all(buffers.clone(), &matchers, None);
+        assert_eq!(result, vec![first.clone(), second, third]);
+
+        let matchers = [
+            serialize.word_matcher(&words),
+            serialize.phrase_matcher("connection refused"),
+        ];
+        let result = Module::filter_all(buffers.clone(), &matchers, Some(5));
+        assert_eq!(result, vec![first]);
+
+        assert_eq!(Module::filter_all(buffers.clone(), &[], None), buffers);
+        assert!(Module::filter_all(buffers, &[], Some(0)).is_empty());
+    }
+
+    #[test]
+    fn test_filter_all_counted_counts_past_limit_until_deadline() {
+        let mut serialize = Module::new(MyFilterMock::new());
+        let first = serialize.serialize("error connection refused").unwrap();
+        let skipped = serialize.serialize("warning connection slow").unwrap();
+        let second = serialize.serialize("error connection reset").unwrap();
+        let third = serialize.serialize("error disk full").unwrap();
+        let buffers = vec![first.clone(), skipped, second.clone(), third.clone()];
+        let words = vec!["error".to_string()];
+
+        let matchers = [serialize.word_matcher(&words)];
+        let filtered = Module::filter_all_counted(buffers.clone(), &matchers, Some(2), None);
+        assert_eq!(
+            filtered,
+            Filtered {
+                buffers: vec![first.clone(), second.clone()],
+                matched: 3,
+                scanned: 4
+            }
+        );
+
+        let filtered = Module::filter_all_counted(buffers.clone(), &matchers, None, None);
+        assert_eq!(filtered.buffers, vec![first, second, third]);
+        assert_eq!(filtered.matched, 3);
+
+        let filtered = Module::filter_all_counted(buffers.clone(), &[], Some(0), None);
+        assert!(filtered.buffers.is_empty());
+        assert_eq!(filtered.matched, 4);
+
+        let filtered = Module::filter_all_counted(buffers, &[], None, Some(Instant::now()));
+        assert!(filtered.buffers.is_empty());
+        assert_eq!(filtered.scanned, 0);
+    }
+
+    #[test]
+    fn test_top_words() {
+        let mut serialize = Module::new(MyFilterMock::new());
+        let first = serialize.serialize("error disk full error retry").unwrap();
+        serialize.serialize("warning disk slow").unwrap();
+        let third = serialize.serialize("error network").unwrap();
 
-        println!(
-            "Time elapsed in test_deserialize_once is: {:?}",
-            duration / BENCH_LOOP as u32
+        assert_eq!(serialize.top_words(2), vec![("error", 3), ("disk", 2)]);
+        assert_eq!(
+            serialize.top_words_in(3, &[first, third]),
+            vec![("error", 3), ("disk", 1), ("full", 1)]
         );
+        assert!(serialize.top_words(0).is_empty());
     }
 }
@@ -0,0 +1,358 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Elasticsearch version the shim reports, Filebeat refuses to ship to clusters of an older major version than its own.
+pub const VERSION: &str = "8.17.0";
+
+/// Header Elasticsearch clients check responses for, telling them they talk to Elasticsearch.
+pub const PRODUCT_HEADER: (&str, &str) = ("X-Elastic-Product", "Elasticsearch");
+
+/// Info is the answer of `GET /`, the cluster info clients read the version of before they ship.
+///
+#[derive(Debug, Serialize)]
+pub struct Info {
+    name: &'static str,
+    cluster_name: &'static str,
+    version: InfoVersion,
+    tagline: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct InfoVersion {
+    number: &'static str,
+    build_flavor: &'static str,
+}
+
+impl Default for Info {
+    fn default() -> Self {
+        Self {
+            name: "scribe",
+            cluster_name: "scribe",
+            version: InfoVersion {
+                number: VERSION,
+                build_flavor: "default",
+            },
+            tagline: "You Know, for Search",
+        }
+    }
+}
+
+/// Action is the action line of a bulk request, naming what is done to a document of which index.
+///
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Action {
+    Index(Target),
+    Create(Target),
+    Update(Target),
+    Delete(Target),
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Target {
+    #[serde(default, rename = "_index")]
+    index: Option<String>,
+}
+
+/// Document is a document of a bulk request saved as a log of its index.
+///
+#[derive(Debug, PartialEq)]
+pub struct Document {
+    pub log: String,
+    pub timestamp: Option<i64>,
+}
+
+/// Item is an action of a bulk request, with the document to save or the reason it is refused.
+///
+#[derive(Debug, PartialEq)]
+pub struct Item {
+    pub action: &'static str,
+    pub index: String,
+    pub document: Result<Document, Failure>,
+}
+
+/// Failure is the error of an item or a request, as Elasticsearch reports it.
+///
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Failure {
+    #[serde(skip)]
+    pub status: u16,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub reason: String,
+}
+
+impl Failure {
+    pub fn new(status: u16, kind: &'static str, reason: impl Into<String>) -> Self {
+        Self {
+            status,
+            kind,
+            reason: reason.into(),
+        }
+    }
+}
+
+/// Parses the action and source line pairs of the bulk request body, the index of the path applying to actions naming none.
+/// Index and create actions carry the `message` of their source, followed by the `host=` of the source and
+/// the `index=` of the action, at the `@timestamp` of the source. Update and delete actions are refused.
+/// Returns the failure of the whole request if an action line cannot be read.
+///
+pub fn parse(body: &str, index: Option<&str>, now: Duration) -> Result<Vec<Item>, Failure> {
+    let mut items = Vec::new();
+    let mut lines = body.lines().filter(|line| !line.trim().is_empty());
+    while let Some(line) = lines.next() {
+        let action: Action = serde_json::from_str(line).map_err(|e| {
+            Failure::new(
+                400,
+                "illegal_argument_exception",
+                format!("Malformed action/metadata line: {}", e),
+            )
+        })?;
+        let (action, target) = match action {
+            Action::Index(target) => ("index", target),
+            Action::Create(target) => ("create", target),
+            Action::Update(target) => ("update", target),
+            Action::Delete(target) => ("delete", target),
+        };
+        let Some(index) = target.index.as_deref().or(index).map(str::to_string) else {
+            return Err(Failure::new(
+                400,
+                "action_request_validation_exception",
+                "Validation Failed: index is missing",
+            ));
+        };
+        if action == "delete" {
+            items.push(Item {
+                action,
+                index,
+                document: Err(unsupported(action)),
+            });
+            continue;
+        }
+        let Some(source) = lines.next() else {
+            return Err(Failure::new(
+                400,
+                "illegal_argument_exception",
+                "The bulk request must be terminated by a newline [\\n]",
+            ));
+        };
+        let document = match action {
+            "update" => Err(unsupported(action)),
+            _ => document(source, &index, now),
+        };
+        items.push(Item {
+            action,
+            index,
+            document,
+        });
+    }
+    Ok(items)
+}
+
+fn unsupported(action: &str) -> Failure {
+    Failure::new(
+        400,
+        "illegal_argument_exception",
+        format!(
+            "scribe saves logs only, the {} action is not supported",
+            action
+        ),
+    )
+}
+
+fn document(source: &str, index: &str, now: Duration) -> Result<Document, Failure> {
+    let source: Map<String, Value> = serde_json::from_str(source).map_err(|e| {
+        Failure::new(
+            400,
+            "document_parsing_exception",
+            format!("failed to parse the document: {}", e),
+        )
+    })?;
+    let Some(mut log) = source
+        .get("message")
+        .and_then(Value::as_str)
+        .filter(|message| !message.trim().is_empty())
+        .map(str::to_string)
+    else {
+        return Err(Failure::new(
+            400,
+            "document_parsing_exception",
+            "the document has no message",
+        ));
+    };
+    let host = match source.get("host") {
+        Some(Value::String(host)) => Some(host.as_str()),
+        Some(Value::Object(host)) => host.get("name").and_then(Value::as_str),
+        _ => None,
+    };
+    if let Some(host) = host {
+        log.push_str(&format!(" host={}", host));
+    }
+    log.push_str(&format!(" index={}", index));
+    let timestamp = match source.get("@timestamp").and_then(Value::as_str) {
+        Some(time) => {
+            let time = crate::timeparse::parse(time, now).map_err(|e| {
+                Failure::new(
+                    400,
+                    "document_parsing_exception",
+                    format!("@timestamp: {}", e),
+                )
+            })?;
+            i64::try_from(time.as_nanos()).ok()
+        }
+        None => None,
+    };
+    Ok(Document { log, timestamp })
+}
+
+/// Response is the answer of a bulk request, an item for each action in the order of the request.
+///
+#[derive(Debug, Serialize)]
+pub struct Response {
+    took: u64,
+    errors: bool,
+    items: Vec<BTreeMap<&'static str, Outcome>>,
+}
+
+/// Outcome is the result of an action of a bulk request.
+///
+#[derive(Debug, Serialize)]
+pub struct Outcome {
+    #[serde(rename = "_index")]
+    index: String,
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<Failure>,
+}
+
+impl Response {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            took: 0,
+            errors: false,
+            items: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Adds the outcome of the action of the index, created under the id if it has one, or failed.
+    ///
+    pub fn push(
+        &mut self,
+        action: &'static str,
+        index: String,
+        outcome: Result<Option<String>, Failure>,
+    ) {
+        let outcome = match outcome {
+            Ok(id) => Outcome {
+                index,
+                id,
+                status: 201,
+                result: Some("created"),
+                error: None,
+            },
+            Err(failure) => {
+                self.errors = true;
+                Outcome {
+                    index,
+                    id: None,
+                    status: failure.status,
+                    result: None,
+                    error: Some(failure),
+                }
+            }
+        };
+        self.items.push(BTreeMap::from([(action, outcome)]));
+    }
+
+    pub fn took(mut self, took: Duration) -> Self {
+        self.took = took.as_millis() as u64;
+        self
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
+mod tests {
+    use super::*;
+
+    const NOW: Duration = Duration::from_secs(1_714_564_800);
+
+    #[test]
+    fn on_parse_should_read_messages_of_index_and_create_actions() {
+        let body = concat!(
+            "{\"create\":{\"_index\":\"filebeat-8.17.0\"}}\n",
+            "{\"@timestamp\":\"2024-05-01T12:00:00.250Z\",\"message\":\"GET /index 500\",\"host\":{\"name\":\"web-1\"},\"agent\":{\"type\":\"filebeat\"}}\n",
+            "{\"index\":{}}\n",
+            "{\"message\":\"up\"}\n",
+            "{\"delete\":{\"_index\":\"old\",\"_id\":\"1\"}}\n",
+            "{\"update\":{\"_id\":\"2\"}}\n",
+            "{\"doc\":{\"message\":\"x\"}}\n",
+            "{\"index\":{}}\n",
+            "{\"log\":\"no message\"}\n",
+        );
+        let Ok(items) = parse(body, Some("logs"), NOW) else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(items.len(), 5);
+        assert_eq!(
+            items[0].document,
+            Ok(Document {
+                log: "GET /index 500 host=web-1 index=filebeat-8.17.0".to_string(),
+                timestamp: Some(1_714_564_800_250_000_000),
+            })
+        );
+        assert_eq!(
+            items[1].document,
+            Ok(Document {
+                log: "up index=logs".to_string(),
+                timestamp: None,
+            })
+        );
+        assert_eq!(
+            (items[2].action, items[2].index.as_str()),
+            ("delete", "old")
+        );
+        assert!(items[2].document.is_err());
+        assert_eq!(items[3].action, "update");
+        assert!(items[3].document.is_err());
+        assert!(items[4].document.is_err());
+
+        assert!(parse("{\"index\":{}}\n{\"message\":\"up\"}\n", None, NOW).is_err());
+        assert!(parse("not json\n", Some("logs"), NOW).is_err());
+        assert!(parse("{\"index\":{}}\n", Some("logs"), NOW).is_err());
+    }
+
+    #[test]
+    fn on_push_should_flag_errors_and_key_items_by_action() {
+        let mut response = Response::new(2);
+        response.push("create", "logs".to_string(), Ok(Some("7".to_string())));
+        assert!(!response.errors);
+        response.push(
+            "index",
+            "logs".to_string(),
+            Err(Failure::new(503, "unavailable_shards_exception", "down")),
+        );
+        let Ok(json) = serde_json::to_value(response.took(Duration::from_millis(3))) else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "took": 3,
+                "errors": true,
+                "items": [
+                    {"create": {"_index": "logs", "_id": "7", "status": 201, "result": "created"}},
+                    {"index": {"_index": "logs", "status": 503, "error": {"type": "unavailable_shards_exception", "reason": "down"}}}
+                ]
+            })
+        );
+    }
+}
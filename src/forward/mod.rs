@@ -0,0 +1,429 @@
+use flate2::read::GzDecoder;
+use rmpv::Value;
+use std::future::Future;
+use std::io::{self, Cursor, Read};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Largest message accepted, compressed entries included once decompressed.
+const MAX_MESSAGE_BYTES: usize = 8 << 20;
+
+/// Bytes read from a connection at once.
+const READ_BUF_BYTES: usize = 64 << 10;
+
+/// Record fields holding the text of the event, the first one present is written first.
+const TEXT_FIELDS: [&str; 3] = ["log", "message", "msg"];
+
+/// Entry is the time of an event, if it is one after the Unix epoch, and its record.
+type Entry = (Option<i64>, Vec<(Value, Value)>);
+
+/// Event is an entry of a forward message, the tag of its message, its time and record.
+///
+#[derive(Debug, PartialEq)]
+pub struct Event {
+    tag: String,
+    time: Option<i64>,
+    record: Vec<(Value, Value)>,
+}
+
+impl Event {
+    /// Returns the text the event is saved as: the `log`, `message` or `msg` field of the record followed by
+    /// `tag=` and the other fields as `key=value` words, sorted by key.
+    ///
+    pub fn log(&self) -> String {
+        let mut fields: Vec<(&str, &Value)> = self
+            .record
+            .iter()
+            .filter_map(|(key, value)| Some((key.as_str()?, value)))
+            .filter(|(_, value)| !value.is_nil())
+            .collect();
+        fields.sort_by_key(|(key, _)| *key);
+
+        let text = TEXT_FIELDS
+            .iter()
+            .find_map(|name| fields.iter().position(|(key, _)| key == name))
+            .map(|i| fields.remove(i).1);
+        let mut log = text.map(text_of).unwrap_or_default();
+        if !log.is_empty() {
+            log.push(' ');
+        }
+        log.push_str("tag=");
+        log.push_str(&self.tag);
+        for (key, value) in fields {
+            log.push_str(&format!(" {}={}", key, text_of(value)));
+        }
+        log
+    }
+
+    /// Returns the time of the event in nanoseconds since the Unix epoch, None if it is not a time after the epoch.
+    ///
+    pub fn timestamp(&self) -> Option<i64> {
+        self.time
+    }
+}
+
+/// Returns strings and binaries as their text and other values as msgpack displays them.
+///
+fn text_of(value: &Value) -> String {
+    match value {
+        Value::String(s) => String::from_utf8_lossy(s.as_bytes()).trim_end().to_string(),
+        Value::Binary(b) => String::from_utf8_lossy(b).trim_end().to_string(),
+        value => value.to_string(),
+    }
+}
+
+/// Batch holds events of a forward message and the chunk id the sender waits for an ack of, if any.
+///
+#[derive(Debug, PartialEq)]
+pub struct Batch {
+    pub events: Vec<Event>,
+    pub chunk: Option<String>,
+}
+
+/// Decodes the forward message in any of its modes: Message `[tag, time, record, option?]`,
+/// Forward `[tag, [[time, record], ...], option?]` and PackedForward `[tag, entries, option?]`,
+/// entries being concatenated msgpack `[time, record]` arrays, gzip compressed if the option says so.
+///
+pub fn decode(message: Value) -> io::Result<Batch> {
+    let Value::Array(mut items) = message else {
+        return Err(invalid("message is not an array"));
+    };
+    if items.len() < 2 {
+        return Err(invalid("message holds no entries"));
+    }
+    let tag = match items.remove(0) {
+        Value::String(tag) => tag.into_str().ok_or_else(|| invalid("tag is not UTF-8"))?,
+        _ => return Err(invalid("tag is not a string")),
+    };
+    let (entries, option) = match items.remove(0) {
+        Value::Array(entries) => (
+            entries
+                .into_iter()
+                .map(entry)
+                .collect::<io::Result<Vec<_>>>()?,
+            items.into_iter().next(),
+        ),
+        Value::String(packed) => packed_entries(packed.as_bytes(), items.first())
+            .map(|entries| (entries, items.into_iter().next()))?,
+        Value::Binary(packed) => packed_entries(&packed, items.first())
+            .map(|entries| (entries, items.into_iter().next()))?,
+        time => {
+            let mut rest = items.into_iter();
+            let record = rest
+                .next()
+                .ok_or_else(|| invalid("message holds no record"))?;
+            (vec![entry(Value::Array(vec![time, record]))?], rest.next())
+        }
+    };
+
+    Ok(Batch {
+        events: entries
+            .into_iter()
+            .map(|(time, record)| Event {
+                tag: tag.clone(),
+                time,
+                record,
+            })
+            .collect(),
+        chunk: option
+            .as_ref()
+            .and_then(|option| option_str(option, "chunk"))
+            .map(str::to_string),
+    })
+}
+
+/// Reads the `[time, record]` arrays concatenated in packed, gunzipping them first
+/// if the option holds `compressed: gzip`.
+///
+fn packed_entries(packed: &[u8], option: Option<&Value>) -> io::Result<Vec<Entry>> {
+    let mut unpacked = Vec::new();
+    let packed = match option.and_then(|option| option_str(option, "compressed")) {
+        Some("gzip") => {
+            GzDecoder::new(packed)
+                .take(MAX_MESSAGE_BYTES as u64 + 1)
+                .read_to_end(&mut unpacked)?;
+            if unpacked.len() > MAX_MESSAGE_BYTES {
+                return Err(invalid("decompressed entries are too large"));
+            }
+            &unpacked[..]
+        }
+        Some(_) => return Err(invalid("entries are compressed with an unknown method")),
+        None => packed,
+    };
+    let mut cursor = Cursor::new(packed);
+    let mut entries = Vec::new();
+    while (cursor.position() as usize) < packed.len() {
+        let value = rmpv::decode::read_value(&mut cursor)
+            .map_err(|e| invalid(&format!("cannot read packed entry: {}", e)))?;
+        entries.push(entry(value)?);
+    }
+    Ok(entries)
+}
+
+/// Reads the `[time, record]` entry.
+///
+fn entry(value: Value) -> io::Result<Entry> {
+    let Value::Array(mut pair) = value else {
+        return Err(invalid("entry is not an array"));
+    };
+    if pair.len() != 2 {
+        return Err(invalid("entry is not a time and a record"));
+    }
+    let Value::Map(record) = pair.remove(1) else {
+        return Err(invalid("record is not a map"));
+    };
+    Ok((time(&pair[0]), record))
+}
+
+/// Reads the time of an entry in nanoseconds since the Unix epoch, given as seconds or as the EventTime
+/// extension holding seconds and nanoseconds as big endian 32 bit integers.
+///
+fn time(value: &Value) -> Option<i64> {
+    match value {
+        Value::Integer(secs) => secs.as_i64()?.checked_mul(1_000_000_000),
+        Value::Ext(0, data) if data.len() == 8 => {
+            let secs = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as i64;
+            let nanos = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as i64;
+            Some(secs * 1_000_000_000 + nanos)
+        }
+        Value::F64(secs) if secs.is_finite() && *secs >= 0.0 => (secs.trunc() as i64)
+            .checked_mul(1_000_000_000)?
+            .checked_add((secs.fract() * 1e9).round() as i64),
+        _ => None,
+    }
+    .filter(|nanos| *nanos >= 0)
+}
+
+/// Returns the string of the option map under the key.
+///
+fn option_str<'a>(option: &'a Value, key: &str) -> Option<&'a str> {
+    option
+        .as_map()?
+        .iter()
+        .find(|(k, _)| k.as_str() == Some(key))
+        .and_then(|(_, v)| v.as_str())
+}
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason.to_string())
+}
+
+/// Returns the msgpack `{"ack": chunk}` answer to a message of the chunk id.
+///
+pub fn ack(chunk: &str) -> Vec<u8> {
+    let mut buf = Vec::new();
+    let _ = rmpv::encode::write_value(
+        &mut buf,
+        &Value::Map(vec![(Value::from("ack"), Value::from(chunk))]),
+    );
+    buf
+}
+
+/// Accepts connections of fluent forward senders, like fluent-bit, on the listener.
+/// Events of each message are passed to save, and a message carrying a chunk id is acked once save returns true,
+/// so the sender resends messages that are not saved. Runs until the listener fails.
+///
+pub async fn serve<S, F>(listener: TcpListener, save: S)
+where
+    S: Fn(Vec<Event>) -> F + Clone + 'static,
+    F: Future<Output = bool> + 'static,
+{
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                tracing::warn!(error = %e, "cannot accept fluent forward connection");
+                continue;
+            }
+        };
+        let save = save.clone();
+        actix_web::rt::spawn(async move {
+            if let Err(e) = receive(stream, save).await {
+                tracing::warn!(error = %e, %peer, "fluent forward connection failed");
+            }
+        });
+    }
+}
+
+/// Reads messages of the connection until the sender closes it, saving and acking them in order.
+///
+async fn receive<S, F>(mut stream: TcpStream, save: S) -> io::Result<()>
+where
+    S: Fn(Vec<Event>) -> F,
+    F: Future<Output = bool>,
+{
+    let mut buf = Vec::new();
+    let mut read = vec![0; READ_BUF_BYTES];
+    loop {
+        loop {
+            let mut cursor = Cursor::new(&buf[..]);
+            let message = match rmpv::decode::read_value(&mut cursor) {
+                Ok(message) => message,
+                Err(
+                    rmpv::decode::Error::InvalidMarkerRead(e)
+                    | rmpv::decode::Error::InvalidDataRead(e),
+                ) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(invalid(&format!("cannot read message: {}", e))),
+            };
+            let used = cursor.position() as usize;
+            buf.drain(..used);
+            let batch = decode(message)?;
+            if save(batch.events).await {
+                if let Some(chunk) = batch.chunk {
+                    stream.write_all(&ack(&chunk)).await?;
+                }
+            }
+        }
+        if buf.len() > MAX_MESSAGE_BYTES {
+            return Err(invalid("message is too large"));
+        }
+        let n = stream.read(&mut read).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&read[..n]);
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::cell::RefCell;
+    use std::io::Write;
+    use std::rc::Rc;
+
+    fn record(log: &str) -> Value {
+        Value::Map(vec![
+            (Value::from("log"), Value::from(log)),
+            (Value::from("stream"), Value::from("stderr")),
+            (Value::from("pid"), Value::from(42)),
+        ])
+    }
+
+    fn event_time(secs: u32, nanos: u32) -> Value {
+        let mut data = secs.to_be_bytes().to_vec();
+        data.extend_from_slice(&nanos.to_be_bytes());
+        Value::Ext(0, data)
+    }
+
+    fn encode(value: &Value) -> Vec<u8> {
+        let mut buf = Vec::new();
+        rmpv::encode::write_value(&mut buf, value).unwrap();
+        buf
+    }
+
+    #[test]
+    fn on_decode_should_read_message_forward_and_packed_forward_modes() {
+        let chunk = Value::Map(vec![(Value::from("chunk"), Value::from("c1"))]);
+        let Ok(batch) = decode(Value::Array(vec![
+            Value::from("app.web"),
+            event_time(1_714_567_890, 5),
+            record("GET /index 500\n"),
+            chunk.clone(),
+        ])) else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(batch.chunk.as_deref(), Some("c1"));
+        assert_eq!(batch.events.len(), 1);
+        assert_eq!(
+            batch.events[0].log(),
+            "GET /index 500 tag=app.web pid=42 stream=stderr"
+        );
+        assert_eq!(batch.events[0].timestamp(), Some(1_714_567_890_000_000_005));
+
+        let entries = vec![
+            Value::Array(vec![Value::from(1), record("one")]),
+            Value::Array(vec![Value::from(2), record("two")]),
+        ];
+        let Ok(batch) = decode(Value::Array(vec![
+            Value::from("app"),
+            Value::Array(entries.clone()),
+        ])) else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(batch.chunk, None);
+        assert_eq!(batch.events[1].timestamp(), Some(2_000_000_000));
+
+        let packed: Vec<u8> = entries.iter().flat_map(encode).collect();
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(&packed).unwrap();
+        let Ok(compressed) = decode(Value::Array(vec![
+            Value::from("app"),
+            Value::Binary(gzip.finish().unwrap()),
+            Value::Map(vec![(Value::from("compressed"), Value::from("gzip"))]),
+        ])) else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(compressed.events, batch.events);
+
+        let Ok(batch) = decode(Value::Array(vec![
+            Value::from("app"),
+            Value::Integer(3.into()),
+            Value::Map(vec![(Value::from("key"), Value::from("value"))]),
+        ])) else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(batch.events[0].log(), "tag=app key=value");
+
+        for message in [
+            Value::from("app"),
+            Value::Array(vec![Value::from("app")]),
+            Value::Array(vec![Value::from(1), Value::from(1), record("x")]),
+            Value::Array(vec![Value::from("app"), Value::from(1), Value::from("x")]),
+            Value::Array(vec![Value::from("app"), Value::Binary(vec![0xc1])]),
+        ] {
+            assert!(decode(message.clone()).is_err(), "{}", message);
+        }
+    }
+
+    #[actix_web::test]
+    async fn on_serve_should_save_messages_split_across_reads_and_ack_saved_chunks() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let saved = Rc::new(RefCell::new(Vec::new()));
+        let sink = saved.clone();
+        actix_web::rt::spawn(serve(listener, move |events: Vec<Event>| {
+            let sink = sink.clone();
+            async move {
+                sink.borrow_mut().extend(events.iter().map(Event::log));
+                true
+            }
+        }));
+
+        let mut message = encode(&Value::Array(vec![
+            Value::from("app"),
+            Value::from(1),
+            record("first"),
+        ]));
+        message.extend(encode(&Value::Array(vec![
+            Value::from("app"),
+            Value::from(2),
+            record("second"),
+            Value::Map(vec![(Value::from("chunk"), Value::from("c2"))]),
+        ])));
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let (head, tail) = message.split_at(message.len() - 3);
+        stream.write_all(head).await.unwrap();
+        stream.flush().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        stream.write_all(tail).await.unwrap();
+
+        let mut answer = vec![0; ack("c2").len()];
+        stream.read_exact(&mut answer).await.unwrap();
+        assert_eq!(answer, ack("c2"));
+        assert_eq!(
+            *saved.borrow(),
+            [
+                "first tag=app pid=42 stream=stderr",
+                "second tag=app pid=42 stream=stderr"
+            ]
+        );
+    }
+}
@@ -0,0 +1,299 @@
+use flate2::read::{GzDecoder, ZlibDecoder};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::{self, Read};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+
+/// Bytes starting a chunk of a message sent over UDP in parts.
+const CHUNK_MAGIC: [u8; 2] = [0x1e, 0x0f];
+
+/// Length of the chunk header, the magic bytes, the message id, the sequence number and the count of chunks.
+const CHUNK_HEADER_LEN: usize = 12;
+
+/// Most chunks a message is sent in.
+const MAX_CHUNKS: u8 = 128;
+
+/// Time all chunks of a message have to arrive in, a message missing some later is dropped.
+const CHUNK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Most messages waiting for their remaining chunks at once, chunks of more are dropped.
+const MAX_PENDING: usize = 1024;
+
+/// Largest message accepted once decompressed.
+const MAX_MESSAGE_BYTES: u64 = 1 << 20;
+
+/// Largest UDP datagram.
+const MAX_DATAGRAM_BYTES: usize = 65_536;
+
+/// Message is a GELF message as Graylog shippers send it.
+/// Additional fields are the ones prefixed with `_`, other unknown fields are ignored.
+///
+#[derive(Debug, Deserialize)]
+pub struct Message {
+    host: String,
+    short_message: String,
+    #[serde(default)]
+    full_message: Option<String>,
+    #[serde(default)]
+    timestamp: Option<f64>,
+    #[serde(default)]
+    level: Option<u8>,
+    #[serde(flatten)]
+    fields: Map<String, Value>,
+}
+
+impl Message {
+    /// Returns the text the message is saved as: the short and the full message followed by
+    /// `host=`, `level=` and additional fields as `key=value` words, sorted by key.
+    ///
+    pub fn log(&self) -> String {
+        let mut log = self.short_message.clone();
+        if let Some(full) = self
+            .full_message
+            .as_ref()
+            .filter(|full| **full != self.short_message)
+        {
+            log.push(' ');
+            log.push_str(full);
+        }
+        log.push_str(" host=");
+        log.push_str(&self.host);
+        if let Some(level) = self.level {
+            log.push_str(&format!(" level={}", level));
+        }
+        let mut fields: Vec<(&str, &Value)> = self
+            .fields
+            .iter()
+            .filter_map(|(key, value)| Some((key.strip_prefix('_')?, value)))
+            .filter(|(key, value)| !key.is_empty() && *key != "id" && !value.is_null())
+            .collect();
+        fields.sort_by_key(|(key, _)| *key);
+        for (key, value) in fields {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            log.push_str(&format!(" {}={}", key, value));
+        }
+        log
+    }
+
+    /// Returns the timestamp of the message in nanoseconds since the Unix epoch,
+    /// None if the message has none or it is not a time after the epoch.
+    ///
+    pub fn timestamp(&self) -> Option<i64> {
+        let secs = self.timestamp.filter(|t| t.is_finite() && *t >= 0.0)?;
+        let nanos = (secs.fract() * 1_000_000_000.0).round() as i64;
+        (secs.trunc() as i64)
+            .checked_mul(1_000_000_000)?
+            .checked_add(nanos)
+    }
+}
+
+/// Parses the GELF message of the payload, plain JSON or compressed with gzip or zlib.
+///
+pub fn parse(payload: &[u8]) -> io::Result<Message> {
+    let mut json = Vec::new();
+    match payload {
+        [0x1f, 0x8b, ..] => GzDecoder::new(payload)
+            .take(MAX_MESSAGE_BYTES + 1)
+            .read_to_end(&mut json)?,
+        [0x78, ..] => ZlibDecoder::new(payload)
+            .take(MAX_MESSAGE_BYTES + 1)
+            .read_to_end(&mut json)?,
+        _ => {
+            json.extend_from_slice(payload);
+            json.len()
+        }
+    };
+    if json.len() as u64 > MAX_MESSAGE_BYTES {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message is larger than {} bytes", MAX_MESSAGE_BYTES),
+        ));
+    }
+    serde_json::from_slice(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Pending holds chunks of a message received so far.
+///
+struct Pending {
+    chunks: Vec<Option<Vec<u8>>>,
+    received: usize,
+    started: Instant,
+}
+
+/// Chunks joins messages sent over UDP in chunks, passing whole messages through.
+///
+#[derive(Default)]
+pub struct Chunks {
+    pending: HashMap<[u8; 8], Pending>,
+}
+
+impl Chunks {
+    /// Takes the datagram received at now, returning the payload of the message it completes, if any.
+    /// Chunks of messages not completed within CHUNK_TIMEOUT are dropped.
+    ///
+    pub fn push(&mut self, datagram: &[u8], now: Instant) -> io::Result<Option<Vec<u8>>> {
+        if !datagram.starts_with(&CHUNK_MAGIC) {
+            return Ok(Some(datagram.to_vec()));
+        }
+        if datagram.len() < CHUNK_HEADER_LEN {
+            return Err(invalid_chunk("chunk is shorter than its header"));
+        }
+        let mut id = [0; 8];
+        id.copy_from_slice(&datagram[2..10]);
+        let (seq, count) = (datagram[10], datagram[11]);
+        if count == 0 || count > MAX_CHUNKS || seq >= count {
+            return Err(invalid_chunk("chunk is numbered out of range"));
+        }
+
+        self.pending
+            .retain(|_, p| now.duration_since(p.started) < CHUNK_TIMEOUT);
+        if !self.pending.contains_key(&id) && self.pending.len() >= MAX_PENDING {
+            return Err(invalid_chunk("too many messages wait for chunks"));
+        }
+        let pending = self.pending.entry(id).or_insert_with(|| Pending {
+            chunks: vec![None; count as usize],
+            received: 0,
+            started: now,
+        });
+        if pending.chunks.len() != count as usize {
+            return Err(invalid_chunk("chunks of a message differ in count"));
+        }
+        let chunk = &mut pending.chunks[seq as usize];
+        if chunk.is_none() {
+            *chunk = Some(datagram[CHUNK_HEADER_LEN..].to_vec());
+            pending.received += 1;
+        }
+        if pending.received < pending.chunks.len() {
+            return Ok(None);
+        }
+
+        let Some(pending) = self.pending.remove(&id) else {
+            return Ok(None);
+        };
+        Ok(Some(
+            pending.chunks.into_iter().flatten().flatten().collect(),
+        ))
+    }
+}
+
+fn invalid_chunk(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+/// Receives GELF messages on the socket, whole or in chunks, passing each to save in the order they complete.
+/// Datagrams that cannot be read are passed to reject with the reason. Runs until the socket fails.
+///
+pub async fn receive<S, F>(socket: UdpSocket, mut save: S, reject: impl Fn(io::Error))
+where
+    S: FnMut(Message) -> F,
+    F: Future<Output = ()>,
+{
+    let mut chunks = Chunks::default();
+    let mut buf = vec![0; MAX_DATAGRAM_BYTES];
+    loop {
+        let len = match socket.recv(&mut buf).await {
+            Ok(len) => len,
+            Err(e) => {
+                tracing::error!(error = %e, "cannot receive GELF messages");
+                return;
+            }
+        };
+        match chunks
+            .push(&buf[..len], Instant::now())
+            .and_then(|payload| payload.map(|p| parse(&p)).transpose())
+        {
+            Ok(Some(message)) => save(message).await,
+            Ok(None) => (),
+            Err(e) => reject(e),
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    const MESSAGE: &str = r#"{"version":"1.1","host":"web-1","short_message":"GET /index 500","full_message":"GET /index 500\nat handler","timestamp":1714567890.25,"level":3,"_user_id":42,"_path":"/index","_id":"x","facility":"ignored"}"#;
+
+    #[test]
+    fn on_parse_should_map_the_message_and_additional_fields_to_the_log() {
+        let Ok(message) = parse(MESSAGE.as_bytes()) else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(
+            message.log(),
+            "GET /index 500 GET /index 500\nat handler host=web-1 level=3 path=/index user_id=42"
+        );
+        assert_eq!(message.timestamp(), Some(1_714_567_890_250_000_000));
+
+        let Ok(message) = parse(br#"{"version":"1.1","host":"db","short_message":"up"}"#) else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(message.log(), "up host=db");
+        assert_eq!(message.timestamp(), None);
+
+        assert!(parse(br#"{"version":"1.1","host":"db"}"#).is_err());
+    }
+
+    #[test]
+    fn on_parse_should_read_gzip_and_zlib_compressed_messages() {
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(MESSAGE.as_bytes()).unwrap();
+        let gzip = gzip.finish().unwrap();
+        let mut zlib = flate2::write::ZlibEncoder::new(Vec::new(), Compression::default());
+        zlib.write_all(MESSAGE.as_bytes()).unwrap();
+        let zlib = zlib.finish().unwrap();
+
+        for payload in [gzip, zlib] {
+            let Ok(message) = parse(&payload) else {
+                assert!(false);
+                return;
+            };
+            assert_eq!(message.host, "web-1");
+        }
+    }
+
+    fn chunk(id: u8, seq: u8, count: u8, data: &[u8]) -> Vec<u8> {
+        let mut chunk = vec![0x1e, 0x0f, id, 0, 0, 0, 0, 0, 0, 0, seq, count];
+        chunk.extend_from_slice(data);
+        chunk
+    }
+
+    #[test]
+    fn on_push_should_join_chunks_in_any_order_and_drop_late_ones() {
+        let mut chunks = Chunks::default();
+        let now = Instant::now();
+        let (head, tail) = MESSAGE.as_bytes().split_at(20);
+
+        assert_eq!(chunks.push(&chunk(1, 1, 2, tail), now).unwrap(), None);
+        assert_eq!(chunks.push(&chunk(1, 1, 2, tail), now).unwrap(), None);
+        assert_eq!(
+            chunks.push(&chunk(1, 0, 2, head), now).unwrap().as_deref(),
+            Some(MESSAGE.as_bytes())
+        );
+
+        assert_eq!(chunks.push(&chunk(2, 0, 2, head), now).unwrap(), None);
+        let late = now + CHUNK_TIMEOUT;
+        assert_eq!(chunks.push(&chunk(2, 1, 2, tail), late).unwrap(), None);
+
+        assert_eq!(
+            chunks.push(MESSAGE.as_bytes(), now).unwrap().as_deref(),
+            Some(MESSAGE.as_bytes())
+        );
+        assert!(chunks.push(&chunk(3, 2, 2, tail), now).is_err());
+        assert!(chunks.push(&chunk(3, 0, 129, tail), now).is_err());
+        assert!(chunks.push(&[0x1e, 0x0f, 1], now).is_err());
+    }
+}
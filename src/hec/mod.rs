@@ -0,0 +1,264 @@
+use crate::auth;
+use actix_web::http::header::HeaderValue;
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Scheme of the `Authorization: Splunk <token>` header HEC clients send their token in.
+const SPLUNK: &str = "Splunk ";
+
+/// Fields of an event object taken for its message, the first one present is written first.
+const MESSAGE_FIELDS: [&str; 3] = ["message", "log", "msg"];
+
+/// Reply is the answer of the HTTP Event Collector, with the status and the code HEC clients branch on.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Reply {
+    #[serde(skip)]
+    status: u16,
+    text: &'static str,
+    code: u8,
+    #[serde(
+        rename = "invalid-event-number",
+        skip_serializing_if = "Option::is_none"
+    )]
+    invalid_event: Option<usize>,
+}
+
+impl Reply {
+    pub const SUCCESS: Self = Self::new(200, "Success", 0);
+    pub const TOKEN_DISABLED: Self = Self::new(403, "Token disabled", 1);
+    pub const TOKEN_REQUIRED: Self = Self::new(401, "Token is required", 2);
+    pub const INVALID_AUTHORIZATION: Self = Self::new(401, "Invalid authorization", 3);
+    pub const INVALID_TOKEN: Self = Self::new(403, "Invalid token", 4);
+    pub const NO_DATA: Self = Self::new(400, "No data", 5);
+    pub const INVALID_DATA_FORMAT: Self = Self::new(400, "Invalid data format", 6);
+    pub const SERVER_BUSY: Self = Self::new(503, "Server is busy", 9);
+    pub const EVENT_REQUIRED: Self = Self::new(400, "Event field is required", 12);
+    pub const EVENT_BLANK: Self = Self::new(400, "Event field cannot be blank", 13);
+
+    const fn new(status: u16, text: &'static str, code: u8) -> Self {
+        Self {
+            status,
+            text,
+            code,
+            invalid_event: None,
+        }
+    }
+
+    /// Returns the reply naming the event, counted from 0, it is about.
+    ///
+    pub fn at(self, event: usize) -> Self {
+        Self {
+            invalid_event: Some(event),
+            ..self
+        }
+    }
+
+    pub fn response(&self) -> HttpResponse {
+        HttpResponse::build(StatusCode::from_u16(self.status).unwrap_or(StatusCode::BAD_REQUEST))
+            .json(self)
+    }
+}
+
+/// Checks the `Authorization: Splunk <token>` header holds one of the tokens.
+/// Without tokens the collector is disabled and no request is authorized.
+///
+pub fn authorize(header: Option<&HeaderValue>, tokens: &[String]) -> Result<(), Reply> {
+    if tokens.is_empty() {
+        return Err(Reply::TOKEN_DISABLED);
+    }
+    let Some(header) = header else {
+        return Err(Reply::TOKEN_REQUIRED);
+    };
+    if !header.as_bytes().starts_with(SPLUNK.as_bytes()) {
+        return Err(Reply::INVALID_AUTHORIZATION);
+    }
+    // Every token is compared, so the time taken tells nothing of which one matched.
+    let held = tokens.iter().fold(false, |held, token| {
+        auth::holds_token(Some(header), SPLUNK, token) | held
+    });
+    if held {
+        Ok(())
+    } else {
+        Err(Reply::INVALID_TOKEN)
+    }
+}
+
+/// Event is an event of the HTTP Event Collector, its `event` being a string or an object.
+///
+#[derive(Debug, Deserialize)]
+pub struct Event {
+    #[serde(default)]
+    time: Option<Value>,
+    #[serde(default)]
+    host: Option<String>,
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(default)]
+    sourcetype: Option<String>,
+    #[serde(default)]
+    index: Option<String>,
+    #[serde(default)]
+    event: Option<Value>,
+    #[serde(default)]
+    fields: Map<String, Value>,
+}
+
+impl Event {
+    /// Returns the text the event is saved as: the event followed by `host=`, `source=`, `sourcetype=`, `index=`
+    /// and its fields as `key=value` words sorted by key, None if the event is blank.
+    /// An event object is written as its `message`, `log` or `msg` field followed by its other fields sorted by key.
+    ///
+    pub fn log(&self) -> Option<String> {
+        let mut log = match self.event.as_ref()? {
+            Value::Object(event) => {
+                let message = MESSAGE_FIELDS.iter().find(|key| event.contains_key(**key));
+                let mut log = message
+                    .and_then(|key| event.get(*key))
+                    .map(text_of)
+                    .unwrap_or_default();
+                push_fields(
+                    &mut log,
+                    event
+                        .iter()
+                        .filter(|(key, _)| Some(&key.as_str()) != message),
+                );
+                log
+            }
+            event => text_of(event),
+        };
+        for (key, value) in [
+            ("host", &self.host),
+            ("source", &self.source),
+            ("sourcetype", &self.sourcetype),
+            ("index", &self.index),
+        ] {
+            if let Some(value) = value {
+                log.push_str(&format!(" {}={}", key, value));
+            }
+        }
+        push_fields(&mut log, self.fields.iter());
+        let log = log.trim_start().to_string();
+        (!log.trim().is_empty()).then_some(log)
+    }
+
+    /// Returns the time of the event in nanoseconds since the Unix epoch, written by HEC clients as seconds,
+    /// None if the event has none or it is not a time after the epoch.
+    ///
+    pub fn timestamp(&self) -> Option<i64> {
+        let secs = match self.time.as_ref()? {
+            Value::Number(secs) => secs.as_f64()?,
+            Value::String(secs) => secs.trim().parse::<f64>().ok()?,
+            _ => return None,
+        };
+        if !secs.is_finite() || secs < 0.0 {
+            return None;
+        }
+        let nanos = (secs.fract() * 1_000_000_000.0).round() as i64;
+        (secs.trunc() as i64)
+            .checked_mul(1_000_000_000)?
+            .checked_add(nanos)
+    }
+}
+
+fn text_of(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn push_fields<'a>(log: &mut String, fields: impl Iterator<Item = (&'a String, &'a Value)>) {
+    let mut fields: Vec<(&String, &Value)> = fields.filter(|(_, value)| !value.is_null()).collect();
+    fields.sort_by_key(|(key, _)| *key);
+    for (key, value) in fields {
+        log.push_str(&format!(" {}={}", key, text_of(value)));
+    }
+}
+
+/// Parses the events of the body, written one after another as HEC clients batch them.
+/// Returns the reply naming the first event that cannot be read otherwise.
+///
+pub fn parse(body: &[u8]) -> Result<Vec<Event>, Reply> {
+    let mut events = Vec::new();
+    for (i, value) in serde_json::Deserializer::from_slice(body)
+        .into_iter::<Value>()
+        .enumerate()
+    {
+        let Ok(value) = value else {
+            return Err(Reply::INVALID_DATA_FORMAT.at(i));
+        };
+        if !value.as_object().is_some_and(|v| v.contains_key("event")) {
+            return Err(Reply::EVENT_REQUIRED.at(i));
+        }
+        let event: Event =
+            serde_json::from_value(value).map_err(|_| Reply::INVALID_DATA_FORMAT.at(i))?;
+        events.push(event);
+    }
+    if events.is_empty() {
+        return Err(Reply::NO_DATA);
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn on_parse_should_read_batched_events_as_logs() {
+        let Ok(events) = parse(
+            br#"{"time":1714567890.25,"host":"web-1","sourcetype":"access","index":"main","event":"GET /index 500","fields":{"user":"ann","code":500}}
+            {"time":"1714567891","event":{"msg":"login","user":"bob","ok":true}}"#,
+        ) else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(
+            events[0].log().as_deref(),
+            Some("GET /index 500 host=web-1 sourcetype=access index=main code=500 user=ann")
+        );
+        assert_eq!(events[0].timestamp(), Some(1_714_567_890_250_000_000));
+        assert_eq!(events[1].log().as_deref(), Some("login ok=true user=bob"));
+        assert_eq!(events[1].timestamp(), Some(1_714_567_891_000_000_000));
+
+        assert_eq!(parse(b"").err(), Some(Reply::NO_DATA));
+        assert_eq!(
+            parse(br#"{"event":"up"} {"host":"web-1"}"#).err(),
+            Some(Reply::EVENT_REQUIRED.at(1))
+        );
+        assert_eq!(
+            parse(br#"{"event":"up"} {"event":"#).err(),
+            Some(Reply::INVALID_DATA_FORMAT.at(1))
+        );
+        let Ok(events) = parse(br#"{"event":"  "}"#) else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(events[0].log(), None);
+    }
+
+    #[test]
+    fn on_authorize_should_accept_any_of_the_tokens_in_the_splunk_scheme() {
+        let tokens = ["first".to_string(), "second".to_string()];
+        let header = |value: &'static str| HeaderValue::from_static(value);
+
+        assert_eq!(authorize(Some(&header("Splunk second")), &tokens), Ok(()));
+        assert_eq!(
+            authorize(Some(&header("Splunk third")), &tokens),
+            Err(Reply::INVALID_TOKEN)
+        );
+        assert_eq!(
+            authorize(Some(&header("Bearer first")), &tokens),
+            Err(Reply::INVALID_AUTHORIZATION)
+        );
+        assert_eq!(authorize(None, &tokens), Err(Reply::TOKEN_REQUIRED));
+        assert_eq!(
+            authorize(Some(&header("Splunk first")), &[]),
+            Err(Reply::TOKEN_DISABLED)
+        );
+    }
+}
@@ -0,0 +1,276 @@
+use crate::dedup::Deduplicator;
+use crate::metrics::Metrics;
+use crate::repository::error::Result;
+use crate::repository::interface::RepositoryProvider;
+use crate::settings::Runtime;
+use arc_swap::ArcSwap;
+use scribe::dictionary::Module;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::{interval, Instant, MissedTickBehavior};
+
+const MIN_CHECK_PERIOD: Duration = Duration::from_secs(1);
+const MAX_EXPIRY_PERIOD: Duration = Duration::from_secs(60);
+
+/// Activity keeps track of the last moment the server handled a request or received a log.
+///
+#[derive(Debug)]
+pub struct Activity {
+    started: Instant,
+    last_millis: AtomicU64,
+}
+
+impl Activity {
+    /// Creates a new Activity marking the server as active now.
+    ///
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            last_millis: AtomicU64::new(0),
+        }
+    }
+
+    /// Marks the server as active now.
+    ///
+    #[inline(always)]
+    pub fn touch(&self) {
+        self.last_millis
+            .store(self.started.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Returns for how long the server has been idle.
+    ///
+    #[inline(always)]
+    pub fn idle_for(&self) -> Duration {
+        self.started.elapsed().saturating_sub(self.last())
+    }
+
+    #[inline(always)]
+    fn last(&self) -> Duration {
+        Duration::from_millis(self.last_millis.load(Ordering::Relaxed))
+    }
+}
+
+/// Housekeeper reclaims memory held by the dictionary and logs remembered by the deduplicator
+/// once the server has been idle for long enough.
+/// Reclamation runs once per idle period, so a quiet server is not repeatedly locked.
+///
+pub struct Housekeeper {
+    activity: Arc<Activity>,
+    idle_timeout: Duration,
+    reclaimed_at: Option<Duration>,
+    dedup: Option<Arc<Deduplicator>>,
+}
+
+impl Housekeeper {
+    /// Creates a new Housekeeper watching given activity.
+    ///
+    pub fn new(activity: Arc<Activity>, idle_timeout: Duration) -> Self {
+        Self {
+            activity,
+            idle_timeout,
+            reclaimed_at: None,
+            dedup: None,
+        }
+    }
+
+    /// Sets the deduplicator to forget logs of past windows once the server is idle.
+    ///
+    pub fn with_deduplicator(mut self, dedup: Option<Arc<Deduplicator>>) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
+    /// Shrinks the dictionary and the deduplicator if the server is idle and they were not shrunk since the last activity.
+    /// Returns true if reclamation happened.
+    ///
+    pub fn reclaim_if_idle(&mut self, dict: &RwLock<Module>) -> bool {
+        if self.activity.idle_for() < self.idle_timeout {
+            return false;
+        }
+        let last = self.activity.last();
+        if self.reclaimed_at == Some(last) {
+            return false;
+        }
+        let Ok(mut dict) = dict.write() else {
+            return false;
+        };
+        dict.shrink_to_fit();
+        drop(dict);
+        if let Some(dedup) = &self.dedup {
+            dedup.shrink(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default(),
+            );
+        }
+        self.reclaimed_at = Some(last);
+
+        true
+    }
+
+    /// Runs the housekeeping loop forever, checking for idleness a few times per idle period.
+    ///
+    pub async fn run(mut self, dict: Arc<RwLock<Module>>) {
+        let mut ticker = interval((self.idle_timeout / 4).max(MIN_CHECK_PERIOD));
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            self.reclaim_if_idle(&dict);
+        }
+    }
+}
+
+/// Retention deletes logs older than the retention period of the runtime settings from the repository.
+/// Expiry runs often enough to keep each delete small, but at most once a second.
+/// A reloaded period takes effect from the next run, logs are kept forever while there is none.
+///
+pub struct Retention {
+    runtime: Arc<ArcSwap<Runtime>>,
+    metrics: Arc<Metrics>,
+}
+
+impl Retention {
+    /// Creates a new Retention keeping logs for the period of the runtime settings.
+    ///
+    pub fn new(runtime: Arc<ArcSwap<Runtime>>, metrics: Arc<Metrics>) -> Self {
+        Self { runtime, metrics }
+    }
+
+    /// Deletes logs older than the retention, counting runs, failures and deleted logs.
+    /// Returns the number of deleted logs, nothing is deleted without a retention.
+    ///
+    pub async fn expire(&self, repo: &impl RepositoryProvider) -> Result<u64> {
+        let Some(retention) = self.runtime.load().retention else {
+            return Ok(0);
+        };
+        let before = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .saturating_sub(retention);
+        self.metrics.retention_runs.add(1);
+        match repo.expire_logs(&before).await {
+            Ok(reclaimed) => {
+                self.metrics.retention_reclaimed_logs.add(reclaimed);
+                Ok(reclaimed)
+            }
+            Err(e) => {
+                self.metrics.retention_failures.add(1);
+                Err(e)
+            }
+        }
+    }
+
+    /// Runs the expiry loop forever, waiting a quarter of the current retention between runs.
+    ///
+    pub async fn run(self, repo: impl RepositoryProvider) {
+        loop {
+            if let Err(e) = self.expire(&repo).await {
+                tracing::error!(error = %e, "cannot expire logs");
+            }
+            let period = match self.runtime.load().retention {
+                Some(retention) => (retention / 4).clamp(MIN_CHECK_PERIOD, MAX_EXPIRY_PERIOD),
+                None => MAX_EXPIRY_PERIOD,
+            };
+            tokio::time::sleep(period).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::sql::{DatabaseStorage, WarehouseSql};
+    use crate::repository::PoolConfig;
+    use scribe::token::Token;
+    use scribe::trie::Trie;
+
+    fn now_nanos() -> i64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as i64
+    }
+
+    const IDLE: Duration = Duration::from_secs(60);
+
+    fn dict() -> RwLock<Module> {
+        let mut m = Module::new(Trie::new());
+        m.serialize("some words to fill the dictionary with")
+            .unwrap();
+        RwLock::new(m)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn on_reclaim_if_idle_should_not_reclaim_while_active() {
+        let activity = Arc::new(Activity::new());
+        let mut keeper = Housekeeper::new(activity.clone(), IDLE);
+        let dict = dict();
+
+        tokio::time::advance(IDLE / 2).await;
+        activity.touch();
+        tokio::time::advance(IDLE / 2).await;
+
+        assert!(!keeper.reclaim_if_idle(&dict));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn on_reclaim_if_idle_should_reclaim_once_per_idle_period() {
+        let activity = Arc::new(Activity::new());
+        let mut keeper = Housekeeper::new(activity.clone(), IDLE);
+        let dict = dict();
+
+        tokio::time::advance(IDLE).await;
+        assert!(keeper.reclaim_if_idle(&dict));
+        assert!(!keeper.reclaim_if_idle(&dict));
+
+        activity.touch();
+        tokio::time::advance(IDLE).await;
+        assert!(keeper.reclaim_if_idle(&dict));
+    }
+
+    #[tokio::test]
+    async fn on_expire_should_delete_logs_older_than_reloaded_retention_and_count_them() {
+        let repo = WarehouseSql::new(DatabaseStorage::Ram, &PoolConfig::default())
+            .await
+            .unwrap();
+        repo.migrate().await.unwrap();
+        let metrics = Arc::new(Metrics::default());
+        let runtime = Arc::new(ArcSwap::from_pointee(Runtime {
+            retention: None,
+            ..crate::settings::Setup::default().get_runtime()
+        }));
+        let retention = Retention::new(runtime.clone(), metrics.clone());
+
+        repo.insert_log_at(&[Token::from(1)], now_nanos())
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        repo.insert_log_at(&[Token::from(2)], now_nanos())
+            .await
+            .unwrap();
+
+        assert_eq!(retention.expire(&repo).await.unwrap(), 0);
+        assert_eq!(metrics.retention_runs.get(), 0);
+        runtime.rcu(|r| Runtime {
+            retention: Some(Duration::from_millis(50)),
+            ..(**r).clone()
+        });
+
+        assert_eq!(retention.expire(&repo).await.unwrap(), 1);
+        assert_eq!(retention.expire(&repo).await.unwrap(), 0);
+        assert_eq!(metrics.retention_runs.get(), 2);
+        assert_eq!(metrics.retention_reclaimed_logs.get(), 1);
+        assert_eq!(metrics.retention_failures.get(), 0);
+
+        let all = repo
+            .find_logs(&Duration::ZERO, &Duration::from_nanos(i64::MAX as u64))
+            .await
+            .unwrap();
+        assert_eq!(
+            all.into_iter().map(|l| l.data).collect::<Vec<_>>(),
+            vec![vec![Token::from(2)]]
+        );
+    }
+}
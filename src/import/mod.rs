@@ -0,0 +1,192 @@
+use crate::timeparse::TimeExpr;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Format is how lines of an imported file are written.
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// Each line is a log.
+    #[default]
+    Text,
+    /// Each line is a JSON object with the `log` and an optional `timestamp`.
+    Ndjson,
+}
+
+impl Format {
+    /// Returns the format of a body or a file of the content type, NDJSON for `application/x-ndjson`,
+    /// `application/jsonl` and `application/json`, text for any other.
+    ///
+    pub fn of_content_type(content_type: &str) -> Self {
+        let essence = content_type.split(';').next().unwrap_or_default().trim();
+        match essence.to_ascii_lowercase().as_str() {
+            "application/x-ndjson" | "application/jsonl" | "application/json" => Self::Ndjson,
+            _ => Self::Text,
+        }
+    }
+}
+
+/// Line is the NDJSON form of an imported log.
+///
+#[derive(Debug, Deserialize)]
+struct Line {
+    log: String,
+    #[serde(default)]
+    timestamp: Option<TimeExpr>,
+}
+
+/// Returns the log of the line with its timestamp in nanoseconds since the Unix epoch, None if the line has none.
+/// A text line has a timestamp only with leading, written as its first word in nanoseconds or as an RFC 3339 date.
+/// Relative timestamps are counted from now.
+///
+pub fn parse(
+    format: Format,
+    leading: bool,
+    line: &str,
+    now: Duration,
+) -> Result<(String, Option<i64>), String> {
+    let (log, timestamp) = match format {
+        Format::Ndjson => {
+            let line: Line = serde_json::from_str(line).map_err(|e| e.to_string())?;
+            (
+                line.log,
+                line.timestamp.map(|t| t.resolve(now)).transpose()?,
+            )
+        }
+        Format::Text if leading => {
+            let (time, log) = line
+                .trim_start()
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| "the line holds no log after its timestamp".to_string())?;
+            (
+                log.trim_start().to_string(),
+                Some(crate::timeparse::parse(time, now)?),
+            )
+        }
+        Format::Text => (line.to_string(), None),
+    };
+    let timestamp = timestamp
+        .map(|t| {
+            i64::try_from(t.as_nanos()).map_err(|_| "the timestamp is out of range".to_string())
+        })
+        .transpose()?;
+    Ok((log, timestamp))
+}
+
+/// Lines splits a stream of chunks in to lines, holding the tail of a chunk until the line it starts ends.
+///
+#[derive(Debug, Default)]
+pub struct Lines {
+    pending: Vec<u8>,
+}
+
+impl Lines {
+    /// Takes the chunk, returning the lines it completes without their line endings.
+    /// Returns an error if a line grows longer than max bytes.
+    ///
+    pub fn push(&mut self, chunk: &[u8], max: usize) -> Result<Vec<String>, usize> {
+        self.pending.extend_from_slice(chunk);
+        let mut lines = Vec::new();
+        let mut start = 0;
+        while let Some(end) = self.pending[start..].iter().position(|b| *b == b'\n') {
+            lines.push(line_of(&self.pending[start..start + end]));
+            start += end + 1;
+        }
+        self.pending.drain(..start);
+        if self.pending.len() > max {
+            return Err(self.pending.len());
+        }
+        Ok(lines)
+    }
+
+    /// Returns the last line, which ends with the stream instead of a line break, if there is one.
+    ///
+    pub fn finish(&mut self) -> Option<String> {
+        let pending = std::mem::take(&mut self.pending);
+        (!pending.is_empty()).then(|| line_of(&pending))
+    }
+}
+
+fn line_of(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes.strip_suffix(b"\r").unwrap_or(bytes)).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: Duration = Duration::from_secs(1_714_564_800);
+
+    #[test]
+    fn on_parse_should_read_logs_and_timestamps_of_each_format() {
+        assert_eq!(
+            parse(
+                Format::Ndjson,
+                false,
+                r#"{"log":"GET /index 200","timestamp":"2024-05-01T12:00:00Z"}"#,
+                NOW
+            ),
+            Ok((
+                "GET /index 200".to_string(),
+                Some(1_714_564_800_000_000_000)
+            ))
+        );
+        assert_eq!(
+            parse(
+                Format::Ndjson,
+                false,
+                r#"{"log":"up","timestamp":1500}"#,
+                NOW
+            ),
+            Ok(("up".to_string(), Some(1500)))
+        );
+        assert_eq!(
+            parse(Format::Ndjson, false, r#"{"log":"up"}"#, NOW),
+            Ok(("up".to_string(), None))
+        );
+        assert!(parse(Format::Ndjson, false, r#"{"message":"up"}"#, NOW).is_err());
+
+        assert_eq!(
+            parse(
+                Format::Text,
+                true,
+                "2024-05-01T12:00:00Z  GET /index 200",
+                NOW
+            ),
+            Ok((
+                "GET /index 200".to_string(),
+                Some(1_714_564_800_000_000_000)
+            ))
+        );
+        assert_eq!(
+            parse(Format::Text, false, "2024-05-01T12:00:00Z GET", NOW),
+            Ok(("2024-05-01T12:00:00Z GET".to_string(), None))
+        );
+        assert!(parse(Format::Text, true, "yesterday GET /index", NOW).is_err());
+        assert!(parse(Format::Text, true, "1500", NOW).is_err());
+
+        assert_eq!(
+            Format::of_content_type("application/x-ndjson; charset=utf-8"),
+            Format::Ndjson
+        );
+        assert_eq!(Format::of_content_type("text/plain"), Format::Text);
+    }
+
+    #[test]
+    fn on_push_should_split_chunks_in_to_lines_across_chunk_bounds() {
+        let mut lines = Lines::default();
+        assert_eq!(
+            lines.push(b"first\r\nsec", 16),
+            Ok(vec!["first".to_string()])
+        );
+        assert_eq!(
+            lines.push(b"ond\n\nthird", 16),
+            Ok(vec!["second".to_string(), String::new()])
+        );
+        assert_eq!(lines.finish(), Some("third".to_string()));
+        assert_eq!(lines.finish(), None);
+
+        assert_eq!(lines.push(b"far too long for the limit", 16), Err(26));
+    }
+}
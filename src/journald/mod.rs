@@ -0,0 +1,265 @@
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::future::Future;
+use std::io;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+
+/// Program the journal is read with.
+const JOURNALCTL: &str = "journalctl";
+
+/// Longest time the cursor of a saved entry waits to be written to the checkpoint file,
+/// entries saved within it are read again after a restart.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time waited before the journal is read again after journalctl stops or an entry cannot be saved.
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// Journal fields written after the message, with the key they are written as.
+const FIELDS: [(&str, &str); 5] = [
+    ("_HOSTNAME", "host"),
+    ("_SYSTEMD_UNIT", "unit"),
+    ("SYSLOG_IDENTIFIER", "identifier"),
+    ("_PID", "pid"),
+    ("PRIORITY", "priority"),
+];
+
+/// Entry is an entry of the systemd journal as `journalctl --output=json` writes it.
+///
+#[derive(Debug, Deserialize)]
+pub struct Entry {
+    #[serde(rename = "__CURSOR")]
+    cursor: String,
+    #[serde(rename = "__REALTIME_TIMESTAMP", default)]
+    realtime: Option<String>,
+    #[serde(rename = "MESSAGE", default)]
+    message: Option<Value>,
+    #[serde(flatten)]
+    fields: Map<String, Value>,
+}
+
+impl Entry {
+    /// Returns the text the entry is saved as: the message followed by `host=`, `unit=`, `identifier=`,
+    /// `pid=` and `priority=` words of the fields the entry has, None if the entry has no message.
+    ///
+    pub fn log(&self) -> Option<String> {
+        let mut log = self.message.as_ref().and_then(text_of)?;
+        for (field, key) in FIELDS {
+            if let Some(value) = self.fields.get(field).and_then(text_of) {
+                log.push_str(&format!(" {}={}", key, value));
+            }
+        }
+        Some(log)
+    }
+
+    /// Returns the time the entry is written at in nanoseconds since the Unix epoch,
+    /// None if the entry has none.
+    ///
+    pub fn timestamp(&self) -> Option<i64> {
+        self.realtime
+            .as_deref()?
+            .parse::<i64>()
+            .ok()?
+            .checked_mul(1_000)
+    }
+}
+
+/// Returns the text of the journal field value, written by journalctl as a string,
+/// as an array of bytes when it is not valid UTF-8, or as an array when the field is repeated.
+///
+fn text_of(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Array(items) if items.iter().all(Value::is_u64) => {
+            let bytes: Vec<u8> = items
+                .iter()
+                .filter_map(|b| b.as_u64().and_then(|b| u8::try_from(b).ok()))
+                .collect();
+            Some(String::from_utf8_lossy(&bytes).into_owned())
+        }
+        Value::Array(items) => items.first().and_then(text_of),
+        _ => None,
+    }
+}
+
+/// Checkpoint is the file holding the cursor of the last saved journal entry.
+///
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    path: PathBuf,
+}
+
+impl Checkpoint {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Reads the cursor of the file, None when there is no file yet.
+    ///
+    pub fn load(&self) -> io::Result<Option<String>> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(cursor) if cursor.trim().is_empty() => Ok(None),
+            Ok(cursor) => Ok(Some(cursor.trim().to_string())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes the cursor to the file, replacing the file at once so a crash never leaves half a cursor.
+    ///
+    pub fn store(&self, cursor: &str) -> io::Result<()> {
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        std::fs::write(&tmp, cursor)?;
+        std::fs::rename(&tmp, &self.path)
+    }
+}
+
+/// Follows the journal of the units, all of them when none is given, passing each entry with a message to save.
+/// Reading starts after the cursor of the checkpoint, or at the end of the journal when there is none.
+/// The cursor of an entry is checkpointed once save returns true; when it returns false journalctl is stopped
+/// and the journal is read again from the last saved entry. Runs for as long as the server does.
+///
+pub async fn follow<S, F>(checkpoint: Checkpoint, units: Vec<String>, mut save: S)
+where
+    S: FnMut(Entry) -> F,
+    F: Future<Output = bool>,
+{
+    loop {
+        let cursor = checkpoint.load().unwrap_or_else(|e| {
+            tracing::error!(error = %e, "cannot read the journal cursor, reading from the end");
+            None
+        });
+        if let Err(e) = read(&checkpoint, cursor, &units, &mut save).await {
+            tracing::error!(error = %e, "cannot read the journal");
+        }
+        tokio::time::sleep(RESTART_DELAY).await;
+    }
+}
+
+async fn read<S, F>(
+    checkpoint: &Checkpoint,
+    cursor: Option<String>,
+    units: &[String],
+    save: &mut S,
+) -> io::Result<()>
+where
+    S: FnMut(Entry) -> F,
+    F: Future<Output = bool>,
+{
+    let mut command = Command::new(JOURNALCTL);
+    command.args(["--output=json", "--follow", "--no-pager"]);
+    for unit in units {
+        command.arg(format!("--unit={}", unit));
+    }
+    match &cursor {
+        Some(cursor) => command.arg(format!("--after-cursor={}", cursor)),
+        None => command.arg("--lines=0"),
+    };
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+    let Some(stdout) = child.stdout.take() else {
+        return Err(io::Error::other("journalctl has no output"));
+    };
+    tracing::info!(after = cursor.as_deref(), "reading the journal");
+
+    let mut lines = BufReader::new(stdout).lines();
+    let mut unsaved: Option<String> = None;
+    let mut stored = Instant::now();
+    let result = loop {
+        let line = match tokio::time::timeout(CHECKPOINT_INTERVAL, lines.next_line()).await {
+            Ok(Ok(Some(line))) => line,
+            Ok(Ok(None)) => break Err(io::Error::other("journalctl stopped")),
+            Ok(Err(e)) => break Err(e),
+            Err(_) => {
+                if let Some(cursor) = unsaved.take() {
+                    checkpoint.store(&cursor)?;
+                    stored = Instant::now();
+                }
+                continue;
+            }
+        };
+        let entry: Entry = match serde_json::from_str(&line) {
+            Ok(entry) => entry,
+            Err(e) => {
+                tracing::debug!(error = %e, "cannot read journal entry");
+                continue;
+            }
+        };
+        let cursor = entry.cursor.clone();
+        if entry.message.is_some() && !save(entry).await {
+            break Err(io::Error::other("cannot save journal entry"));
+        }
+        unsaved = Some(cursor);
+        if stored.elapsed() >= CHECKPOINT_INTERVAL {
+            if let Some(cursor) = unsaved.take() {
+                checkpoint.store(&cursor)?;
+                stored = Instant::now();
+            }
+        }
+    };
+    if let Some(cursor) = unsaved {
+        checkpoint.store(&cursor)?;
+    }
+    let _ = child.kill().await;
+    result
+}
+
+#[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn on_log_should_write_the_message_followed_by_known_fields() {
+        let Ok(entry) = serde_json::from_str::<Entry>(
+            r#"{"__CURSOR":"s=1;i=2","__REALTIME_TIMESTAMP":"1714567890250000","MESSAGE":"Started nginx","_HOSTNAME":"web-1","_SYSTEMD_UNIT":"nginx.service","_PID":"42","PRIORITY":"6","_BOOT_ID":"ignored"}"#,
+        ) else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(
+            entry.log().as_deref(),
+            Some("Started nginx host=web-1 unit=nginx.service pid=42 priority=6")
+        );
+        assert_eq!(entry.timestamp(), Some(1_714_567_890_250_000_000));
+
+        let Ok(entry) = serde_json::from_str::<Entry>(
+            r#"{"__CURSOR":"s=1;i=3","MESSAGE":[104,105,255],"SYSLOG_IDENTIFIER":["a","b"]}"#,
+        ) else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(entry.log().as_deref(), Some("hi\u{fffd} identifier=a"));
+        assert_eq!(entry.timestamp(), None);
+
+        let Ok(entry) = serde_json::from_str::<Entry>(r#"{"__CURSOR":"s=1;i=4","MESSAGE":null}"#)
+        else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(entry.log(), None);
+        assert!(serde_json::from_str::<Entry>(r#"{"MESSAGE":"no cursor"}"#).is_err());
+    }
+
+    #[test]
+    fn on_store_should_replace_the_cursor_the_checkpoint_loads() {
+        let path =
+            std::env::temp_dir().join(format!("scribe-journal-{}.cursor", std::process::id()));
+        let checkpoint = Checkpoint::new(&path);
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(checkpoint.load().unwrap(), None);
+        checkpoint.store("s=1;i=2").unwrap();
+        checkpoint.store("s=1;i=3").unwrap();
+        assert_eq!(checkpoint.load().unwrap().as_deref(), Some("s=1;i=3"));
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
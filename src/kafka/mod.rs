@@ -0,0 +1,91 @@
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::error::KafkaResult;
+use rdkafka::Message;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::Duration;
+
+/// Time waited before a message that cannot be saved is saved again, or a failed receive is retried.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Source configures the Kafka topics consumed by the `kafka` feature.
+/// Options are passed to librdkafka as they are, like `security.protocol` or `auto.offset.reset`,
+/// and take precedence over the ones set from the other fields.
+///
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Source {
+    pub brokers: String,
+    pub topics: Vec<String>,
+    pub group_id: String,
+    #[serde(default)]
+    pub options: BTreeMap<String, String>,
+}
+
+/// Done tells what became of a message passed to save.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Done {
+    /// The message is saved, or will never be and is skipped, so its offset is committed.
+    Committed,
+    /// The message is not saved yet and is to be saved again.
+    Retry,
+}
+
+/// Returns the consumer of the source subscribed to its topics.
+/// Offsets are committed in the background, but only of messages the consumer stored them of.
+///
+pub fn subscribe(source: &Source) -> KafkaResult<StreamConsumer> {
+    let mut config = ClientConfig::new();
+    config
+        .set("bootstrap.servers", &source.brokers)
+        .set("group.id", &source.group_id)
+        .set("enable.auto.commit", "true")
+        .set("enable.auto.offset.store", "false")
+        .set("auto.offset.reset", "earliest");
+    for (key, value) in source.options.iter() {
+        config.set(key, value);
+    }
+    let consumer: StreamConsumer = config.create()?;
+    let topics: Vec<&str> = source.topics.iter().map(String::as_str).collect();
+    consumer.subscribe(&topics)?;
+    Ok(consumer)
+}
+
+/// Consumes messages of the consumer one at a time, passing the text of each with its timestamp,
+/// in nanoseconds since the Unix epoch when the message has one, to save.
+/// The offset of a message is stored for commit only once save is done with it, a message save asks
+/// to retry is saved again until it is, so every message is saved at least once. Runs for as long as the server does.
+///
+pub async fn consume<S, F>(consumer: StreamConsumer, mut save: S)
+where
+    S: FnMut(String, Option<i64>) -> F,
+    F: Future<Output = Done>,
+{
+    loop {
+        let message = match consumer.recv().await {
+            Ok(message) => message,
+            Err(e) => {
+                tracing::warn!(error = %e, "cannot receive Kafka message");
+                tokio::time::sleep(RETRY_DELAY).await;
+                continue;
+            }
+        };
+        let log = String::from_utf8_lossy(message.payload().unwrap_or_default());
+        let log = log.trim_end();
+        let timestamp = message
+            .timestamp()
+            .to_millis()
+            .filter(|millis| *millis >= 0)
+            .and_then(|millis| millis.checked_mul(1_000_000));
+        if !log.is_empty() {
+            while save(log.to_string(), timestamp).await == Done::Retry {
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+        }
+        if let Err(e) = consumer.store_offset_from_message(&message) {
+            tracing::warn!(error = %e, topic = message.topic(), "cannot store Kafka offset");
+        }
+    }
+}
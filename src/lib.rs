@@ -0,0 +1,9 @@
+//! Word filters and the dictionary translating logs to tokens,
+//! shared by the server and the benchmarks.
+//!
+pub mod bloom;
+pub mod dawg;
+pub mod dictionary;
+pub mod token;
+pub mod transducer;
+pub mod trie;
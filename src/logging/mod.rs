@@ -0,0 +1,298 @@
+use crate::settings::LogFormat;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use actix_web::Error;
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+use opentelemetry_sdk::Resource;
+use std::cell::Cell;
+use std::future::Future;
+use std::io::{self, IsTerminal};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, EnvFilter, Registry};
+
+/// Header a request id is accepted from and sent back in.
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest request id accepted from a client, a longer one is replaced.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    /// Time the request served by the task spent waiting for the repository.
+    static REPOSITORY_TIME: Cell<Duration>;
+    /// Id of the request served by the task.
+    static REQUEST_ID: Arc<str>;
+}
+
+/// LevelHandle changes the level of logs written by the subscriber installed by init while it runs.
+///
+#[derive(Clone)]
+pub struct LevelHandle(reload::Handle<EnvFilter, Registry>);
+
+impl LevelHandle {
+    /// Writes logs of the level, or of directives per module, from now on.
+    ///
+    pub fn set(&self, level: &str) -> io::Result<()> {
+        self.0.reload(filter(level)?).map_err(io::Error::other)
+    }
+}
+
+/// Returns the filter of the level, or of directives per module like `warn,scribe=debug`.
+///
+fn filter(level: &str) -> io::Result<EnvFilter> {
+    EnvFilter::try_new(level).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("cannot parse log level [ {} ]: {}", level, e),
+        )
+    })
+}
+
+/// Traces exports spans to the OTLP collector in batches.
+///
+pub struct Traces(SdkTracerProvider);
+
+impl Traces {
+    /// Exports spans not exported yet and stops the exporter.
+    ///
+    pub fn shutdown(self) {
+        if let Err(e) = self.0.shutdown() {
+            tracing::warn!(error = %e, "cannot export remaining spans");
+        }
+    }
+}
+
+/// Returns the tracer provider exporting the ratio of traces to the OTLP/HTTP endpoint,
+/// following the sampling decision of the caller for traces started by it.
+///
+fn traces(endpoint: &str, ratio: f64) -> io::Result<SdkTracerProvider> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()
+        .map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cannot export spans to [ {} ]: {}", endpoint, e),
+            )
+        })?;
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            ratio,
+        ))))
+        .with_resource(Resource::builder().with_service_name("scribe").build())
+        .build())
+}
+
+/// Installs the global subscriber writing logs of the level, or of directives per module like `warn,scribe=debug`,
+/// to the standard output in the format. Returns the handle the level is changed with.
+/// Given the OTLP endpoint and sample ratio, spans are exported there too until Traces are shut down.
+///
+pub fn init(
+    level: &str,
+    format: LogFormat,
+    otlp: Option<(&str, f64)>,
+) -> io::Result<(LevelHandle, Option<Traces>)> {
+    let (filter, handle) = reload::Layer::new(filter(level)?);
+    let provider = match otlp {
+        Some((endpoint, ratio)) => Some(traces(endpoint, ratio)?),
+        None => None,
+    };
+    let registry = tracing_subscriber::registry().with(filter).with(
+        provider
+            .as_ref()
+            .map(|p| tracing_opentelemetry::layer().with_tracer(p.tracer("scribe"))),
+    );
+    let ansi = io::stdout().is_terminal();
+    match format {
+        LogFormat::Pretty => registry.with(fmt::layer().with_ansi(ansi)).try_init(),
+        LogFormat::Json => registry
+            .with(
+                fmt::layer()
+                    .with_ansi(ansi)
+                    .json()
+                    .with_current_span(true)
+                    .with_span_list(false),
+            )
+            .try_init(),
+    }
+    .map_err(io::Error::other)?;
+    if provider.is_some() {
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    }
+    Ok((LevelHandle(handle), provider.map(Traces)))
+}
+
+/// Adds the duration to the repository time of the request served by the current task.
+/// Outside of a request, like in the write queue, it does nothing.
+///
+#[inline(always)]
+pub fn add_repository_time(d: Duration) {
+    let _ = REPOSITORY_TIME.try_with(|t| t.set(t.get() + d));
+}
+
+/// Returns the id of the request served by the current task, None outside of a request.
+///
+pub fn request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.to_string()).ok()
+}
+
+/// Returns the id the client sent in the `X-Request-Id` header, or a new random one
+/// if there is none or it is longer than MAX_REQUEST_ID_LEN or holds other than visible ASCII.
+///
+fn request_id_of(req: &ServiceRequest) -> Arc<str> {
+    req.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|id| id.to_str().ok())
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LEN
+                && id.bytes().all(|b| b.is_ascii_graphic())
+        })
+        .map(Arc::from)
+        .unwrap_or_else(|| Arc::from(format!("{:032x}", rand::random::<u128>())))
+}
+
+/// HeaderExtractor reads the trace context of the caller from the `traceparent` and `tracestate` headers.
+///
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(HeaderName::as_str).collect()
+    }
+}
+
+/// Runs the future returning its output together with the repository time added while it ran.
+///
+async fn with_repository_time<T>(fut: impl Future<Output = T>) -> (T, Duration) {
+    REPOSITORY_TIME
+        .scope(Cell::new(Duration::ZERO), async {
+            let out = fut.await;
+            (out, REPOSITORY_TIME.with(Cell::get))
+        })
+        .await
+}
+
+/// Serves the request in a `request` span of its method, path and request id, logging its status, latency
+/// and the part of the latency spent in the repository once it is served.
+/// Events logged while serving it carry the fields of the span, and the response carries the request id
+/// in the `X-Request-Id` header, so a failed request is found in the logs by the id the client holds.
+/// Exported spans continue the trace of the caller given in the `traceparent` header.
+///
+pub fn access<S, B>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse<B>, Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    let id = request_id_of(&req);
+    let span = tracing::info_span!(
+        "request",
+        method = %req.method(),
+        path = %req.path(),
+        request_id = %id
+    );
+    let parent = opentelemetry::global::get_text_map_propagator(|p| {
+        p.extract(&HeaderExtractor(req.headers()))
+    });
+    let _ = span.set_parent(parent);
+    let start = Instant::now();
+    let fut = span.in_scope(|| REQUEST_ID.sync_scope(id.clone(), || srv.call(req)));
+
+    async move {
+        let (mut res, repository) = REQUEST_ID
+            .scope(id.clone(), with_repository_time(fut))
+            .await;
+        if let (Ok(res), Ok(id)) = (&mut res, HeaderValue::from_str(&id)) {
+            res.headers_mut().insert(REQUEST_ID_HEADER, id);
+        }
+        let status = match &res {
+            Ok(res) => res.status(),
+            Err(e) => e.as_response_error().status_code(),
+        };
+        tracing::info!(
+            status = status.as_u16(),
+            latency_ms = start.elapsed().as_secs_f64() * 1000.0,
+            repository_ms = repository.as_secs_f64() * 1000.0,
+            "served"
+        );
+
+        res
+    }
+    .instrument(span)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn on_with_repository_time_should_sum_time_added_while_running() {
+        add_repository_time(Duration::from_secs(1));
+
+        let (out, repository) = with_repository_time(async {
+            add_repository_time(Duration::from_millis(2));
+            tokio::task::yield_now().await;
+            add_repository_time(Duration::from_millis(3));
+            7
+        })
+        .await;
+
+        assert_eq!(out, 7);
+        assert_eq!(repository, Duration::from_millis(5));
+    }
+
+    #[actix_web::test]
+    async fn on_access_should_send_back_the_request_id_in_the_header_and_error_envelope() {
+        use crate::api::{ApiError, ErrorCode};
+        use actix_web::{test, web, App};
+
+        async fn fail() -> Result<&'static str, ApiError> {
+            Err(ApiError::new(ErrorCode::NotFound, "Unknown log 1."))
+        }
+
+        let app =
+            test::init_service(App::new().wrap_fn(access).route("/", web::get().to(fail))).await;
+
+        let req = test::TestRequest::get()
+            .uri("/")
+            .insert_header((REQUEST_ID_HEADER, "client-42"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.headers().get(REQUEST_ID_HEADER).unwrap(), "client-42");
+        let body = test::read_body(res).await;
+        assert_eq!(
+            &body[..],
+            br#"{"code":"not_found","message":"Unknown log 1.","request_id":"client-42"}"#
+        );
+
+        for header in [None, Some("has space"), Some(&*"x".repeat(129))] {
+            let mut req = test::TestRequest::get().uri("/");
+            if let Some(header) = header {
+                req = req.insert_header((REQUEST_ID_HEADER, header));
+            }
+            let res = test::call_service(&app, req.to_request()).await;
+            let id = res.headers().get(REQUEST_ID_HEADER).unwrap();
+            assert_eq!(id.len(), 32, "{:?}", header);
+            assert_ne!(Some(id.to_str().unwrap()), header);
+        }
+    }
+}
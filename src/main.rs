@@ -1,164 +1,457 @@
-mod dictionary;
+mod api;
+mod auth;
+mod backup;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod cli;
+mod dedup;
+mod elastic;
+mod forward;
+mod gelf;
+mod hec;
+mod housekeeping;
+mod import;
+mod journald;
+#[cfg(feature = "kafka")]
+mod kafka;
+mod logging;
+mod methods;
+mod metrics;
+mod queue;
 mod repository;
+mod server;
 mod settings;
-mod trie;
+mod snapshot;
+mod tail;
+mod timeparse;
 
-use actix_web::{error, get, post, web, App, HttpResponse, HttpServer, Responder, Result};
+use actix_web::dev::Service;
+use actix_web::http::KeepAlive;
+use actix_web::middleware::{NormalizePath, TrailingSlash};
+use actix_web::{web, App, HttpServer};
+use arc_swap::ArcSwap;
+use backup::{backup_to, ensure_streaming, import_schema, restore_from};
+use clap::Parser;
+use cli::Command;
+use repository::instrument::Instrumented;
 use repository::interface::RepositoryProvider;
+use repository::retry::Retrying;
 use repository::Repository;
-use serde::{Deserialize, Serialize};
+use server::{
+    admin_routes, connect, load_dictionary, not_found, now, public_routes, save, save_trie,
+    ServerActor,
+};
+use socket2::{Domain, Socket, Type};
 use std::env;
+use std::net::SocketAddr;
 use std::sync::{Arc, RwLock};
-use std::time::Duration;
-use web::{Data, Json};
 
-/// VERSION shall be updated before creating release.
-static VERSION: &str = "Scribe 1.0.0";
+/// VERSION is taken from the package version at compile time.
+static VERSION: &str = concat!("Scribe ", env!("CARGO_PKG_VERSION"));
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Version {
-    version: String,
-}
+/// Commit the binary is built from, written by the build script.
+static COMMIT: &str = env!("SCRIBE_COMMIT");
 
-#[derive(Debug, Serialize, Deserialize)]
-struct LogInput {
-    log: String,
-}
+/// RFC 3339 time the binary is built at, written by the build script.
+static BUILT_AT: &str = env!("SCRIBE_BUILT_AT");
 
-#[derive(Debug, Serialize, Deserialize)]
-struct LogsOutput {
-    logs: Vec<String>,
-}
+/// Connections waiting to be accepted by a TCP listener, the backlog actix binds with.
+const LISTEN_BACKLOG: i32 = 2048;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Query {
-    prefix: Option<String>,
-    words: Option<Vec<String>>,
-    from: u64,
-    to: u64,
+/// Runs the command other than serving against the repository of the setup.
+///
+async fn run(
+    command: Command,
+    repo: &Retrying<Instrumented<Repository>>,
+    setup: &settings::Setup,
+    metrics: &metrics::Metrics,
+) -> std::io::Result<()> {
+    match command {
+        Command::Serve | Command::Journald { .. } | Command::Tail { .. } => Ok(()),
+        Command::Migrate => {
+            tracing::info!("migrated");
+            Ok(())
+        }
+        Command::Export { path } => {
+            let (dict, _) = load_dictionary(repo, setup).await?;
+            let mut file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+            dict.write_schema_binary(&mut file)?;
+            std::io::Write::flush(&mut file)?;
+            tracing::info!(words = dict.len(), path, "exported dictionary");
+            Ok(())
+        }
+        Command::ImportSchema { path } => {
+            let words = import_schema(repo, setup, &path).await?;
+            tracing::info!(words, path, "imported dictionary");
+            Ok(())
+        }
+        Command::Compact => {
+            repo.compact().await.map_err(std::io::Error::other)?;
+            tracing::info!("compacted");
+            Ok(())
+        }
+        Command::Backup { path } => {
+            ensure_streaming(repo, setup).await?;
+            let dict = RwLock::new(load_dictionary(repo, setup).await?.0);
+            let (logs, words) = backup_to(repo, &dict, tokio::fs::File::create(&path).await?)
+                .await
+                .map_err(std::io::Error::other)?;
+            tracing::info!(logs, words, path, "backed up");
+            Ok(())
+        }
+        Command::Restore { path } => {
+            let (logs, words) = restore_from(repo, setup, &path).await?;
+            tracing::info!(logs, words, path, "restored");
+            Ok(())
+        }
+        Command::Copy { to } => {
+            let target_setup = settings::Setup::from_file(&to)?;
+            let target = connect(&target_setup, metrics).await?;
+            let snapshot = env::temp_dir().join(format!("scribe-{}.snapshot", std::process::id()));
+            let snapshot = snapshot.to_string_lossy();
+            ensure_streaming(repo, setup).await?;
+            let dict = RwLock::new(load_dictionary(repo, setup).await?.0);
+            let copied =
+                match backup_to(repo, &dict, tokio::fs::File::create(&*snapshot).await?).await {
+                    Ok(_) => restore_from(&target, &target_setup, &snapshot).await,
+                    Err(e) => Err(std::io::Error::other(e)),
+                };
+            let _ = std::fs::remove_file(&*snapshot);
+            let (logs, words) = copied?;
+            tracing::info!(logs, words, to, "copied");
+            Ok(())
+        }
+    }
 }
 
-struct ServerActor {
-    version: String,
-    repo: Repository,
-    dict: Box<Arc<RwLock<dictionary::Module>>>,
+/// Returns a listener bound to the address. An IPv6 address is bound IPv6 only when an IPv4 address
+/// of the same port is listened on too, so `[::]:8000` and `0.0.0.0:8000` are bound side by side,
+/// while `[::]:8000` alone takes both IPv6 and IPv4 connections.
+///
+fn tcp_listener(
+    addr: &SocketAddr,
+    listen: &[settings::Listen],
+) -> std::io::Result<std::net::TcpListener> {
+    let socket = Socket::new(Domain::for_address(*addr), Type::STREAM, None)?;
+    socket.set_reuse_address(true)?;
+    if addr.is_ipv6() {
+        let ipv4_on_port = listen.iter().any(|l| {
+            matches!(l, settings::Listen::Tcp(other) if other.is_ipv4() && other.port() == addr.port())
+        });
+        socket.set_only_v6(ipv4_on_port)?;
+    }
+    socket.bind(&(*addr).into())?;
+    socket.listen(LISTEN_BACKLOG)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
 }
 
-impl Clone for ServerActor {
-    fn clone(&self) -> Self {
-        Self {
-            version: self.version.clone(),
-            repo: self.repo.clone(),
-            dict: self.dict.clone(),
-        }
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    let cli = cli::Cli::parse();
+    let setup = settings::Setup::load(cli.config.as_deref(), &cli.overrides)?;
+    let (log_level, traces) = logging::init(
+        setup.get_log_level(),
+        setup.get_log_format(),
+        setup
+            .get_otlp_endpoint()
+            .map(|endpoint| (endpoint, setup.get_otlp_sample_ratio())),
+    )?;
+
+    #[cfg(feature = "chaos")]
+    if let Some(faults) = setup.get_chaos() {
+        tracing::warn!(?faults, "injecting faults");
+        chaos::install(faults.clone());
     }
-}
 
-#[inline(always)]
-#[get("/version")]
-async fn version(state: Data<ServerActor>) -> Result<impl Responder> {
-    let v = Version {
-        version: state.version.to_string(),
-    };
-    Ok(Json(v))
-}
+    let metrics = Arc::new(metrics::Metrics::default());
+    let repo = connect(&setup, &metrics).await?;
 
-#[inline(always)]
-#[post("/save")]
-async fn save_log(input: Json<LogInput>, state: Data<ServerActor>) -> Result<impl Responder> {
-    let Ok(mut dict) = state.dict.write() else {
-        return Err(error::ErrorInternalServerError(
-            "Dictionary is not responding.",
-        ));
+    let command = cli.command.unwrap_or(Command::Serve);
+    if !command.serves() {
+        let result = run(command, &repo, &setup, &metrics).await;
+        repo.close().await;
+        if let Some(traces) = traces {
+            traces.shutdown();
+        }
+        return result;
+    }
+
+    let (dict, prefixes) = load_dictionary(&repo, &setup).await?;
+    let dict = Arc::new(RwLock::new(dict));
+    let (queue, flushing) = match setup.get_write_queue() {
+        Some(size) => {
+            let (queue, flusher) = queue::new(
+                size,
+                setup.get_write_flush_interval(),
+                repo.clone(),
+                metrics.clone(),
+            );
+            let flusher = flusher.with_dictionary(dict.clone());
+            (Some(queue), Some(actix_web::rt::spawn(flusher.run())))
+        }
+        None => (None, None),
     };
-    let buf = dict.serialize(&input.log);
-    if let Err(e) = state.repo.insert_log(&buf).await {
-        return Err(error::ErrorInternalServerError(e.to_string()));
+    let runtime = Arc::new(ArcSwap::from_pointee(setup.get_runtime()));
+    let service = ServerActor {
+        version: VERSION.to_string(),
+        repo: repo.clone(),
+        dict: dict.clone(),
+        filter: setup.get_filter(),
+        fold_case: setup.get_fold_case(),
+        prefixes,
+        metrics,
+        dedup: match setup.get_dedup() {
+            settings::DedupMode::Off => None,
+            mode => Some(Arc::new(dedup::Deduplicator::new(
+                mode,
+                setup.get_dedup_window(),
+            ))),
+        },
+        queue: queue.clone(),
+        runtime: runtime.clone(),
+        setup: Arc::new(setup.clone()),
+        config_digest: Arc::new(ArcSwap::from_pointee(setup.digest())),
+        setup_path: cli.config,
+        overrides: cli.overrides,
+        log_level,
+        import_gate: Arc::new(tokio::sync::RwLock::new(())),
+        activity: Arc::new(housekeeping::Activity::new()),
     };
 
-    Ok(HttpResponse::Ok())
-}
-
-#[inline(always)]
-#[post("/read")]
-async fn read_logs(input: Json<Query>, state: Data<ServerActor>) -> Result<impl Responder> {
-    let from = Duration::from_nanos(input.from);
-    let to = Duration::from_nanos(input.to);
-    let Ok(mut logs) = state.repo.find_logs(&from, &to).await else {
-        return Err(error::ErrorInternalServerError("Database not responding."));
-    };
+    if let Some(idle_timeout) = setup.get_idle_timeout() {
+        let keeper = housekeeping::Housekeeper::new(service.activity.clone(), idle_timeout)
+            .with_deduplicator(service.dedup.clone());
+        actix_web::rt::spawn(keeper.run(service.dict.clone()));
+    }
+    let retention = housekeeping::Retention::new(runtime, service.metrics.clone());
+    actix_web::rt::spawn(retention.run(repo.clone()));
 
-    let Ok(dict) = state.dict.read() else {
-        return Err(error::ErrorInternalServerError(
-            "Dictionary is not responding.",
+    if let Some(addr) = setup.get_gelf_udp() {
+        let socket = tokio::net::UdpSocket::bind(addr).await?;
+        tracing::info!(addr = %addr, "receiving GELF messages over UDP");
+        let state = Arc::new(service.clone());
+        let rejected = service.metrics.clone();
+        actix_web::rt::spawn(gelf::receive(
+            socket,
+            move |message| {
+                let state = state.clone();
+                async move {
+                    let timestamp = message.timestamp().unwrap_or_else(now);
+                    if let Err(e) = save(&state, &message.log(), timestamp).await {
+                        tracing::warn!(error = %e, "cannot save GELF message");
+                    }
+                }
+            },
+            move |e| {
+                rejected.gelf_rejected_messages.add(1);
+                tracing::debug!(error = %e, "cannot read GELF datagram");
+            },
         ));
-    };
+    }
 
-    if let Some(prefix) = input.prefix.as_ref() {
-        logs = dict.filter_prefixed(prefix, logs);
+    if let Some(addr) = setup.get_forward_listen() {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        tracing::info!(addr = %addr, "receiving fluent forward events");
+        let state = Arc::new(service.clone());
+        actix_web::rt::spawn(forward::serve(listener, move |events| {
+            let state = state.clone();
+            async move {
+                let mut saved = true;
+                for event in events {
+                    let timestamp = event.timestamp().unwrap_or_else(now);
+                    if let Err(e) = save(&state, &event.log(), timestamp).await {
+                        tracing::warn!(error = %e, "cannot save fluent forward event");
+                        saved = false;
+                    }
+                }
+                saved
+            }
+        }));
     }
 
-    if let Some(words) = input.words.as_ref() {
-        logs = dict.filter_word(words, logs);
+    #[cfg(feature = "kafka")]
+    if let Some(source) = setup.get_kafka() {
+        let consumer = kafka::subscribe(source).map_err(std::io::Error::other)?;
+        tracing::info!(topics = ?source.topics, "consuming Kafka topics");
+        // Messages skip the write queue, so their offsets are committed only once they are in the repository.
+        let state = Arc::new(ServerActor {
+            queue: None,
+            ..service.clone()
+        });
+        actix_web::rt::spawn(kafka::consume(consumer, move |log, timestamp| {
+            let state = state.clone();
+            async move {
+                match save(&state, &log, timestamp.unwrap_or_else(now)).await {
+                    Ok(_) => kafka::Done::Committed,
+                    Err(e) if actix_web::ResponseError::status_code(&e).is_client_error() => {
+                        tracing::warn!(error = %e, "skipping Kafka message that cannot be saved");
+                        kafka::Done::Committed
+                    }
+                    Err(e) => {
+                        tracing::warn!(error = %e, "cannot save Kafka message, retrying");
+                        kafka::Done::Retry
+                    }
+                }
+            }
+        }));
     }
 
-    let mut output = LogsOutput { logs: Vec::new() };
-    for log in logs.iter() {
-        output.logs.push(dict.deserialize(&log));
+    if let Command::Journald { cursor, unit } = &command {
+        let state = Arc::new(service.clone());
+        actix_web::rt::spawn(journald::follow(
+            journald::Checkpoint::new(cursor),
+            unit.clone(),
+            move |entry| {
+                let state = state.clone();
+                async move {
+                    let Some(log) = entry.log() else {
+                        return true;
+                    };
+                    let timestamp = entry.timestamp().unwrap_or_else(now);
+                    match save(&state, &log, timestamp).await {
+                        Ok(_) => true,
+                        Err(e) => {
+                            tracing::warn!(error = %e, "cannot save journal entry");
+                            false
+                        }
+                    }
+                }
+            },
+        ));
     }
 
-    Ok(Json(output))
-}
+    if let Command::Tail { path, positions } = command {
+        let state = Arc::new(service.clone());
+        actix_web::rt::spawn(tail::follow(
+            path,
+            tail::Positions::new(positions),
+            move |log| {
+                let state = state.clone();
+                async move {
+                    match save(&state, &log, now()).await {
+                        Ok(_) => true,
+                        Err(e) => {
+                            tracing::warn!(error = %e, "cannot save tailed line");
+                            false
+                        }
+                    }
+                }
+            },
+        ));
+    }
 
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    let args: Vec<String> = env::args().collect();
-    let setup = match args.len() {
-        0 | 1 => settings::Setup::default(),
-        _ => settings::Setup::from_file(&args[1])?,
-    };
+    let listen = setup.get_listen();
+    let admin_listen = setup.get_admin_listen().cloned();
+    let admin_on_public = admin_listen.is_none();
+    let admin_token: Option<Arc<str>> = setup.get_admin_token().map(Arc::from);
+    if admin_on_public && admin_token.is_none() {
+        tracing::warn!("admin_token is not set, admin endpoints but health are not served without admin_listen");
+    }
 
-    let Ok(repo) = Repository::new(&setup).await else {
-        return Err(std::io::Error::new::<String>(
-            std::io::ErrorKind::NotConnected,
-            "repository is not responding".to_string(),
-        ));
-    };
+    for addr in listen.iter() {
+        tracing::info!(addr = %addr, "starting scribe server");
+    }
 
-    if let Err(e) = repo.migrate().await {
-        return Err(std::io::Error::new::<String>(
-            std::io::ErrorKind::NotConnected,
-            e.to_string(),
-        ));
+    let public = {
+        let service = service.clone();
+        let admin_token = admin_token.clone();
+        let max_body_bytes = setup.get_max_body_bytes();
+        let compress_responses = setup.get_compress_responses();
+        let public = HttpServer::new(move || {
+            let activity = service.activity.clone();
+            App::new()
+                .app_data(web::Data::new(service.clone()))
+                .app_data(
+                    web::JsonConfig::default()
+                        .limit(max_body_bytes)
+                        .error_handler(api::json_error),
+                )
+                .app_data(web::PayloadConfig::new(max_body_bytes))
+                .app_data(web::QueryConfig::default().error_handler(api::query_error))
+                .app_data(web::PathConfig::default().error_handler(api::path_error))
+                .wrap_fn(methods::handle)
+                .wrap(NormalizePath::new(TrailingSlash::Trim))
+                .wrap_fn(move |req, srv| {
+                    activity.touch();
+                    srv.call(req)
+                })
+                .wrap_fn(logging::access)
+                .configure(|cfg| {
+                    if admin_on_public {
+                        admin_routes(cfg, admin_token.clone(), false);
+                    }
+                })
+                .configure(|cfg| public_routes(cfg, compress_responses))
+                .default_service(web::to(not_found))
+        })
+        .client_request_timeout(setup.get_client_request_timeout())
+        .keep_alive(match setup.get_keep_alive() {
+            Some(keep_alive) => KeepAlive::Timeout(keep_alive),
+            None => KeepAlive::Disabled,
+        });
+        let public = match setup.get_workers() {
+            Some(workers) => public.workers(workers),
+            None => public,
+        };
+        let public = match setup.get_max_connections() {
+            Some(max) => public.max_connections(max),
+            None => public,
+        };
+        let mut public = public;
+        for addr in listen.iter() {
+            public = match addr {
+                settings::Listen::Tcp(addr) => public.listen(tcp_listener(addr, &listen)?)?,
+                settings::Listen::Unix(path) => public.bind_uds(path)?,
+            };
+        }
+        public.run()
     };
 
-    let service = ServerActor {
-        version: VERSION.to_string(),
-        repo: repo.clone(),
-        dict: Box::new(Arc::new(RwLock::new(dictionary::Module::new(
-            trie::Node::new(),
-        )))),
+    let result = match admin_listen {
+        Some(listen) => {
+            let service = service.clone();
+            let admin = HttpServer::new(move || {
+                App::new()
+                    .app_data(web::Data::new(service.clone()))
+                    .app_data(web::JsonConfig::default().error_handler(api::json_error))
+                    .wrap_fn(methods::handle)
+                    .wrap(NormalizePath::new(TrailingSlash::Trim))
+                    .wrap_fn(logging::access)
+                    .configure(|cfg| admin_routes(cfg, admin_token.clone(), true))
+                    .default_service(web::to(not_found))
+            })
+            .workers(1);
+            let admin = match &listen {
+                settings::Listen::Tcp(addr) => admin.bind(addr)?,
+                settings::Listen::Unix(path) => admin.bind_uds(path)?,
+            };
+            tracing::info!(addr = %listen, "starting scribe admin server");
+            tokio::try_join!(public, admin.run()).map(|_| ())
+        }
+        None => public.await,
     };
+    if let Err(e) = result {
+        tracing::error!(error = %e, "cannot run scribe server");
+    }
 
-    println!("\nStarting scribe server at [ {} ]\n", setup.get_addr());
-
-    HttpServer::new(move || {
-        App::new()
-            .app_data(web::Data::new(service.clone()))
-            .service(version)
-            .service(save_log)
-            .service(read_logs)
-    })
-    .bind((setup.get_ip(), setup.get_port()))?
-    .run()
-    .await
-    .unwrap_or_else(|e| println!("\nCannot run scribe server due to: {}\n", e));
+    tracing::info!("stopping scribe server");
 
-    println!("\nStopping the scribe server.\n");
+    if let (Some(queue), Some(flushing)) = (queue, flushing) {
+        queue.stop();
+        let _ = flushing.await;
+    }
+    if let Err(e) = save_trie(&setup, &dict) {
+        tracing::warn!(error = %e, "cannot save trie file");
+    }
 
     repo.close().await;
 
-    println!("All connections closed.\n");
+    tracing::info!("all connections closed");
+
+    if let Some(traces) = traces {
+        traces.shutdown();
+    }
 
     Ok(())
 }
@@ -0,0 +1,147 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderValue, ALLOW};
+use actix_web::http::{Method, StatusCode};
+use actix_web::Error;
+use std::future::Future;
+
+/// Serves HEAD on every GET route and answers OPTIONS with the methods allowed on a path.
+/// HEAD is routed as GET, the HTTP codec has already noted the original method and sends headers only.
+/// OPTIONS turns the `405 Method Not Allowed` of a matched resource in to `204 No Content`.
+/// Every Allow header is completed with HEAD and OPTIONS.
+///
+/// Routes have to be registered with method routes on a resource (`web::resource(..).route(..)`),
+/// as only then actix answers not matching methods with 405 and the Allow header.
+///
+pub fn handle<S, B>(
+    mut req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse<BoxBody>, Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    let method = req.method().clone();
+    if method == Method::HEAD {
+        req.head_mut().method = Method::GET;
+    }
+    let fut = srv.call(req);
+
+    async move {
+        let mut res = fut.await?.map_into_boxed_body();
+
+        if res.status() == StatusCode::METHOD_NOT_ALLOWED {
+            if let Some(allow) = complete_allow(res.headers().get(ALLOW)) {
+                res.headers_mut().insert(ALLOW, allow);
+            }
+            if method == Method::OPTIONS {
+                *res.response_mut().status_mut() = StatusCode::NO_CONTENT;
+            }
+        }
+
+        Ok(res)
+    }
+}
+
+fn complete_allow(allow: Option<&HeaderValue>) -> Option<HeaderValue> {
+    let mut methods: Vec<&str> = allow?
+        .to_str()
+        .ok()?
+        .split(',')
+        .map(str::trim)
+        .filter(|m| !m.is_empty())
+        .collect();
+    if methods.contains(&Method::GET.as_str()) && !methods.contains(&Method::HEAD.as_str()) {
+        methods.push(Method::HEAD.as_str());
+    }
+    if !methods.contains(&Method::OPTIONS.as_str()) {
+        methods.push(Method::OPTIONS.as_str());
+    }
+
+    HeaderValue::from_str(&methods.join(", ")).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::middleware::{NormalizePath, TrailingSlash};
+    use actix_web::{test, web, App, HttpResponse};
+
+    async fn hello() -> HttpResponse {
+        HttpResponse::Ok().body("hello")
+    }
+
+    macro_rules! app {
+        () => {
+            test::init_service(
+                App::new()
+                    .wrap_fn(handle)
+                    .wrap(NormalizePath::new(TrailingSlash::Trim))
+                    .service(web::resource("/hello").route(web::get().to(hello)))
+                    .service(web::resource("/save").route(web::post().to(hello))),
+            )
+            .await
+        };
+    }
+
+    #[actix_web::test]
+    async fn on_head_request_should_be_served_by_get_route() {
+        let app = app!();
+
+        let req = test::TestRequest::with_uri("/hello")
+            .method(Method::HEAD)
+            .to_request();
+        let res = test::call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn on_options_request_should_respond_with_allowed_methods() {
+        let app = app!();
+
+        let req = test::TestRequest::with_uri("/hello")
+            .method(Method::OPTIONS)
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(res.headers().get(ALLOW).unwrap(), "GET, HEAD, OPTIONS");
+
+        let req = test::TestRequest::with_uri("/save")
+            .method(Method::OPTIONS)
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(res.headers().get(ALLOW).unwrap(), "POST, OPTIONS");
+    }
+
+    #[actix_web::test]
+    async fn on_not_allowed_method_should_respond_with_complete_allow_header() {
+        let app = app!();
+
+        let req = test::TestRequest::with_uri("/save")
+            .method(Method::HEAD)
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(res.headers().get(ALLOW).unwrap(), "POST, OPTIONS");
+
+        let req = test::TestRequest::with_uri("/missing")
+            .method(Method::OPTIONS)
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn on_trailing_slash_should_match_route_without_it() {
+        let app = app!();
+
+        let req = test::TestRequest::with_uri("/hello/").to_request();
+        let res = test::call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(test::read_body(res).await, "hello");
+    }
+}
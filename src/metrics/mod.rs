@@ -0,0 +1,338 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Upper bounds of histogram buckets in seconds, from a hundred microseconds to ten seconds.
+const BUCKETS: [f64; 15] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+    10.0,
+];
+
+/// Counter is a metric that only goes up.
+///
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    /// Increases the counter by n.
+    ///
+    #[inline(always)]
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Returns the current value.
+    ///
+    #[inline(always)]
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Gauge is a metric that goes up and down.
+///
+#[derive(Debug, Default)]
+pub struct Gauge(AtomicI64);
+
+impl Gauge {
+    /// Changes the gauge by n, decreasing it for negative n.
+    ///
+    #[inline(always)]
+    pub fn add(&self, n: i64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Returns the current value.
+    ///
+    #[inline(always)]
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Histogram counts observed durations in buckets of BUCKETS, together with their sum.
+///
+#[derive(Debug, Default)]
+pub struct Histogram {
+    buckets: [AtomicU64; BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    /// Counts the duration in the first bucket it fits in, durations longer than all buckets are only counted.
+    ///
+    #[inline(always)]
+    pub fn observe(&self, d: Duration) {
+        let secs = d.as_secs_f64();
+        if let Some(i) = BUCKETS.iter().position(|le| secs <= *le) {
+            self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(d.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Runs f, observing how long it took.
+    ///
+    #[inline(always)]
+    pub fn time<T>(&self, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let out = f();
+        self.observe(start.elapsed());
+        out
+    }
+
+    /// Returns the number of observed durations.
+    ///
+    #[inline(always)]
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Writes the cumulative buckets, the sum and the count of the histogram named name with the labels.
+    ///
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (le, bucket) in BUCKETS.iter().zip(self.buckets.iter()) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{name}_bucket{{{labels},le=\"{le}\"}} {cumulative}");
+        }
+        let count = self.count();
+        let _ = writeln!(out, "{name}_bucket{{{labels},le=\"+Inf\"}} {count}");
+        let _ = writeln!(
+            out,
+            "{name}_sum{{{labels}}} {}",
+            self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0
+        );
+        let _ = writeln!(out, "{name}_count{{{labels}}} {count}");
+    }
+}
+
+/// Operation holds the latency and the errors of an operation of a repository.
+///
+#[derive(Debug, Default)]
+pub struct Operation {
+    pub latency: Histogram,
+    pub errors: Counter,
+}
+
+/// Stages holds the latency of each stage of saving and reading logs, so a slow request is told
+/// to wait for the dictionary lock, the dictionary itself or the database.
+///
+#[derive(Debug, Default)]
+pub struct Stages {
+    /// Waiting for the dictionary lock.
+    pub lock_wait: Histogram,
+    /// Splitting saved logs in to tokens of the dictionary.
+    pub tokenize: Histogram,
+    /// Looking up tokens of the words and prefixes of a read in the dictionary.
+    pub lookup: Histogram,
+    /// Scanning fetched buffers for the tokens of a read.
+    pub scan: Histogram,
+    /// Fetching logs of a read from the repository.
+    pub fetch: Histogram,
+    /// Turning tokens of read logs back in to words of the response.
+    pub deserialize: Histogram,
+}
+
+/// Metrics holds counters and gauges of the server, rendered in the Prometheus text format.
+///
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub retention_runs: Counter,
+    pub retention_failures: Counter,
+    pub retention_reclaimed_logs: Counter,
+    pub dedup_repeated_logs: Counter,
+    pub write_queue_depth: Gauge,
+    pub write_queue_rejected_logs: Counter,
+    pub write_queue_failures: Counter,
+    pub gelf_rejected_messages: Counter,
+    pub stages: Stages,
+    operations: Mutex<Vec<(String, &'static str, Arc<Operation>)>>,
+}
+
+impl Metrics {
+    /// Returns metrics of the operation of the repository backend, registering them on the first call,
+    /// so callers keep them at hand instead of looking them up on every operation.
+    ///
+    pub fn operation(&self, backend: &str, operation: &'static str) -> Arc<Operation> {
+        let Ok(mut operations) = self.operations.lock() else {
+            return Arc::new(Operation::default());
+        };
+        if let Some((_, _, o)) = operations
+            .iter()
+            .find(|(b, op, _)| b == backend && *op == operation)
+        {
+            return o.clone();
+        }
+        let o = Arc::new(Operation::default());
+        operations.push((backend.to_string(), operation, o.clone()));
+        o
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    ///
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for (name, help, counter) in [
+            (
+                "scribe_retention_runs_total",
+                "Runs of the retention expiry.",
+                &self.retention_runs,
+            ),
+            (
+                "scribe_retention_failures_total",
+                "Runs of the retention expiry that failed.",
+                &self.retention_failures,
+            ),
+            (
+                "scribe_retention_reclaimed_logs_total",
+                "Logs deleted for being older than the retention.",
+                &self.retention_reclaimed_logs,
+            ),
+            (
+                "scribe_dedup_repeated_logs_total",
+                "Logs identical to one saved within the dedup window.",
+                &self.dedup_repeated_logs,
+            ),
+            (
+                "scribe_write_queue_rejected_logs_total",
+                "Logs refused for the write queue being full.",
+                &self.write_queue_rejected_logs,
+            ),
+            (
+                "scribe_write_queue_failures_total",
+                "Flushes of the write queue that failed and are retried.",
+                &self.write_queue_failures,
+            ),
+            (
+                "scribe_gelf_rejected_messages_total",
+                "GELF datagrams that cannot be read or joined in to a message.",
+                &self.gelf_rejected_messages,
+            ),
+        ] {
+            let _ = write!(
+                out,
+                "# HELP {name} {help}\n# TYPE {name} counter\n{name} {}\n",
+                counter.get()
+            );
+        }
+        let name = "scribe_write_queue_depth";
+        let _ = write!(
+            out,
+            "# HELP {name} Logs waiting in the write queue.\n# TYPE {name} gauge\n{name} {}\n",
+            self.write_queue_depth.get()
+        );
+        let name = "scribe_stage_seconds";
+        let _ = write!(
+            out,
+            "# HELP {name} Duration of stages of saving and reading logs.\n# TYPE {name} histogram\n"
+        );
+        for (stage, histogram) in [
+            ("lock_wait", &self.stages.lock_wait),
+            ("tokenize", &self.stages.tokenize),
+            ("lookup", &self.stages.lookup),
+            ("scan", &self.stages.scan),
+            ("fetch", &self.stages.fetch),
+            ("deserialize", &self.stages.deserialize),
+        ] {
+            histogram.render(&mut out, name, &format!("stage=\"{}\"", stage));
+        }
+        let Ok(operations) = self.operations.lock() else {
+            return out;
+        };
+        if operations.is_empty() {
+            return out;
+        }
+        let name = "scribe_repository_operation_seconds";
+        let _ = write!(
+            out,
+            "# HELP {name} Duration of repository operations.\n# TYPE {name} histogram\n"
+        );
+        for (backend, operation, o) in operations.iter() {
+            let labels = format!("backend=\"{}\",operation=\"{}\"", backend, operation);
+            o.latency.render(&mut out, name, &labels);
+        }
+        let name = "scribe_repository_errors_total";
+        let _ = write!(
+            out,
+            "# HELP {name} Repository operations that failed.\n# TYPE {name} counter\n"
+        );
+        for (backend, operation, o) in operations.iter() {
+            let _ = writeln!(
+                out,
+                "{name}{{backend=\"{}\",operation=\"{}\"}} {}",
+                backend,
+                operation,
+                o.errors.get()
+            );
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn on_render_should_write_counters_in_prometheus_text_format() {
+        let m = Metrics::default();
+        m.retention_reclaimed_logs.add(40);
+        m.retention_reclaimed_logs.add(2);
+
+        let out = m.render();
+        assert!(out.contains("# TYPE scribe_retention_reclaimed_logs_total counter\n"));
+        assert!(out.contains("\nscribe_retention_reclaimed_logs_total 42\n"));
+        assert!(out.contains("\nscribe_retention_runs_total 0\n"));
+
+        m.write_queue_depth.add(3);
+        m.write_queue_depth.add(-1);
+        let out = m.render();
+        assert!(out.contains("# TYPE scribe_write_queue_depth gauge\nscribe_write_queue_depth 2\n"));
+    }
+
+    #[test]
+    fn on_render_should_write_operation_histograms_with_cumulative_buckets() {
+        let m = Metrics::default();
+        assert!(!m.render().contains("scribe_repository_operation_seconds"));
+
+        let insert = m.operation("SQLite", "insert");
+        insert.latency.observe(Duration::from_micros(500));
+        insert.latency.observe(Duration::from_millis(20));
+        insert.latency.observe(Duration::from_secs(60));
+        m.operation("SQLite", "insert").errors.add(1);
+
+        let out = m.render();
+        let labels = "backend=\"SQLite\",operation=\"insert\"";
+        assert!(out.contains("# TYPE scribe_repository_operation_seconds histogram\n"));
+        assert!(out.contains(&format!(
+            "scribe_repository_operation_seconds_bucket{{{labels},le=\"0.001\"}} 1\n"
+        )));
+        assert!(out.contains(&format!(
+            "scribe_repository_operation_seconds_bucket{{{labels},le=\"0.025\"}} 2\n"
+        )));
+        assert!(out.contains(&format!(
+            "scribe_repository_operation_seconds_bucket{{{labels},le=\"+Inf\"}} 3\n"
+        )));
+        assert!(out.contains(&format!(
+            "scribe_repository_operation_seconds_count{{{labels}}} 3\n"
+        )));
+        assert!(out.contains(&format!("scribe_repository_errors_total{{{labels}}} 1\n")));
+    }
+
+    #[test]
+    fn on_render_should_write_stage_histograms_labeled_by_stage() {
+        let m = Metrics::default();
+        m.stages.lock_wait.observe(Duration::from_micros(50));
+        assert_eq!(m.stages.scan.time(|| 7), 7);
+
+        let out = m.render();
+        assert!(out.contains("# TYPE scribe_stage_seconds histogram\n"));
+        assert!(out.contains("scribe_stage_seconds_bucket{stage=\"lock_wait\",le=\"0.0001\"} 1\n"));
+        assert!(out.contains("scribe_stage_seconds_count{stage=\"scan\"} 1\n"));
+        assert!(out.contains("scribe_stage_seconds_count{stage=\"fetch\"} 0\n"));
+    }
+}
@@ -0,0 +1,234 @@
+use crate::metrics::Metrics;
+use crate::repository::interface::RepositoryProvider;
+use scribe::dictionary::Module;
+use scribe::token::Token;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::{mpsc, Notify};
+use tokio::time::{interval, MissedTickBehavior};
+
+/// WriteQueue holds saved logs in memory until the Flusher inserts them in batches,
+/// so saving a log does not wait for the repository.
+/// The queue is bounded, once the repository falls behind and it fills up logs are refused.
+///
+#[derive(Debug, Clone)]
+pub struct WriteQueue {
+    sender: mpsc::Sender<(i64, Vec<Token>)>,
+    stop: Arc<Notify>,
+    metrics: Arc<Metrics>,
+}
+
+/// Flusher drains the WriteQueue to the repository every interval.
+/// A batch that fails to insert is retried on the next interval before more logs are taken.
+///
+pub struct Flusher<R: RepositoryProvider> {
+    receiver: mpsc::Receiver<(i64, Vec<Token>)>,
+    stop: Arc<Notify>,
+    metrics: Arc<Metrics>,
+    repo: R,
+    dict: Option<Arc<RwLock<Module>>>,
+    size: usize,
+    interval: Duration,
+}
+
+/// Creates a WriteQueue holding up to size logs and the Flusher inserting them to the repository.
+///
+pub fn new<R: RepositoryProvider>(
+    size: usize,
+    interval: Duration,
+    repo: R,
+    metrics: Arc<Metrics>,
+) -> (WriteQueue, Flusher<R>) {
+    let (sender, receiver) = mpsc::channel(size);
+    let stop = Arc::new(Notify::new());
+    (
+        WriteQueue {
+            sender,
+            stop: stop.clone(),
+            metrics: metrics.clone(),
+        },
+        Flusher {
+            receiver,
+            stop,
+            metrics,
+            repo,
+            dict: None,
+            size,
+            interval,
+        },
+    )
+}
+
+impl WriteQueue {
+    /// Queues the log saved at the timestamp in nanoseconds since the epoch.
+    /// Returns false if the queue is full or stopped.
+    ///
+    pub fn push(&self, timestamp: i64, input: Vec<Token>) -> bool {
+        self.metrics.write_queue_depth.add(1);
+        if self.sender.try_send((timestamp, input)).is_err() {
+            self.metrics.write_queue_depth.add(-1);
+            self.metrics.write_queue_rejected_logs.add(1);
+            return false;
+        }
+        true
+    }
+
+    /// Stops the queue, the Flusher inserts logs queued so far and returns.
+    ///
+    pub fn stop(&self) {
+        self.stop.notify_one();
+    }
+}
+
+impl<R: RepositoryProvider> Flusher<R> {
+    /// Sets the dictionary queued logs are serialized with, each batch is inserted together
+    /// with its words not saved yet, so no inserted log refers to a word that was never saved.
+    ///
+    pub fn with_dictionary(mut self, dict: Arc<RwLock<Module>>) -> Self {
+        self.dict = Some(dict);
+        self
+    }
+
+    #[inline(always)]
+    fn unsaved_words(&self) -> Vec<(String, Token)> {
+        match &self.dict {
+            Some(dict) => dict.read().map(|d| d.unsaved_words()).unwrap_or_default(),
+            None => Vec::new(),
+        }
+    }
+
+    #[inline(always)]
+    fn mark_saved(&self, words: &[(String, Token)]) {
+        let Some(dict) = &self.dict else {
+            return;
+        };
+        if let Ok(mut dict) = dict.write() {
+            dict.mark_saved(words);
+        }
+    }
+
+    /// Runs the flushing loop until the queue is stopped and drained.
+    /// Once stopped a failing batch is not retried, its logs are reported as lost.
+    ///
+    pub async fn run(mut self) {
+        let mut ticker = interval(self.interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut batch = Vec::with_capacity(self.size);
+        let mut stopped = false;
+        loop {
+            if !stopped {
+                tokio::select! {
+                    _ = ticker.tick() => (),
+                    _ = self.stop.notified() => stopped = true,
+                }
+            }
+            if stopped {
+                self.receiver.close();
+            }
+            if batch.is_empty() {
+                let received = tokio::select! {
+                    received = self.receiver.recv_many(&mut batch, self.size) => received,
+                    _ = self.stop.notified(), if !stopped => {
+                        stopped = true;
+                        self.receiver.close();
+                        self.receiver.recv_many(&mut batch, self.size).await
+                    }
+                };
+                if received == 0 {
+                    return;
+                }
+                self.metrics.write_queue_depth.add(-(received as i64));
+            }
+            let words = self.unsaved_words();
+            match self.repo.insert_logs_with_words(&batch, &words).await {
+                Ok(()) => {
+                    self.mark_saved(&words);
+                    batch.clear();
+                }
+                Err(e) if stopped => {
+                    tracing::error!(
+                        logs = batch.len(),
+                        error = %e,
+                        "cannot flush queued logs, dropping them"
+                    );
+                    batch.clear();
+                }
+                Err(e) => {
+                    self.metrics.write_queue_failures.add(1);
+                    tracing::warn!(error = %e, "cannot flush queued logs, retrying");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::sql::{DatabaseStorage, WarehouseSql};
+    use crate::repository::PoolConfig;
+
+    const INTERVAL: Duration = Duration::from_millis(10);
+
+    #[tokio::test]
+    async fn on_stop_should_flush_all_queued_logs_in_order() {
+        let repo = WarehouseSql::new(DatabaseStorage::Ram, &PoolConfig::default())
+            .await
+            .unwrap();
+        repo.migrate().await.unwrap();
+        let metrics = Arc::new(Metrics::default());
+        let (queue, flusher) = new(10, INTERVAL, repo.clone(), metrics.clone());
+        let flushing = tokio::spawn(flusher.run());
+
+        let logs: Vec<Vec<Token>> = (0..5u32).map(|i| vec![Token::from(i)]).collect();
+        for (i, log) in logs.iter().enumerate() {
+            assert!(queue.push(i as i64 + 1, log.clone()));
+        }
+        queue.stop();
+        flushing.await.unwrap();
+
+        let found = repo
+            .find_logs(&Duration::ZERO, &Duration::from_nanos(10))
+            .await
+            .unwrap();
+        assert_eq!(found.into_iter().map(|l| l.data).collect::<Vec<_>>(), logs);
+        assert_eq!(metrics.write_queue_depth.get(), 0);
+        assert!(!queue.push(6, vec![Token::from(6)]));
+    }
+
+    #[tokio::test]
+    async fn on_flush_should_save_unsaved_words_with_the_batch() {
+        let repo = WarehouseSql::new(DatabaseStorage::Ram, &PoolConfig::default())
+            .await
+            .unwrap();
+        repo.migrate().await.unwrap();
+        let dict = Arc::new(RwLock::new(Module::new(scribe::trie::Trie::new())));
+        let (queue, flusher) = new(10, INTERVAL, repo.clone(), Arc::new(Metrics::default()));
+        let flushing = tokio::spawn(flusher.with_dictionary(dict.clone()).run());
+
+        let log = dict.write().unwrap().serialize("saved with words").unwrap();
+        assert!(queue.push(1, log));
+        queue.stop();
+        flushing.await.unwrap();
+
+        assert!(dict.read().unwrap().unsaved_words().is_empty());
+        let saved = repo.find_words().await.unwrap();
+        let num = dict.read().unwrap().num("words").unwrap();
+        assert!(saved.contains(&("words".to_string(), num)));
+    }
+
+    #[tokio::test]
+    async fn on_push_to_full_queue_should_refuse_the_log() {
+        let repo = WarehouseSql::new(DatabaseStorage::Ram, &PoolConfig::default())
+            .await
+            .unwrap();
+        let metrics = Arc::new(Metrics::default());
+        let (queue, _flusher) = new(2, INTERVAL, repo, metrics.clone());
+
+        assert!(queue.push(1, vec![Token::from(1)]));
+        assert!(queue.push(2, vec![Token::from(2)]));
+        assert!(!queue.push(3, vec![Token::from(3)]));
+        assert_eq!(metrics.write_queue_depth.get(), 2);
+        assert_eq!(metrics.write_queue_rejected_logs.get(), 1);
+    }
+}
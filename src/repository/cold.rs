@@ -0,0 +1,524 @@
+use super::error::{RepositoryError, Result};
+use super::interface::{
+    paged, Log, LogId, LogsPage, LogsStream, Page, RepositoryProvider, STREAM_PAGE,
+};
+use super::segments::{
+    decode_records, entries_in, index_id, logs_page, parse_index, segment_name, PageWindow,
+    WarehouseSegments, INDEX_EXTENSION, LOG_EXTENSION,
+};
+use futures::TryStreamExt;
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use scribe::token::Token;
+use std::collections::BTreeSet;
+use std::io::ErrorKind;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+
+const S3_SCHEME: &str = "s3://";
+
+/// WarehouseCold serves access to segments split between a local directory and object storage.
+/// Logs are appended to local segments, once a segment is closed it is uploaded
+/// to the object storage and removed from the directory, so old logs take no local disc
+/// but are still found by time span queries reading both tiers.
+///
+#[derive(Debug, Clone)]
+pub struct WarehouseCold {
+    local: WarehouseSegments,
+    store: Arc<dyn ObjectStore>,
+    prefix: Path,
+    flushing: Arc<Mutex<()>>,
+    current: Arc<AtomicU64>,
+}
+
+impl WarehouseCold {
+    /// Creates a new Warehouse keeping open segments in the local tier and closed ones
+    /// in the S3 compatible bucket under the url of the form `s3://bucket/prefix`.
+    /// Credentials, region and endpoint are read from the `AWS_` environment variables,
+    /// for example `AWS_ENDPOINT` points to a MinIO server.
+    ///
+    pub async fn new(local: WarehouseSegments, url: &str) -> Result<Self> {
+        let Some(location) = url.strip_prefix(S3_SCHEME) else {
+            return Err(RepositoryError::InvalidInput(format!(
+                "expected {}bucket/prefix url, got: {}",
+                S3_SCHEME, url
+            )));
+        };
+        let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+        let store = match AmazonS3Builder::from_env().with_bucket_name(bucket).build() {
+            Ok(store) => store,
+            Err(e) => return Err(RepositoryError::NotConnected(format!("{}: {}", url, e))),
+        };
+        Ok(Self::with_store(local, Arc::new(store), Path::from(prefix)))
+    }
+
+    /// Creates a new Warehouse uploading closed segments to the store under the prefix.
+    ///
+    pub fn with_store(local: WarehouseSegments, store: Arc<dyn ObjectStore>, prefix: Path) -> Self {
+        Self {
+            local,
+            store,
+            prefix,
+            flushing: Arc::new(Mutex::new(())),
+            current: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    #[inline(always)]
+    fn remote_path(&self, id: u64, extension: &str) -> Path {
+        self.prefix.child(segment_name(id, extension))
+    }
+
+    /// Uploads closed segments to the store and removes them from the directory.
+    /// The log is uploaded before the index, as segments are listed by their indexes,
+    /// and the local index is removed first, so a segment is always found in one of the tiers.
+    ///
+    pub async fn flush(&self) -> Result<()> {
+        let _guard = self.flushing.lock().await;
+        for id in self.local.closed_ids()? {
+            for extension in [LOG_EXTENSION, INDEX_EXTENSION] {
+                let path = self.local.dir().join(segment_name(id, extension));
+                let data = tokio::fs::read(&path).await?;
+                if let Err(e) = self
+                    .store
+                    .put(&self.remote_path(id, extension), data.into())
+                    .await
+                {
+                    return Err(RepositoryError::query(
+                        format!("cannot upload segment: {}", segment_name(id, extension)),
+                        e,
+                    ));
+                }
+            }
+            for extension in [INDEX_EXTENSION, LOG_EXTENSION] {
+                tokio::fs::remove_file(self.local.dir().join(segment_name(id, extension))).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Uploads closed segments in the background once the clock enters a new segment.
+    ///
+    fn flush_on_new_segment(&self) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        let current = self.local.segment_of(now);
+        if self.current.swap(current, Ordering::AcqRel) < current {
+            let warehouse = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = warehouse.flush().await {
+                    tracing::error!(error = %e, "cannot flush segments");
+                }
+            });
+        }
+    }
+
+    /// Returns ids of segments uploaded to the store, ascending.
+    ///
+    async fn remote_ids(&self) -> Result<BTreeSet<u64>> {
+        let objects = match self
+            .store
+            .list(Some(&self.prefix))
+            .try_collect::<Vec<_>>()
+            .await
+        {
+            Ok(objects) => objects,
+            Err(e) => return Err(RepositoryError::query("cannot list segments", e)),
+        };
+        Ok(objects
+            .iter()
+            .filter_map(|o| o.location.filename().and_then(index_id))
+            .collect())
+    }
+
+    /// Reads logs of the uploaded segment in the time span, fetching only the bytes of the span.
+    ///
+    async fn read_remote(&self, id: u64, from: u64, to: u64) -> Result<Vec<Log>> {
+        let entries = self.read_remote_index(id).await?;
+        let (first, last) = entries_in(&entries, from, to);
+        self.read_remote_entries(id, &entries, first, last).await
+    }
+
+    /// Reads entries of the index of the uploaded segment.
+    ///
+    async fn read_remote_index(&self, id: u64) -> Result<Vec<(u64, u64)>> {
+        let index = self.remote_path(id, INDEX_EXTENSION);
+        let bytes = match self.store.get(&index).await {
+            Ok(object) => object.bytes().await,
+            Err(e) => Err(e),
+        };
+        match bytes {
+            Ok(bytes) => Ok(parse_index(&bytes)),
+            Err(e) => Err(RepositoryError::query("cannot read segment", e)),
+        }
+    }
+
+    /// Reads logs of the entries of the uploaded segment from the first up to the last, exclusive,
+    /// fetching only their bytes.
+    ///
+    async fn read_remote_entries(
+        &self,
+        id: u64,
+        entries: &[(u64, u64)],
+        first: usize,
+        last: usize,
+    ) -> Result<Vec<Log>> {
+        if first >= last {
+            return Ok(Vec::new());
+        }
+
+        let log = self.remote_path(id, LOG_EXTENSION);
+        let start = entries[first].1 as usize;
+        let end = match entries.get(last) {
+            Some((_, offset)) => *offset as usize,
+            None => match self.store.head(&log).await {
+                Ok(meta) => meta.size,
+                Err(e) => return Err(RepositoryError::query("cannot read segment", e)),
+            },
+        };
+        let bytes = match self.store.get_range(&log, start..end).await {
+            Ok(bytes) => bytes,
+            Err(e) => return Err(RepositoryError::query("cannot read segment", e)),
+        };
+        Ok(decode_records(id, &entries[first..last], &bytes)?)
+    }
+
+    /// Reads logs of the window from the local segment,
+    /// None if the segment is not stored in the directory as it was uploaded.
+    ///
+    async fn read_local_page(
+        &self,
+        id: u64,
+        from: u64,
+        to: u64,
+        window: &mut PageWindow,
+    ) -> Result<Option<Vec<Log>>> {
+        let local = self.local.clone();
+        let mut narrowed = *window;
+        let read = move || -> std::io::Result<(Vec<Log>, PageWindow)> {
+            let entries = local.read_segment_index(id)?;
+            let (first, last) = narrowed.entries(id, &entries, from, to);
+            Ok((local.read_entries(id, &entries, first, last)?, narrowed))
+        };
+        match tokio::task::spawn_blocking(read).await {
+            Ok(Err(e)) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Ok(Err(e)) => Err(e.into()),
+            Ok(Ok((logs, narrowed))) => {
+                *window = narrowed;
+                Ok(Some(logs))
+            }
+            Err(e) => Err(RepositoryError::query("cannot read logs", e)),
+        }
+    }
+}
+
+impl RepositoryProvider for WarehouseCold {
+    /// Prepares the local tier and uploads segments closed while the server was down.
+    ///
+    async fn migrate(&self) -> Result<()> {
+        self.local.migrate().await?;
+        self.flush().await
+    }
+
+    /// Appends single log to the local tier,
+    /// uploading closed segments in the background once the log opens a new segment.
+    ///
+    async fn insert_log_at(&self, input: &[Token], timestamp: i64) -> Result<LogId> {
+        self.insert_log_with_words(input, timestamp, &[]).await
+    }
+
+    /// Appends single log and the words to the local tier in the manner of `insert_log_at`.
+    ///
+    async fn insert_log_with_words(
+        &self,
+        input: &[Token],
+        timestamp: i64,
+        words: &[(String, Token)],
+    ) -> Result<LogId> {
+        let id = self
+            .local
+            .insert_log_with_words(input, timestamp, words)
+            .await?;
+        self.flush_on_new_segment();
+        Ok(id)
+    }
+
+    /// Appends logs and the words to the local tier in the manner of `insert_log_at`.
+    ///
+    async fn insert_logs_with_words(
+        &self,
+        inputs: &[(i64, Vec<Token>)],
+        words: &[(String, Token)],
+    ) -> Result<()> {
+        self.local.insert_logs_with_words(inputs, words).await?;
+        self.flush_on_new_segment();
+        Ok(())
+    }
+
+    /// Gets words of the local tier, the words file is never uploaded
+    /// as the whole dictionary is read on start.
+    ///
+    async fn find_words(&self) -> Result<Vec<(String, Token)>> {
+        self.local.find_words().await
+    }
+
+    /// Gets data in time span from both tiers, a segment is read from the directory
+    /// and from the store if it is uploaded meanwhile.
+    ///
+    async fn find_logs(&self, from: &Duration, to: &Duration) -> Result<Vec<Log>> {
+        let (from, to) = (from.as_nanos() as u64, to.as_nanos() as u64);
+        if from > to {
+            return Ok(Vec::new());
+        }
+        let (first, last) = (self.local.segment_of(from), self.local.segment_of(to));
+        let mut ids = self.remote_ids().await?;
+        ids.extend(self.local.segment_ids()?);
+
+        let mut result = Vec::new();
+        for id in ids.range(first..=last) {
+            let local = self.local.clone();
+            let id = *id;
+            let logs =
+                match tokio::task::spawn_blocking(move || local.read_segment(id, from, to)).await {
+                    Ok(Err(e)) if e.kind() == ErrorKind::NotFound => {
+                        self.read_remote(id, from, to).await?
+                    }
+                    Ok(logs) => logs?,
+                    Err(e) => return Err(RepositoryError::query("cannot read logs", e)),
+                };
+            result.extend(logs);
+        }
+        Ok(result)
+    }
+
+    /// Gets a page of logs in time span from both tiers, fetching only records of the page,
+    /// logs skipped by offset are counted by the indexes. The cursor is the id of the last log of the page.
+    ///
+    fn find_logs_stream(&self, from: &Duration, to: &Duration) -> LogsStream<'_> {
+        let (from, to) = (*from, *to);
+        paged(move |page| async move { self.find_logs_page(&from, &to, STREAM_PAGE, &page).await })
+    }
+
+    async fn find_logs_page(
+        &self,
+        from: &Duration,
+        to: &Duration,
+        limit: usize,
+        page: &Page,
+    ) -> Result<LogsPage> {
+        let mut window = PageWindow::new(limit, page)?;
+        let (from, to) = (from.as_nanos() as u64, to.as_nanos() as u64);
+        if from > to {
+            return Ok(logs_page(Vec::new(), limit));
+        }
+        let (first, last) = (self.local.segment_of(from), self.local.segment_of(to));
+        let mut ids = self.remote_ids().await?;
+        ids.extend(self.local.segment_ids()?);
+
+        let mut result = Vec::new();
+        for id in ids.range(first..=last) {
+            if window.passes(*id) {
+                continue;
+            }
+            if let Some(logs) = self.read_local_page(*id, from, to, &mut window).await? {
+                result.extend(logs);
+                continue;
+            }
+            let entries = self.read_remote_index(*id).await?;
+            let (first, last) = window.entries(*id, &entries, from, to);
+            result.extend(self.read_remote_entries(*id, &entries, first, last).await?);
+        }
+        Ok(logs_page(result, limit))
+    }
+
+    async fn close(&self) {
+        self.local.close().await;
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
+mod tests {
+    use super::*;
+    use object_store::memory::InMemory;
+
+    fn now() -> Duration {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+    }
+
+    fn now_nanos() -> i64 {
+        now().as_nanos() as i64
+    }
+
+    #[tokio::test]
+    async fn on_flush_should_move_closed_segments_to_the_store_and_find_logs_in_both_tiers() {
+        let dir = std::env::temp_dir().join("scribe_cold_tiers");
+        let _ = std::fs::remove_dir_all(&dir);
+        let Ok(local) = WarehouseSegments::new(&dir, Duration::from_millis(5)).await else {
+            assert!(false);
+            return;
+        };
+        let store = Arc::new(InMemory::new());
+        let warehouse = WarehouseCold::with_store(local, store.clone(), Path::from("logs"));
+        let Ok(()) = warehouse.migrate().await else {
+            assert!(false);
+            return;
+        };
+
+        let from = now();
+        let mut logs = Vec::new();
+        for i in 0..10u32 {
+            let log = vec![Token::from(i)];
+            let Ok(_) = warehouse.local.insert_log_at(&log, now_nanos()).await else {
+                assert!(false);
+                return;
+            };
+            logs.push(log);
+            tokio::time::sleep(Duration::from_millis(2)).await;
+        }
+        let to = now();
+
+        let Ok(()) = warehouse.flush().await else {
+            assert!(false);
+            return;
+        };
+        let Ok(uploaded) = warehouse.remote_ids().await else {
+            assert!(false);
+            return;
+        };
+        assert!(!uploaded.is_empty());
+        let local = warehouse.local.segment_ids().unwrap();
+        assert!(local.iter().all(|id| !uploaded.contains(id)));
+
+        let Ok(result) = warehouse.find_logs(&from, &to).await else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(result.into_iter().map(|l| l.data).collect::<Vec<_>>(), logs);
+
+        let Ok(result) = warehouse
+            .find_logs(&(from + Duration::from_millis(7)), &to)
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        assert!(!result.is_empty() && result.len() < logs.len());
+        let result: Vec<_> = result.into_iter().map(|l| l.data).collect();
+        assert_eq!(result[..], logs[logs.len() - result.len()..]);
+
+        warehouse.close().await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn on_find_logs_page_should_page_across_both_tiers_by_offset_and_by_cursor() {
+        let dir = std::env::temp_dir().join("scribe_cold_page");
+        let _ = std::fs::remove_dir_all(&dir);
+        let Ok(local) = WarehouseSegments::new(&dir, Duration::from_millis(5)).await else {
+            assert!(false);
+            return;
+        };
+        let store = Arc::new(InMemory::new());
+        let warehouse = WarehouseCold::with_store(local, store, Path::from("logs"));
+        let Ok(()) = warehouse.migrate().await else {
+            assert!(false);
+            return;
+        };
+
+        let mut logs: Vec<Vec<Token>> = (0..5u32).map(|i| vec![Token::from(i)]).collect();
+        for (i, log) in logs.iter().enumerate() {
+            let timestamp = Duration::from_millis(3 * i as u64).as_nanos() as i64;
+            let Ok(_) = warehouse.local.insert_log_at(log, timestamp).await else {
+                assert!(false);
+                return;
+            };
+        }
+        let Ok(()) = warehouse.flush().await else {
+            assert!(false);
+            return;
+        };
+        let recent = vec![Token::from(5)];
+        let Ok(_) = warehouse.local.insert_log_at(&recent, now_nanos()).await else {
+            assert!(false);
+            return;
+        };
+        logs.push(recent);
+        let (from, to) = (Duration::ZERO, now() + Duration::from_secs(1));
+
+        let Ok(page) = warehouse
+            .find_logs_page(&from, &to, 2, &Page::Offset(3))
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(
+            page.logs.into_iter().map(|l| l.data).collect::<Vec<_>>(),
+            logs[3..5]
+        );
+
+        let mut found = Vec::new();
+        let mut next = Page::Offset(0);
+        loop {
+            let Ok(page) = warehouse.find_logs_page(&from, &to, 2, &next).await else {
+                assert!(false);
+                return;
+            };
+            found.extend(page.logs.into_iter().map(|l| l.data));
+            match page.next {
+                Some(cursor) => next = Page::After(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(found, logs);
+
+        warehouse.close().await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn on_flush_should_keep_words_in_the_local_tier() {
+        let dir = std::env::temp_dir().join("scribe_cold_words");
+        let _ = std::fs::remove_dir_all(&dir);
+        let Ok(local) = WarehouseSegments::new(&dir, Duration::from_millis(5)).await else {
+            assert!(false);
+            return;
+        };
+        let warehouse =
+            WarehouseCold::with_store(local, Arc::new(InMemory::new()), Path::from("logs"));
+        let Ok(()) = warehouse.migrate().await else {
+            assert!(false);
+            return;
+        };
+        let words = [("first".to_string(), Token::from(1))];
+        let Ok(_) = warehouse
+            .insert_log_with_words(&[Token::from(1)], 1, &words)
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        let Ok(()) = warehouse.flush().await else {
+            assert!(false);
+            return;
+        };
+        assert!(!warehouse.remote_ids().await.unwrap().is_empty());
+
+        let Ok(found) = warehouse.find_words().await else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(found, words);
+
+        warehouse.close().await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,17 +1,129 @@
-pub const SQL_COMMANDS: [&str; 5] = [
-    r#"
+/// Migration is a versioned step of the schema, applied once and recorded in the schema_migrations table.
+/// Statements of migrations a database created before migrations were versioned has applied are idempotent,
+/// so such a database is migrated safely.
+///
+pub struct Migration {
+    pub version: i64,
+    pub statements: &'static [&'static str],
+}
+
+pub const SQL_CREATE_MIGRATIONS: &str = r#"
+    CREATE TABLE IF NOT EXISTS schema_migrations (
+      version INTEGER PRIMARY KEY NOT NULL,
+      applied_at INTEGER NOT NULL
+    );"#;
+
+pub const SQL_MIGRATIONS: [Migration; 4] = [
+    Migration {
+        version: 1,
+        statements: &[
+            r#"
     CREATE TABLE IF NOT EXISTS logs (
       id INTEGER PRIMARY KEY NOT NULL,
       timestamp INTEGER NOT NULL,
-      data BLOB NOT NULL
+      data BLOB NOT NULL,
+      occurrences INTEGER NOT NULL DEFAULT 1
     );"#,
-    r#"CREATE INDEX timestamp_index ON logs (timestamp);"#,
-    r#"
+            r#"CREATE INDEX IF NOT EXISTS timestamp_index ON logs (timestamp);"#,
+        ],
+    },
+    Migration {
+        version: 2,
+        statements: &[
+            r#"
     CREATE TABLE IF NOT EXISTS serializer (
       id INTEGER PRIMARY KEY NOT NULL,
       word TEXT NOT NULL UNIQUE,
       num INTEGER NOT NULL UNIQUE
     );"#,
-    r#"CREATE INDEX word_index ON serializer (word);"#,
-    r#"CREATE INDEX num_index ON serializer (num);"#,
+            r#"CREATE INDEX IF NOT EXISTS word_index ON serializer (word);"#,
+            r#"CREATE INDEX IF NOT EXISTS num_index ON serializer (num);"#,
+        ],
+    },
+    Migration {
+        version: 3,
+        statements: &[r#"ALTER TABLE logs ADD COLUMN compressed INTEGER NOT NULL DEFAULT 0;"#],
+    },
+    Migration {
+        version: 4,
+        statements: &[
+            r#"
+    CREATE TABLE IF NOT EXISTS log_tokens (
+      log_id INTEGER NOT NULL,
+      token INTEGER NOT NULL,
+      PRIMARY KEY (log_id, token)
+    ) WITHOUT ROWID;"#,
+            r#"CREATE INDEX IF NOT EXISTS log_tokens_token_index ON log_tokens (token, log_id);"#,
+        ],
+    },
+];
+
+/// Statements creating a partition of logs, with the table name in place of `{table}`.
+///
+pub const SQL_CREATE_PARTITION: [&str; 2] = [
+    r#"
+    CREATE TABLE IF NOT EXISTS "{table}" (
+      id INTEGER PRIMARY KEY NOT NULL,
+      timestamp INTEGER NOT NULL,
+      data BLOB NOT NULL,
+      occurrences INTEGER NOT NULL DEFAULT 1,
+      compressed INTEGER NOT NULL DEFAULT 0
+    );"#,
+    r#"CREATE INDEX IF NOT EXISTS "{table}_timestamp_index" ON "{table}" (timestamp);"#,
+];
+
+/// Statements creating the token index of a partition, with the table name of the index in place of `{table}`.
+///
+pub const SQL_CREATE_PARTITION_TOKENS: [&str; 2] = [
+    r#"
+    CREATE TABLE IF NOT EXISTS "{table}" (
+      log_id INTEGER NOT NULL,
+      token INTEGER NOT NULL,
+      PRIMARY KEY (log_id, token)
+    ) WITHOUT ROWID;"#,
+    r#"CREATE INDEX IF NOT EXISTS "{table}_token_index" ON "{table}" (token, log_id);"#,
+];
+
+pub const SQL_LIST_PARTITIONS: &str =
+    r#"SELECT name FROM sqlite_master WHERE type = 'table' AND name LIKE 'logs\_%' ESCAPE '\'"#;
+
+pub const MYSQL_CREATE_MIGRATIONS: &str = r#"
+    CREATE TABLE IF NOT EXISTS schema_migrations (
+      version BIGINT PRIMARY KEY NOT NULL,
+      applied_at BIGINT NOT NULL
+    );"#;
+
+/// Migrations of the MySQL and MariaDB schema.
+/// Indexes are declared with the tables, as MySQL has no CREATE INDEX IF NOT EXISTS.
+/// Words are compared byte by byte, as the dictionary keeps words differing only in case apart,
+/// and are unique by their hash, as MySQL cannot index whole TEXT columns.
+///
+pub const MYSQL_MIGRATIONS: [Migration; 3] = [
+    Migration {
+        version: 1,
+        statements: &[r#"
+    CREATE TABLE IF NOT EXISTS logs (
+      id BIGINT PRIMARY KEY NOT NULL AUTO_INCREMENT,
+      timestamp BIGINT NOT NULL,
+      data LONGBLOB NOT NULL,
+      occurrences BIGINT NOT NULL DEFAULT 1,
+      INDEX timestamp_index (timestamp)
+    );"#],
+    },
+    Migration {
+        version: 2,
+        statements: &[r#"
+    CREATE TABLE IF NOT EXISTS serializer (
+      id BIGINT PRIMARY KEY NOT NULL AUTO_INCREMENT,
+      word TEXT CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL,
+      num BIGINT NOT NULL UNIQUE
+    );"#],
+    },
+    Migration {
+        version: 3,
+        statements: &[r#"
+    ALTER TABLE serializer
+      ADD COLUMN word_hash BINARY(32) AS (UNHEX(SHA2(word, 256))) STORED,
+      ADD UNIQUE INDEX word_index (word_hash);"#],
+    },
 ];
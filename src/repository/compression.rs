@@ -0,0 +1,69 @@
+use super::error::{RepositoryError, Result};
+use scribe::token::Token;
+
+/// Level of zstd compression, a low level keeps inserts fast while repeated words still shrink well.
+const LEVEL: i32 = 3;
+
+/// Encodes tokens in to a blob, compressed with zstd if compress is set and it makes the blob smaller.
+/// Returns the blob with the flag it is stored with, true if the blob is compressed.
+///
+#[inline]
+pub fn pack(input: &[Token], compress: bool) -> Result<(Vec<u8>, bool)> {
+    let data = Token::encode(input);
+    if !compress {
+        return Ok((data, false));
+    }
+    let compressed = zstd::bulk::compress(&data, LEVEL)?;
+    if compressed.len() < data.len() {
+        return Ok((compressed, true));
+    }
+    Ok((data, false))
+}
+
+/// Decodes tokens from the blob stored with the compressed flag.
+///
+#[inline]
+pub fn unpack(data: &[u8], compressed: bool) -> Result<Vec<Token>> {
+    if !compressed {
+        return Ok(Token::decode(data)?);
+    }
+    match zstd::stream::decode_all(data) {
+        Ok(data) => Ok(Token::decode(&data)?),
+        Err(e) => Err(RepositoryError::Corrupt(format!(
+            "cannot decompress log: {}",
+            e
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn on_pack_should_compress_repetitive_logs_and_unpack_them() {
+        let input: Vec<Token> = (0..256).map(|i| Token::from(i % 8)).collect();
+
+        let (data, compressed) = pack(&input, true).unwrap();
+
+        assert!(compressed);
+        assert!(data.len() < Token::encode(&input).len());
+        assert_eq!(unpack(&data, compressed).unwrap(), input);
+    }
+
+    #[test]
+    fn on_pack_should_keep_blobs_compression_does_not_shrink() {
+        let input = [Token::from(1), Token::from(2)];
+
+        assert_eq!(pack(&input, true).unwrap(), (Token::encode(&input), false));
+        assert_eq!(pack(&input, false).unwrap(), (Token::encode(&input), false));
+    }
+
+    #[test]
+    fn on_unpack_should_reject_corrupt_compressed_blobs() {
+        assert!(matches!(
+            unpack(&[1, 2, 3, 4, 5], true),
+            Err(RepositoryError::Corrupt(_))
+        ));
+    }
+}
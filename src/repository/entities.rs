@@ -8,7 +8,7 @@ use sqlx::FromRow;
 #[derive(FromRow, Debug)]
 pub struct DictSql {
     pub word: String,
-    pub num: i32,
+    pub num: i64,
 }
 
 /// Dict contains dictionary mapping.
@@ -18,7 +18,7 @@ pub struct DictMongo {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<ObjectId>,
     pub word: String,
-    pub num: i32,
+    pub num: i64,
 }
 
 /// Log contains log data in binary format.
@@ -30,6 +30,25 @@ pub struct LogMongo {
     pub id: Option<ObjectId>,
     pub timestamp: DateTime,
     pub data: Vec<u8>,
+    #[serde(default = "default_occurrences")]
+    pub occurrences: i64,
+    #[serde(default)]
+    pub compressed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<Vec<i64>>,
+}
+
+/// Migration records a migration step applied to the database under its version.
+///
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MigrationMongo {
+    #[serde(rename = "_id")]
+    pub version: i64,
+    pub applied_at: DateTime,
+}
+
+fn default_occurrences() -> i64 {
+    1
 }
 
 /// Log contains log data in binary format.
@@ -40,4 +59,6 @@ pub struct LogSql {
     pub id: i64,
     pub timestamp: i64,
     pub data: Vec<u8>,
+    #[sqlx(default)]
+    pub compressed: bool,
 }
@@ -0,0 +1,153 @@
+use std::error::Error as StdError;
+use std::fmt::{self, Display};
+use std::io;
+
+/// Result of a repository operation.
+///
+pub type Result<T> = std::result::Result<T, RepositoryError>;
+
+/// Source is the error of the driver a failed query is reported with.
+///
+pub type Source = Box<dyn StdError + Send + Sync>;
+
+/// RepositoryError tells apart why a repository operation failed,
+/// so a lost database is told from a bad query or from data that cannot be decoded.
+///
+#[derive(Debug)]
+pub enum RepositoryError {
+    /// The database cannot be reached or dropped the connection, the operation may succeed once retried.
+    NotConnected(String),
+    /// The database refused or failed the operation.
+    QueryFailed { context: String, source: Source },
+    /// Stored data cannot be decoded.
+    Corrupt(String),
+    /// The operation did not complete in time.
+    Timeout(String),
+    /// The operation was given input it cannot use, like a malformed cursor.
+    InvalidInput(String),
+    /// The repository does not support the operation.
+    Unsupported(String),
+}
+
+impl RepositoryError {
+    /// Creates a QueryFailed error of the operation described by context failing with source.
+    ///
+    pub fn query(context: impl Into<String>, source: impl Into<Source>) -> Self {
+        Self::QueryFailed {
+            context: context.into(),
+            source: source.into(),
+        }
+    }
+}
+
+impl Display for RepositoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotConnected(msg) => write!(f, "not connected: {}", msg),
+            Self::QueryFailed { context, source } => write!(f, "{}: {}", context, source),
+            Self::Corrupt(msg) => write!(f, "corrupt data: {}", msg),
+            Self::Timeout(msg) => write!(f, "timed out: {}", msg),
+            Self::InvalidInput(msg) | Self::Unsupported(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl StdError for RepositoryError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::QueryFailed { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// Converts errors of files and of repositories speaking io errors by their kind.
+///
+impl From<io::Error> for RepositoryError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::NotConnected
+            | io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe => Self::NotConnected(e.to_string()),
+            io::ErrorKind::TimedOut => Self::Timeout(e.to_string()),
+            io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => {
+                Self::Corrupt(e.to_string())
+            }
+            io::ErrorKind::InvalidInput => Self::InvalidInput(e.to_string()),
+            io::ErrorKind::Unsupported => Self::Unsupported(e.to_string()),
+            _ => Self::query("storage failed", e),
+        }
+    }
+}
+
+impl From<sqlx::Error> for RepositoryError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::Io(_) | sqlx::Error::PoolClosed | sqlx::Error::WorkerCrashed => {
+                Self::NotConnected(e.to_string())
+            }
+            sqlx::Error::PoolTimedOut => Self::Timeout(e.to_string()),
+            sqlx::Error::Decode(_) | sqlx::Error::ColumnDecode { .. } => {
+                Self::Corrupt(e.to_string())
+            }
+            e => Self::query("cannot execute query", e),
+        }
+    }
+}
+
+impl From<::redis::RedisError> for RepositoryError {
+    fn from(e: ::redis::RedisError) -> Self {
+        if e.is_timeout() {
+            return Self::Timeout(e.to_string());
+        }
+        if e.is_io_error() || e.is_connection_dropped() || e.is_connection_refusal() {
+            return Self::NotConnected(e.to_string());
+        }
+        match e.kind() {
+            ::redis::ErrorKind::TypeError => Self::Corrupt(e.to_string()),
+            _ => Self::query("cannot execute command", e),
+        }
+    }
+}
+
+impl From<sled::Error> for RepositoryError {
+    fn from(e: sled::Error) -> Self {
+        match e {
+            sled::Error::Io(e) => e.into(),
+            sled::Error::Corruption { .. } => Self::Corrupt(e.to_string()),
+            e => Self::query("cannot access store", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn on_from_io_error_should_keep_the_cause_of_the_failure() {
+        let e = RepositoryError::from(io::Error::new(io::ErrorKind::ConnectionReset, "reset"));
+        assert!(matches!(e, RepositoryError::NotConnected(_)));
+        let e = RepositoryError::from(io::Error::new(io::ErrorKind::InvalidData, "truncated"));
+        assert!(matches!(e, RepositoryError::Corrupt(_)));
+        let e = RepositoryError::from(io::Error::other("disc full"));
+        assert_eq!(e.to_string(), "storage failed: disc full");
+        assert!(e.source().is_some());
+    }
+
+    #[test]
+    fn on_from_sqlx_error_should_tell_lost_connections_from_failed_queries() {
+        assert!(matches!(
+            RepositoryError::from(sqlx::Error::PoolTimedOut),
+            RepositoryError::Timeout(_)
+        ));
+        assert!(matches!(
+            RepositoryError::from(sqlx::Error::PoolClosed),
+            RepositoryError::NotConnected(_)
+        ));
+        let e = RepositoryError::from(sqlx::Error::RowNotFound);
+        assert!(e.to_string().starts_with("cannot execute query: "));
+    }
+}
@@ -0,0 +1,190 @@
+use super::error::Result;
+use super::interface::{Log, LogId, LogsPage, LogsStream, Page, RepositoryProvider};
+use crate::metrics::{Metrics, Operation};
+use scribe::token::Token;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+
+/// Times the operation into its latency histogram and the repository time of the request,
+/// and counts it in errors if it fails.
+///
+async fn measure<T>(operation: &Operation, f: impl Future<Output = Result<T>>) -> Result<T> {
+    let start = Instant::now();
+    let result = f.await;
+    let elapsed = start.elapsed();
+    operation.latency.observe(elapsed);
+    crate::logging::add_repository_time(elapsed);
+    if result.is_err() {
+        operation.errors.add(1);
+    }
+    result
+}
+
+/// Instrumented wraps a repository, timing its operations and counting their errors per backend,
+/// so a slow or failing tier shows in the metrics.
+/// Operations are grouped as insert, save (insert with new dictionary words), find, read (of the dictionary words),
+/// expire and count. Streams are not timed, as they last as long as the client reads them.
+/// Each operation runs in a `repository` span of the backend and the called method.
+///
+#[derive(Debug, Clone)]
+pub struct Instrumented<R: RepositoryProvider> {
+    repo: R,
+    backend: Arc<str>,
+    insert: Arc<Operation>,
+    save: Arc<Operation>,
+    find: Arc<Operation>,
+    read: Arc<Operation>,
+    expire: Arc<Operation>,
+    count: Arc<Operation>,
+}
+
+impl<R: RepositoryProvider> Instrumented<R> {
+    pub fn new(repo: R, backend: &str, metrics: &Metrics) -> Self {
+        Self {
+            repo,
+            backend: Arc::from(backend),
+            insert: metrics.operation(backend, "insert"),
+            save: metrics.operation(backend, "save"),
+            find: metrics.operation(backend, "find"),
+            read: metrics.operation(backend, "read"),
+            expire: metrics.operation(backend, "expire"),
+            count: metrics.operation(backend, "count"),
+        }
+    }
+
+    fn span(&self, method: &'static str) -> tracing::Span {
+        tracing::info_span!("repository", backend = %self.backend, method)
+    }
+}
+
+impl<R: RepositoryProvider> RepositoryProvider for Instrumented<R> {
+    async fn migrate(&self) -> Result<()> {
+        self.repo.migrate().instrument(self.span("migrate")).await
+    }
+
+    async fn insert_log_at(&self, input: &[Token], timestamp: i64) -> Result<LogId> {
+        measure(&self.insert, self.repo.insert_log_at(input, timestamp))
+            .instrument(self.span("insert_log_at"))
+            .await
+    }
+
+    async fn insert_logs(&self, inputs: &[(i64, Vec<Token>)]) -> Result<()> {
+        measure(&self.insert, self.repo.insert_logs(inputs))
+            .instrument(self.span("insert_logs"))
+            .await
+    }
+
+    async fn insert_log_with_words(
+        &self,
+        input: &[Token],
+        timestamp: i64,
+        words: &[(String, Token)],
+    ) -> Result<LogId> {
+        measure(
+            &self.save,
+            self.repo.insert_log_with_words(input, timestamp, words),
+        )
+        .instrument(self.span("insert_log_with_words"))
+        .await
+    }
+
+    async fn insert_logs_with_words(
+        &self,
+        inputs: &[(i64, Vec<Token>)],
+        words: &[(String, Token)],
+    ) -> Result<()> {
+        measure(&self.save, self.repo.insert_logs_with_words(inputs, words))
+            .instrument(self.span("insert_logs_with_words"))
+            .await
+    }
+
+    async fn find_words(&self) -> Result<Vec<(String, Token)>> {
+        measure(&self.read, self.repo.find_words())
+            .instrument(self.span("find_words"))
+            .await
+    }
+
+    async fn find_logs(&self, from: &Duration, to: &Duration) -> Result<Vec<Log>> {
+        measure(&self.find, self.repo.find_logs(from, to))
+            .instrument(self.span("find_logs"))
+            .await
+    }
+
+    async fn find_logs_with_any(
+        &self,
+        from: &Duration,
+        to: &Duration,
+        tokens: &[Token],
+    ) -> Result<Vec<Log>> {
+        measure(&self.find, self.repo.find_logs_with_any(from, to, tokens))
+            .instrument(self.span("find_logs_with_any"))
+            .await
+    }
+
+    async fn find_log_by_id(&self, id: &LogId) -> Result<Option<Log>> {
+        measure(&self.find, self.repo.find_log_by_id(id))
+            .instrument(self.span("find_log_by_id"))
+            .await
+    }
+
+    async fn find_logs_page(
+        &self,
+        from: &Duration,
+        to: &Duration,
+        limit: usize,
+        page: &Page,
+    ) -> Result<LogsPage> {
+        measure(&self.find, self.repo.find_logs_page(from, to, limit, page))
+            .instrument(self.span("find_logs_page"))
+            .await
+    }
+
+    fn find_logs_stream(&self, from: &Duration, to: &Duration) -> LogsStream<'_> {
+        self.repo.find_logs_stream(from, to)
+    }
+
+    async fn expire_logs(&self, before: &Duration) -> Result<u64> {
+        measure(&self.expire, self.repo.expire_logs(before))
+            .instrument(self.span("expire_logs"))
+            .await
+    }
+
+    async fn compact(&self) -> Result<()> {
+        self.repo.compact().instrument(self.span("compact")).await
+    }
+
+    async fn count_occurrence(&self, input: &[Token], since: &Duration) -> Result<bool> {
+        measure(&self.count, self.repo.count_occurrence(input, since))
+            .instrument(self.span("count_occurrence"))
+            .await
+    }
+
+    async fn close(&self) {
+        self.repo.close().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::error::RepositoryError;
+
+    #[tokio::test]
+    async fn on_measure_should_time_operations_and_count_failures() {
+        let metrics = Metrics::default();
+        let operation = metrics.operation("SQLite", "find");
+
+        let result = measure(&operation, async { Ok(1) }).await;
+        assert_eq!(result.unwrap(), 1);
+        let result: Result<()> = measure(&operation, async {
+            Err(RepositoryError::Timeout("broken".to_string()))
+        })
+        .await;
+        assert!(result.is_err());
+
+        assert_eq!(operation.latency.count(), 2);
+        assert_eq!(operation.errors.get(), 1);
+    }
+}
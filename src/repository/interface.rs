@@ -1,27 +1,290 @@
-use crate::dictionary::Module;
-use std::io::Result;
+use super::error::{RepositoryError, Result};
+use crate::snapshot::{self, Snapshot};
+use futures::stream::{self, BoxStream, StreamExt};
+use scribe::token::Token;
+use std::collections::HashSet;
+use std::future::Future;
+use std::io::{Read, Seek};
 use std::time::Duration;
+use tokio::io::{AsyncSeek, AsyncWrite};
+
+/// Cursor points past the last log of a page, so the next page starts right after it
+/// even if logs were inserted in front of it meanwhile.
+/// It is opaque to clients, each repository encodes the position of the log its own way.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor(pub String);
+
+impl Cursor {
+    /// Creates a cursor of the log at the timestamp with the id unique among logs of the timestamp.
+    ///
+    pub fn new(timestamp: i64, id: impl std::fmt::Display) -> Self {
+        Self(format!("{}:{}", timestamp, id))
+    }
+
+    /// Returns the timestamp and the id of the log the cursor points at.
+    ///
+    pub fn parts(&self) -> Result<(i64, &str)> {
+        let Some((timestamp, id)) = self.0.split_once(':') else {
+            return Err(RepositoryError::InvalidInput(
+                "malformed cursor".to_string(),
+            ));
+        };
+        let Ok(timestamp) = timestamp.parse() else {
+            return Err(RepositoryError::InvalidInput(
+                "malformed cursor".to_string(),
+            ));
+        };
+        Ok((timestamp, id))
+    }
+}
+
+/// LogId identifies a stored log.
+/// It is opaque to clients, each repository forms ids its own way.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LogId(pub String);
+
+impl std::fmt::Display for LogId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Log is a stored log with its id and timestamp in nanoseconds since the epoch.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Log {
+    pub id: LogId,
+    pub timestamp: i64,
+    pub data: Vec<Token>,
+}
+
+impl AsRef<[Token]> for Log {
+    fn as_ref(&self) -> &[Token] {
+        &self.data
+    }
+}
+
+/// Page selects where a page of logs in the time span starts.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Page {
+    /// Skips given number of the oldest logs.
+    Offset(usize),
+    /// Starts after the log the cursor of the previous page points at.
+    After(Cursor),
+}
+
+/// LogsPage holds logs of a page and the cursor of the next page, None if it is the last one.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogsPage {
+    pub logs: Vec<Log>,
+    pub next: Option<Cursor>,
+}
+
+/// LogsStream yields logs with their timestamps in nanoseconds since the epoch.
+///
+pub type LogsStream<'a> = BoxStream<'a, Result<(i64, Vec<Token>)>>;
 
 /// RepositoryProvider provides full functionality of the persistent repository.
 ///
-#[allow(dead_code)]
 pub trait RepositoryProvider: Send + Sync + Clone {
     async fn migrate(&self) -> Result<()>;
-    async fn insert_log(&self, input: &[u32]) -> Result<()>;
-    async fn find_logs(&self, from: &Duration, to: &Duration) -> Result<Vec<Vec<u32>>>;
+    /// Inserts single log at the timestamp in nanoseconds since the epoch,
+    /// so logs shipped late keep the time of the event.
+    ///
+    async fn insert_log_at(&self, input: &[Token], timestamp: i64) -> Result<LogId>;
+    /// Inserts logs with their timestamps in nanoseconds since the epoch at once.
+    /// Repositories able to write many rows in one round trip should override it,
+    /// the default inserts logs one by one.
+    ///
+    async fn insert_logs(&self, inputs: &[(i64, Vec<Token>)]) -> Result<()> {
+        for (timestamp, input) in inputs.iter() {
+            self.insert_log_at(input, *timestamp).await?;
+        }
+        Ok(())
+    }
+    /// Inserts single log at the timestamp together with words of the dictionary not saved yet,
+    /// so a log is never saved without the words its tokens stand for.
+    /// Every repository keeps the dictionary, saving the words before or at once with the log,
+    /// as logs cannot be read back without it. A repository not able to keep words
+    /// must fail to migrate rather than drop them.
+    ///
+    async fn insert_log_with_words(
+        &self,
+        input: &[Token],
+        timestamp: i64,
+        words: &[(String, Token)],
+    ) -> Result<LogId>;
+    /// Inserts logs with their timestamps together with words of the dictionary not saved yet,
+    /// in the manner of `insert_log_with_words`.
+    ///
+    async fn insert_logs_with_words(
+        &self,
+        inputs: &[(i64, Vec<Token>)],
+        words: &[(String, Token)],
+    ) -> Result<()>;
+    /// Finds words of the dictionary saved together with logs, ordered by number.
+    ///
+    async fn find_words(&self) -> Result<Vec<(String, Token)>>;
+    /// Finds logs in the time range ordered by timestamp, oldest first,
+    /// with logs of the same timestamp kept in order of insertion.
+    ///
+    async fn find_logs(&self, from: &Duration, to: &Duration) -> Result<Vec<Log>>;
+    /// Finds logs in the time range holding any of the tokens, in order of `find_logs`.
+    /// Repositories keeping an index of tokens should override it to fetch only matching logs,
+    /// the default reads the whole range and filters logs in memory.
+    ///
+    async fn find_logs_with_any(
+        &self,
+        from: &Duration,
+        to: &Duration,
+        tokens: &[Token],
+    ) -> Result<Vec<Log>> {
+        Ok(holding_any(self.find_logs(from, to).await?, tokens))
+    }
+    /// Finds the log of the id, None if there is no such log or the id is malformed.
+    /// Repositories not able to look logs up by id keep the default returning an Unsupported error.
+    ///
+    async fn find_log_by_id(&self, _id: &LogId) -> Result<Option<Log>> {
+        Err(RepositoryError::Unsupported(
+            "repository cannot find logs by id".to_string(),
+        ))
+    }
+    /// Finds at most limit logs in the time range starting at the page, in order of `find_logs`.
+    /// Every repository overrides it to read only the logs of the page. The default loads the whole range
+    /// on every page and points cursors by the offset of the next page, so a new repository has to
+    /// override it before it serves large ranges.
+    ///
+    async fn find_logs_page(
+        &self,
+        from: &Duration,
+        to: &Duration,
+        limit: usize,
+        page: &Page,
+    ) -> Result<LogsPage> {
+        let offset = match page {
+            Page::Offset(offset) => *offset,
+            Page::After(cursor) => {
+                let Ok(offset) = cursor.0.parse() else {
+                    return Err(RepositoryError::InvalidInput(
+                        "malformed cursor".to_string(),
+                    ));
+                };
+                offset
+            }
+        };
+        let logs: Vec<_> = self
+            .find_logs(from, to)
+            .await?
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .collect();
+        let next = match logs.len() {
+            0 => None,
+            len if len == limit => Some(Cursor((offset + len).to_string())),
+            _ => None,
+        };
+        Ok(LogsPage { logs, next })
+    }
+    /// Streams logs in the time range with their timestamps in order of `find_logs`,
+    /// so results are processed as they are read instead of being collected first.
+    /// Repositories not reading rows as a stream page through `find_logs_page` with `paged`,
+    /// the default yields a single Unsupported error.
+    ///
+    fn find_logs_stream(&self, from: &Duration, to: &Duration) -> LogsStream<'_> {
+        let _ = (from, to);
+        stream::once(async {
+            Err(RepositoryError::Unsupported(
+                "repository cannot stream logs".to_string(),
+            ))
+        })
+        .boxed()
+    }
+    /// Exports every log and the dictionary schema the closure returns once logs are written
+    /// to a snapshot in the backend neutral format, returning the number of exported logs.
+    /// Repositories not streaming logs fail with the Unsupported error of `find_logs_stream`.
+    ///
+    async fn export<W: AsyncWrite + AsyncSeek + Unpin + Send>(
+        &self,
+        w: &mut W,
+        schema: impl FnOnce() -> std::io::Result<Vec<u8>>,
+    ) -> Result<u64> {
+        snapshot::write(self, w, schema).await
+    }
+    /// Imports logs of the snapshot together with the words of its dictionary,
+    /// returning the number of imported logs.
+    ///
+    async fn import<R: Read + Seek>(
+        &self,
+        snapshot: Snapshot<R>,
+        words: &[(String, Token)],
+    ) -> Result<u64> {
+        snapshot.insert_into(self, words).await
+    }
+    /// Deletes logs older than before and returns how many were deleted.
+    /// Repositories that expire logs on their own or cannot delete them keep the default, deleting nothing.
+    ///
+    async fn expire_logs(&self, _before: &Duration) -> Result<u64> {
+        Ok(0)
+    }
+    /// Reclaims space of deleted logs, returning it to the file system.
+    /// Repositories not able to do it on demand keep the default returning an Unsupported error.
+    ///
+    async fn compact(&self) -> Result<()> {
+        Err(RepositoryError::Unsupported(
+            "repository cannot be compacted".to_string(),
+        ))
+    }
+    /// Adds an occurrence to the latest log identical to input inserted since given time.
+    /// Returns false if there is no such log, the default for repositories not counting occurrences.
+    ///
+    async fn count_occurrence(&self, _input: &[Token], _since: &Duration) -> Result<bool> {
+        Ok(false)
+    }
     async fn close(&self);
 }
 
-/// Stores Serializer in Self.
+/// Logs of a page read by `paged`.
+///
+pub const STREAM_PAGE: usize = 1000;
+
+/// Streams logs of pages the fetch reads, starting at the first one and following cursors
+/// of next pages until the last one, so a repository streams logs with `find_logs_page`
+/// holding a page of logs in memory at a time.
 ///
-#[allow(dead_code)]
-pub trait SerializerSaver {
-    async fn save(&self, s: &Module) -> Result<()>;
+pub fn paged<'a, F, Fut>(fetch: F) -> LogsStream<'a>
+where
+    F: Fn(Page) -> Fut + Send + 'a,
+    Fut: Future<Output = Result<LogsPage>> + Send + 'a,
+{
+    stream::unfold((fetch, Some(Page::Offset(0))), |(fetch, page)| async move {
+        let page = page?;
+        match fetch(page).await {
+            Ok(page) => Some((Ok(page.logs), (fetch, page.next.map(Page::After)))),
+            Err(e) => Some((Err(e), (fetch, None))),
+        }
+    })
+    .flat_map(|logs| {
+        stream::iter(match logs {
+            Ok(logs) => logs
+                .into_iter()
+                .map(|log| Ok((log.timestamp, log.data)))
+                .collect(),
+            Err(e) => vec![Err(e)],
+        })
+    })
+    .boxed()
 }
 
-/// Reads stored Serializer in Self.
+/// Keeps logs holding any of the tokens.
 ///
-#[allow(dead_code)]
-pub trait SerializerReader {
-    async fn read(&self) -> Result<Module>;
+pub fn holding_any(logs: Vec<Log>, tokens: &[Token]) -> Vec<Log> {
+    let tokens: HashSet<&Token> = tokens.iter().collect();
+    logs.into_iter()
+        .filter(|log| log.data.iter().any(|t| tokens.contains(t)))
+        .collect()
 }
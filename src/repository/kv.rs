@@ -0,0 +1,518 @@
+use super::error::{RepositoryError, Result};
+use super::interface::{
+    paged, Cursor, Log, LogId, LogsPage, LogsStream, Page, RepositoryProvider, STREAM_PAGE,
+};
+use scribe::token::Token;
+use sled::transaction::TransactionError;
+use sled::Transactional;
+use std::ops::Bound;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const TREE_LOGS: &str = "logs";
+const TREE_WORDS: &str = "words";
+const KEY_WIDTH: usize = 16;
+const LOCK_ATTEMPTS: usize = 100;
+const LOCK_BACKOFF: Duration = Duration::from_millis(50);
+
+/// WarehouseKv serves access to an embedded sled key value store in a directory via facade methods.
+/// Logs are keyed by the big endian timestamp followed by a big endian sequence number,
+/// so keys sort by time with logs of the same timestamp in order of insertion,
+/// and a time span query is a single range scan. Writers do not block each other or readers.
+/// Words of the dictionary are keyed by their big endian number in a tree of their own,
+/// written in the transaction of the logs bringing them.
+///
+#[derive(Debug, Clone)]
+pub struct WarehouseKv {
+    db: sled::Db,
+    logs: sled::Tree,
+    words: sled::Tree,
+}
+
+impl WarehouseKv {
+    /// Opens or creates the store in the directory.
+    /// A store closed in this process releases its lock once sled drops it in the background,
+    /// so a locked directory is retried for a few seconds before giving up.
+    ///
+    pub async fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        let mut attempt = 1;
+        let db = loop {
+            match sled::open(&dir) {
+                Err(sled::Error::Io(e))
+                    if attempt < LOCK_ATTEMPTS
+                        && e.to_string().starts_with("could not acquire lock") =>
+                {
+                    attempt += 1;
+                    tokio::time::sleep(LOCK_BACKOFF).await;
+                }
+                opened => break opened?,
+            }
+        };
+        let logs = db.open_tree(TREE_LOGS)?;
+        let words = db.open_tree(TREE_WORDS)?;
+        Ok(Self { db, logs, words })
+    }
+}
+
+#[inline(always)]
+fn key(timestamp: u64, sequence: u64) -> [u8; KEY_WIDTH] {
+    let mut k = [0u8; KEY_WIDTH];
+    k[..8].copy_from_slice(&timestamp.to_be_bytes());
+    k[8..].copy_from_slice(&sequence.to_be_bytes());
+    k
+}
+
+/// Returns the timestamp of the log under the key.
+///
+#[inline(always)]
+fn key_timestamp(key: &[u8]) -> i64 {
+    let mut timestamp = [0u8; 8];
+    timestamp.copy_from_slice(&key[..8]);
+    u64::from_be_bytes(timestamp) as i64
+}
+
+/// Returns the id of the log under the key, the key in hex.
+///
+#[inline(always)]
+fn log_id(key: &[u8]) -> LogId {
+    LogId(key.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Returns the key of the log id, None if the id is malformed.
+///
+#[inline(always)]
+fn id_key(id: &LogId) -> Option<[u8; KEY_WIDTH]> {
+    let hex = id.0.as_bytes();
+    if hex.len() != KEY_WIDTH * 2 {
+        return None;
+    }
+    let mut k = [0u8; KEY_WIDTH];
+    for (i, pair) in hex.chunks(2).enumerate() {
+        k[i] = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(k)
+}
+
+impl WarehouseKv {
+    /// Writes the rows of logs and the words in a single transaction of both trees.
+    /// Rows bringing no words are written in a batch of the logs tree, most writes bring none.
+    ///
+    fn write(&self, rows: &[([u8; KEY_WIDTH], Vec<u8>)], words: &[(String, Token)]) -> Result<()> {
+        if words.is_empty() {
+            let mut batch = sled::Batch::default();
+            for (k, data) in rows.iter() {
+                batch.insert(k, data.as_slice());
+            }
+            self.logs.apply_batch(batch)?;
+            return Ok(());
+        }
+        let written = (&self.logs, &self.words).transaction(|(logs, tree)| {
+            for (word, num) in words.iter() {
+                tree.insert(&i64::from(*num).to_be_bytes(), word.as_bytes())?;
+            }
+            for (k, data) in rows.iter() {
+                logs.insert(k, data.as_slice())?;
+            }
+            Ok::<_, sled::transaction::ConflictableTransactionError<sled::Error>>(())
+        });
+        match written {
+            Ok(()) => Ok(()),
+            Err(TransactionError::Abort(e) | TransactionError::Storage(e)) => Err(e.into()),
+        }
+    }
+}
+
+impl RepositoryProvider for WarehouseKv {
+    /// The store needs no schema, trees are created on open.
+    ///
+    async fn migrate(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Insert single log data under the timestamp and the next sequence number.
+    ///
+    async fn insert_log_at(&self, input: &[Token], timestamp: i64) -> Result<LogId> {
+        self.insert_log_with_words(input, timestamp, &[]).await
+    }
+
+    /// Inserts logs with their timestamps in a single transaction.
+    ///
+    async fn insert_logs(&self, inputs: &[(i64, Vec<Token>)]) -> Result<()> {
+        self.insert_logs_with_words(inputs, &[]).await
+    }
+
+    /// Inserts single log at the timestamp and the words in a single transaction.
+    ///
+    async fn insert_log_with_words(
+        &self,
+        input: &[Token],
+        timestamp: i64,
+        words: &[(String, Token)],
+    ) -> Result<LogId> {
+        let k = key(timestamp.max(0) as u64, self.db.generate_id()?);
+        self.write(&[(k, Token::encode(input))], words)?;
+        Ok(log_id(&k))
+    }
+
+    /// Inserts logs with their timestamps and the words in a single transaction.
+    ///
+    async fn insert_logs_with_words(
+        &self,
+        inputs: &[(i64, Vec<Token>)],
+        words: &[(String, Token)],
+    ) -> Result<()> {
+        let mut rows = Vec::with_capacity(inputs.len());
+        for (timestamp, input) in inputs.iter() {
+            let k = key((*timestamp).max(0) as u64, self.db.generate_id()?);
+            rows.push((k, Token::encode(input)));
+        }
+        self.write(&rows, words)
+    }
+
+    /// Gets words of the words tree ordered by number.
+    ///
+    async fn find_words(&self) -> Result<Vec<(String, Token)>> {
+        let mut result = Vec::new();
+        for entry in self.words.iter() {
+            let (k, word) = entry?;
+            let Ok(num) = <[u8; 8]>::try_from(k.as_ref()) else {
+                return Err(RepositoryError::Corrupt("malformed word key".to_string()));
+            };
+            let Ok(word) = String::from_utf8(word.to_vec()) else {
+                return Err(RepositoryError::Corrupt("word is not utf-8".to_string()));
+            };
+            result.push((word, Token::try_from(i64::from_be_bytes(num))?));
+        }
+        Ok(result)
+    }
+
+    /// Gets data in time span.
+    ///
+    async fn find_logs(&self, from: &Duration, to: &Duration) -> Result<Vec<Log>> {
+        let (from, to) = (from.as_nanos() as u64, to.as_nanos() as u64);
+        if from > to {
+            return Ok(Vec::new());
+        }
+        let logs = self.logs.clone();
+        let scan = move || -> Result<Vec<Log>> {
+            let mut data = Vec::new();
+            for entry in logs.range(key(from, 0)..=key(to, u64::MAX)) {
+                let (k, value) = entry?;
+                data.push(Log {
+                    id: log_id(&k),
+                    timestamp: key_timestamp(&k),
+                    data: Token::decode(&value)?,
+                });
+            }
+            Ok(data)
+        };
+        match tokio::task::spawn_blocking(scan).await {
+            Ok(result) => result,
+            Err(e) => Err(RepositoryError::query("cannot scan logs", e)),
+        }
+    }
+
+    /// Gets a page of logs in time span, scanning keys from the start of the span or after the key of the cursor,
+    /// so a page decodes only its own logs. The cursor is the id of the last log of the page.
+    ///
+    fn find_logs_stream(&self, from: &Duration, to: &Duration) -> LogsStream<'_> {
+        let (from, to) = (*from, *to);
+        paged(move |page| async move { self.find_logs_page(&from, &to, STREAM_PAGE, &page).await })
+    }
+
+    async fn find_logs_page(
+        &self,
+        from: &Duration,
+        to: &Duration,
+        limit: usize,
+        page: &Page,
+    ) -> Result<LogsPage> {
+        let first = key(from.as_nanos() as u64, 0);
+        let last = key(to.as_nanos() as u64, u64::MAX);
+        let (start, offset) = match page {
+            Page::Offset(offset) => (Bound::Included(first), *offset),
+            Page::After(cursor) => {
+                let Some(after) = id_key(&LogId(cursor.0.clone())) else {
+                    return Err(RepositoryError::InvalidInput(
+                        "malformed cursor".to_string(),
+                    ));
+                };
+                if after < first {
+                    (Bound::Included(first), 0)
+                } else {
+                    (Bound::Excluded(after), 0)
+                }
+            }
+        };
+        if first > last || limit == 0 {
+            return Ok(LogsPage {
+                logs: Vec::new(),
+                next: None,
+            });
+        }
+        let logs = self.logs.clone();
+        let scan = move || -> Result<LogsPage> {
+            let mut page = LogsPage {
+                logs: Vec::with_capacity(limit),
+                next: None,
+            };
+            let keys = logs.range((start, Bound::Included(last)));
+            for entry in keys.skip(offset).take(limit) {
+                let (k, value) = entry?;
+                page.logs.push(Log {
+                    id: log_id(&k),
+                    timestamp: key_timestamp(&k),
+                    data: Token::decode(&value)?,
+                });
+            }
+            if page.logs.len() == limit {
+                page.next = page.logs.last().map(|l| Cursor(l.id.0.clone()));
+            }
+            Ok(page)
+        };
+        match tokio::task::spawn_blocking(scan).await {
+            Ok(result) => result,
+            Err(e) => Err(RepositoryError::query("cannot scan logs", e)),
+        }
+    }
+
+    /// Gets the log of the id, the hex of its key.
+    ///
+    async fn find_log_by_id(&self, id: &LogId) -> Result<Option<Log>> {
+        let Some(k) = id_key(id) else {
+            return Ok(None);
+        };
+        let value = self.logs.get(k)?;
+        match value {
+            Some(value) => Ok(Some(Log {
+                id: id.clone(),
+                timestamp: key_timestamp(&k),
+                data: Token::decode(&value)?,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    async fn close(&self) {
+        let _ = self.db.flush_async().await;
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
+mod tests {
+    use super::*;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn now() -> Duration {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+    }
+
+    fn now_nanos() -> i64 {
+        now().as_nanos() as i64
+    }
+
+    #[tokio::test]
+    async fn on_insert_should_find_logs_in_time_span_in_order_of_insertion_after_reopen() {
+        let dir = std::env::temp_dir().join("scribe_kv");
+        let _ = std::fs::remove_dir_all(&dir);
+        let Ok(warehouse) = WarehouseKv::new(&dir).await else {
+            assert!(false);
+            return;
+        };
+        let Ok(()) = warehouse.migrate().await else {
+            assert!(false);
+            return;
+        };
+
+        let Ok(_) = warehouse
+            .insert_log_at(&[Token::from(100)], now_nanos())
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        let from = now();
+        let logs: Vec<Vec<Token>> = (0..300u32)
+            .map(|i| vec![Token::from(i), Token::from(i + 1)])
+            .collect();
+        for log in logs.iter() {
+            let Ok(_) = warehouse.insert_log_at(log, now_nanos()).await else {
+                assert!(false);
+                return;
+            };
+        }
+        let to = now();
+        let Ok(_) = warehouse
+            .insert_log_at(&[Token::from(200)], now_nanos())
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        warehouse.close().await;
+        drop(warehouse);
+
+        let Ok(warehouse) = WarehouseKv::new(&dir).await else {
+            assert!(false);
+            return;
+        };
+        let Ok(result) = warehouse.find_logs(&from, &to).await else {
+            assert!(false);
+            return;
+        };
+        let Ok(Some(first)) = warehouse.find_log_by_id(&result[0].id).await else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(first, result[0]);
+        assert!(matches!(
+            warehouse.find_log_by_id(&LogId("zz".to_string())).await,
+            Ok(None)
+        ));
+        assert_eq!(result.into_iter().map(|l| l.data).collect::<Vec<_>>(), logs);
+        assert!(warehouse.find_logs(&to, &from).await.unwrap().is_empty());
+
+        warehouse.close().await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn on_insert_with_words_should_find_the_words_by_number_after_reopen() {
+        let dir = std::env::temp_dir().join("scribe_kv_words");
+        let _ = std::fs::remove_dir_all(&dir);
+        let Ok(warehouse) = WarehouseKv::new(&dir).await else {
+            assert!(false);
+            return;
+        };
+        let words = [
+            ("first".to_string(), Token::from(1)),
+            ("second".to_string(), Token::from(256)),
+        ];
+        let Ok(_) = warehouse
+            .insert_log_with_words(&[Token::from(256)], 1, &words[1..])
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        let Ok(()) = warehouse
+            .insert_logs_with_words(&[(2, vec![Token::from(1)])], &words[..1])
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        warehouse.close().await;
+        drop(warehouse);
+
+        let Ok(warehouse) = WarehouseKv::new(&dir).await else {
+            assert!(false);
+            return;
+        };
+        let Ok(found) = warehouse.find_words().await else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(found, words);
+        let Ok(logs) = warehouse
+            .find_logs(&Duration::from_nanos(0), &Duration::from_nanos(2))
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(logs.len(), 2);
+
+        warehouse.close().await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn on_find_logs_page_should_page_by_offset_and_by_cursor() {
+        let dir = std::env::temp_dir().join("scribe_kv_page");
+        let _ = std::fs::remove_dir_all(&dir);
+        let Ok(warehouse) = WarehouseKv::new(&dir).await else {
+            assert!(false);
+            return;
+        };
+        let logs: Vec<Vec<Token>> = (0..5u32).map(|i| vec![Token::from(i)]).collect();
+        for log in logs.iter() {
+            let Ok(_) = warehouse.insert_log_at(log, now_nanos()).await else {
+                assert!(false);
+                return;
+            };
+        }
+        let (from, to) = (Duration::ZERO, Duration::from_nanos(i64::MAX as u64));
+
+        let Ok(page) = warehouse
+            .find_logs_page(&from, &to, 2, &Page::Offset(1))
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(
+            page.logs.into_iter().map(|l| l.data).collect::<Vec<_>>(),
+            logs[1..3]
+        );
+
+        let mut found = Vec::new();
+        let mut next = Page::Offset(0);
+        loop {
+            let Ok(page) = warehouse.find_logs_page(&from, &to, 2, &next).await else {
+                assert!(false);
+                return;
+            };
+            found.extend(page.logs.into_iter().map(|l| l.data));
+            match page.next {
+                Some(cursor) => next = Page::After(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(found, logs);
+
+        let malformed = Page::After(Cursor("1:x".to_string()));
+        let Err(e) = warehouse.find_logs_page(&from, &to, 2, &malformed).await else {
+            assert!(false);
+            return;
+        };
+        assert!(matches!(e, RepositoryError::InvalidInput(_)));
+
+        warehouse.close().await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn on_find_logs_stream_should_stream_logs_of_every_page() {
+        let dir = std::env::temp_dir().join("scribe_kv_stream");
+        let _ = std::fs::remove_dir_all(&dir);
+        let Ok(warehouse) = WarehouseKv::new(&dir).await else {
+            assert!(false);
+            return;
+        };
+        let logs: Vec<(i64, Vec<Token>)> = (0..2500)
+            .map(|i| (i, vec![Token::from(i as u32)]))
+            .collect();
+        let Ok(_) = warehouse.insert_logs(&logs).await else {
+            assert!(false);
+            return;
+        };
+
+        let streamed: Vec<_> = futures::StreamExt::collect(
+            warehouse.find_logs_stream(&Duration::ZERO, &Duration::from_nanos(i64::MAX as u64)),
+        )
+        .await;
+        let Ok(streamed) = streamed.into_iter().collect::<Result<Vec<_>>>() else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(streamed, logs);
+
+        warehouse.close().await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
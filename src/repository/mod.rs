@@ -1,31 +1,207 @@
+pub mod clickhouse;
+pub mod cold;
 mod commands;
+mod compression;
 mod entities;
+pub mod error;
+pub mod instrument;
 pub mod interface;
+pub mod kv;
 pub mod mongo;
+pub mod mysql;
+mod partition;
+pub mod redis;
+pub mod retry;
+pub mod segments;
 pub mod sql;
 use crate::settings::Setup;
-use std::{io::Result, time::Duration};
+use error::{RepositoryError, Result};
+use interface::{Log, LogId, LogsPage, LogsStream, Page};
+use scribe::token::Token;
+use std::fmt::{self, Display};
+use std::time::Duration;
+
+/// PoolConfig sizes and times out the connection pools of database repositories.
+/// Settings left as None keep the defaults of the driver.
+///
+#[derive(Clone, Debug, Default)]
+pub struct PoolConfig {
+    /// Most connections open at once.
+    pub size: Option<u32>,
+    /// Pooled connections unused for longer are closed.
+    pub idle_timeout: Option<Duration>,
+    /// Longest wait for a connection of a busy pool.
+    pub acquire_timeout: Option<Duration>,
+    /// Longest wait for the database to accept a connection.
+    pub connect_timeout: Option<Duration>,
+}
+
+/// Names of the repository backends built in to the binary, as a Repository of each is displayed.
+pub const BACKENDS: [&str; 8] = [
+    "MongoDB",
+    "MySQL",
+    "Redis",
+    "segments",
+    "segments with cold storage",
+    "ClickHouse",
+    "sled",
+    "SQLite",
+];
 
 #[derive(Clone, Debug)]
 pub enum Repository {
     Mongo(mongo::WarehouseMongo),
+    MySql(mysql::WarehouseMySql),
+    Redis(redis::WarehouseRedis),
+    Segments(segments::WarehouseSegments),
+    Cold(cold::WarehouseCold),
+    ClickHouse(clickhouse::WarehouseClickHouse),
+    Kv(kv::WarehouseKv),
     Sql(sql::WarehouseSql),
 }
 
+/// Settings applied by some repositories only, each with the names of the repositories applying it.
+const SETTINGS: [(&str, &[&str]); 6] = [
+    ("retention", &["MongoDB", "MySQL", "SQLite"]),
+    ("compress", &["MongoDB", "SQLite"]),
+    ("partition", &["MongoDB", "SQLite"]),
+    ("token_index", &["MongoDB", "SQLite"]),
+    ("read_url", &["MongoDB", "SQLite"]),
+    ("cold_url", &["segments with cold storage"]),
+];
+
+/// Returns the name of the repository the setup selects by its db_url, as the Repository is displayed.
+///
+pub fn backend(s: &Setup) -> &'static str {
+    let conn_str = s.get_connection_str();
+    if conn_str.contains("mongodb") {
+        return "MongoDB";
+    }
+    if conn_str.starts_with("mysql://") {
+        return "MySQL";
+    }
+    if conn_str.starts_with("redis://") {
+        return "Redis";
+    }
+    if conn_str.starts_with("clickhouse://") {
+        return "ClickHouse";
+    }
+    if conn_str.starts_with("sled://") {
+        return "sled";
+    }
+    if conn_str.starts_with("segments://") {
+        return match s.get_cold_url() {
+            Some(_) => "segments with cold storage",
+            None => "segments",
+        };
+    }
+    "SQLite"
+}
+
+/// Returns an InvalidInput error naming the first setting the repository selected by the setup would ignore,
+/// so a setup asking for retention, compression, partitions, the token index or a replica
+/// of a repository without them fails instead of running without them unnoticed.
+///
+pub fn check_settings(s: &Setup) -> Result<()> {
+    let backend = backend(s);
+    let set = [
+        s.get_retention().is_some(),
+        s.get_compress(),
+        s.get_partition().is_some(),
+        s.get_token_index(),
+        s.get_read_url().is_some(),
+        s.get_cold_url().is_some(),
+    ];
+    for ((name, backends), set) in SETTINGS.iter().zip(set) {
+        if set && !backends.contains(&backend) {
+            return Err(RepositoryError::InvalidInput(format!(
+                "{} is not supported by the {} repository, only by {}",
+                name,
+                backend,
+                backends.join(", ")
+            )));
+        }
+    }
+    Ok(())
+}
+
 impl Repository {
+    /// Connects the repository selected by db_url of the setup,
+    /// failing with InvalidInput if the setup holds settings it does not apply.
+    ///
     pub async fn new(s: &Setup) -> Result<Self> {
+        check_settings(s)?;
         let conn_str = s.get_connection_str();
+        let pool = PoolConfig {
+            size: s.get_pool_size(),
+            idle_timeout: s.get_pool_idle_timeout(),
+            acquire_timeout: Some(s.get_acquire_timeout()),
+            connect_timeout: Some(s.get_connect_timeout()),
+        };
 
-        if conn_str.contains("mongodb") {
-            let m = mongo::WarehouseMongo::new(&conn_str).await?;
-            return Ok(Self::Mongo(m));
-        }
-        if !conn_str.is_empty() {
-            let s = sql::WarehouseSql::new(sql::DatabaseStorage::Path(conn_str)).await?;
-            return Ok(Self::Sql(s));
+        match backend(s) {
+            "MongoDB" => {
+                let m = mongo::WarehouseMongo::new(&conn_str, &pool)
+                    .await?
+                    .with_retention(s.get_retention())
+                    .with_compression(s.get_compress())
+                    .with_partitions(s.get_partition())
+                    .with_token_index(s.get_token_index())
+                    .with_replica(s.get_read_url(), &pool)
+                    .await?;
+                Ok(Self::Mongo(m))
+            }
+            "MySQL" => Ok(Self::MySql(
+                mysql::WarehouseMySql::new(&conn_str, &pool).await?,
+            )),
+            "Redis" => Ok(Self::Redis(
+                redis::WarehouseRedis::new(&conn_str, s.get_redis_window()).await?,
+            )),
+            "ClickHouse" => Ok(Self::ClickHouse(
+                clickhouse::WarehouseClickHouse::new(&conn_str).await?,
+            )),
+            "sled" => {
+                let dir = conn_str.trim_start_matches("sled://");
+                Ok(Self::Kv(kv::WarehouseKv::new(dir).await?))
+            }
+            "segments" | "segments with cold storage" => {
+                let dir = conn_str.trim_start_matches("segments://");
+                let w = segments::WarehouseSegments::new(dir, segments::SEGMENT_SPAN).await?;
+                match s.get_cold_url() {
+                    Some(url) => Ok(Self::Cold(cold::WarehouseCold::new(w, url).await?)),
+                    None => Ok(Self::Segments(w)),
+                }
+            }
+            _ => {
+                let storage = match conn_str.is_empty() {
+                    true => sql::DatabaseStorage::Ram,
+                    false => sql::DatabaseStorage::Path(conn_str),
+                };
+                let w = sql::WarehouseSql::new(storage, &pool)
+                    .await?
+                    .with_compression(s.get_compress())
+                    .with_partitions(s.get_partition())
+                    .with_token_index(s.get_token_index())
+                    .with_replica(s.get_read_url(), &pool)
+                    .await?;
+                Ok(Self::Sql(w))
+            }
         }
-        let s = sql::WarehouseSql::new(sql::DatabaseStorage::Ram).await?;
-        Ok(Self::Sql(s))
+    }
+}
+
+impl Display for Repository {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Repository::Mongo(_) => "MongoDB",
+            Repository::MySql(_) => "MySQL",
+            Repository::Redis(_) => "Redis",
+            Repository::Segments(_) => "segments",
+            Repository::Cold(_) => "segments with cold storage",
+            Repository::ClickHouse(_) => "ClickHouse",
+            Repository::Kv(_) => "sled",
+            Repository::Sql(_) => "SQLite",
+        })
     }
 }
 
@@ -33,27 +209,302 @@ impl interface::RepositoryProvider for Repository {
     async fn migrate(&self) -> Result<()> {
         match &self {
             Repository::Mongo(r) => r.migrate().await,
+            Repository::MySql(r) => r.migrate().await,
+            Repository::Redis(r) => r.migrate().await,
+            Repository::Segments(r) => r.migrate().await,
+            Repository::Cold(r) => r.migrate().await,
+            Repository::ClickHouse(r) => r.migrate().await,
+            Repository::Kv(r) => r.migrate().await,
             Repository::Sql(r) => r.migrate().await,
         }
     }
-    async fn insert_log(&self, input: &[u32]) -> Result<()> {
+    async fn insert_log_at(&self, input: &[Token], timestamp: i64) -> Result<LogId> {
+        #[cfg(feature = "chaos")]
+        {
+            crate::chaos::repository_latency().await;
+            crate::chaos::insert_failure()?;
+        }
+        match &self {
+            Repository::Mongo(r) => r.insert_log_at(input, timestamp).await,
+            Repository::MySql(r) => r.insert_log_at(input, timestamp).await,
+            Repository::Redis(r) => r.insert_log_at(input, timestamp).await,
+            Repository::Segments(r) => r.insert_log_at(input, timestamp).await,
+            Repository::Cold(r) => r.insert_log_at(input, timestamp).await,
+            Repository::ClickHouse(r) => r.insert_log_at(input, timestamp).await,
+            Repository::Kv(r) => r.insert_log_at(input, timestamp).await,
+            Repository::Sql(r) => r.insert_log_at(input, timestamp).await,
+        }
+    }
+
+    async fn insert_logs(&self, inputs: &[(i64, Vec<Token>)]) -> Result<()> {
+        #[cfg(feature = "chaos")]
+        {
+            crate::chaos::repository_latency().await;
+            crate::chaos::insert_failure()?;
+        }
+        match &self {
+            Repository::Mongo(r) => r.insert_logs(inputs).await,
+            Repository::MySql(r) => r.insert_logs(inputs).await,
+            Repository::Redis(r) => r.insert_logs(inputs).await,
+            Repository::Segments(r) => r.insert_logs(inputs).await,
+            Repository::Cold(r) => r.insert_logs(inputs).await,
+            Repository::ClickHouse(r) => r.insert_logs(inputs).await,
+            Repository::Kv(r) => r.insert_logs(inputs).await,
+            Repository::Sql(r) => r.insert_logs(inputs).await,
+        }
+    }
+
+    async fn insert_log_with_words(
+        &self,
+        input: &[Token],
+        timestamp: i64,
+        words: &[(String, Token)],
+    ) -> Result<LogId> {
+        #[cfg(feature = "chaos")]
+        {
+            crate::chaos::repository_latency().await;
+            crate::chaos::insert_failure()?;
+        }
+        match &self {
+            Repository::Mongo(r) => r.insert_log_with_words(input, timestamp, words).await,
+            Repository::MySql(r) => r.insert_log_with_words(input, timestamp, words).await,
+            Repository::Redis(r) => r.insert_log_with_words(input, timestamp, words).await,
+            Repository::Segments(r) => r.insert_log_with_words(input, timestamp, words).await,
+            Repository::Cold(r) => r.insert_log_with_words(input, timestamp, words).await,
+            Repository::ClickHouse(r) => r.insert_log_with_words(input, timestamp, words).await,
+            Repository::Kv(r) => r.insert_log_with_words(input, timestamp, words).await,
+            Repository::Sql(r) => r.insert_log_with_words(input, timestamp, words).await,
+        }
+    }
+
+    async fn insert_logs_with_words(
+        &self,
+        inputs: &[(i64, Vec<Token>)],
+        words: &[(String, Token)],
+    ) -> Result<()> {
+        #[cfg(feature = "chaos")]
+        {
+            crate::chaos::repository_latency().await;
+            crate::chaos::insert_failure()?;
+        }
+        match &self {
+            Repository::Mongo(r) => r.insert_logs_with_words(inputs, words).await,
+            Repository::MySql(r) => r.insert_logs_with_words(inputs, words).await,
+            Repository::Redis(r) => r.insert_logs_with_words(inputs, words).await,
+            Repository::Segments(r) => r.insert_logs_with_words(inputs, words).await,
+            Repository::Cold(r) => r.insert_logs_with_words(inputs, words).await,
+            Repository::ClickHouse(r) => r.insert_logs_with_words(inputs, words).await,
+            Repository::Kv(r) => r.insert_logs_with_words(inputs, words).await,
+            Repository::Sql(r) => r.insert_logs_with_words(inputs, words).await,
+        }
+    }
+
+    async fn find_log_by_id(&self, id: &LogId) -> Result<Option<Log>> {
+        #[cfg(feature = "chaos")]
+        crate::chaos::repository_latency().await;
+        match &self {
+            Repository::Mongo(r) => r.find_log_by_id(id).await,
+            Repository::MySql(r) => r.find_log_by_id(id).await,
+            Repository::Redis(r) => r.find_log_by_id(id).await,
+            Repository::Segments(r) => r.find_log_by_id(id).await,
+            Repository::Cold(r) => r.find_log_by_id(id).await,
+            Repository::ClickHouse(r) => r.find_log_by_id(id).await,
+            Repository::Kv(r) => r.find_log_by_id(id).await,
+            Repository::Sql(r) => r.find_log_by_id(id).await,
+        }
+    }
+
+    async fn find_words(&self) -> Result<Vec<(String, Token)>> {
+        #[cfg(feature = "chaos")]
+        crate::chaos::repository_latency().await;
         match &self {
-            Repository::Mongo(r) => r.insert_log(input).await,
-            Repository::Sql(r) => r.insert_log(input).await,
+            Repository::Mongo(r) => r.find_words().await,
+            Repository::MySql(r) => r.find_words().await,
+            Repository::Redis(r) => r.find_words().await,
+            Repository::Segments(r) => r.find_words().await,
+            Repository::Cold(r) => r.find_words().await,
+            Repository::ClickHouse(r) => r.find_words().await,
+            Repository::Kv(r) => r.find_words().await,
+            Repository::Sql(r) => r.find_words().await,
         }
     }
 
-    async fn find_logs(&self, from: &Duration, to: &Duration) -> Result<Vec<Vec<u32>>> {
+    async fn find_logs(&self, from: &Duration, to: &Duration) -> Result<Vec<Log>> {
+        #[cfg(feature = "chaos")]
+        crate::chaos::repository_latency().await;
         match &self {
             Repository::Mongo(r) => r.find_logs(from, to).await,
+            Repository::MySql(r) => r.find_logs(from, to).await,
+            Repository::Redis(r) => r.find_logs(from, to).await,
+            Repository::Segments(r) => r.find_logs(from, to).await,
+            Repository::Cold(r) => r.find_logs(from, to).await,
+            Repository::ClickHouse(r) => r.find_logs(from, to).await,
+            Repository::Kv(r) => r.find_logs(from, to).await,
             Repository::Sql(r) => r.find_logs(from, to).await,
         }
     }
 
+    async fn find_logs_with_any(
+        &self,
+        from: &Duration,
+        to: &Duration,
+        tokens: &[Token],
+    ) -> Result<Vec<Log>> {
+        #[cfg(feature = "chaos")]
+        crate::chaos::repository_latency().await;
+        match &self {
+            Repository::Mongo(r) => r.find_logs_with_any(from, to, tokens).await,
+            Repository::MySql(r) => r.find_logs_with_any(from, to, tokens).await,
+            Repository::Redis(r) => r.find_logs_with_any(from, to, tokens).await,
+            Repository::Segments(r) => r.find_logs_with_any(from, to, tokens).await,
+            Repository::Cold(r) => r.find_logs_with_any(from, to, tokens).await,
+            Repository::ClickHouse(r) => r.find_logs_with_any(from, to, tokens).await,
+            Repository::Kv(r) => r.find_logs_with_any(from, to, tokens).await,
+            Repository::Sql(r) => r.find_logs_with_any(from, to, tokens).await,
+        }
+    }
+
+    async fn find_logs_page(
+        &self,
+        from: &Duration,
+        to: &Duration,
+        limit: usize,
+        page: &Page,
+    ) -> Result<LogsPage> {
+        #[cfg(feature = "chaos")]
+        crate::chaos::repository_latency().await;
+        match &self {
+            Repository::Mongo(r) => r.find_logs_page(from, to, limit, page).await,
+            Repository::MySql(r) => r.find_logs_page(from, to, limit, page).await,
+            Repository::Redis(r) => r.find_logs_page(from, to, limit, page).await,
+            Repository::Segments(r) => r.find_logs_page(from, to, limit, page).await,
+            Repository::Cold(r) => r.find_logs_page(from, to, limit, page).await,
+            Repository::ClickHouse(r) => r.find_logs_page(from, to, limit, page).await,
+            Repository::Kv(r) => r.find_logs_page(from, to, limit, page).await,
+            Repository::Sql(r) => r.find_logs_page(from, to, limit, page).await,
+        }
+    }
+
+    fn find_logs_stream(&self, from: &Duration, to: &Duration) -> LogsStream<'_> {
+        match &self {
+            Repository::Mongo(r) => r.find_logs_stream(from, to),
+            Repository::MySql(r) => r.find_logs_stream(from, to),
+            Repository::Redis(r) => r.find_logs_stream(from, to),
+            Repository::Segments(r) => r.find_logs_stream(from, to),
+            Repository::Cold(r) => r.find_logs_stream(from, to),
+            Repository::ClickHouse(r) => r.find_logs_stream(from, to),
+            Repository::Kv(r) => r.find_logs_stream(from, to),
+            Repository::Sql(r) => r.find_logs_stream(from, to),
+        }
+    }
+
+    async fn expire_logs(&self, before: &Duration) -> Result<u64> {
+        match &self {
+            Repository::Mongo(r) => r.expire_logs(before).await,
+            Repository::MySql(r) => r.expire_logs(before).await,
+            Repository::Redis(r) => r.expire_logs(before).await,
+            Repository::Segments(r) => r.expire_logs(before).await,
+            Repository::Cold(r) => r.expire_logs(before).await,
+            Repository::ClickHouse(r) => r.expire_logs(before).await,
+            Repository::Kv(r) => r.expire_logs(before).await,
+            Repository::Sql(r) => r.expire_logs(before).await,
+        }
+    }
+
+    async fn compact(&self) -> Result<()> {
+        match &self {
+            Repository::Mongo(r) => r.compact().await,
+            Repository::MySql(r) => r.compact().await,
+            Repository::Redis(r) => r.compact().await,
+            Repository::Segments(r) => r.compact().await,
+            Repository::Cold(r) => r.compact().await,
+            Repository::ClickHouse(r) => r.compact().await,
+            Repository::Kv(r) => r.compact().await,
+            Repository::Sql(r) => r.compact().await,
+        }
+    }
+
+    async fn count_occurrence(&self, input: &[Token], since: &Duration) -> Result<bool> {
+        match &self {
+            Repository::Mongo(r) => r.count_occurrence(input, since).await,
+            Repository::MySql(r) => r.count_occurrence(input, since).await,
+            Repository::Redis(r) => r.count_occurrence(input, since).await,
+            Repository::Segments(r) => r.count_occurrence(input, since).await,
+            Repository::Cold(r) => r.count_occurrence(input, since).await,
+            Repository::ClickHouse(r) => r.count_occurrence(input, since).await,
+            Repository::Kv(r) => r.count_occurrence(input, since).await,
+            Repository::Sql(r) => r.count_occurrence(input, since).await,
+        }
+    }
+
     async fn close(&self) {
         match &self {
             Repository::Mongo(r) => r.close().await,
+            Repository::MySql(r) => r.close().await,
+            Repository::Redis(r) => r.close().await,
+            Repository::Segments(r) => r.close().await,
+            Repository::Cold(r) => r.close().await,
+            Repository::ClickHouse(r) => r.close().await,
+            Repository::Kv(r) => r.close().await,
             Repository::Sql(r) => r.close().await,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn on_new_should_select_repository_by_db_url() {
+        let s: Setup = serde_yaml::from_str("ip: 0.0.0.0\nport: 8000\ndb_url: \"\"\n").unwrap();
+        let repo = Repository::new(&s).await.unwrap();
+        assert!(matches!(repo, Repository::Sql(_)));
+        assert_eq!(repo.to_string(), "SQLite");
+
+        let dir = std::env::temp_dir().join("scribe_select_kv");
+        let s: Setup = serde_yaml::from_str(&format!(
+            "ip: 0.0.0.0\nport: 8000\ndb_url: \"sled://{}\"\n",
+            dir.display()
+        ))
+        .unwrap();
+        let repo = Repository::new(&s).await.unwrap();
+        assert!(matches!(repo, Repository::Kv(_)));
+        drop(repo);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn on_check_settings_should_refuse_settings_the_repository_ignores() {
+        let s: Setup = serde_yaml::from_str(
+            "ip: 0.0.0.0\nport: 8000\ndb_url: \"\"\ncompress: true\nretention: 60\n",
+        )
+        .unwrap();
+        assert!(check_settings(&s).is_ok());
+
+        for setting in [
+            "retention: 60",
+            "compress: true",
+            "partition: day",
+            "token_index: true",
+            "read_url: redis://replica:6379/",
+            "cold_url: s3://bucket/logs",
+        ] {
+            let s: Setup = serde_yaml::from_str(&format!(
+                "ip: 0.0.0.0\nport: 8000\ndb_url: \"redis://localhost:6379/\"\n{}\n",
+                setting
+            ))
+            .unwrap();
+            let Err(RepositoryError::InvalidInput(e)) = check_settings(&s) else {
+                panic!("{} is not refused", setting);
+            };
+            assert!(e.contains("Redis"));
+        }
+
+        let s: Setup = serde_yaml::from_str(
+            "ip: 0.0.0.0\nport: 8000\ndb_url: \"segments://logs\"\ncold_url: s3://bucket/logs\n",
+        )
+        .unwrap();
+        assert!(check_settings(&s).is_ok());
+    }
+}
@@ -0,0 +1,137 @@
+use super::interface::LogId;
+use std::time::Duration;
+
+const PREFIX: &str = "logs_";
+const NANOS_PER_SEC: i64 = 1_000_000_000;
+
+/// Partitions split time in to periods of equal span, logs of each period are stored apart,
+/// so queries read only periods their range intersects and expiry drops whole periods.
+/// A partition is named after the second its period starts at, like `logs_1700006400`.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Partitions {
+    span: i64,
+}
+
+impl Partitions {
+    /// Creates partitions of given span, rounded down to whole seconds but at least one second long.
+    ///
+    pub fn new(span: Duration) -> Self {
+        Self {
+            span: (span.as_secs() as i64).max(1),
+        }
+    }
+
+    /// Returns the start in seconds of the partition the timestamp in nanoseconds falls in.
+    ///
+    #[inline(always)]
+    pub fn of(&self, timestamp: i64) -> i64 {
+        timestamp.div_euclid(NANOS_PER_SEC).div_euclid(self.span) * self.span
+    }
+
+    /// Returns the nanosecond right after the last one of the partition starting at start.
+    ///
+    #[inline(always)]
+    pub fn end(&self, start: i64) -> i64 {
+        start
+            .saturating_add(self.span)
+            .saturating_mul(NANOS_PER_SEC)
+    }
+
+    /// Returns starts of the named partitions intersecting the range of nanoseconds from to inclusive, oldest first.
+    /// Names of other tables or collections are skipped.
+    ///
+    pub fn intersecting<S: AsRef<str>>(
+        &self,
+        names: impl IntoIterator<Item = S>,
+        from: i64,
+        to: i64,
+    ) -> Vec<i64> {
+        let (from, to) = (self.of(from), self.of(to));
+        let mut starts: Vec<i64> = names
+            .into_iter()
+            .filter_map(|name| parse(name.as_ref()))
+            .filter(|start| (from..=to).contains(start))
+            .collect();
+        starts.sort_unstable();
+        starts
+    }
+}
+
+/// Returns the name of the partition starting at start.
+///
+#[inline(always)]
+pub fn name(start: i64) -> String {
+    format!("{}{}", PREFIX, start)
+}
+
+/// Returns the start of the partition of the name, None if it does not name a partition.
+///
+#[inline(always)]
+pub fn parse(name: &str) -> Option<i64> {
+    name.strip_prefix(PREFIX)?.parse().ok()
+}
+
+/// Returns the id of a log of the partition starting at start, made of the start and the id within the partition.
+///
+#[inline(always)]
+pub fn log_id(start: i64, id: impl std::fmt::Display) -> LogId {
+    LogId(format!("{}.{}", start, id))
+}
+
+/// Splits the id of a partitioned log in to the start of its partition and the id within the partition.
+/// Returns None if the id is malformed.
+///
+#[inline(always)]
+pub fn split_id(id: &LogId) -> Option<(i64, &str)> {
+    let (start, id) = id.0.split_once('.')?;
+    Some((start.parse().ok()?, id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: i64 = 3600;
+
+    #[test]
+    fn on_of_should_return_the_start_of_the_period() {
+        let p = Partitions::new(Duration::from_secs(HOUR as u64));
+
+        assert_eq!(p.of(0), 0);
+        assert_eq!(p.of(HOUR * NANOS_PER_SEC - 1), 0);
+        assert_eq!(p.of(HOUR * NANOS_PER_SEC), HOUR);
+        assert_eq!(p.of(-1), -HOUR);
+        assert_eq!(p.end(0), HOUR * NANOS_PER_SEC);
+    }
+
+    #[test]
+    fn on_intersecting_should_keep_partitions_of_the_range_in_order() {
+        let p = Partitions::new(Duration::from_secs(HOUR as u64));
+        let names = [
+            name(2 * HOUR),
+            "serializer".to_string(),
+            name(0),
+            name(HOUR),
+            name(5 * HOUR),
+        ];
+
+        assert_eq!(
+            p.intersecting(&names, HOUR * NANOS_PER_SEC - 1, 2 * HOUR * NANOS_PER_SEC),
+            vec![0, HOUR, 2 * HOUR]
+        );
+        assert_eq!(
+            p.intersecting(&names, 3 * HOUR * NANOS_PER_SEC, i64::MAX),
+            vec![5 * HOUR]
+        );
+    }
+
+    #[test]
+    fn on_split_id_should_return_the_parts_of_log_id() {
+        let id = log_id(HOUR, 42);
+
+        assert_eq!(split_id(&id), Some((HOUR, "42")));
+        assert_eq!(split_id(&LogId("42".to_string())), None);
+        assert_eq!(split_id(&LogId("x.42".to_string())), None);
+    }
+}
@@ -0,0 +1,460 @@
+use super::error::{RepositoryError, Result};
+use super::interface::{
+    paged, Cursor, Log, LogId, LogsPage, LogsStream, Page, RepositoryProvider, STREAM_PAGE,
+};
+use ::redis::aio::ConnectionManager;
+use ::redis::{AsyncCommands, Client};
+use scribe::token::Token;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const KEY_LOGS: &str = "scribe:logs";
+const KEY_SEQUENCE: &str = "scribe:logs:sequence";
+const KEY_WORDS: &str = "scribe:words";
+const SEQUENCE_WIDTH: usize = 8;
+
+/// WarehouseRedis serves access to Redis repository via facade methods.
+/// Logs are kept in a sorted set scored by the timestamp in microseconds,
+/// a score that is exact in the 53 bits of a double for the next few centuries.
+/// Only logs of the last window are kept, older ones are dropped on every insert,
+/// so it suits a low latency tier of recent logs for live tail rather than an archive.
+/// Words of the dictionary are kept in a hash of words by number, outside of the window,
+/// so logs of the window are always read back.
+///
+#[derive(Clone)]
+pub struct WarehouseRedis {
+    conn: ConnectionManager,
+    window: Duration,
+}
+
+impl std::fmt::Debug for WarehouseRedis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WarehouseRedis")
+            .field("window", &self.window)
+            .finish()
+    }
+}
+
+impl WarehouseRedis {
+    /// New creates a new WarehouseRedis client keeping logs of the last window.
+    /// The connection string is of the form `redis://host:port/db`.
+    ///
+    pub async fn new(connection_str: &str, window: Duration) -> Result<Self> {
+        let client = Client::open(connection_str)?;
+        let conn = client.get_connection_manager().await?;
+
+        Ok(Self { conn, window })
+    }
+}
+
+/// Returns the score of the sorted set for the time since the epoch.
+///
+#[inline(always)]
+fn score(t: &Duration) -> f64 {
+    t.as_micros() as f64
+}
+
+/// Creates a unique member of the sorted set from the sequence number and the encoded log.
+/// The big endian sequence prefix keeps logs of the same score in order of insertion,
+/// as members of equal score are ordered by their bytes.
+///
+#[inline(always)]
+fn member(sequence: u64, data: &[u8]) -> Vec<u8> {
+    let mut m = Vec::with_capacity(SEQUENCE_WIDTH + data.len());
+    m.extend_from_slice(&sequence.to_be_bytes());
+    m.extend_from_slice(data);
+    m
+}
+
+/// Returns the sequence number and the encoded log held by the member.
+///
+#[inline(always)]
+fn parts(member: &[u8]) -> Result<(u64, &[u8])> {
+    if member.len() < SEQUENCE_WIDTH {
+        return Err(RepositoryError::Corrupt("member is too short".to_string()));
+    }
+    let (sequence, data) = member.split_at(SEQUENCE_WIDTH);
+    let mut bytes = [0u8; SEQUENCE_WIDTH];
+    bytes.copy_from_slice(sequence);
+    Ok((u64::from_be_bytes(bytes), data))
+}
+
+impl WarehouseRedis {
+    /// Adds the logs numbered from the first sequence number on and the words in a single transaction,
+    /// dropping logs older than the window.
+    ///
+    async fn write<'a>(
+        &self,
+        conn: &mut ConnectionManager,
+        first: u64,
+        inputs: impl IntoIterator<Item = (i64, &'a [Token])>,
+        words: &[(String, Token)],
+    ) -> Result<()> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut pipe = ::redis::pipe();
+        pipe.atomic();
+        if !words.is_empty() {
+            let fields: Vec<(i64, &str)> = words
+                .iter()
+                .map(|(word, num)| (i64::from(*num), word.as_str()))
+                .collect();
+            pipe.hset_multiple(KEY_WORDS, &fields).ignore();
+        }
+        for (sequence, (timestamp, input)) in (first..).zip(inputs) {
+            let timestamp = Duration::from_nanos(timestamp.max(0) as u64);
+            pipe.zadd(
+                KEY_LOGS,
+                member(sequence, &Token::encode(input)),
+                score(&timestamp),
+            )
+            .ignore();
+        }
+        pipe.zrembyscore(
+            KEY_LOGS,
+            "-inf",
+            format!("({}", score(&now.saturating_sub(self.window))),
+        )
+        .ignore()
+        .query_async::<_, ()>(conn)
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl RepositoryProvider for WarehouseRedis {
+    /// Checks the connection, the sorted set needs no schema.
+    ///
+    async fn migrate(&self) -> Result<()> {
+        let mut conn = self.conn.clone();
+        ::redis::cmd("PING")
+            .query_async::<_, String>(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Inserts single log at the timestamp and drops logs older than the window.
+    /// The log is identified by its sequence number.
+    ///
+    async fn insert_log_at(&self, input: &[Token], timestamp: i64) -> Result<LogId> {
+        self.insert_log_with_words(input, timestamp, &[]).await
+    }
+
+    /// Inserts logs with their timestamps in a single transaction, numbered by one increment of the sequence.
+    ///
+    async fn insert_logs(&self, inputs: &[(i64, Vec<Token>)]) -> Result<()> {
+        self.insert_logs_with_words(inputs, &[]).await
+    }
+
+    /// Inserts single log at the timestamp and the words in a single transaction.
+    ///
+    async fn insert_log_with_words(
+        &self,
+        input: &[Token],
+        timestamp: i64,
+        words: &[(String, Token)],
+    ) -> Result<LogId> {
+        let mut conn = self.conn.clone();
+        let sequence = conn.incr::<_, _, u64>(KEY_SEQUENCE, 1).await?;
+        self.write(&mut conn, sequence, [(timestamp, input)], words)
+            .await?;
+
+        Ok(LogId(sequence.to_string()))
+    }
+
+    /// Inserts logs with their timestamps and the words in a single transaction.
+    ///
+    async fn insert_logs_with_words(
+        &self,
+        inputs: &[(i64, Vec<Token>)],
+        words: &[(String, Token)],
+    ) -> Result<()> {
+        let mut conn = self.conn.clone();
+        let first = match inputs.len() {
+            0 => 0,
+            len => conn.incr::<_, _, u64>(KEY_SEQUENCE, len).await? + 1 - len as u64,
+        };
+        self.write(
+            &mut conn,
+            first,
+            inputs.iter().map(|(t, input)| (*t, input.as_slice())),
+            words,
+        )
+        .await
+    }
+
+    /// Gets words saved in the hash of words ordered by number.
+    ///
+    async fn find_words(&self) -> Result<Vec<(String, Token)>> {
+        let mut conn = self.conn.clone();
+        let mut fields = conn.hgetall::<_, Vec<(i64, String)>>(KEY_WORDS).await?;
+        fields.sort_unstable_by_key(|(num, _)| *num);
+        fields
+            .into_iter()
+            .map(|(num, word)| Ok((word, Token::try_from(num)?)))
+            .collect()
+    }
+
+    /// Gets data in time span with microsecond precision, timestamps of logs included.
+    ///
+    async fn find_logs(&self, from: &Duration, to: &Duration) -> Result<Vec<Log>> {
+        let mut conn = self.conn.clone();
+        let members = conn
+            .zrangebyscore_withscores::<_, _, _, Vec<(Vec<u8>, f64)>>(
+                KEY_LOGS,
+                score(from),
+                score(to),
+            )
+            .await?;
+
+        let mut result = Vec::with_capacity(members.len());
+        for (m, micros) in members.iter() {
+            let (sequence, data) = parts(m)?;
+            result.push(Log {
+                id: LogId(sequence.to_string()),
+                timestamp: *micros as i64 * 1000,
+                data: Token::decode(data)?,
+            });
+        }
+
+        Ok(result)
+    }
+
+    /// Gets a page of logs in time span with the limit of the sorted set range, so a page reads only its own members.
+    /// The cursor is the score and the sequence of the last log, the next page starts at the score
+    /// skipping members of the same score up to the sequence.
+    ///
+    fn find_logs_stream(&self, from: &Duration, to: &Duration) -> LogsStream<'_> {
+        let (from, to) = (*from, *to);
+        paged(move |page| async move { self.find_logs_page(&from, &to, STREAM_PAGE, &page).await })
+    }
+
+    async fn find_logs_page(
+        &self,
+        from: &Duration,
+        to: &Duration,
+        limit: usize,
+        page: &Page,
+    ) -> Result<LogsPage> {
+        let (min, mut offset, after) = match page {
+            Page::Offset(offset) => (score(from), *offset, None),
+            Page::After(cursor) => {
+                let (micros, sequence) = cursor.parts()?;
+                let Ok(sequence) = sequence.parse::<u64>() else {
+                    return Err(RepositoryError::InvalidInput(
+                        "malformed cursor".to_string(),
+                    ));
+                };
+                let micros = (micros as f64).max(score(from));
+                (micros, 0, Some((micros, sequence)))
+            }
+        };
+        let mut conn = self.conn.clone();
+        let mut page = LogsPage {
+            logs: Vec::with_capacity(limit),
+            next: None,
+        };
+        while page.logs.len() < limit {
+            let count = limit - page.logs.len();
+            let members = conn
+                .zrangebyscore_limit_withscores::<_, _, _, Vec<(Vec<u8>, f64)>>(
+                    KEY_LOGS,
+                    min,
+                    score(to),
+                    offset as isize,
+                    count as isize,
+                )
+                .await?;
+            offset += members.len();
+            let last = members.len() < count;
+            for (m, micros) in members.iter() {
+                let (sequence, data) = parts(m)?;
+                if after.is_some_and(|(a, s)| *micros == a && sequence <= s) {
+                    continue;
+                }
+                page.logs.push(Log {
+                    id: LogId(sequence.to_string()),
+                    timestamp: *micros as i64 * 1000,
+                    data: Token::decode(data)?,
+                });
+                page.next = Some(Cursor::new(*micros as i64, sequence));
+            }
+            if last {
+                page.next = None;
+                break;
+            }
+        }
+        Ok(page)
+    }
+
+    /// Connections are closed once the last clone of the manager is dropped.
+    ///
+    async fn close(&self) {}
+}
+
+#[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
+mod tests {
+    use super::*;
+
+    fn now_nanos() -> i64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as i64
+    }
+
+    const CONNECTION_STR_TEST: &str = "redis://localhost:6379/";
+
+    #[test]
+    fn on_member_should_order_by_sequence_and_keep_the_data() {
+        let encoded = Token::encode(&[Token::from(7), Token::from(1)]);
+        let first = member(255, &encoded);
+        let second = member(256, &encoded);
+
+        assert!(first < second);
+        assert_eq!(parts(&second).unwrap(), (256, encoded.as_slice()));
+        assert!(parts(&[0, 1, 2]).is_err());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn on_insert_should_find_recent_logs_and_drop_logs_out_of_the_window() {
+        let Ok(warehouse) =
+            WarehouseRedis::new(CONNECTION_STR_TEST, Duration::from_millis(200)).await
+        else {
+            assert!(false);
+            return;
+        };
+        let Ok(_) = warehouse.migrate().await else {
+            assert!(false);
+            return;
+        };
+
+        let from = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let old = vec![Token::from(1), Token::from(2)];
+        let Ok(_) = warehouse.insert_log_at(&old, now_nanos()).await else {
+            assert!(false);
+            return;
+        };
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let recent = vec![Token::from(3)];
+        for _ in 0..2 {
+            let Ok(_) = warehouse.insert_log_at(&recent, now_nanos()).await else {
+                assert!(false);
+                return;
+            };
+        }
+        let to = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        let Ok(result) = warehouse.find_logs(&from, &to).await else {
+            assert!(false);
+            return;
+        };
+
+        assert_eq!(
+            result.into_iter().map(|l| l.data).collect::<Vec<_>>(),
+            vec![recent.clone(), recent]
+        );
+        warehouse.close().await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn on_insert_with_words_should_find_the_words_after_the_logs_left_the_window() {
+        let Ok(warehouse) =
+            WarehouseRedis::new(CONNECTION_STR_TEST, Duration::from_millis(100)).await
+        else {
+            assert!(false);
+            return;
+        };
+        let mut conn = warehouse.conn.clone();
+        let Ok(()) = conn.del::<_, ()>(KEY_WORDS).await else {
+            assert!(false);
+            return;
+        };
+        let words = vec![
+            ("first".to_string(), Token::from(1)),
+            ("second".to_string(), Token::from(2)),
+        ];
+        let Ok(_) = warehouse
+            .insert_log_with_words(&[Token::from(2)], 0, &words[1..])
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        let Ok(()) = warehouse
+            .insert_logs_with_words(&[(0, vec![Token::from(1)])], &words[..1])
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+
+        let Ok(found) = warehouse.find_words().await else {
+            assert!(false);
+            return;
+        };
+
+        assert_eq!(found, words);
+        warehouse.close().await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn on_find_logs_page_should_page_logs_of_the_same_score_by_cursor() {
+        let Ok(warehouse) = WarehouseRedis::new(CONNECTION_STR_TEST, Duration::from_secs(60)).await
+        else {
+            assert!(false);
+            return;
+        };
+        let mut conn = warehouse.conn.clone();
+        let Ok(()) = conn.del::<_, ()>(KEY_LOGS).await else {
+            assert!(false);
+            return;
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let logs: Vec<(i64, Vec<Token>)> = (0..5u32)
+            .map(|i| (now.as_nanos() as i64, vec![Token::from(i)]))
+            .collect();
+        let Ok(()) = warehouse.insert_logs(&logs).await else {
+            assert!(false);
+            return;
+        };
+        let (from, to) = (now, now + Duration::from_secs(1));
+
+        let Ok(page) = warehouse
+            .find_logs_page(&from, &to, 2, &Page::Offset(1))
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(page.logs.len(), 2);
+        assert_eq!(page.logs[0].data, logs[1].1);
+
+        let mut found = Vec::new();
+        let mut next = Page::Offset(0);
+        loop {
+            let Ok(page) = warehouse.find_logs_page(&from, &to, 2, &next).await else {
+                assert!(false);
+                return;
+            };
+            found.extend(page.logs.into_iter().map(|l| l.data));
+            match page.next {
+                Some(cursor) => next = Page::After(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(found, logs.into_iter().map(|(_, l)| l).collect::<Vec<_>>());
+        warehouse.close().await;
+    }
+}
@@ -0,0 +1,239 @@
+use super::error::{RepositoryError, Result};
+use super::interface::{Log, LogId, LogsPage, LogsStream, Page, RepositoryProvider};
+use rand::Rng;
+use scribe::token::Token;
+use std::future::Future;
+use std::time::Duration;
+
+/// Longest wait between two attempts of an operation.
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// RetryPolicy describes how an operation failing on a lost connection is retried.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts of an operation, the first one included, 1 means no retries.
+    pub attempts: u32,
+    /// Wait before the first retry, doubled for every next one up to MAX_BACKOFF.
+    pub backoff: Duration,
+    /// Fraction of the wait drawn at random, so clients failing together do not retry together.
+    pub jitter: f64,
+}
+
+/// Returns true if the error comes from a lost or refused connection or a timeout,
+/// so the operation may succeed once the database is back.
+/// Any other error is permanent and retrying it only delays the failure.
+///
+#[inline(always)]
+pub fn is_transient(e: &RepositoryError) -> bool {
+    matches!(
+        e,
+        RepositoryError::NotConnected(_) | RepositoryError::Timeout(_)
+    )
+}
+
+impl RetryPolicy {
+    /// Returns the wait before the retry, counted from 0.
+    ///
+    fn delay(&self, retry: u32) -> Duration {
+        let backoff = self
+            .backoff
+            .saturating_mul(1 << retry.min(16))
+            .min(MAX_BACKOFF);
+        let jitter = self.jitter.clamp(0.0, 1.0) * rand::thread_rng().gen::<f64>();
+        backoff.mul_f64(1.0 - jitter)
+    }
+
+    /// Runs the operation until it succeeds, fails with a permanent error or runs out of attempts.
+    ///
+    pub async fn run<T, F, Fut>(&self, mut operation: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut retry = 0;
+        loop {
+            match operation().await {
+                Err(e) if retry + 1 < self.attempts && is_transient(&e) => {
+                    tracing::warn!(error = %e, retry, "retrying repository operation");
+                    tokio::time::sleep(self.delay(retry)).await;
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Retrying wraps a repository, retrying its operations that fail on transient errors with the policy,
+/// so a brief failover of the database is not seen by clients.
+/// An insert retried after it reached the database may store the log twice.
+/// Streams are not retried, as part of the logs may already be sent.
+///
+#[derive(Debug, Clone)]
+pub struct Retrying<R: RepositoryProvider> {
+    repo: R,
+    policy: RetryPolicy,
+}
+
+impl<R: RepositoryProvider> Retrying<R> {
+    pub fn new(repo: R, policy: RetryPolicy) -> Self {
+        Self { repo, policy }
+    }
+}
+
+impl<R: RepositoryProvider> RepositoryProvider for Retrying<R> {
+    async fn migrate(&self) -> Result<()> {
+        self.policy.run(|| self.repo.migrate()).await
+    }
+
+    async fn insert_log_at(&self, input: &[Token], timestamp: i64) -> Result<LogId> {
+        self.policy
+            .run(|| self.repo.insert_log_at(input, timestamp))
+            .await
+    }
+
+    async fn insert_logs(&self, inputs: &[(i64, Vec<Token>)]) -> Result<()> {
+        self.policy.run(|| self.repo.insert_logs(inputs)).await
+    }
+
+    async fn insert_log_with_words(
+        &self,
+        input: &[Token],
+        timestamp: i64,
+        words: &[(String, Token)],
+    ) -> Result<LogId> {
+        self.policy
+            .run(|| self.repo.insert_log_with_words(input, timestamp, words))
+            .await
+    }
+
+    async fn insert_logs_with_words(
+        &self,
+        inputs: &[(i64, Vec<Token>)],
+        words: &[(String, Token)],
+    ) -> Result<()> {
+        self.policy
+            .run(|| self.repo.insert_logs_with_words(inputs, words))
+            .await
+    }
+
+    async fn find_words(&self) -> Result<Vec<(String, Token)>> {
+        self.policy.run(|| self.repo.find_words()).await
+    }
+
+    async fn find_logs(&self, from: &Duration, to: &Duration) -> Result<Vec<Log>> {
+        self.policy.run(|| self.repo.find_logs(from, to)).await
+    }
+
+    async fn find_logs_with_any(
+        &self,
+        from: &Duration,
+        to: &Duration,
+        tokens: &[Token],
+    ) -> Result<Vec<Log>> {
+        self.policy
+            .run(|| self.repo.find_logs_with_any(from, to, tokens))
+            .await
+    }
+
+    async fn find_log_by_id(&self, id: &LogId) -> Result<Option<Log>> {
+        self.policy.run(|| self.repo.find_log_by_id(id)).await
+    }
+
+    async fn find_logs_page(
+        &self,
+        from: &Duration,
+        to: &Duration,
+        limit: usize,
+        page: &Page,
+    ) -> Result<LogsPage> {
+        self.policy
+            .run(|| self.repo.find_logs_page(from, to, limit, page))
+            .await
+    }
+
+    fn find_logs_stream(&self, from: &Duration, to: &Duration) -> LogsStream<'_> {
+        self.repo.find_logs_stream(from, to)
+    }
+
+    async fn expire_logs(&self, before: &Duration) -> Result<u64> {
+        self.policy.run(|| self.repo.expire_logs(before)).await
+    }
+
+    async fn compact(&self) -> Result<()> {
+        self.policy.run(|| self.repo.compact()).await
+    }
+
+    async fn count_occurrence(&self, input: &[Token], since: &Duration) -> Result<bool> {
+        self.policy
+            .run(|| self.repo.count_occurrence(input, since))
+            .await
+    }
+
+    async fn close(&self) {
+        self.repo.close().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    const POLICY: RetryPolicy = RetryPolicy {
+        attempts: 3,
+        backoff: Duration::from_millis(100),
+        jitter: 0.5,
+    };
+
+    #[tokio::test(start_paused = true)]
+    async fn on_transient_errors_should_retry_until_success_or_out_of_attempts() {
+        let calls = Cell::new(0);
+        let result = POLICY
+            .run(|| async {
+                calls.set(calls.get() + 1);
+                match calls.get() {
+                    3 => Ok(calls.get()),
+                    _ => Err(RepositoryError::NotConnected("failover".to_string())),
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 3);
+
+        calls.set(0);
+        let result: Result<()> = POLICY
+            .run(|| async {
+                calls.set(calls.get() + 1);
+                Err(RepositoryError::Timeout("failover".to_string()))
+            })
+            .await;
+        assert!(matches!(result, Err(RepositoryError::Timeout(_))));
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn on_permanent_error_should_fail_at_once() {
+        let calls = Cell::new(0);
+        let result: Result<()> = POLICY
+            .run(|| async {
+                calls.set(calls.get() + 1);
+                Err(RepositoryError::InvalidInput(
+                    "malformed cursor".to_string(),
+                ))
+            })
+            .await;
+        assert!(matches!(result, Err(RepositoryError::InvalidInput(_))));
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn on_delay_should_double_the_backoff_up_to_the_limit_less_the_jitter() {
+        for retry in 0..3 {
+            let delay = POLICY.delay(retry);
+            let backoff = POLICY.backoff * (1 << retry);
+            assert!(delay <= backoff && delay >= backoff / 2);
+        }
+        assert!(POLICY.delay(20) <= MAX_BACKOFF);
+    }
+}
@@ -0,0 +1,996 @@
+use super::error::{RepositoryError, Result};
+use super::interface::{
+    paged, Cursor, Log, LogId, LogsPage, LogsStream, Page, RepositoryProvider, STREAM_PAGE,
+};
+use scribe::token::Token;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Time span of logs held by a single segment.
+///
+pub const SEGMENT_SPAN: Duration = Duration::from_secs(3600);
+
+pub(super) const LOG_EXTENSION: &str = "log";
+pub(super) const INDEX_EXTENSION: &str = "idx";
+const WORDS_FILE: &str = "words.dict";
+const LEN_WIDTH: usize = 4;
+const ENTRY_WIDTH: usize = 16;
+const NUM_WIDTH: usize = 8;
+
+/// Segment is the pair of files logs of one time bucket are appended to.
+/// The log file holds records of a little endian u32 length followed by the encoded log,
+/// the index file holds entries of a little endian u64 timestamp and u64 offset of the record.
+/// Records are written before their entries, so an entry never points past written data.
+///
+#[derive(Debug)]
+struct Segment {
+    id: u64,
+    log: File,
+    index: File,
+    len: u64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    last: u64,
+    segment: Option<Segment>,
+    words: Option<File>,
+}
+
+/// WarehouseSegments serves access to append only segment files in a directory via facade methods.
+/// Logs are bucketed by time in to segments, each with an index of timestamps sorted ascending,
+/// so a time span query reads only the segments of the span and seeks straight to its first log.
+/// Files are synced when a segment is left and on close,
+/// logs of the open segment survive a crash of the process but may be lost on a power failure.
+/// Words of the dictionary are appended to a file of their own next to the segments
+/// before the log bringing them, as records of a little endian u32 length, the word
+/// and its little endian u64 number, a later record of the same number replacing an earlier one.
+///
+#[derive(Debug, Clone)]
+pub struct WarehouseSegments {
+    dir: PathBuf,
+    span: u64,
+    state: Arc<Mutex<State>>,
+}
+
+impl WarehouseSegments {
+    /// Creates a new Warehouse storing segments spanning given time in the directory.
+    ///
+    pub async fn new(dir: impl Into<PathBuf>, span: Duration) -> io::Result<Self> {
+        let span = span.as_nanos() as u64;
+        if span == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "segment span cannot be zero",
+            ));
+        }
+        Ok(Self {
+            dir: dir.into(),
+            span,
+            state: Arc::new(Mutex::new(State::default())),
+        })
+    }
+}
+
+/// Returns the name of the segment file, ids are zero padded so names sort as ids do.
+///
+#[inline(always)]
+pub(super) fn segment_name(id: u64, extension: &str) -> String {
+    format!("{:020}.{}", id, extension)
+}
+
+/// Returns the id of the segment the index file name belongs to.
+///
+#[inline(always)]
+pub(super) fn index_id(name: &str) -> Option<u64> {
+    name.strip_suffix(INDEX_EXTENSION)?
+        .strip_suffix('.')?
+        .parse()
+        .ok()
+}
+
+#[inline(always)]
+fn segment_path(dir: &Path, id: u64, extension: &str) -> PathBuf {
+    dir.join(segment_name(id, extension))
+}
+
+/// Returns ids of all segments in the directory, ascending.
+///
+fn segment_ids(dir: &Path) -> io::Result<Vec<u64>> {
+    let mut ids = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        if let Some(id) = entry?.file_name().to_str().and_then(index_id) {
+            ids.push(id);
+        }
+    }
+    ids.sort_unstable();
+    Ok(ids)
+}
+
+/// Parses whole entries of the index, a torn entry left by a crash is skipped.
+///
+pub(super) fn parse_index(bytes: &[u8]) -> Vec<(u64, u64)> {
+    bytes
+        .chunks_exact(ENTRY_WIDTH)
+        .map(|e| {
+            let (timestamp, offset) = e.split_at(8);
+            (
+                u64::from_le_bytes(timestamp.try_into().unwrap_or_default()),
+                u64::from_le_bytes(offset.try_into().unwrap_or_default()),
+            )
+        })
+        .collect()
+}
+
+#[inline(always)]
+fn read_index(path: &Path) -> io::Result<Vec<(u64, u64)>> {
+    Ok(parse_index(&fs::read(path)?))
+}
+
+/// Returns positions of the first entry in the time span and of the entry past the last one.
+///
+#[inline(always)]
+pub(super) fn entries_in(entries: &[(u64, u64)], from: u64, to: u64) -> (usize, usize) {
+    (
+        entries.partition_point(|(t, _)| *t < from),
+        entries.partition_point(|(t, _)| *t <= to),
+    )
+}
+
+/// Returns the id of the record at the offset of the segment log.
+///
+#[inline(always)]
+pub(super) fn log_id(segment: u64, offset: u64) -> LogId {
+    LogId(format!("{}-{}", segment, offset))
+}
+
+/// Decodes records of the entries from bytes of the log starting at the offset of the first entry.
+///
+pub(super) fn decode_records(
+    segment: u64,
+    entries: &[(u64, u64)],
+    bytes: &[u8],
+) -> io::Result<Vec<Log>> {
+    let Some((_, start)) = entries.first() else {
+        return Ok(Vec::new());
+    };
+    let mut result = Vec::with_capacity(entries.len());
+    for (timestamp, offset) in entries.iter() {
+        let at = (offset - start) as usize;
+        let Some(len) = bytes.get(at..at + LEN_WIDTH) else {
+            return Err(Error::new(ErrorKind::InvalidData, "record is truncated"));
+        };
+        let len = u32::from_le_bytes(len.try_into().unwrap_or_default()) as usize;
+        let Some(data) = bytes.get(at + LEN_WIDTH..at + LEN_WIDTH + len) else {
+            return Err(Error::new(ErrorKind::InvalidData, "record is truncated"));
+        };
+        result.push(Log {
+            id: log_id(segment, *offset),
+            timestamp: *timestamp as i64,
+            data: Token::decode(data)?,
+        });
+    }
+    Ok(result)
+}
+
+/// Parses whole records of the words file, returning the words by number
+/// and the length of the records, a torn record left by a crash is skipped.
+///
+fn parse_words(bytes: &[u8]) -> io::Result<(BTreeMap<i64, String>, usize)> {
+    let mut words = BTreeMap::new();
+    let mut at = 0;
+    while let Some(len) = bytes.get(at..at + LEN_WIDTH) {
+        let len = u32::from_le_bytes(len.try_into().unwrap_or_default()) as usize;
+        let start = at + LEN_WIDTH;
+        let Some(num) = bytes.get(start + len..start + len + NUM_WIDTH) else {
+            break;
+        };
+        let Ok(word) = std::str::from_utf8(&bytes[start..start + len]) else {
+            return Err(Error::new(ErrorKind::InvalidData, "word is not utf-8"));
+        };
+        words.insert(
+            i64::from_le_bytes(num.try_into().unwrap_or_default()),
+            word.to_string(),
+        );
+        at = start + len + NUM_WIDTH;
+    }
+    Ok((words, at))
+}
+
+/// Opens the words file for appending, cutting off a torn record so new records stay aligned.
+///
+fn open_words(dir: &Path) -> io::Result<File> {
+    let path = dir.join(WORDS_FILE);
+    let words = OpenOptions::new().create(true).append(true).open(&path)?;
+    let (_, len) = parse_words(&fs::read(&path)?)?;
+    words.set_len(len as u64)?;
+    Ok(words)
+}
+
+/// Opens the segment for appending, cutting off a torn index entry so new entries stay aligned.
+///
+fn open_segment(dir: &Path, id: u64) -> io::Result<Segment> {
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(segment_path(dir, id, LOG_EXTENSION))?;
+    let index = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(segment_path(dir, id, INDEX_EXTENSION))?;
+    let entries = index.metadata()?.len();
+    index.set_len(entries - entries % ENTRY_WIDTH as u64)?;
+    let len = log.metadata()?.len();
+    Ok(Segment {
+        id,
+        log,
+        index,
+        len,
+    })
+}
+
+/// Returns the timestamp of the latest log stored in the directory.
+///
+fn last_timestamp(dir: &Path) -> io::Result<u64> {
+    let Some(id) = segment_ids(dir)?.pop() else {
+        return Ok(0);
+    };
+    let entries = read_index(&segment_path(dir, id, INDEX_EXTENSION))?;
+    Ok(entries.last().map(|(t, _)| *t).unwrap_or_default())
+}
+
+impl WarehouseSegments {
+    /// Returns the directory segments are stored in.
+    ///
+    pub(super) fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the id of the segment holding logs of the timestamp.
+    ///
+    #[inline(always)]
+    pub(super) fn segment_of(&self, timestamp: u64) -> u64 {
+        timestamp / self.span
+    }
+
+    /// Returns ids of all segments stored in the directory, ascending.
+    ///
+    pub(super) fn segment_ids(&self) -> io::Result<Vec<u64>> {
+        segment_ids(&self.dir)
+    }
+
+    /// Returns ids of segments no log will be appended to any more, ascending.
+    ///
+    pub(super) fn closed_ids(&self) -> io::Result<Vec<u64>> {
+        let Ok(state) = self.state.lock() else {
+            return Err(Error::other("segments state is poisoned"));
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        let open = self.segment_of(now.max(state.last));
+        let mut ids = segment_ids(&self.dir)?;
+        ids.retain(|id| *id < open);
+        Ok(ids)
+    }
+
+    /// Reads logs of the segment in the time span.
+    /// Fails with `NotFound` if the segment is not stored in the directory.
+    ///
+    pub(super) fn read_segment(&self, id: u64, from: u64, to: u64) -> io::Result<Vec<Log>> {
+        let entries = read_index(&segment_path(&self.dir, id, INDEX_EXTENSION))?;
+        let (first, last) = entries_in(&entries, from, to);
+        self.read_entries(id, &entries, first, last)
+    }
+
+    /// Reads entries of the index of the segment.
+    /// Fails with `NotFound` if the segment is not stored in the directory.
+    ///
+    pub(super) fn read_segment_index(&self, id: u64) -> io::Result<Vec<(u64, u64)>> {
+        read_index(&segment_path(&self.dir, id, INDEX_EXTENSION))
+    }
+
+    /// Reads logs of the entries of the segment from the first up to the last, exclusive.
+    ///
+    pub(super) fn read_entries(
+        &self,
+        id: u64,
+        entries: &[(u64, u64)],
+        first: usize,
+        last: usize,
+    ) -> io::Result<Vec<Log>> {
+        if first >= last {
+            return Ok(Vec::new());
+        }
+
+        let mut log = File::open(segment_path(&self.dir, id, LOG_EXTENSION))?;
+        let start = entries[first].1;
+        let end = match entries.get(last) {
+            Some((_, offset)) => *offset,
+            None => log.metadata()?.len(),
+        };
+        let mut bytes = vec![0u8; end.saturating_sub(start) as usize];
+        log.seek(SeekFrom::Start(start))?;
+        log.read_exact(&mut bytes)?;
+
+        decode_records(id, &entries[first..last], &bytes)
+    }
+
+    /// Appends records of the words to the words file, opening it on first use.
+    ///
+    fn write_words(&self, state: &mut State, words: &[(String, Token)]) -> io::Result<()> {
+        if words.is_empty() {
+            return Ok(());
+        }
+        if state.words.is_none() {
+            state.words = Some(open_words(&self.dir)?);
+        }
+        let Some(file) = state.words.as_mut() else {
+            return Err(Error::other("words file is not open"));
+        };
+        let mut records = Vec::new();
+        for (word, num) in words.iter() {
+            let Ok(len) = u32::try_from(word.len()) else {
+                return Err(Error::new(ErrorKind::InvalidInput, "word is too large"));
+            };
+            records.extend_from_slice(&len.to_le_bytes());
+            records.extend_from_slice(word.as_bytes());
+            records.extend_from_slice(&i64::from(*num).to_le_bytes());
+        }
+        file.write_all(&records)
+    }
+
+    fn append_words(&self, words: &[(String, Token)]) -> io::Result<()> {
+        let Ok(mut state) = self.state.lock() else {
+            return Err(Error::other("segments state is poisoned"));
+        };
+        self.write_words(&mut state, words)
+    }
+
+    fn append(&self, words: &[(String, Token)], data: &[u8], timestamp: u64) -> io::Result<LogId> {
+        let Ok(mut state) = self.state.lock() else {
+            return Err(Error::other("segments state is poisoned"));
+        };
+        self.write_words(&mut state, words)?;
+        // Timestamps never go back, so the index of each segment stays sorted when the clock does
+        // or a log comes with a timestamp older than the last one.
+        let timestamp = timestamp.max(state.last);
+        let id = self.segment_of(timestamp);
+
+        if state.segment.as_ref().map(|s| s.id) != Some(id) {
+            if let Some(previous) = state.segment.take() {
+                previous.log.sync_all()?;
+                previous.index.sync_all()?;
+                if let Some(words) = state.words.as_ref() {
+                    words.sync_all()?;
+                }
+            }
+            state.segment = Some(open_segment(&self.dir, id)?);
+        }
+        let Some(segment) = state.segment.as_mut() else {
+            return Err(Error::other("segment is not open"));
+        };
+
+        let Ok(len) = u32::try_from(data.len()) else {
+            return Err(Error::new(ErrorKind::InvalidInput, "log is too large"));
+        };
+        let mut record = Vec::with_capacity(LEN_WIDTH + data.len());
+        record.extend_from_slice(&len.to_le_bytes());
+        record.extend_from_slice(data);
+        segment.log.write_all(&record)?;
+
+        let mut entry = [0u8; ENTRY_WIDTH];
+        entry[..8].copy_from_slice(&timestamp.to_le_bytes());
+        entry[8..].copy_from_slice(&segment.len.to_le_bytes());
+        segment.index.write_all(&entry)?;
+
+        let appended = log_id(id, segment.len);
+        segment.len += record.len() as u64;
+        state.last = timestamp;
+        Ok(appended)
+    }
+
+    fn read_words(&self) -> io::Result<Vec<(String, Token)>> {
+        let bytes = match fs::read(self.dir.join(WORDS_FILE)) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let (words, _) = parse_words(&bytes)?;
+        words
+            .into_iter()
+            .map(|(num, word)| match Token::try_from(num) {
+                Ok(num) => Ok((word, num)),
+                Err(e) => Err(Error::new(ErrorKind::InvalidData, e.to_string())),
+            })
+            .collect()
+    }
+
+    fn read(&self, from: u64, to: u64) -> io::Result<Vec<Log>> {
+        let mut result = Vec::new();
+        for id in segment_ids(&self.dir)? {
+            if id < self.segment_of(from) || id > self.segment_of(to) {
+                continue;
+            }
+            result.extend(self.read_segment(id, from, to)?);
+        }
+        Ok(result)
+    }
+}
+
+/// PageWindow tracks how much of a page of logs read segment by segment is still to be skipped and taken.
+///
+#[derive(Debug, Clone, Copy)]
+pub(super) struct PageWindow {
+    pub(super) skip: usize,
+    pub(super) take: usize,
+    pub(super) after: Option<(u64, u64)>,
+}
+
+impl PageWindow {
+    /// Creates a window taking at most limit logs, skipping the offset or starting after the log at the position,
+    /// its segment and offset, the position of the cursor.
+    ///
+    pub(super) fn new(limit: usize, page: &Page) -> Result<Self> {
+        let (skip, after) = match page {
+            Page::Offset(offset) => (*offset, None),
+            Page::After(cursor) => (0, Some(cursor_position(cursor)?)),
+        };
+        Ok(Self {
+            skip,
+            take: limit,
+            after,
+        })
+    }
+
+    /// Tells if the segment holds no log of the window, lying before the position or past a full page.
+    ///
+    pub(super) fn passes(&self, id: u64) -> bool {
+        self.take == 0 || self.after.is_some_and(|(segment, _)| id < segment)
+    }
+
+    /// Narrows positions of entries of the segment in the time span to the logs of the window,
+    /// counting skipped and taken logs.
+    ///
+    pub(super) fn entries(
+        &mut self,
+        id: u64,
+        entries: &[(u64, u64)],
+        from: u64,
+        to: u64,
+    ) -> (usize, usize) {
+        let (mut first, last) = entries_in(entries, from, to);
+        if let Some((_, offset)) = self.after.filter(|(segment, _)| *segment == id) {
+            first = first.max(entries.partition_point(|(_, o)| *o <= offset));
+        }
+        let len = last.saturating_sub(first);
+        if self.skip >= len {
+            self.skip -= len;
+            return (first, first);
+        }
+        first += self.skip;
+        self.skip = 0;
+        let last = last.min(first + self.take);
+        self.take -= last - first;
+        (first, last)
+    }
+}
+
+/// Returns the page of logs and the cursor of the next page, the id of the last log if the page is full.
+///
+pub(super) fn logs_page(logs: Vec<Log>, limit: usize) -> LogsPage {
+    let next = match logs.last() {
+        Some(last) if logs.len() == limit => Some(Cursor(last.id.0.clone())),
+        _ => None,
+    };
+    LogsPage { logs, next }
+}
+
+impl WarehouseSegments {
+    /// Reads logs of the window in the time span, only logs of the page are read, skipped ones are counted by the index.
+    ///
+    fn read_page(&self, from: u64, to: u64, mut window: PageWindow) -> io::Result<Vec<Log>> {
+        let mut result = Vec::new();
+        for id in segment_ids(&self.dir)? {
+            if id < self.segment_of(from) || id > self.segment_of(to) || window.passes(id) {
+                continue;
+            }
+            let entries = self.read_segment_index(id)?;
+            let (first, last) = window.entries(id, &entries, from, to);
+            result.extend(self.read_entries(id, &entries, first, last)?);
+        }
+        Ok(result)
+    }
+}
+
+/// Returns the segment and the offset of the log the cursor points at.
+///
+fn cursor_position(cursor: &Cursor) -> Result<(u64, u64)> {
+    let position = cursor
+        .0
+        .split_once('-')
+        .and_then(|(segment, offset)| Some((segment.parse().ok()?, offset.parse().ok()?)));
+    match position {
+        Some(position) => Ok(position),
+        None => Err(RepositoryError::InvalidInput(
+            "malformed cursor".to_string(),
+        )),
+    }
+}
+
+impl RepositoryProvider for WarehouseSegments {
+    /// Creates the directory and reads the latest timestamp, so new logs are never stored before it.
+    ///
+    async fn migrate(&self) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let last = last_timestamp(&self.dir)?;
+        let Ok(mut state) = self.state.lock() else {
+            return Err(Error::other("segments state is poisoned").into());
+        };
+        state.last = state.last.max(last);
+        Ok(())
+    }
+
+    /// Appends single log to the segment of the timestamp,
+    /// a timestamp older than the last appended log is moved forward to it.
+    ///
+    async fn insert_log_at(&self, input: &[Token], timestamp: i64) -> Result<LogId> {
+        self.insert_log_with_words(input, timestamp, &[]).await
+    }
+
+    /// Appends the words and then single log under one hold of the segment,
+    /// so the log is never stored without the words it refers to.
+    ///
+    async fn insert_log_with_words(
+        &self,
+        input: &[Token],
+        timestamp: i64,
+        words: &[(String, Token)],
+    ) -> Result<LogId> {
+        let warehouse = self.clone();
+        let data = Token::encode(input);
+        let words = words.to_vec();
+        let timestamp = timestamp.max(0) as u64;
+        match tokio::task::spawn_blocking(move || warehouse.append(&words, &data, timestamp)).await
+        {
+            Ok(result) => Ok(result?),
+            Err(e) => Err(RepositoryError::query("cannot append log", e)),
+        }
+    }
+
+    /// Appends the words and then logs with their timestamps.
+    ///
+    async fn insert_logs_with_words(
+        &self,
+        inputs: &[(i64, Vec<Token>)],
+        words: &[(String, Token)],
+    ) -> Result<()> {
+        let warehouse = self.clone();
+        let records: Vec<_> = inputs
+            .iter()
+            .map(|(timestamp, input)| (Token::encode(input), (*timestamp).max(0) as u64))
+            .collect();
+        let mut words = words.to_vec();
+        let appended = tokio::task::spawn_blocking(move || {
+            for (data, timestamp) in records.iter() {
+                warehouse.append(&std::mem::take(&mut words), data, *timestamp)?;
+            }
+            match words.is_empty() {
+                true => Ok(()),
+                false => warehouse.append_words(&words),
+            }
+        })
+        .await;
+        match appended {
+            Ok(result) => Ok(result?),
+            Err(e) => Err(RepositoryError::query("cannot append logs", e)),
+        }
+    }
+
+    /// Gets words of the words file ordered by number.
+    ///
+    async fn find_words(&self) -> Result<Vec<(String, Token)>> {
+        let warehouse = self.clone();
+        match tokio::task::spawn_blocking(move || warehouse.read_words()).await {
+            Ok(result) => Ok(result?),
+            Err(e) => Err(RepositoryError::query("cannot read words", e)),
+        }
+    }
+
+    /// Gets data in time span.
+    ///
+    async fn find_logs(&self, from: &Duration, to: &Duration) -> Result<Vec<Log>> {
+        let warehouse = self.clone();
+        let (from, to) = (from.as_nanos() as u64, to.as_nanos() as u64);
+        match tokio::task::spawn_blocking(move || warehouse.read(from, to)).await {
+            Ok(result) => Ok(result?),
+            Err(e) => Err(RepositoryError::query("cannot read logs", e)),
+        }
+    }
+
+    /// Gets a page of logs in time span, reading only records of the page.
+    /// The cursor is the id of the last log of the page.
+    ///
+    fn find_logs_stream(&self, from: &Duration, to: &Duration) -> LogsStream<'_> {
+        let (from, to) = (*from, *to);
+        paged(move |page| async move { self.find_logs_page(&from, &to, STREAM_PAGE, &page).await })
+    }
+
+    async fn find_logs_page(
+        &self,
+        from: &Duration,
+        to: &Duration,
+        limit: usize,
+        page: &Page,
+    ) -> Result<LogsPage> {
+        let window = PageWindow::new(limit, page)?;
+        let warehouse = self.clone();
+        let (from, to) = (from.as_nanos() as u64, to.as_nanos() as u64);
+        match tokio::task::spawn_blocking(move || warehouse.read_page(from, to, window)).await {
+            Ok(result) => Ok(logs_page(result?, limit)),
+            Err(e) => Err(RepositoryError::query("cannot read logs", e)),
+        }
+    }
+
+    async fn close(&self) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        if let Some(segment) = state.segment.take() {
+            let _ = segment.log.sync_all();
+            let _ = segment.index.sync_all();
+        }
+        if let Some(words) = state.words.take() {
+            let _ = words.sync_all();
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
+mod tests {
+    use super::*;
+
+    fn now() -> Duration {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+    }
+
+    fn now_nanos() -> i64 {
+        now().as_nanos() as i64
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("scribe_segments_{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[tokio::test]
+    async fn on_insert_should_find_logs_in_time_span_across_segments() {
+        let dir = temp_dir("span");
+        let Ok(warehouse) = WarehouseSegments::new(&dir, Duration::from_millis(5)).await else {
+            assert!(false);
+            return;
+        };
+        let Ok(()) = warehouse.migrate().await else {
+            assert!(false);
+            return;
+        };
+
+        let Ok(_) = warehouse
+            .insert_log_at(&[Token::from(100)], now_nanos())
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        let from = now();
+        let mut logs = Vec::new();
+        for i in 0..20u32 {
+            let log = vec![Token::from(i), Token::from(i + 1)];
+            let Ok(_) = warehouse.insert_log_at(&log, now_nanos()).await else {
+                assert!(false);
+                return;
+            };
+            logs.push(log);
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        let to = now();
+        let Ok(_) = warehouse
+            .insert_log_at(&[Token::from(200)], now_nanos())
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+
+        let Ok(result) = warehouse.find_logs(&from, &to).await else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(result.into_iter().map(|l| l.data).collect::<Vec<_>>(), logs);
+        assert!(segment_ids(&dir).unwrap().len() > 1);
+
+        warehouse.close().await;
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn on_reopen_should_keep_logs_and_skip_torn_index_entry() {
+        let dir = temp_dir("reopen");
+        let Ok(warehouse) = WarehouseSegments::new(&dir, SEGMENT_SPAN).await else {
+            assert!(false);
+            return;
+        };
+        let Ok(()) = warehouse.migrate().await else {
+            assert!(false);
+            return;
+        };
+        let from = now();
+        let at = from.as_nanos() as i64;
+        let first = vec![Token::from(1), Token::from(2)];
+        let Ok(first_id) = warehouse.insert_log_at(&first, at).await else {
+            assert!(false);
+            return;
+        };
+        warehouse.close().await;
+
+        let id = segment_ids(&dir).unwrap()[0];
+        let mut index = OpenOptions::new()
+            .append(true)
+            .open(segment_path(&dir, id, INDEX_EXTENSION))
+            .unwrap();
+        index.write_all(&[1, 2, 3]).unwrap();
+
+        let Ok(warehouse) = WarehouseSegments::new(&dir, SEGMENT_SPAN).await else {
+            assert!(false);
+            return;
+        };
+        let Ok(()) = warehouse.migrate().await else {
+            assert!(false);
+            return;
+        };
+        let second = vec![Token::from(3)];
+        let Ok(second_id) = warehouse.insert_log_at(&second, at + 1).await else {
+            assert!(false);
+            return;
+        };
+
+        let Ok(result) = warehouse.find_logs(&from, &now()).await else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(
+            result,
+            vec![
+                Log {
+                    id: first_id,
+                    timestamp: at,
+                    data: first
+                },
+                Log {
+                    id: second_id,
+                    timestamp: at + 1,
+                    data: second
+                }
+            ]
+        );
+
+        warehouse.close().await;
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn on_reopen_should_find_words_saved_with_logs_and_skip_torn_record() {
+        let dir = temp_dir("words");
+        let Ok(warehouse) = WarehouseSegments::new(&dir, SEGMENT_SPAN).await else {
+            assert!(false);
+            return;
+        };
+        let Ok(()) = warehouse.migrate().await else {
+            assert!(false);
+            return;
+        };
+        let words = [
+            ("first".to_string(), Token::from(1)),
+            ("żółć".to_string(), Token::from(2)),
+        ];
+        let Ok(_) = warehouse
+            .insert_log_with_words(&[Token::from(1)], 1, &words[..1])
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        let Ok(()) = warehouse
+            .insert_logs_with_words(&[(2, vec![Token::from(2)])], &words[1..])
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        warehouse.close().await;
+
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(dir.join(WORDS_FILE))
+            .unwrap();
+        file.write_all(&[9, 0, 0, 0, b't']).unwrap();
+
+        let Ok(warehouse) = WarehouseSegments::new(&dir, SEGMENT_SPAN).await else {
+            assert!(false);
+            return;
+        };
+        let Ok(()) = warehouse
+            .insert_logs_with_words(&[], &[("renamed".to_string(), Token::from(1))])
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        let Ok(found) = warehouse.find_words().await else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(
+            found,
+            vec![
+                ("renamed".to_string(), Token::from(1)),
+                ("żółć".to_string(), Token::from(2))
+            ]
+        );
+
+        warehouse.close().await;
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn on_find_logs_page_should_page_across_segments_by_offset_and_by_cursor() {
+        let dir = temp_dir("page");
+        let Ok(warehouse) = WarehouseSegments::new(&dir, Duration::from_millis(5)).await else {
+            assert!(false);
+            return;
+        };
+        let Ok(()) = warehouse.migrate().await else {
+            assert!(false);
+            return;
+        };
+        let logs: Vec<Vec<Token>> = (0..5u32).map(|i| vec![Token::from(i)]).collect();
+        for (i, log) in logs.iter().enumerate() {
+            let timestamp = Duration::from_millis(3 * i as u64).as_nanos() as i64;
+            let Ok(_) = warehouse.insert_log_at(log, timestamp).await else {
+                assert!(false);
+                return;
+            };
+        }
+        let (from, to) = (Duration::ZERO, Duration::from_millis(12));
+
+        let Ok(page) = warehouse
+            .find_logs_page(&from, &to, 2, &Page::Offset(1))
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(
+            page.logs.into_iter().map(|l| l.data).collect::<Vec<_>>(),
+            logs[1..3]
+        );
+
+        let mut found = Vec::new();
+        let mut next = Page::Offset(0);
+        loop {
+            let Ok(page) = warehouse.find_logs_page(&from, &to, 2, &next).await else {
+                assert!(false);
+                return;
+            };
+            found.extend(page.logs.into_iter().map(|l| l.data));
+            match page.next {
+                Some(cursor) => next = Page::After(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(found, logs);
+
+        let malformed = Page::After(Cursor("1:x".to_string()));
+        let Err(e) = warehouse.find_logs_page(&from, &to, 2, &malformed).await else {
+            assert!(false);
+            return;
+        };
+        assert!(matches!(e, RepositoryError::InvalidInput(_)));
+
+        warehouse.close().await;
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn on_read_should_find_logs_and_words_of_v2_fixture() {
+        let dir = temp_dir("v2_fixture");
+        let Ok(()) = fs::create_dir_all(&dir) else {
+            assert!(false);
+            return;
+        };
+        let Ok(entries) = fs::read_dir("./assets/fixtures/v2/segments") else {
+            assert!(false);
+            return;
+        };
+        for entry in entries {
+            let path = entry.unwrap().path();
+            let Ok(_) = fs::copy(&path, dir.join(path.file_name().unwrap())) else {
+                assert!(false);
+                return;
+            };
+        }
+
+        let Ok(warehouse) = WarehouseSegments::new(&dir, SEGMENT_SPAN).await else {
+            assert!(false);
+            return;
+        };
+        let Ok(words) = warehouse.find_words().await else {
+            assert!(false);
+            return;
+        };
+        let mut s = scribe::dictionary::Module::new(scribe::trie::Trie::new());
+        s.load_words(words);
+        let Ok(logs) = warehouse
+            .find_logs(
+                &Duration::from_secs(1_700_000_000),
+                &Duration::from_secs(1_700_000_002),
+            )
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+
+        let logs: Vec<String> = logs.iter().map(|l| s.deserialize(&l.data)).collect();
+        assert_eq!(
+            logs,
+            vec![
+                "connection refused by peer",
+                "request timeout reached",
+                "connection timeout"
+            ]
+        );
+
+        warehouse.close().await;
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn on_export_should_stream_logs_of_every_segment() {
+        let dir = temp_dir("export");
+        let Ok(warehouse) = WarehouseSegments::new(&dir, Duration::from_millis(1)).await else {
+            assert!(false);
+            return;
+        };
+        let Ok(()) = warehouse.migrate().await else {
+            assert!(false);
+            return;
+        };
+        let logs: Vec<(i64, Vec<Token>)> = (0..2500)
+            .map(|i| (i * 1000, vec![Token::from(i as u32)]))
+            .collect();
+        let Ok(()) = warehouse.insert_logs(&logs).await else {
+            assert!(false);
+            return;
+        };
+
+        let mut exported = io::Cursor::new(Vec::new());
+        let Ok(count) = warehouse.export(&mut exported, || Ok(Vec::new())).await else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(count, 2500);
+
+        warehouse.close().await;
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,16 +1,29 @@
-use super::commands::SQL_COMMANDS;
+use super::commands::{
+    SQL_CREATE_MIGRATIONS, SQL_CREATE_PARTITION, SQL_CREATE_PARTITION_TOKENS, SQL_LIST_PARTITIONS,
+    SQL_MIGRATIONS,
+};
+use super::compression;
 use super::entities::{DictSql, LogSql};
-use super::interface::RepositoryProvider;
-use super::interface::{SerializerReader, SerializerSaver};
-use crate::dictionary::Module;
-use crate::trie::Node;
-use sqlx::{sqlite::SqlitePool, FromRow};
-use std::io::{Error, ErrorKind, Result};
+use super::error::{RepositoryError, Result};
+use super::interface::{
+    holding_any, Cursor, Log, LogId, LogsPage, LogsStream, Page, RepositoryProvider,
+};
+use super::partition::{self, Partitions};
+use super::PoolConfig;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use scribe::token::Token;
+use sqlx::{
+    sqlite::{SqliteConnection, SqlitePool, SqlitePoolOptions},
+    Connection, FromRow,
+};
 use std::{
-    collections::HashMap,
+    collections::HashSet,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+const TABLE_LOGS: &str = "logs";
+const TABLE_LOG_TOKENS: &str = "log_tokens";
+
 #[derive(Debug, Clone)]
 pub enum DatabaseStorage {
     Ram,
@@ -22,183 +35,736 @@ pub enum DatabaseStorage {
 #[derive(Debug, Clone)]
 pub struct WarehouseSql {
     pool: SqlitePool,
+    reader: Option<SqlitePool>,
+    compress: bool,
+    partitions: Option<Partitions>,
+    token_index: bool,
 }
 
 impl WarehouseSql {
-    /// Cerate a new Warehouse connected to SQLite database.
+    /// Cerate a new Warehouse connected to SQLite database with the pool configured by the config.
+    /// RAM storage always keeps one connection open, as the database lives only as long as it does.
     ///
-    pub async fn new(dbs: DatabaseStorage) -> Result<Self> {
-        let url = match dbs {
-            DatabaseStorage::Ram => "sqlite::memory:".to_string(),
-            DatabaseStorage::Path(s) => s,
-        };
-        let Ok(pool) = SqlitePool::connect(&url).await else {
-            return Err(Error::new(ErrorKind::NotConnected, "connection error"));
+    pub async fn new(dbs: DatabaseStorage, config: &PoolConfig) -> Result<Self> {
+        let pool = connect(dbs, config).await?;
+        Ok(Self {
+            pool,
+            reader: None,
+            compress: false,
+            partitions: None,
+            token_index: false,
+        })
+    }
+
+    /// Sets the replica database logs are read from with a pool of its own configured by the config,
+    /// so heavy queries do not hold connections inserts wait for. None reads logs with the pool writing them.
+    ///
+    pub async fn with_replica(mut self, url: Option<&str>, config: &PoolConfig) -> Result<Self> {
+        if let Some(url) = url {
+            self.reader = Some(connect(DatabaseStorage::Path(url.to_string()), config).await?);
+        }
+        Ok(self)
+    }
+
+    /// Sets whether logs are compressed with zstd before they are inserted.
+    /// Each row is flagged, so logs inserted with either setting are read back.
+    ///
+    pub fn with_compression(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    /// Sets the span of periods logs are partitioned by, each period in a table of its own.
+    /// None keeps logs in the single logs table, logs inserted there are not read while partitioning is on.
+    ///
+    pub fn with_partitions(mut self, span: Option<Duration>) -> Self {
+        self.partitions = span.map(Partitions::new);
+        self
+    }
+
+    /// Sets whether tokens of inserted logs are indexed, so reads filtered by tokens fetch only logs holding them.
+    /// Logs inserted while the index is off are not found by filtered reads once it is on.
+    ///
+    pub fn with_token_index(mut self, token_index: bool) -> Self {
+        self.token_index = token_index;
+        self
+    }
+
+    #[inline(always)]
+    fn reader(&self) -> &SqlitePool {
+        self.reader.as_ref().unwrap_or(&self.pool)
+    }
+
+    /// Inserts the log in to the table, indexing its tokens if the index is kept, and returns the id of its row.
+    ///
+    async fn insert_row(
+        &self,
+        conn: &mut SqliteConnection,
+        table: &str,
+        input: &[Token],
+        timestamp: i64,
+    ) -> Result<i64> {
+        let (data, compressed) = compression::pack(input, self.compress)?;
+        let id = sqlx::query(&format!(
+            r#"INSERT INTO "{}" (timestamp, data, compressed) VALUES (?1, ?2, ?3)"#,
+            table
+        ))
+        .bind(timestamp)
+        .bind(data)
+        .bind(compressed)
+        .execute(&mut *conn)
+        .await?
+        .last_insert_rowid();
+        if !self.token_index {
+            return Ok(id);
+        }
+        let sql = format!(
+            r#"INSERT OR IGNORE INTO "{}" (log_id, token) VALUES (?1, ?2)"#,
+            tokens_table(table)
+        );
+        for token in input.iter().collect::<HashSet<_>>() {
+            sqlx::query(&sql)
+                .bind(id)
+                .bind(i64::from(*token))
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        Ok(id)
+    }
+
+    /// Returns the start of the partition and the table a log at the timestamp is inserted in to.
+    ///
+    #[inline(always)]
+    fn table_at(&self, timestamp: i64) -> (Option<i64>, String) {
+        match self.partitions {
+            Some(partitions) => {
+                let start = partitions.of(timestamp);
+                (Some(start), partition::name(start))
+            }
+            None => (None, TABLE_LOGS.to_string()),
+        }
+    }
+
+    /// Returns tables holding logs in the range of nanoseconds from to inclusive, oldest first,
+    /// together with starts of their partitions.
+    ///
+    async fn tables(
+        &self,
+        conn: &mut SqliteConnection,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<(Option<i64>, String)>> {
+        let Some(partitions) = self.partitions else {
+            return Ok(vec![(None, TABLE_LOGS.to_string())]);
         };
-        Ok(Self { pool })
+        let names: Vec<String> = sqlx::query_scalar(SQL_LIST_PARTITIONS)
+            .fetch_all(&mut *conn)
+            .await?;
+        Ok(partitions
+            .intersecting(names, from, to)
+            .into_iter()
+            .map(|start| (Some(start), partition::name(start)))
+            .collect())
     }
 }
 
 impl RepositoryProvider for WarehouseSql {
+    /// Applies migrations not recorded in the schema_migrations table yet in order of their versions,
+    /// each in a transaction with its record, so migrating an up to date database is a no-op.
+    ///
     async fn migrate(&self) -> Result<()> {
-        let Ok(mut conn) = self.pool.acquire().await else {
-            return Err(Error::new(
-                ErrorKind::ConnectionRefused,
-                "cannot acquire connection",
-            ));
-        };
-        for migration in SQL_COMMANDS {
-            let Ok(_) = sqlx::query(&migration).execute(&mut *conn).await else {
-                return Err(Error::new(ErrorKind::NotConnected, "cannot acquire pool"));
-            };
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query(SQL_CREATE_MIGRATIONS)
+            .execute(&mut *conn)
+            .await?;
+        let applied: Vec<i64> = sqlx::query_scalar("SELECT version FROM schema_migrations")
+            .fetch_all(&mut *conn)
+            .await?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64;
+        for migration in SQL_MIGRATIONS
+            .iter()
+            .filter(|m| !applied.contains(&m.version))
+        {
+            let mut tx = conn.begin().await?;
+            for statement in migration.statements {
+                sqlx::query(statement).execute(&mut *tx).await?;
+            }
+            sqlx::query(
+                "INSERT OR IGNORE INTO schema_migrations (version, applied_at) VALUES (?1, ?2)",
+            )
+            .bind(migration.version)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await?;
         }
         Ok(())
     }
 
-    /// Insert single log data to Warehouse SQLite database.
+    /// Insert single log data at the timestamp to Warehouse SQLite database.
     ///
-    async fn insert_log(&self, input: &[u32]) -> Result<()> {
-        let Ok(mut conn) = self.pool.acquire().await else {
-            return Err(Error::new(ErrorKind::NotConnected, "cannot acquire pool"));
-        };
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_nanos() as i64;
-        let mut data = Vec::new();
+    async fn insert_log_at(&self, input: &[Token], timestamp: i64) -> Result<LogId> {
+        self.insert_log_with_words(input, timestamp, &[]).await
+    }
+
+    /// Inserts logs with their timestamps in a single transaction.
+    ///
+    async fn insert_logs(&self, inputs: &[(i64, Vec<Token>)]) -> Result<()> {
+        self.insert_logs_with_words(inputs, &[]).await
+    }
 
-        for elem in input {
-            data.extend(elem.to_ne_bytes().to_vec());
+    /// Inserts single log at the timestamp and the words in a single transaction.
+    ///
+    async fn insert_log_with_words(
+        &self,
+        input: &[Token],
+        timestamp: i64,
+        words: &[(String, Token)],
+    ) -> Result<LogId> {
+        let mut tx = self.pool.begin().await?;
+        insert_words(&mut tx, words).await?;
+        let (start, table) = self.table_at(timestamp);
+        if start.is_some() {
+            create_partition(&mut tx, &table).await?;
         }
+        let id = self.insert_row(&mut tx, &table, input, timestamp).await?;
+        tx.commit().await?;
 
-        let Ok(_) = sqlx::query("INSERT INTO logs (timestamp, data) VALUES (?1, ?2)")
-            .bind(timestamp)
-            .bind(data)
-            .execute(&mut *conn)
-            .await
-        else {
-            return Err(Error::new(ErrorKind::Interrupted, "cannot execute query"));
-        };
+        Ok(log_id(start, id))
+    }
+
+    /// Inserts logs with their timestamps and the words in a single transaction.
+    ///
+    async fn insert_logs_with_words(
+        &self,
+        inputs: &[(i64, Vec<Token>)],
+        words: &[(String, Token)],
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        insert_words(&mut tx, words).await?;
+        let mut created = HashSet::new();
+        for (timestamp, input) in inputs.iter() {
+            let (start, table) = self.table_at(*timestamp);
+            if let Some(start) = start {
+                if created.insert(start) {
+                    create_partition(&mut tx, &table).await?;
+                }
+            }
+            self.insert_row(&mut tx, &table, input, *timestamp).await?;
+        }
+        tx.commit().await?;
 
         Ok(())
     }
 
-    /// Gets data in time span.
-    ///  
-    async fn find_logs(&self, from: &Duration, to: &Duration) -> Result<Vec<Vec<u32>>> {
-        let Ok(mut conn) = self.pool.acquire().await else {
-            return Err(Error::new(ErrorKind::NotConnected, "cannot acquire pool"));
-        };
-        let Ok(rows) = sqlx::query("SELECT * FROM logs WHERE timestamp BETWEEN ? AND ?")
-            .bind(from.as_nanos() as i64)
-            .bind(to.as_nanos() as i64)
+    /// Gets words saved in the serializer table ordered by number.
+    ///
+    async fn find_words(&self) -> Result<Vec<(String, Token)>> {
+        let mut conn = self.pool.acquire().await?;
+        let rows = sqlx::query_as::<_, DictSql>("SELECT word, num FROM serializer ORDER BY num")
             .fetch_all(&mut *conn)
-            .await
-        else {
-            return Err(Error::new(ErrorKind::Interrupted, "cannot execute query"));
-        };
+            .await?;
+        rows.into_iter()
+            .map(|dict| Ok((dict.word, Token::try_from(dict.num)?)))
+            .collect()
+    }
+
+    /// Gets data in time span, reading partitions the span intersects one by one.
+    ///  
+    async fn find_logs(&self, from: &Duration, to: &Duration) -> Result<Vec<Log>> {
+        let mut conn = self.reader().acquire().await?;
+        let (from, to) = (from.as_nanos() as i64, to.as_nanos() as i64);
 
         let mut data = Vec::new();
-        for rec in rows {
-            let mut d: Vec<u32> = Vec::new();
-            let Ok(log) = LogSql::from_row(&rec) else {
-                return Err(Error::new(ErrorKind::Interrupted, "cannot execute query"));
-            };
-            for (i, _) in log.data.iter().enumerate().step_by(4) {
-                d.push(u32::from_ne_bytes([
-                    log.data[i],
-                    log.data[i + 1],
-                    log.data[i + 2],
-                    log.data[i + 3],
-                ]));
+        for (start, table) in self.tables(&mut conn, from, to).await? {
+            let rows = sqlx::query(&format!(
+                r#"SELECT * FROM "{}" WHERE timestamp BETWEEN ? AND ? ORDER BY timestamp, id"#,
+                table
+            ))
+            .bind(from)
+            .bind(to)
+            .fetch_all(&mut *conn)
+            .await?;
+            for rec in rows {
+                let log = LogSql::from_row(&rec)?;
+                data.push(Log {
+                    id: log_id(start, log.id),
+                    timestamp: log.timestamp,
+                    data: compression::unpack(&log.data, log.compressed)?,
+                });
             }
-            data.push(d);
         }
 
         Ok(data)
     }
 
-    async fn close(&self) {
-        self.pool.close().await;
+    /// Gets data in time span holding any of the tokens, looking logs up in the token index if it is kept.
+    ///
+    async fn find_logs_with_any(
+        &self,
+        from: &Duration,
+        to: &Duration,
+        tokens: &[Token],
+    ) -> Result<Vec<Log>> {
+        if !self.token_index {
+            return Ok(holding_any(self.find_logs(from, to).await?, tokens));
+        }
+        if tokens.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut conn = self.reader().acquire().await?;
+        let (from, to) = (from.as_nanos() as i64, to.as_nanos() as i64);
+        let placeholders = vec!["?"; tokens.len()].join(", ");
+
+        let mut data = Vec::new();
+        for (start, table) in self.tables(&mut conn, from, to).await? {
+            let sql = format!(
+                r#"SELECT * FROM "{}" WHERE timestamp BETWEEN ? AND ? AND id IN (SELECT log_id FROM "{}" WHERE token IN ({})) ORDER BY timestamp, id"#,
+                table,
+                tokens_table(&table),
+                placeholders
+            );
+            let mut query = sqlx::query(&sql).bind(from).bind(to);
+            for token in tokens.iter() {
+                query = query.bind(i64::from(*token));
+            }
+            for rec in query.fetch_all(&mut *conn).await? {
+                let log = LogSql::from_row(&rec)?;
+                data.push(Log {
+                    id: log_id(start, log.id),
+                    timestamp: log.timestamp,
+                    data: compression::unpack(&log.data, log.compressed)?,
+                });
+            }
+        }
+
+        Ok(data)
     }
-}
 
-impl SerializerReader for WarehouseSql {
-    #[inline]
-    async fn read(&self) -> Result<Module> {
-        let Ok(mut conn) = self.pool.acquire().await else {
-            return Err(Error::new(ErrorKind::NotConnected, "cannot acquire pool"));
+    /// Gets the log of the numeric id, prefixed by the start of its partition if logs are partitioned.
+    ///
+    async fn find_log_by_id(&self, id: &LogId) -> Result<Option<Log>> {
+        let mut conn = self.reader().acquire().await?;
+        let (start, table, id) = match self.partitions {
+            None => (None, TABLE_LOGS.to_string(), id.0.as_str()),
+            Some(_) => {
+                let Some((start, id)) = partition::split_id(id) else {
+                    return Ok(None);
+                };
+                let table = partition::name(start);
+                let exists: i64 = sqlx::query_scalar(
+                    "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?",
+                )
+                .bind(&table)
+                .fetch_one(&mut *conn)
+                .await?;
+                if exists == 0 {
+                    return Ok(None);
+                }
+                (Some(start), table, id)
+            }
         };
-
-        let Ok(mut rows) = sqlx::query("SELECT * FROM serializer")
-            .fetch_all(&mut *conn)
-            .await
-        else {
-            return Err(Error::new(ErrorKind::Interrupted, "cannot execute query"));
+        let Ok(id) = id.parse::<i64>() else {
+            return Ok(None);
         };
+        let log = sqlx::query_as::<_, LogSql>(&format!(
+            r#"SELECT id, timestamp, data, compressed FROM "{}" WHERE id = ?"#,
+            table
+        ))
+        .bind(id)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        match log {
+            Some(log) => Ok(Some(Log {
+                id: log_id(start, log.id),
+                timestamp: log.timestamp,
+                data: compression::unpack(&log.data, log.compressed)?,
+            })),
+            None => Ok(None),
+        }
+    }
 
-        let mut m: HashMap<String, u32> = HashMap::new();
-
-        for rec in rows {
-            let Ok(dict) = DictSql::from_row(&rec) else {
-                return Err(Error::new(ErrorKind::Interrupted, "cannot execute query"));
-            };
-            m.insert(dict.word, dict.num as u32);
+    /// Streams data in time span, decoding rows as they are fetched.
+    /// Partitioned logs are fetched a partition at a time.
+    ///
+    fn find_logs_stream(&self, from: &Duration, to: &Duration) -> LogsStream<'_> {
+        let (from, to) = (from.as_nanos() as i64, to.as_nanos() as i64);
+        if self.partitions.is_none() {
+            return sqlx::query_as::<_, LogSql>(
+                "SELECT * FROM logs WHERE timestamp BETWEEN ? AND ? ORDER BY timestamp, id",
+            )
+            .bind(from)
+            .bind(to)
+            .fetch(self.reader())
+            .map(|row| {
+                let log = row?;
+                Ok((
+                    log.timestamp,
+                    compression::unpack(&log.data, log.compressed)?,
+                ))
+            })
+            .boxed();
         }
+        stream::once(async move {
+            let mut conn = self.reader().acquire().await?;
+            let tables = self.tables(&mut conn, from, to).await?;
+            Ok::<_, RepositoryError>(
+                stream::iter(tables)
+                    .then(move |(_, table)| async move {
+                        let rows = sqlx::query_as::<_, LogSql>(&format!(
+                            r#"SELECT * FROM "{}" WHERE timestamp BETWEEN ? AND ? ORDER BY timestamp, id"#,
+                            table
+                        ))
+                        .bind(from)
+                        .bind(to)
+                        .fetch_all(self.reader())
+                        .await?;
+                        Ok::<_, RepositoryError>(stream::iter(
+                            rows.into_iter().map(Ok::<_, RepositoryError>),
+                        ))
+                    })
+                    .try_flatten(),
+            )
+        })
+        .try_flatten()
+        .map(|row| {
+            let log = row?;
+            Ok((
+                log.timestamp,
+                compression::unpack(&log.data, log.compressed)?,
+            ))
+        })
+        .boxed()
+    }
 
-        let graph = Node::new();
+    /// Gets a page of data in time span, skipping rows in the database.
+    /// Partitions are read oldest first until the page is full, skipping whole partitions counted within the offset.
+    ///
+    async fn find_logs_page(
+        &self,
+        from: &Duration,
+        to: &Duration,
+        limit: usize,
+        page: &Page,
+    ) -> Result<LogsPage> {
+        let mut conn = self.reader().acquire().await?;
+        let (from, to) = (from.as_nanos() as i64, to.as_nanos() as i64);
+        let after = match page {
+            Page::Offset(_) => None,
+            Page::After(cursor) => {
+                let (timestamp, id) = cursor.parts()?;
+                let Ok(id) = id.parse::<i64>() else {
+                    return Err(RepositoryError::InvalidInput(
+                        "malformed cursor".to_string(),
+                    ));
+                };
+                Some((timestamp, id))
+            }
+        };
+        let mut offset = match page {
+            Page::Offset(offset) => *offset,
+            Page::After(_) => 0,
+        };
+        let tables = match (self.partitions, after) {
+            (Some(_), Some((timestamp, _))) => {
+                self.tables(&mut conn, timestamp.max(from), to).await?
+            }
+            _ => self.tables(&mut conn, from, to).await?,
+        };
 
-        let mut s = Module::new(graph);
-        s.set_map_from(m);
+        let mut logs = Vec::new();
+        let mut next = None;
+        for (start, table) in tables {
+            if logs.len() >= limit {
+                break;
+            }
+            let remaining = (limit - logs.len()) as i64;
+            let rows = match after {
+                None => {
+                    if offset > 0 && self.partitions.is_some() {
+                        let count: i64 = sqlx::query_scalar(&format!(
+                            r#"SELECT COUNT(*) FROM "{}" WHERE timestamp BETWEEN ? AND ?"#,
+                            table
+                        ))
+                        .bind(from)
+                        .bind(to)
+                        .fetch_one(&mut *conn)
+                        .await?;
+                        if count as usize <= offset {
+                            offset -= count as usize;
+                            continue;
+                        }
+                    }
+                    let rows = sqlx::query(&format!(
+                        r#"SELECT * FROM "{}" WHERE timestamp BETWEEN ? AND ? ORDER BY timestamp, id LIMIT ? OFFSET ?"#,
+                        table
+                    ))
+                    .bind(from)
+                    .bind(to)
+                    .bind(remaining)
+                    .bind(offset as i64)
+                    .fetch_all(&mut *conn)
+                    .await?;
+                    offset = 0;
+                    rows
+                }
+                Some((timestamp, id)) => {
+                    sqlx::query(&format!(
+                        r#"SELECT * FROM "{}" WHERE timestamp BETWEEN ? AND ? AND (timestamp > ? OR (timestamp = ? AND id > ?)) ORDER BY timestamp, id LIMIT ?"#,
+                        table
+                    ))
+                    .bind(from)
+                    .bind(to)
+                    .bind(timestamp)
+                    .bind(timestamp)
+                    .bind(id)
+                    .bind(remaining)
+                    .fetch_all(&mut *conn)
+                    .await?
+                }
+            };
+            for rec in rows {
+                let log = LogSql::from_row(&rec)?;
+                logs.push(Log {
+                    id: log_id(start, log.id),
+                    timestamp: log.timestamp,
+                    data: compression::unpack(&log.data, log.compressed)?,
+                });
+                next = Some(Cursor::new(log.timestamp, log.id));
+            }
+        }
+        if logs.len() < limit {
+            next = None;
+        }
 
-        Ok(s)
+        Ok(LogsPage { logs, next })
     }
-}
 
-impl SerializerSaver for WarehouseSql {
-    #[inline]
-    async fn save(&self, s: &Module) -> Result<()> {
-        let Ok(mut transaction) = self.pool.begin().await else {
-            return Err(Error::new(
-                ErrorKind::NotConnected,
-                "cannot begin transaction pool",
-            ));
+    /// Deletes logs older than before.
+    /// Partitions ending before it are dropped whole, rows are deleted only from the partition it falls in.
+    ///
+    async fn expire_logs(&self, before: &Duration) -> Result<u64> {
+        let mut conn = self.pool.acquire().await?;
+        let before = before.as_nanos() as i64;
+        let Some(partitions) = self.partitions else {
+            return delete_before(&mut conn, TABLE_LOGS, before).await;
         };
 
-        for (w, n) in s.iter() {
-            let Ok(_) = sqlx::query("INSERT INTO serializer (word, num) VALUES (?1, ?2)")
-                .bind(w)
-                .bind(*n as i32)
-                .execute(&mut *transaction)
-                .await
-            else {
-                return Err(Error::new(
-                    ErrorKind::Interrupted,
-                    "cannot execute transaction",
-                ));
+        let mut deleted = 0;
+        for (start, table) in self.tables(&mut conn, i64::MIN, before).await? {
+            let Some(start) = start else {
+                continue;
             };
+            if partitions.end(start) <= before {
+                let mut tx = conn.begin().await?;
+                let count: i64 =
+                    sqlx::query_scalar(&format!(r#"SELECT COUNT(*) FROM "{}""#, table))
+                        .fetch_one(&mut *tx)
+                        .await?;
+                sqlx::query(&format!(r#"DROP TABLE "{}""#, table))
+                    .execute(&mut *tx)
+                    .await?;
+                sqlx::query(&format!(
+                    r#"DROP TABLE IF EXISTS "{}""#,
+                    tokens_table(&table)
+                ))
+                .execute(&mut *tx)
+                .await?;
+                tx.commit().await?;
+                deleted += count as u64;
+                continue;
+            }
+            create_partition(&mut conn, &table).await?;
+            deleted += delete_before(&mut conn, &table, before).await?;
         }
 
-        let Ok(_) = transaction.commit().await else {
-            return Err(Error::new(ErrorKind::Interrupted, "cannot execute query"));
-        };
+        Ok(deleted)
+    }
+
+    /// Rebuilds the database file with VACUUM, so pages of expired logs are given back to the file system.
+    ///
+    async fn compact(&self) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query("VACUUM").execute(&mut *conn).await?;
 
         Ok(())
     }
+
+    /// Adds an occurrence to the latest identical log inserted since given time.
+    /// Logs are compared as stored, so a log inserted before compression was switched on or off is not matched.
+    ///
+    async fn count_occurrence(&self, input: &[Token], since: &Duration) -> Result<bool> {
+        let mut conn = self.pool.acquire().await?;
+        let (data, compressed) = compression::pack(input, self.compress)?;
+        let since = since.as_nanos() as i64;
+        for (_, table) in self
+            .tables(&mut conn, since, i64::MAX)
+            .await?
+            .into_iter()
+            .rev()
+        {
+            let result = sqlx::query(&format!(
+                r#"UPDATE "{table}" SET occurrences = occurrences + 1 WHERE id = (SELECT id FROM "{table}" WHERE timestamp >= ? AND data = ? AND compressed = ? ORDER BY timestamp DESC, id DESC LIMIT 1)"#,
+                table = table
+            ))
+            .bind(since)
+            .bind(&data)
+            .bind(compressed)
+            .execute(&mut *conn)
+            .await?;
+            if result.rows_affected() > 0 {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    async fn close(&self) {
+        if let Some(reader) = &self.reader {
+            reader.close().await;
+        }
+        self.pool.close().await;
+    }
+}
+
+/// Connects a pool to the database storage configured by the config.
+///
+async fn connect(dbs: DatabaseStorage, config: &PoolConfig) -> Result<SqlitePool> {
+    let (url, mut options) = match dbs {
+        DatabaseStorage::Ram => (
+            "sqlite::memory:".to_string(),
+            SqlitePoolOptions::new()
+                .min_connections(1)
+                .max_lifetime(None)
+                .idle_timeout(config.idle_timeout),
+        ),
+        DatabaseStorage::Path(s) => (
+            s,
+            SqlitePoolOptions::new()
+                .min_connections(0)
+                .idle_timeout(config.idle_timeout),
+        ),
+    };
+    if let Some(size) = config.size {
+        options = options.max_connections(size);
+    }
+    if let Some(timeout) = config.acquire_timeout {
+        options = options.acquire_timeout(timeout);
+    }
+    let connecting = options.connect(&url);
+    let connected = match config.connect_timeout {
+        Some(timeout) => tokio::time::timeout(timeout, connecting).await,
+        None => Ok(connecting.await),
+    };
+    match connected {
+        Ok(Ok(pool)) => Ok(pool),
+        Ok(Err(e)) => Err(RepositoryError::NotConnected(format!(
+            "cannot connect to: {}: {}",
+            url, e
+        ))),
+        Err(_) => Err(RepositoryError::Timeout(format!(
+            "cannot connect to: {}",
+            url
+        ))),
+    }
+}
+
+/// Saves the words of the dictionary, replacing rows of the same word or number,
+/// so the table follows the dictionary once it is imported.
+///
+async fn insert_words(conn: &mut SqliteConnection, words: &[(String, Token)]) -> Result<()> {
+    for (word, num) in words.iter() {
+        sqlx::query("INSERT OR REPLACE INTO serializer (word, num) VALUES (?1, ?2)")
+            .bind(word)
+            .bind(i64::from(*num))
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Creates the table of a partition and the table of its token index if they do not exist.
+///
+async fn create_partition(conn: &mut SqliteConnection, table: &str) -> Result<()> {
+    for statement in SQL_CREATE_PARTITION {
+        sqlx::query(&statement.replace("{table}", table))
+            .execute(&mut *conn)
+            .await?;
+    }
+    for statement in SQL_CREATE_PARTITION_TOKENS {
+        sqlx::query(&statement.replace("{table}", &tokens_table(table)))
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Deletes logs of the table older than before together with their tokens in a transaction.
+///
+async fn delete_before(conn: &mut SqliteConnection, table: &str, before: i64) -> Result<u64> {
+    let mut tx = conn.begin().await?;
+    sqlx::query(&format!(
+        r#"DELETE FROM "{}" WHERE log_id IN (SELECT id FROM "{}" WHERE timestamp < ?)"#,
+        tokens_table(table),
+        table
+    ))
+    .bind(before)
+    .execute(&mut *tx)
+    .await?;
+    let result = sqlx::query(&format!(r#"DELETE FROM "{}" WHERE timestamp < ?"#, table))
+        .bind(before)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Returns the table of the token index of the logs table.
+///
+#[inline(always)]
+fn tokens_table(table: &str) -> String {
+    match table {
+        TABLE_LOGS => TABLE_LOG_TOKENS.to_string(),
+        table => format!("{}_tokens", table),
+    }
+}
+
+/// Returns the id of the row, prefixed by the start of its partition if it is partitioned.
+///
+#[inline(always)]
+fn log_id(start: Option<i64>, id: i64) -> LogId {
+    match start {
+        Some(start) => partition::log_id(start, id),
+        None => LogId(id.to_string()),
+    }
 }
 
 #[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
 mod tests {
-    use super::super::interface::{SerializerReader, SerializerSaver};
     use super::*;
-    use crate::dictionary::Module;
-    use std::time::Instant;
+    use futures::TryStreamExt;
+    use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+    fn now_nanos() -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as i64
+    }
 
     const BENCH_LOOP: usize = 1000;
     const INSERTS: usize = 100;
 
-    fn get_data() -> Vec<u32> {
-        vec![
+    fn get_data() -> Vec<Token> {
+        [
             1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 1, 2, 3, 4,
             5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 1, 2, 3, 4, 5, 6, 7, 8,
             9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11,
@@ -262,13 +828,17 @@ mod tests {
             12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14,
             15, 16, 17, 18, 19, 20, 21,
         ]
+        .into_iter()
+        .map(Token::from)
+        .collect()
     }
 
     #[tokio::test]
     async fn on_insert_should_insert_data_in_to_database_and_read_the_data_without_side_effects() {
-        let data: Vec<u32> = get_data();
+        let data: Vec<Token> = get_data();
 
-        let Ok(mut warehouse) = WarehouseSql::new(DatabaseStorage::Ram).await else {
+        let Ok(warehouse) = WarehouseSql::new(DatabaseStorage::Ram, &PoolConfig::default()).await
+        else {
             println!("Cannot create warehouse");
             assert!(false);
             return;
@@ -281,7 +851,7 @@ mod tests {
         };
 
         for _ in 0..INSERTS * 2 {
-            let Ok(()) = warehouse.insert_log(&data).await else {
+            let Ok(_) = warehouse.insert_log_at(&data, now_nanos()).await else {
                 println!("Cannot insert logs into warehouse.");
                 assert!(false);
                 return;
@@ -294,7 +864,7 @@ mod tests {
 
         let num_inserted_on_time = INSERTS;
         for _ in 0..num_inserted_on_time {
-            let Ok(()) = warehouse.insert_log(&data).await else {
+            let Ok(_) = warehouse.insert_log_at(&data, now_nanos()).await else {
                 println!("Cannot insert logs into warehouse.");
                 assert!(false);
                 return;
@@ -312,167 +882,882 @@ mod tests {
         };
 
         assert_eq!(result.len(), num_inserted_on_time);
-        assert_eq!(data, result[0]);
+        assert_eq!(data, result[0].data);
 
         warehouse.close().await;
     }
 
     #[tokio::test]
-    async fn bench_insert_sql_ram() {
-        let data: Vec<u32> = get_data();
-        let Ok(warehouse) = WarehouseSql::new(DatabaseStorage::Ram).await else {
-            println!("Cannot create warehouse");
+    async fn on_find_logs_page_should_page_by_offset_and_by_cursor() {
+        let Ok(warehouse) = WarehouseSql::new(DatabaseStorage::Ram, &PoolConfig::default()).await
+        else {
             assert!(false);
             return;
         };
-
         let Ok(()) = warehouse.migrate().await else {
-            println!("Cannot migrate warehouse.");
             assert!(false);
             return;
         };
+        let logs: Vec<Vec<Token>> = (0..5u32).map(|i| vec![Token::from(i)]).collect();
+        for log in logs.iter() {
+            let Ok(_) = warehouse.insert_log_at(log, now_nanos()).await else {
+                assert!(false);
+                return;
+            };
+        }
+        let (from, to) = (Duration::ZERO, Duration::from_nanos(i64::MAX as u64));
 
-        let start = Instant::now();
+        let Ok(page) = warehouse
+            .find_logs_page(&from, &to, 2, &Page::Offset(1))
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(
+            page.logs.into_iter().map(|l| l.data).collect::<Vec<_>>(),
+            logs[1..3]
+        );
 
-        for _ in 0..BENCH_LOOP {
-            let Ok(()) = warehouse.insert_log(&data).await else {
-                println!("Cannot insert logs into warehouse.");
+        let mut found = Vec::new();
+        let mut next = Page::Offset(0);
+        loop {
+            let Ok(page) = warehouse.find_logs_page(&from, &to, 2, &next).await else {
                 assert!(false);
                 return;
             };
+            found.extend(page.logs.into_iter().map(|l| l.data));
+            match page.next {
+                Some(cursor) => next = Page::After(cursor),
+                None => break,
+            }
         }
+        assert_eq!(found, logs);
 
-        let duration = start.elapsed();
-
-        println!(
-            "Time elapsed in bench_insert_sql_ram is: {:?}",
-            duration / BENCH_LOOP as u32
-        );
+        let malformed = Page::After(Cursor("1:x".to_string()));
+        let Err(e) = warehouse.find_logs_page(&from, &to, 2, &malformed).await else {
+            assert!(false);
+            return;
+        };
+        assert!(matches!(e, RepositoryError::InvalidInput(_)));
 
         warehouse.close().await;
     }
 
     #[tokio::test]
-    async fn bench_find_sql_ram() {
-        let data: Vec<u32> = get_data();
-        let Ok(warehouse) = WarehouseSql::new(DatabaseStorage::Ram).await else {
-            println!("Cannot create warehouse");
+    async fn on_find_logs_stream_should_yield_logs_in_time_span_with_ascending_timestamps() {
+        let Ok(warehouse) = WarehouseSql::new(DatabaseStorage::Ram, &PoolConfig::default()).await
+        else {
             assert!(false);
             return;
         };
-
         let Ok(()) = warehouse.migrate().await else {
-            println!("Cannot migrate warehouse.");
             assert!(false);
             return;
         };
-
-        let time_0 = SystemTime::now()
+        let from = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap_or_default();
-
-        for _ in 0..INSERTS {
-            let Ok(()) = warehouse.insert_log(&data).await else {
-                println!("Cannot insert logs into warehouse.");
+        let logs: Vec<Vec<Token>> = (0..5u32).map(|i| vec![Token::from(i)]).collect();
+        for log in logs.iter() {
+            let Ok(_) = warehouse.insert_log_at(log, now_nanos()).await else {
                 assert!(false);
                 return;
             };
         }
-
-        let time_1 = SystemTime::now()
+        let to = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap_or_default();
 
-        let start = Instant::now();
-
-        for _ in 0..BENCH_LOOP {
-            let Ok(_) = warehouse.find_logs(&time_0, &time_1).await else {
-                println!("Cannot get logs from warehouse.");
-                assert!(false);
-                return;
-            };
-        }
-
-        let duration = start.elapsed();
-
-        println!(
-            "Time elapsed in bench_find_sql_ram is: {:?}",
-            duration / BENCH_LOOP as u32
-        );
+        let Ok(found) = warehouse
+            .find_logs_stream(&from, &to)
+            .try_collect::<Vec<_>>()
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        assert!(found.windows(2).all(|w| w[0].0 <= w[1].0));
+        assert!(found
+            .iter()
+            .all(|(t, _)| (from.as_nanos() as i64..=to.as_nanos() as i64).contains(t)));
+        assert_eq!(found.into_iter().map(|(_, l)| l).collect::<Vec<_>>(), logs);
 
         warehouse.close().await;
     }
 
     #[tokio::test]
-    async fn test_serializer_save() {
-        let mut hm = HashMap::new();
-
-        for (i, w) in ["a", "b", "c", "d"].iter().enumerate() {
-            hm.insert(w.to_string(), i as u32);
-        }
-        let graph = Node::new();
-
-        let mut s: Module = Module::new(graph);
-        s.set_map_from(hm);
-
-        let Ok(mut warehouse) = WarehouseSql::new(DatabaseStorage::Ram).await else {
-            println!("Cannot create warehouse");
+    async fn on_count_occurrence_should_count_only_identical_logs_inserted_since() {
+        let Ok(warehouse) = WarehouseSql::new(DatabaseStorage::Ram, &PoolConfig::default()).await
+        else {
             assert!(false);
             return;
         };
-
         let Ok(()) = warehouse.migrate().await else {
-            println!("Cannot migrate warehouse.");
+            assert!(false);
+            return;
+        };
+        let since = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let log = vec![Token::from(1), Token::from(2)];
+        let Ok(_) = warehouse.insert_log_at(&log, now_nanos()).await else {
             assert!(false);
             return;
         };
 
-        let Ok(()) = warehouse.save(&s).await else {
-            println!("Cannot save serializer warehouse");
+        for _ in 0..2 {
+            assert!(matches!(
+                warehouse.count_occurrence(&log, &since).await,
+                Ok(true)
+            ));
+        }
+        assert!(matches!(
+            warehouse.count_occurrence(&[Token::from(1)], &since).await,
+            Ok(false)
+        ));
+        let later = since + Duration::from_secs(60);
+        assert!(matches!(
+            warehouse.count_occurrence(&log, &later).await,
+            Ok(false)
+        ));
+
+        let Ok(occurrences) = sqlx::query_scalar::<_, i64>("SELECT occurrences FROM logs")
+            .fetch_one(&warehouse.pool)
+            .await
+        else {
             assert!(false);
             return;
         };
+        assert_eq!(occurrences, 3);
 
         warehouse.close().await;
     }
 
     #[tokio::test]
-    async fn test_serializer_read() {
-        let mut hm = HashMap::new();
-
-        for (i, w) in ["a", "b", "c", "d"].iter().enumerate() {
-            hm.insert(w.to_string(), i as u32);
-        }
-
-        let graph = Node::new();
-
-        let mut s: Module = Module::new(graph);
-        s.set_map_from(hm);
-
-        let Ok(mut warehouse) = WarehouseSql::new(DatabaseStorage::Ram).await else {
-            println!("Cannot create warehouse");
+    async fn on_compression_should_store_smaller_blobs_and_read_logs_of_either_setting() {
+        let Ok(warehouse) = WarehouseSql::new(DatabaseStorage::Ram, &PoolConfig::default()).await
+        else {
             assert!(false);
             return;
         };
-
         let Ok(()) = warehouse.migrate().await else {
-            println!("Cannot migrate warehouse.");
             assert!(false);
             return;
         };
-
-        let Ok(()) = warehouse.save(&s).await else {
+        let log: Vec<Token> = (0..256).map(|i| Token::from(i % 8)).collect();
+        let Ok(_) = warehouse.insert_log_at(&log, 100).await else {
+            assert!(false);
+            return;
+        };
+        let warehouse = warehouse.with_compression(true);
+        let Ok(id) = warehouse.insert_log_at(&log, 200).await else {
+            assert!(false);
+            return;
+        };
+
+        let Ok(sizes) =
+            sqlx::query_scalar::<_, i64>("SELECT length(data) FROM logs ORDER BY timestamp")
+                .fetch_all(&warehouse.pool)
+                .await
+        else {
+            assert!(false);
+            return;
+        };
+        assert!(sizes[1] < sizes[0]);
+        let Ok(found) = warehouse
+            .find_logs(&Duration::from_nanos(0), &Duration::from_nanos(300))
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(
+            found.into_iter().map(|l| l.data).collect::<Vec<_>>(),
+            vec![log.clone(), log.clone()]
+        );
+        let Ok(Some(found)) = warehouse.find_log_by_id(&id).await else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(found.data, log);
+        assert!(matches!(
+            warehouse
+                .count_occurrence(&log, &Duration::from_nanos(150))
+                .await,
+            Ok(true)
+        ));
+
+        warehouse.close().await;
+    }
+
+    #[tokio::test]
+    async fn on_partitions_should_read_logs_across_periods_and_expire_whole_periods() {
+        const HOUR: i64 = 3_600_000_000_000;
+        let Ok(warehouse) = WarehouseSql::new(DatabaseStorage::Ram, &PoolConfig::default()).await
+        else {
+            assert!(false);
+            return;
+        };
+        let warehouse = warehouse.with_partitions(Some(Duration::from_secs(3600)));
+        let Ok(()) = warehouse.migrate().await else {
+            assert!(false);
+            return;
+        };
+        let Ok(()) = warehouse
+            .insert_logs(&[
+                (HOUR + 1, vec![Token::from(2)]),
+                (1, vec![Token::from(1)]),
+                (2 * HOUR + 1, vec![Token::from(3)]),
+            ])
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        let Ok(id) = warehouse
+            .insert_log_at(&[Token::from(4)], 2 * HOUR + 2)
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        let (from, to) = (Duration::ZERO, Duration::from_nanos(3 * HOUR as u64));
+
+        let Ok(found) = warehouse.find_logs(&from, &to).await else {
+            assert!(false);
+            return;
+        };
+        let all: Vec<Vec<Token>> = (1..=4).map(|t| vec![Token::from(t)]).collect();
+        assert_eq!(found.into_iter().map(|l| l.data).collect::<Vec<_>>(), all);
+        let Ok(found) = warehouse
+            .find_logs(&Duration::from_nanos(HOUR as u64), &to)
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(found.len(), 3);
+        let Ok(Some(log)) = warehouse.find_log_by_id(&id).await else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(log.data, vec![Token::from(4)]);
+
+        let Ok(page) = warehouse
+            .find_logs_page(&from, &to, 2, &Page::Offset(1))
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(
+            page.logs.into_iter().map(|l| l.data).collect::<Vec<_>>(),
+            all[1..3]
+        );
+        let Some(cursor) = page.next else {
+            assert!(false);
+            return;
+        };
+        let Ok(page) = warehouse
+            .find_logs_page(&from, &to, 2, &Page::After(cursor))
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(
+            page.logs.into_iter().map(|l| l.data).collect::<Vec<_>>(),
+            all[3..]
+        );
+        let Ok(streamed) = warehouse
+            .find_logs_stream(&from, &to)
+            .try_collect::<Vec<_>>()
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(
+            streamed.into_iter().map(|(_, l)| l).collect::<Vec<_>>(),
+            all
+        );
+        assert!(matches!(
+            warehouse
+                .count_occurrence(&[Token::from(1)], &Duration::ZERO)
+                .await,
+            Ok(true)
+        ));
+
+        let Ok(deleted) = warehouse
+            .expire_logs(&Duration::from_nanos((2 * HOUR + 2) as u64))
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(deleted, 3);
+        let Ok(tables) = sqlx::query_scalar::<_, String>(SQL_LIST_PARTITIONS)
+            .fetch_all(&warehouse.pool)
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(
+            tables
+                .iter()
+                .filter_map(|t| partition::parse(t))
+                .collect::<Vec<_>>(),
+            vec![7200]
+        );
+
+        warehouse.close().await;
+    }
+
+    #[tokio::test]
+    async fn on_find_logs_with_any_should_fetch_logs_holding_indexed_tokens() {
+        let Ok(warehouse) = WarehouseSql::new(DatabaseStorage::Ram, &PoolConfig::default()).await
+        else {
+            assert!(false);
+            return;
+        };
+        let warehouse = warehouse.with_token_index(true);
+        let Ok(()) = warehouse.migrate().await else {
+            assert!(false);
+            return;
+        };
+        let logs: Vec<Vec<Token>> = vec![
+            vec![Token::from(1), Token::from(2), Token::from(1)],
+            vec![Token::from(3)],
+            vec![Token::from(2), Token::from(4)],
+        ];
+        for (i, log) in logs.iter().enumerate() {
+            let Ok(_) = warehouse.insert_log_at(log, 100 + i as i64).await else {
+                assert!(false);
+                return;
+            };
+        }
+        let (from, to) = (Duration::from_nanos(0), Duration::from_nanos(200));
+
+        let Ok(found) = warehouse
+            .find_logs_with_any(&from, &to, &[Token::from(2)])
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(
+            found.into_iter().map(|l| l.data).collect::<Vec<_>>(),
+            vec![logs[0].clone(), logs[2].clone()]
+        );
+        let Ok(found) = warehouse.find_logs_with_any(&from, &to, &[]).await else {
+            assert!(false);
+            return;
+        };
+        assert!(found.is_empty());
+
+        let Ok(deleted) = warehouse.expire_logs(&Duration::from_nanos(101)).await else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(deleted, 1);
+        let Ok(indexed) = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM log_tokens")
+            .fetch_one(&warehouse.pool)
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(indexed, 3);
+
+        warehouse.close().await;
+    }
+
+    #[tokio::test]
+    async fn on_compact_should_keep_logs_left_after_expiry() {
+        let Ok(warehouse) = WarehouseSql::new(DatabaseStorage::Ram, &PoolConfig::default()).await
+        else {
+            assert!(false);
+            return;
+        };
+        let Ok(()) = warehouse.migrate().await else {
+            assert!(false);
+            return;
+        };
+        for timestamp in [100, 200] {
+            let Ok(_) = warehouse.insert_log_at(&get_data(), timestamp).await else {
+                assert!(false);
+                return;
+            };
+        }
+        let Ok(1) = warehouse.expire_logs(&Duration::from_nanos(150)).await else {
+            assert!(false);
+            return;
+        };
+
+        let Ok(()) = warehouse.compact().await else {
+            assert!(false);
+            return;
+        };
+        let Ok(found) = warehouse
+            .find_logs(&Duration::from_nanos(0), &Duration::from_nanos(300))
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].timestamp, 200);
+
+        warehouse.close().await;
+    }
+
+    #[tokio::test]
+    async fn on_replica_should_read_logs_from_replica_and_write_to_primary() {
+        let path = std::env::temp_dir().join("scribe_replica.sqlite");
+        let _ = std::fs::remove_file(&path);
+        let url = format!("sqlite://{}?mode=rwc", path.display());
+        let Ok(replica) =
+            WarehouseSql::new(DatabaseStorage::Path(url.clone()), &PoolConfig::default()).await
+        else {
+            assert!(false);
+            return;
+        };
+        let Ok(()) = replica.migrate().await else {
+            assert!(false);
+            return;
+        };
+        let Ok(_) = replica.insert_log_at(&[Token::from(1)], 100).await else {
+            assert!(false);
+            return;
+        };
+        let Ok(warehouse) = WarehouseSql::new(DatabaseStorage::Ram, &PoolConfig::default()).await
+        else {
+            assert!(false);
+            return;
+        };
+        let Ok(warehouse) = warehouse
+            .with_replica(Some(&url), &PoolConfig::default())
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        let Ok(()) = warehouse.migrate().await else {
+            assert!(false);
+            return;
+        };
+        let Ok(_) = warehouse.insert_log_at(&[Token::from(2)], 100).await else {
+            assert!(false);
+            return;
+        };
+
+        let Ok(found) = warehouse
+            .find_logs(&Duration::from_nanos(0), &Duration::from_nanos(200))
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(
+            found.into_iter().map(|l| l.data).collect::<Vec<_>>(),
+            vec![vec![Token::from(1)]]
+        );
+        let Ok(written) = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM logs")
+            .fetch_one(&warehouse.pool)
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(written, 1);
+
+        warehouse.close().await;
+        replica.close().await;
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn on_insert_log_with_words_should_save_words_with_the_log() {
+        let Ok(warehouse) = WarehouseSql::new(DatabaseStorage::Ram, &PoolConfig::default()).await
+        else {
+            assert!(false);
+            return;
+        };
+        let Ok(()) = warehouse.migrate().await else {
+            assert!(false);
+            return;
+        };
+        let words = vec![
+            ("first".to_string(), Token::from(1)),
+            ("second".to_string(), Token::from(2)),
+        ];
+        let Ok(_) = warehouse
+            .insert_log_with_words(&[Token::from(1), Token::from(2)], 100, &words)
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        let Ok(()) = warehouse
+            .insert_logs_with_words(
+                &[(101, vec![Token::from(2)])],
+                &[("renamed".to_string(), Token::from(2))],
+            )
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+
+        let Ok(saved) = warehouse.find_words().await else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(
+            saved,
+            vec![
+                ("first".to_string(), Token::from(1)),
+                ("renamed".to_string(), Token::from(2))
+            ]
+        );
+        let Ok(found) = warehouse
+            .find_logs(&Duration::from_nanos(0), &Duration::from_nanos(200))
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(found.len(), 2);
+
+        warehouse.close().await;
+    }
+
+    #[tokio::test]
+    async fn on_insert_logs_should_keep_given_timestamps_and_order() {
+        let Ok(warehouse) = WarehouseSql::new(DatabaseStorage::Ram, &PoolConfig::default()).await
+        else {
+            assert!(false);
+            return;
+        };
+        let Ok(()) = warehouse.migrate().await else {
+            assert!(false);
+            return;
+        };
+        let inputs: Vec<(i64, Vec<Token>)> = vec![
+            (300, vec![Token::from(3)]),
+            (100, vec![Token::from(1)]),
+            (100, vec![Token::from(2)]),
+        ];
+        let Ok(()) = warehouse.insert_logs(&inputs).await else {
+            assert!(false);
+            return;
+        };
+
+        let Ok(found) = warehouse
+            .find_logs(&Duration::from_nanos(100), &Duration::from_nanos(200))
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(
+            found.into_iter().map(|l| l.data).collect::<Vec<_>>(),
+            vec![vec![Token::from(1)], vec![Token::from(2)]]
+        );
+
+        warehouse.close().await;
+    }
+
+    #[tokio::test]
+    async fn on_find_log_by_id_should_return_the_inserted_log() {
+        let Ok(warehouse) = WarehouseSql::new(DatabaseStorage::Ram, &PoolConfig::default()).await
+        else {
+            assert!(false);
+            return;
+        };
+        let Ok(()) = warehouse.migrate().await else {
+            assert!(false);
+            return;
+        };
+        let log = vec![Token::from(1), Token::from(2)];
+        let Ok(_) = warehouse
+            .insert_log_at(&[Token::from(3)], now_nanos())
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        let Ok(id) = warehouse.insert_log_at(&log, 1_000).await else {
+            assert!(false);
+            return;
+        };
+
+        let Ok(Some(found)) = warehouse.find_log_by_id(&id).await else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(
+            found,
+            Log {
+                id,
+                timestamp: 1_000,
+                data: log
+            }
+        );
+        for unknown in ["100", "x"] {
+            assert!(matches!(
+                warehouse.find_log_by_id(&LogId(unknown.to_string())).await,
+                Ok(None)
+            ));
+        }
+
+        warehouse.close().await;
+    }
+
+    #[tokio::test]
+    async fn bench_insert_sql_ram() {
+        let data: Vec<Token> = get_data();
+        let Ok(warehouse) = WarehouseSql::new(DatabaseStorage::Ram, &PoolConfig::default()).await
+        else {
+            println!("Cannot create warehouse");
+            assert!(false);
+            return;
+        };
+
+        let Ok(()) = warehouse.migrate().await else {
+            println!("Cannot migrate warehouse.");
+            assert!(false);
+            return;
+        };
+
+        let start = Instant::now();
+
+        for _ in 0..BENCH_LOOP {
+            let Ok(_) = warehouse.insert_log_at(&data, now_nanos()).await else {
+                println!("Cannot insert logs into warehouse.");
+                assert!(false);
+                return;
+            };
+        }
+
+        let duration = start.elapsed();
+
+        println!(
+            "Time elapsed in bench_insert_sql_ram is: {:?}",
+            duration / BENCH_LOOP as u32
+        );
+
+        warehouse.close().await;
+    }
+
+    #[tokio::test]
+    async fn bench_find_sql_ram() {
+        let data: Vec<Token> = get_data();
+        let Ok(warehouse) = WarehouseSql::new(DatabaseStorage::Ram, &PoolConfig::default()).await
+        else {
+            println!("Cannot create warehouse");
+            assert!(false);
+            return;
+        };
+
+        let Ok(()) = warehouse.migrate().await else {
+            println!("Cannot migrate warehouse.");
+            assert!(false);
+            return;
+        };
+
+        let time_0 = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        for _ in 0..INSERTS {
+            let Ok(_) = warehouse.insert_log_at(&data, now_nanos()).await else {
+                println!("Cannot insert logs into warehouse.");
+                assert!(false);
+                return;
+            };
+        }
+
+        let time_1 = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        let start = Instant::now();
+
+        for _ in 0..BENCH_LOOP {
+            let Ok(_) = warehouse.find_logs(&time_0, &time_1).await else {
+                println!("Cannot get logs from warehouse.");
+                assert!(false);
+                return;
+            };
+        }
+
+        let duration = start.elapsed();
+
+        println!(
+            "Time elapsed in bench_find_sql_ram is: {:?}",
+            duration / BENCH_LOOP as u32
+        );
+
+        warehouse.close().await;
+    }
+
+    #[tokio::test]
+    async fn test_serializer_save() {
+        let words: Vec<(String, Token)> = ["a", "b", "c", "d"]
+            .iter()
+            .enumerate()
+            .map(|(i, w)| (w.to_string(), Token::from(i as u32)))
+            .collect();
+
+        let Ok(warehouse) = WarehouseSql::new(DatabaseStorage::Ram, &PoolConfig::default()).await
+        else {
+            println!("Cannot create warehouse");
+            assert!(false);
+            return;
+        };
+
+        let Ok(()) = warehouse.migrate().await else {
+            println!("Cannot migrate warehouse.");
+            assert!(false);
+            return;
+        };
+
+        let Ok(()) = warehouse.insert_logs_with_words(&[], &words).await else {
             println!("Cannot save serializer warehouse");
             assert!(false);
             return;
         };
 
-        let Ok(_) = warehouse.read().await else {
+        warehouse.close().await;
+    }
+
+    #[tokio::test]
+    async fn test_serializer_read() {
+        let words: Vec<(String, Token)> = ["a", "b", "c", "d"]
+            .iter()
+            .enumerate()
+            .map(|(i, w)| (w.to_string(), Token::from(i as u32)))
+            .collect();
+
+        let Ok(warehouse) = WarehouseSql::new(DatabaseStorage::Ram, &PoolConfig::default()).await
+        else {
+            println!("Cannot create warehouse");
+            assert!(false);
+            return;
+        };
+
+        let Ok(()) = warehouse.migrate().await else {
+            println!("Cannot migrate warehouse.");
+            assert!(false);
+            return;
+        };
+
+        let Ok(()) = warehouse.insert_logs_with_words(&[], &words).await else {
+            println!("Cannot save serializer warehouse");
+            assert!(false);
+            return;
+        };
+
+        let Ok(read) = warehouse.find_words().await else {
             println!("Cannot save serializer warehouse");
             assert!(false);
             return;
         };
+        assert_eq!(read, words);
+
+        warehouse.close().await;
+    }
+
+    #[tokio::test]
+    async fn test_read_v1_fixture() {
+        let path = std::env::temp_dir().join("scribe_v1_fixture.sqlite");
+        let Ok(_) = std::fs::copy("./assets/fixtures/v1/scribe.sqlite", &path) else {
+            println!("Cannot copy fixture");
+            assert!(false);
+            return;
+        };
+
+        let Ok(warehouse) = WarehouseSql::new(
+            DatabaseStorage::Path(format!("sqlite://{}", path.display())),
+            &PoolConfig::default(),
+        )
+        .await
+        else {
+            println!("Cannot create warehouse");
+            assert!(false);
+            return;
+        };
+
+        let Ok(words) = warehouse.find_words().await else {
+            println!("Cannot read serializer");
+            assert!(false);
+            return;
+        };
+        let mut s = scribe::dictionary::Module::new(scribe::trie::Trie::new());
+        s.load_words(words);
+
+        let Ok(logs) = warehouse
+            .find_logs(
+                &Duration::from_secs(1_700_000_000),
+                &Duration::from_secs(1_700_000_002),
+            )
+            .await
+        else {
+            println!("Cannot find logs");
+            assert!(false);
+            return;
+        };
+
+        let logs: Vec<String> = logs.iter().map(|l| s.deserialize(&l.data)).collect();
+        assert_eq!(
+            logs,
+            vec![
+                "connection refused by peer",
+                "request timeout reached",
+                "connection timeout"
+            ]
+        );
+
+        warehouse.close().await;
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn on_migrate_should_apply_each_version_once_on_databases_created_before_versioning() {
+        let path = std::env::temp_dir().join("scribe_v1_migrate.sqlite");
+        let Ok(_) = std::fs::copy("./assets/fixtures/v1/scribe.sqlite", &path) else {
+            assert!(false);
+            return;
+        };
+        let Ok(warehouse) = WarehouseSql::new(
+            DatabaseStorage::Path(format!("sqlite://{}", path.display())),
+            &PoolConfig::default(),
+        )
+        .await
+        else {
+            assert!(false);
+            return;
+        };
+
+        for _ in 0..2 {
+            let Ok(()) = warehouse.migrate().await else {
+                assert!(false);
+                return;
+            };
+        }
+        let Ok(applied) = sqlx::query_scalar::<_, i64>("SELECT version FROM schema_migrations")
+            .fetch_all(&warehouse.pool)
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(applied, vec![1, 2, 3, 4]);
 
         warehouse.close().await;
+        let _ = std::fs::remove_file(&path);
     }
 }
@@ -0,0 +1,1620 @@
+use super::{
+    deduplicated, mark_saved, new_dictionary, now, read_dictionary, save, save_trie,
+    serialize_error, write_dictionary, Saved, ServerActor,
+};
+use crate::api::{self, ApiError, ErrorCode};
+use crate::backup::{backup_to, ensure_streaming, save_imported};
+use crate::repository;
+use crate::repository::interface::{Cursor, Log, LogId, Page, RepositoryProvider};
+use crate::timeparse::{self, TimeExpr};
+use crate::{elastic, gelf, hec, housekeeping, import, metrics, settings, BUILT_AT, COMMIT};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse, Responder, Result};
+use futures::StreamExt;
+use scribe::dictionary;
+use scribe::token::Token;
+use serde::ser::SerializeSeq;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use web::{Data, Json};
+
+/// Largest edit distance of fuzzy word matching, larger ones match most of the dictionary.
+const MAX_FUZZY_DISTANCE: u8 = 2;
+
+/// Number of suggestions returned when none is asked for and the most that can be asked for.
+const DEFAULT_SUGGESTIONS: usize = 10;
+const MAX_SUGGESTIONS: usize = 1000;
+
+/// Most words a prefix is explained with, a short prefix may expand to most of the dictionary.
+const MAX_EXPLAINED: usize = 1000;
+
+/// Logs of an import serialized under one dictionary lock and inserted in one call to the repository.
+const IMPORT_BATCH: usize = 1000;
+
+/// Longest line of an imported file, a longer one fails the import.
+const MAX_IMPORT_LINE_BYTES: usize = 16 * 1024 * 1024;
+
+/// Most rejected lines of an import reported with their errors, the rest are only counted.
+const MAX_REPORTED_REJECTIONS: usize = 100;
+
+/// Most tokens a filtered read looks up in the token index, more of them are filtered in memory instead.
+const MAX_INDEXED_TOKENS: usize = 1000;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Version {
+    version: String,
+    commit: String,
+    built_at: String,
+    backends: Vec<String>,
+    config_digest: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Health {
+    status: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogInput {
+    log: String,
+    #[serde(default)]
+    timestamp: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogsInput {
+    logs: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedOutput {
+    id: String,
+}
+
+/// LogsImportQuery tells the format of imported lines when the content type does not,
+/// and whether text lines start with their timestamp.
+///
+#[derive(Debug, Deserialize)]
+pub struct LogsImportQuery {
+    #[serde(default)]
+    format: Option<import::Format>,
+    #[serde(default)]
+    timestamps: bool,
+}
+
+/// ImportedOutput counts logs saved by an import and lines it rejected, reporting the first rejected lines.
+///
+#[derive(Debug, Default, Serialize)]
+struct ImportedOutput {
+    imported: usize,
+    rejected: usize,
+    errors: Vec<RejectedLine>,
+}
+
+#[derive(Debug, Serialize)]
+struct RejectedLine {
+    line: usize,
+    message: String,
+}
+
+#[derive(Serialize)]
+struct LogsOutput<'a> {
+    logs: DecodedLogs<'a>,
+    ids: Vec<&'a str>,
+    timestamps: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next: Option<String>,
+    meta: Meta,
+}
+
+/// Meta describes the result of a read, so a client shows how many logs matched without counting them again.
+/// Total is left out for pages of unfiltered logs, as only the page is read.
+///
+#[derive(Serialize)]
+struct Meta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total: Option<usize>,
+    truncated: bool,
+    took_ms: f64,
+}
+
+#[derive(Serialize)]
+struct LogOutput<'a> {
+    id: &'a str,
+    timestamp: String,
+    log: String,
+}
+
+/// DecodedLogs serializes buffers as a sequence of logs,
+/// decoding every log in to the same String instead of allocating a String per log.
+///
+struct DecodedLogs<'a> {
+    dict: &'a dictionary::Module,
+    buffers: &'a [Log],
+}
+
+impl Serialize for DecodedLogs<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.buffers.len()))?;
+        let mut decoded = String::new();
+        for log in self.buffers.iter() {
+            self.dict.deserialize_into(&log.data, &mut decoded);
+            seq.serialize_element(&decoded)?;
+        }
+        seq.end()
+    }
+}
+
+/// Prefixes accepts a single prefix or a list of prefixes, matching logs that contain any of them.
+///
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Prefixes {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Prefixes {
+    fn as_strs(&self) -> Vec<&str> {
+        match self {
+            Self::One(prefix) => vec![prefix.as_str()],
+            Self::Many(prefixes) => prefixes.iter().map(|p| p.as_str()).collect(),
+        }
+    }
+}
+
+/// Near matches logs holding all the words within window positions of each other.
+///
+#[derive(Debug, Serialize, Deserialize)]
+struct Near {
+    words: Vec<String>,
+    window: usize,
+}
+
+/// WordRange matches logs holding a word from `from` inclusive up to `to` exclusive in lexicographic order.
+///
+#[derive(Debug, Serialize, Deserialize)]
+struct WordRange {
+    from: String,
+    to: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Query {
+    prefix: Option<Prefixes>,
+    prefix_fuzzy: Option<u8>,
+    words: Option<Vec<String>>,
+    #[serde(default)]
+    match_all: bool,
+    #[serde(default)]
+    ignore_case: bool,
+    fuzzy: Option<u8>,
+    pattern: Option<String>,
+    suffix: Option<String>,
+    word_range: Option<WordRange>,
+    phrase: Option<String>,
+    words_near: Option<Near>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+    cursor: Option<String>,
+    from: Option<TimeExpr>,
+    to: Option<TimeExpr>,
+}
+
+impl Query {
+    /// Returns true if logs are filtered by their words.
+    ///
+    fn is_filtered(&self) -> bool {
+        self.prefix.is_some()
+            || self.words.is_some()
+            || self.pattern.is_some()
+            || self.suffix.is_some()
+            || self.word_range.is_some()
+            || self.phrase.is_some()
+            || self.words_near.is_some()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TopQuery {
+    n: usize,
+    from: Option<TimeExpr>,
+    to: Option<TimeExpr>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WordCount {
+    word: String,
+    count: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TopWordsOutput {
+    words: Vec<WordCount>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SuggestQuery {
+    prefix: String,
+    #[serde(default = "default_suggestions")]
+    k: usize,
+}
+
+fn default_suggestions() -> usize {
+    DEFAULT_SUGGESTIONS
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DictionaryEntry {
+    word: String,
+    num: Token,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExplainQuery {
+    prefix: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ExplainOutput {
+    words: Vec<DictionaryEntry>,
+    truncated: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct DictionaryStats {
+    words: usize,
+    filter: settings::FilterKind,
+    graph: Option<dictionary::Stats>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportQuery {
+    #[serde(default)]
+    force: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ImportOutput {
+    words: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupInput {
+    path: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BackupOutput {
+    logs: u64,
+    words: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ReloadOutput {
+    restart_required: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WordsOutput {
+    words: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RotateQuery {
+    #[serde(default)]
+    prune: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RotateOutput {
+    words: usize,
+    pruned: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ExpireOutput {
+    expired: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct AdminStats {
+    version: String,
+    dictionary: DictionaryStats,
+    unsaved_words: usize,
+    write_queue: Option<QueueStats>,
+    retention: RetentionStats,
+    dedup_repeated_logs: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct QueueStats {
+    depth: i64,
+    rejected_logs: u64,
+    failures: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct RetentionStats {
+    seconds: Option<u64>,
+    runs: u64,
+    failures: u64,
+    reclaimed_logs: u64,
+}
+
+/// Returns the version, the commit and the time of the build, the repository backends built in
+/// and the digest of the setup the server runs, reloaded fields included.
+///
+#[inline(always)]
+pub async fn version(state: Data<ServerActor>) -> Result<impl Responder, ApiError> {
+    let v = Version {
+        version: state.version.to_string(),
+        commit: COMMIT.to_string(),
+        built_at: BUILT_AT.to_string(),
+        backends: repository::BACKENDS.iter().map(|b| b.to_string()).collect(),
+        config_digest: state.config_digest.load().to_string(),
+    };
+    Ok(Json(v))
+}
+
+#[inline(always)]
+pub async fn health() -> Result<impl Responder, ApiError> {
+    let h = Health {
+        status: "ok".to_string(),
+    };
+    Ok(Json(h))
+}
+
+/// Exports metrics in the Prometheus text format.
+///
+pub async fn metrics(state: Data<ServerActor>) -> Result<impl Responder, ApiError> {
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(state.metrics.render()))
+}
+
+#[inline(always)]
+pub async fn save_log(
+    input: Json<LogInput>,
+    state: Data<ServerActor>,
+) -> Result<impl Responder, ApiError> {
+    let timestamp = match input.timestamp {
+        Some(timestamp) => match i64::try_from(timestamp) {
+            Ok(timestamp) => timestamp,
+            Err(_) => {
+                return Err(ApiError::new(
+                    ErrorCode::InvalidInput,
+                    "Timestamp is out of range.",
+                ))
+            }
+        },
+        None => now(),
+    };
+
+    Ok(match save(&state, &input.log, timestamp).await? {
+        Saved::Id(id) => HttpResponse::Ok().json(SavedOutput { id: id.0 }),
+        Saved::Queued => HttpResponse::Accepted().finish(),
+        Saved::Repeated => HttpResponse::Ok().finish(),
+    })
+}
+
+/// Saves the GELF message of the body, plain or compressed, as Graylog shippers send it to an HTTP input.
+/// The message is saved as the text of Message::log, at its timestamp or now if it has none.
+///
+pub async fn save_gelf(
+    body: web::Bytes,
+    state: Data<ServerActor>,
+) -> Result<impl Responder, ApiError> {
+    let message = gelf::parse(&body).map_err(|e| {
+        ApiError::new(ErrorCode::BadRequest, "Cannot read the GELF message.")
+            .with_details(serde_json::json!({ "cause": e.to_string() }))
+    })?;
+    save(
+        &state,
+        &message.log(),
+        message.timestamp().unwrap_or_else(now),
+    )
+    .await?;
+
+    Ok(HttpResponse::Accepted().finish())
+}
+
+/// Answers the cluster info Elasticsearch clients read the version of before they ship.
+///
+pub async fn elastic_info() -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(elastic::PRODUCT_HEADER)
+        .json(elastic::Info::default())
+}
+
+/// Saves the documents of an Elasticsearch bulk request, as Filebeat's Elasticsearch output sends them,
+/// to the index of the path when an action names none. Each document is saved as elastic::parse writes it,
+/// at its `@timestamp` or now, and answered with an item of its own, so a client retries only the failed ones.
+///
+pub async fn save_bulk(
+    req: actix_web::HttpRequest,
+    body: web::Bytes,
+    state: Data<ServerActor>,
+) -> HttpResponse {
+    let started = Instant::now();
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let body = String::from_utf8_lossy(&body);
+    let items = match elastic::parse(&body, req.match_info().get("index"), since_epoch) {
+        Ok(items) => items,
+        Err(failure) => {
+            return HttpResponse::BadRequest()
+                .insert_header(elastic::PRODUCT_HEADER)
+                .json(serde_json::json!({ "error": failure, "status": failure.status }));
+        }
+    };
+    let mut response = elastic::Response::new(items.len());
+    for item in items {
+        let outcome = match item.document {
+            Ok(document) => {
+                let timestamp = document.timestamp.unwrap_or_else(now);
+                match save(&state, &document.log, timestamp).await {
+                    Ok(Saved::Id(id)) => Ok(Some(id.0)),
+                    Ok(_) => Ok(None),
+                    Err(e) if actix_web::ResponseError::status_code(&e).is_client_error() => Err(
+                        elastic::Failure::new(400, "illegal_argument_exception", e.to_string()),
+                    ),
+                    Err(e) => {
+                        tracing::warn!(error = %e, "cannot save bulk document");
+                        Err(elastic::Failure::new(
+                            503,
+                            "unavailable_shards_exception",
+                            e.to_string(),
+                        ))
+                    }
+                }
+            }
+            Err(failure) => Err(failure),
+        };
+        response.push(item.action, item.index, outcome);
+    }
+
+    HttpResponse::Ok()
+        .insert_header(elastic::PRODUCT_HEADER)
+        .json(response.took(started.elapsed()))
+}
+
+/// Saves the events of the body as the Splunk HTTP Event Collector does, authorized by one of the HEC tokens.
+/// Every event is checked before any is saved, the first one that cannot be is named in the reply.
+/// Replies are the HEC `{text, code}` objects clients of the collector branch on, not the API error envelope.
+///
+pub async fn save_hec(
+    req: actix_web::HttpRequest,
+    body: web::Bytes,
+    state: Data<ServerActor>,
+) -> HttpResponse {
+    let authorization = req.headers().get(actix_web::http::header::AUTHORIZATION);
+    if let Err(reply) = hec::authorize(authorization, state.setup.get_hec_tokens()) {
+        return reply.response();
+    }
+    let events = match hec::parse(&body) {
+        Ok(events) => events,
+        Err(reply) => return reply.response(),
+    };
+    let max_log_tokens = state.runtime.load().max_log_tokens;
+    let mut logs = Vec::with_capacity(events.len());
+    for (i, event) in events.iter().enumerate() {
+        let Some(log) = event.log() else {
+            return hec::Reply::EVENT_BLANK.at(i).response();
+        };
+        if api::validate_log(&log, max_log_tokens).is_err() {
+            return hec::Reply::INVALID_DATA_FORMAT.at(i).response();
+        }
+        logs.push((log, event.timestamp().unwrap_or_else(now)));
+    }
+    for (i, (log, timestamp)) in logs.iter().enumerate() {
+        if let Err(e) = save(&state, log, *timestamp).await {
+            tracing::warn!(error = %e, "cannot save HEC event");
+            if actix_web::ResponseError::status_code(&e).is_client_error() {
+                return hec::Reply::INVALID_DATA_FORMAT.at(i).response();
+            }
+            return hec::Reply::SERVER_BUSY.response();
+        }
+    }
+
+    hec::Reply::SUCCESS.response()
+}
+
+/// Imports logs of the body, one per line, streamed or uploaded as files of a multipart form, to backfill history.
+/// Lines are NDJSON objects with the `log` and its `timestamp`, or text, read as the format query asks,
+/// as the content type of the body or the file tells otherwise. With the timestamps query text lines start with theirs.
+/// Logs are saved in batches, skipping the write queue and dedup. Lines that cannot be read or saved are rejected
+/// and reported without failing the import, a failing repository fails it with the count of logs already imported.
+///
+pub async fn import_logs(
+    req: actix_web::HttpRequest,
+    payload: web::Payload,
+    query: web::Query<LogsImportQuery>,
+    state: Data<ServerActor>,
+) -> Result<impl Responder, ApiError> {
+    let content_type = req
+        .headers()
+        .get(actix_web::http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let mut importer = Importer::new(&state, query.timestamps);
+    if content_type.starts_with("multipart/form-data") {
+        let mut form = actix_multipart::Multipart::new(req.headers(), payload);
+        while let Some(field) = form.next().await {
+            let field = field.map_err(|e| importer.failed(unreadable(e)))?;
+            let is_file = field
+                .content_disposition()
+                .is_some_and(|disposition| disposition.get_filename().is_some());
+            if !is_file {
+                continue;
+            }
+            let format = query.format.unwrap_or_else(|| {
+                field
+                    .content_type()
+                    .map(|mime| import::Format::of_content_type(mime.essence_str()))
+                    .unwrap_or_default()
+            });
+            importer.read(format, field).await?;
+        }
+    } else {
+        let format = query
+            .format
+            .unwrap_or_else(|| import::Format::of_content_type(content_type));
+        importer.read(format, payload).await?;
+    }
+    importer.flush().await?;
+
+    Ok(HttpResponse::Ok().json(importer.output))
+}
+
+fn unreadable(e: impl Display) -> ApiError {
+    ApiError::new(
+        ErrorCode::BadRequest,
+        format!("Cannot read the imported file: {}.", e),
+    )
+}
+
+/// Importer saves lines of an import in batches, numbering lines across all files of the import.
+///
+struct Importer<'a> {
+    state: &'a ServerActor,
+    leading: bool,
+    started: Duration,
+    line: usize,
+    batch: Vec<(usize, String, i64)>,
+    output: ImportedOutput,
+}
+
+impl<'a> Importer<'a> {
+    fn new(state: &'a ServerActor, leading: bool) -> Self {
+        Self {
+            state,
+            leading,
+            started: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+            line: 0,
+            batch: Vec::with_capacity(IMPORT_BATCH),
+            output: ImportedOutput::default(),
+        }
+    }
+
+    /// Reads lines of the stream in the format, saving them in batches as they complete.
+    ///
+    async fn read<S, E>(&mut self, format: import::Format, mut stream: S) -> Result<(), ApiError>
+    where
+        S: futures::Stream<Item = Result<web::Bytes, E>> + Unpin,
+        E: Display,
+    {
+        let mut lines = import::Lines::default();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| self.failed(unreadable(e)))?;
+            let complete = lines.push(&chunk, MAX_IMPORT_LINE_BYTES).map_err(|_| {
+                self.failed(ApiError::new(
+                    ErrorCode::PayloadTooLarge,
+                    format!(
+                        "Line {} is longer than {} bytes.",
+                        self.line + 1,
+                        MAX_IMPORT_LINE_BYTES
+                    ),
+                ))
+            })?;
+            for line in complete {
+                self.push(format, &line).await?;
+            }
+        }
+        if let Some(line) = lines.finish() {
+            self.push(format, &line).await?;
+        }
+        Ok(())
+    }
+
+    async fn push(&mut self, format: import::Format, line: &str) -> Result<(), ApiError> {
+        self.line += 1;
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+        let parsed = import::parse(format, self.leading, line, self.started)
+            .map_err(|cause| {
+                ApiError::new(
+                    ErrorCode::BadRequest,
+                    format!("Cannot read the line: {}.", cause),
+                )
+            })
+            .and_then(|(log, timestamp)| {
+                api::validate_log(&log, self.state.runtime.load().max_log_tokens)?;
+                Ok((log, timestamp))
+            });
+        match parsed {
+            Ok((log, timestamp)) => {
+                self.batch
+                    .push((self.line, log, timestamp.unwrap_or_else(now)));
+            }
+            Err(e) => self.reject(self.line, &e),
+        }
+        if self.batch.len() >= IMPORT_BATCH {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Serializes the batch under a single dictionary lock and inserts it in a single call to the repository.
+    ///
+    async fn flush(&mut self) -> Result<(), ApiError> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let state = self.state;
+        let batch = std::mem::replace(&mut self.batch, Vec::with_capacity(IMPORT_BATCH));
+        let mut rejected = Vec::new();
+        let _saving = state.import_gate.read().await;
+        let (logs, words) = {
+            let Ok(mut dict) = write_dictionary(&state.dict, &state.metrics) else {
+                return Err(self.failed(ApiError::dictionary()));
+            };
+            let _serialize = tracing::info_span!("serialize", logs = batch.len()).entered();
+            let mut logs = Vec::with_capacity(batch.len());
+            for (line, log, timestamp) in batch {
+                if let Err(e) = dict.check_log(&log) {
+                    rejected.push((line, serialize_error(e)));
+                    continue;
+                }
+                match state.metrics.stages.tokenize.time(|| dict.serialize(&log)) {
+                    Ok(buf) => logs.push((timestamp, buf)),
+                    Err(e) => {
+                        drop(dict);
+                        return Err(self.failed(serialize_error(e)));
+                    }
+                }
+            }
+            (logs, dict.unsaved_words())
+        };
+        for (line, e) in rejected {
+            self.reject(line, &e);
+        }
+        if let Err(e) = state.repo.insert_logs_with_words(&logs, &words).await {
+            return Err(self.failed(ApiError::from(e)));
+        }
+        mark_saved(&state.dict, &state.metrics, &words);
+        self.output.imported += logs.len();
+        Ok(())
+    }
+
+    fn reject(&mut self, line: usize, e: &ApiError) {
+        self.output.rejected += 1;
+        if self.output.errors.len() < MAX_REPORTED_REJECTIONS {
+            self.output.errors.push(RejectedLine {
+                line,
+                message: e.to_string(),
+            });
+        }
+    }
+
+    /// Adds the count of logs imported so far and the line the import stopped at to the error failing it.
+    ///
+    fn failed(&self, e: ApiError) -> ApiError {
+        e.with_details(serde_json::json!({
+            "imported": self.output.imported,
+            "line": self.line,
+        }))
+    }
+}
+
+/// Saves many logs at once, serializing them under a single dictionary lock
+/// and inserting them in a single call to the repository.
+///
+pub async fn save_logs(
+    input: Json<LogsInput>,
+    state: Data<ServerActor>,
+) -> Result<impl Responder, ApiError> {
+    let max_log_tokens = state.runtime.load().max_log_tokens;
+    for log in input.logs.iter() {
+        api::validate_log(log, max_log_tokens)?;
+    }
+    let timestamp = now();
+    let _saving = state.import_gate.read().await;
+    let (bufs, words) = {
+        let Ok(mut dict) = write_dictionary(&state.dict, &state.metrics) else {
+            return Err(ApiError::dictionary());
+        };
+        #[cfg(feature = "chaos")]
+        crate::chaos::lock_delay();
+        for log in input.logs.iter() {
+            if let Err(e) = dict.check_log(log) {
+                return Err(serialize_error(e));
+            }
+        }
+        let _serialize = tracing::info_span!("serialize", logs = input.logs.len()).entered();
+        let mut bufs = Vec::with_capacity(input.logs.len());
+        for log in input.logs.iter() {
+            match state.metrics.stages.tokenize.time(|| dict.serialize(log)) {
+                Ok(buf) => bufs.push(buf),
+                Err(e) => return Err(serialize_error(e)),
+            }
+        }
+        (bufs, dict.unsaved_words())
+    };
+    let mut batch = Vec::with_capacity(bufs.len());
+    for buf in bufs {
+        if !deduplicated(&state, &buf).await? {
+            batch.push((timestamp, buf));
+        }
+    }
+    if let Err(e) = state.repo.insert_logs_with_words(&batch, &words).await {
+        if let Some(dedup) = &state.dedup {
+            for (_, buf) in batch.iter() {
+                dedup.forget(buf);
+            }
+        }
+        return Err(ApiError::from(e));
+    };
+    mark_saved(&state.dict, &state.metrics, &words);
+
+    Ok(HttpResponse::Ok())
+}
+
+/// Returns the time range of a query, where missing `to` means now and missing `from`
+/// the oldest log kept by retention, or the beginning of time when logs are kept forever.
+/// Both ends may be written as nanoseconds, RFC 3339 dates or relative to now, see TimeExpr.
+///
+fn time_range(
+    state: &ServerActor,
+    from: Option<&TimeExpr>,
+    to: Option<&TimeExpr>,
+) -> Result<(Duration, Duration), ApiError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let resolve = |expr: &TimeExpr| {
+        expr.resolve(now).map_err(|cause| {
+            ApiError::new(ErrorCode::BadRequest, "Cannot parse the time range.")
+                .with_details(serde_json::json!({ "cause": cause }))
+        })
+    };
+    let from = match (from, state.runtime.load().retention) {
+        (Some(from), _) => resolve(from)?,
+        (None, Some(retention)) => now.saturating_sub(retention),
+        (None, None) => Duration::ZERO,
+    };
+    let to = match to {
+        Some(to) => resolve(to)?,
+        None => now,
+    };
+    Ok((from, to))
+}
+
+/// Returns the error of a read that did not finish within the query timeout,
+/// with details of the stage it was aborted at and how far it got.
+///
+fn query_timeout(timeout: Duration, mut details: serde_json::Value) -> ApiError {
+    let timeout = timeout.as_millis() as u64;
+    details["timeout_ms"] = timeout.into();
+    ApiError::new(
+        ErrorCode::QueryTimeout,
+        format!("Query took longer than {} milliseconds.", timeout),
+    )
+    .with_details(details)
+}
+
+#[inline(always)]
+pub async fn read_logs(
+    input: Json<Query>,
+    state: Data<ServerActor>,
+) -> Result<impl Responder, ApiError> {
+    let start = Instant::now();
+    let runtime = state.runtime.load_full();
+    if [input.fuzzy, input.prefix_fuzzy]
+        .iter()
+        .any(|d| d.is_some_and(|d| d > MAX_FUZZY_DISTANCE))
+    {
+        return Err(ApiError::new(
+            ErrorCode::InvalidInput,
+            format!("Fuzzy distance is limited to {}.", MAX_FUZZY_DISTANCE),
+        )
+        .with_details(serde_json::json!({ "max": MAX_FUZZY_DISTANCE })));
+    }
+    if input.ignore_case && input.prefix_fuzzy.is_some() {
+        return Err(ApiError::new(
+            ErrorCode::InvalidInput,
+            "Fuzzy prefixes cannot ignore case.",
+        ));
+    }
+    let (from, to) = time_range(&state, input.from.as_ref(), input.to.as_ref())?;
+    api::validate_range(&from, &to, runtime.max_query_span)?;
+    let page = match (input.offset, input.cursor.as_ref()) {
+        (Some(_), Some(_)) => {
+            return Err(ApiError::new(
+                ErrorCode::InvalidInput,
+                "Either offset or cursor can be given.",
+            ))
+        }
+        (Some(offset), None) => Some(Page::Offset(offset)),
+        (None, Some(cursor)) => Some(Page::After(Cursor(cursor.clone()))),
+        (None, None) => None,
+    };
+    if page.is_some() && (input.limit.is_none() || input.is_filtered()) {
+        return Err(ApiError::new(
+            ErrorCode::InvalidInput,
+            "Pages need a limit and cannot be filtered by words.",
+        ));
+    }
+
+    // Logs matching words or prefixes hold one of their tokens, so the repository may fetch only those.
+    let (tokens, folded, mut lookup) = {
+        let Ok(dict) = read_dictionary(&state.dict, &state.metrics) else {
+            return Err(ApiError::dictionary());
+        };
+        let lookup = Instant::now();
+        let folded = match input.prefix.as_ref() {
+            Some(prefixes) if input.ignore_case => {
+                Some(dict.prefixed_nums_ignoring_case(&prefixes.as_strs()))
+            }
+            _ => None,
+        };
+        let tokens = match (input.words.as_ref(), input.fuzzy, input.prefix.as_ref()) {
+            (Some(words), None, _) => Some(words.iter().filter_map(|w| dict.num(w)).collect()),
+            (None, _, Some(_)) if folded.is_some() => folded
+                .as_ref()
+                .filter(|set| set.len() <= MAX_INDEXED_TOKENS)
+                .map(|set| set.iter().copied().collect()),
+            (None, _, Some(prefixes)) if input.prefix_fuzzy.is_none() => dict
+                .prefixed_nums(&prefixes.as_strs(), MAX_INDEXED_TOKENS)
+                .map(|set| set.into_iter().collect::<Vec<_>>()),
+            _ => None,
+        };
+        (tokens, folded, lookup.elapsed())
+    };
+
+    // Without filters every log in range matches, so the repository reads only the page.
+    let paged = input.limit.is_some() && !input.is_filtered();
+    let fetch = async {
+        match (input.limit, paged) {
+            (Some(limit), true) => {
+                let page = page.unwrap_or(Page::Offset(0));
+                let page = state.repo.find_logs_page(&from, &to, limit, &page).await?;
+                Ok::<_, ApiError>((page.logs, page.next.map(|c| c.0)))
+            }
+            _ => match tokens.as_ref() {
+                Some(tokens) => Ok((
+                    state.repo.find_logs_with_any(&from, &to, tokens).await?,
+                    None,
+                )),
+                None => Ok((state.repo.find_logs(&from, &to).await?, None)),
+            },
+        }
+    };
+    let deadline = runtime.query_timeout.map(|timeout| start + timeout);
+    let fetching = Instant::now();
+    let fetched = match deadline {
+        Some(deadline) => {
+            match tokio::time::timeout_at(tokio::time::Instant::from_std(deadline), fetch).await {
+                Ok(fetched) => fetched,
+                Err(_) => {
+                    state.metrics.stages.fetch.observe(fetching.elapsed());
+                    return Err(query_timeout(
+                        runtime.query_timeout.unwrap_or_default(),
+                        serde_json::json!({ "stage": "fetch" }),
+                    ));
+                }
+            }
+        }
+        None => fetch.await,
+    };
+    state.metrics.stages.fetch.observe(fetching.elapsed());
+    let (logs, next) = fetched?;
+
+    let looking_up = Instant::now();
+    let prefixed = match (folded, state.prefixes.as_ref(), input.prefix.as_ref()) {
+        (Some(set), _, _) => Some(set),
+        (None, Some(reader), Some(prefixes)) if input.prefix_fuzzy.is_none() => {
+            Some(reader.find_prefix_any(&prefixes.as_strs()))
+        }
+        _ => None,
+    };
+    lookup += looking_up.elapsed();
+
+    let Ok(dict) = read_dictionary(&state.dict, &state.metrics) else {
+        return Err(ApiError::dictionary());
+    };
+    #[cfg(feature = "chaos")]
+    crate::chaos::lock_delay();
+
+    let looking_up = Instant::now();
+    let prefixes = input.prefix.as_ref().map(|p| p.as_strs());
+    let max = logs.iter().map(|log| log.data.len()).sum::<usize>();
+    let mut matchers: Vec<dictionary::Matcher> = Vec::new();
+    match (prefixed, prefixes.as_ref(), input.prefix_fuzzy) {
+        (Some(set), _, _) => matchers.push(dictionary::Module::any_of_matcher(set)),
+        (None, Some(prefixes), Some(distance)) => {
+            matchers.push(dict.prefixed_fuzzy_matcher(prefixes, distance))
+        }
+        (None, Some(prefixes), None) => matchers.push(dict.prefixed_any_matcher(prefixes, max)),
+        (None, None, _) => (),
+    }
+
+    if let Some(words) = input.words.as_ref() {
+        matchers.push(match (input.fuzzy, input.match_all) {
+            (None, true) => dict.words_all_matcher(words),
+            (None, false) => dict.word_matcher(words),
+            (Some(distance), true) => dict.words_all_fuzzy_matcher(words, distance),
+            (Some(distance), false) => dict.word_fuzzy_matcher(words, distance),
+        });
+    }
+
+    if let Some(pattern) = input.pattern.as_ref() {
+        matchers.push(dict.pattern_matcher(pattern));
+    }
+
+    if let Some(suffix) = input.suffix.as_ref() {
+        matchers.push(dict.suffix_matcher(suffix));
+    }
+
+    if let Some(range) = input.word_range.as_ref() {
+        matchers.push(dict.range_matcher(&range.from, &range.to));
+    }
+
+    if let Some(phrase) = input.phrase.as_ref() {
+        matchers.push(dict.phrase_matcher(phrase));
+    }
+
+    if let Some(near) = input.words_near.as_ref() {
+        matchers.push(dict.near_matcher(&near.words, near.window));
+    }
+    state
+        .metrics
+        .stages
+        .lookup
+        .observe(lookup + looking_up.elapsed());
+
+    let (logs, total) = if paged {
+        (
+            state
+                .metrics
+                .stages
+                .scan
+                .time(|| dictionary::Module::filter_all(logs, &matchers, input.limit)),
+            None,
+        )
+    } else {
+        let fetched = logs.len();
+        let filtered = state.metrics.stages.scan.time(|| {
+            dictionary::Module::filter_all_counted(logs, &matchers, input.limit, deadline)
+        });
+        if filtered.scanned < fetched {
+            return Err(query_timeout(
+                runtime.query_timeout.unwrap_or_default(),
+                serde_json::json!({
+                    "stage": "filter",
+                    "fetched": fetched,
+                    "scanned": filtered.scanned,
+                    "matched": filtered.matched,
+                }),
+            ));
+        }
+        (filtered.buffers, Some(filtered.matched))
+    };
+    let meta = Meta {
+        total,
+        truncated: match total {
+            Some(total) => total > logs.len(),
+            None => next.is_some(),
+        },
+        took_ms: start.elapsed().as_secs_f64() * 1000.0,
+    };
+
+    let output = LogsOutput {
+        logs: DecodedLogs {
+            dict: &dict,
+            buffers: &logs,
+        },
+        ids: logs.iter().map(|log| log.id.0.as_str()).collect(),
+        timestamps: logs
+            .iter()
+            .map(|log| timeparse::format(log.timestamp))
+            .collect(),
+        next,
+        meta,
+    };
+    let encoded = tracing::info_span!("encode", logs = logs.len()).in_scope(|| {
+        state
+            .metrics
+            .stages
+            .deserialize
+            .time(|| serde_json::to_vec(&output))
+    });
+    let Ok(body) = encoded else {
+        return Err(ApiError::new(ErrorCode::Internal, "Cannot encode logs."));
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(body))
+}
+
+/// Returns the log of the id given by the save.
+///
+#[inline(always)]
+pub async fn read_log(
+    id: web::Path<String>,
+    state: Data<ServerActor>,
+) -> Result<impl Responder, ApiError> {
+    let id = LogId(id.into_inner());
+    let log = match state.repo.find_log_by_id(&id).await {
+        Ok(Some(log)) => log,
+        Ok(None) => {
+            return Err(ApiError::new(
+                ErrorCode::NotFound,
+                format!("Unknown log {}.", id),
+            ))
+        }
+        Err(e) => return Err(ApiError::from(e)),
+    };
+
+    let Ok(dict) = read_dictionary(&state.dict, &state.metrics) else {
+        return Err(ApiError::dictionary());
+    };
+    let output = LogOutput {
+        id: &log.id.0,
+        timestamp: timeparse::format(log.timestamp),
+        log: dict.deserialize(&log.data),
+    };
+    let Ok(body) = state
+        .metrics
+        .stages
+        .deserialize
+        .time(|| serde_json::to_vec(&output))
+    else {
+        return Err(ApiError::new(ErrorCode::Internal, "Cannot encode log."));
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(body))
+}
+
+/// Returns the most frequent words.
+/// With neither `from` nor `to` counts cover every log saved since the server started,
+/// otherwise only logs in the time range are counted, see time_range.
+///
+#[inline(always)]
+pub async fn top_words(
+    input: Json<TopQuery>,
+    state: Data<ServerActor>,
+) -> Result<impl Responder, ApiError> {
+    let logs = match (input.from.as_ref(), input.to.as_ref()) {
+        (None, None) => None,
+        (from, to) => {
+            let (from, to) = time_range(&state, from, to)?;
+            api::validate_range(&from, &to, state.runtime.load().max_query_span)?;
+            match state.repo.find_logs(&from, &to).await {
+                Ok(logs) => Some(logs),
+                Err(e) => return Err(ApiError::from(e)),
+            }
+        }
+    };
+
+    let Ok(dict) = read_dictionary(&state.dict, &state.metrics) else {
+        return Err(ApiError::dictionary());
+    };
+
+    let top = match logs.as_ref() {
+        Some(logs) => dict.top_words_in(input.n, logs),
+        None => dict.top_words(input.n),
+    };
+    let output = TopWordsOutput {
+        words: top
+            .into_iter()
+            .map(|(word, count)| WordCount {
+                word: word.to_string(),
+                count,
+            })
+            .collect(),
+    };
+
+    Ok(Json(output))
+}
+
+/// Returns up to k most frequent words starting with the prefix, for autocompletion of queries.
+///
+#[inline(always)]
+pub async fn suggest(
+    query: web::Query<SuggestQuery>,
+    state: Data<ServerActor>,
+) -> Result<impl Responder, ApiError> {
+    if query.k > MAX_SUGGESTIONS {
+        return Err(ApiError::new(
+            ErrorCode::InvalidInput,
+            format!("Number of suggestions cannot be above {}.", MAX_SUGGESTIONS),
+        )
+        .with_details(serde_json::json!({ "max": MAX_SUGGESTIONS })));
+    }
+    let Ok(dict) = read_dictionary(&state.dict, &state.metrics) else {
+        return Err(ApiError::dictionary());
+    };
+    let output = TopWordsOutput {
+        words: dict
+            .suggest(&query.prefix, query.k)
+            .into_iter()
+            .map(|(word, count)| WordCount {
+                word: word.to_string(),
+                count,
+            })
+            .collect(),
+    };
+
+    Ok(Json(output))
+}
+
+/// Returns words the prefix expands to in queries with their token ids in lexicographic order,
+/// up to MAX_EXPLAINED of them with truncated set if there are more.
+///
+#[inline(always)]
+pub async fn explain(
+    query: web::Query<ExplainQuery>,
+    state: Data<ServerActor>,
+) -> Result<impl Responder, ApiError> {
+    let Ok(dict) = read_dictionary(&state.dict, &state.metrics) else {
+        return Err(ApiError::dictionary());
+    };
+    let mut words = dict.expand_prefix(&query.prefix);
+    let truncated = words.len() > MAX_EXPLAINED;
+    words.truncate(MAX_EXPLAINED);
+    let output = ExplainOutput {
+        words: words
+            .into_iter()
+            .map(|(word, num)| DictionaryEntry { word, num })
+            .collect(),
+        truncated,
+    };
+
+    Ok(Json(output))
+}
+
+/// Returns the word represented by a token id, for debugging raw blobs.
+///
+#[inline(always)]
+pub async fn dictionary_word(
+    num: web::Path<Token>,
+    state: Data<ServerActor>,
+) -> Result<impl Responder, ApiError> {
+    let num = num.into_inner();
+    let Ok(dict) = read_dictionary(&state.dict, &state.metrics) else {
+        return Err(ApiError::dictionary());
+    };
+    let Some(word) = dict.word(num) else {
+        return Err(ApiError::new(
+            ErrorCode::NotFound,
+            format!("Unknown number {}.", num),
+        ));
+    };
+    let entry = DictionaryEntry {
+        word: word.to_string(),
+        num,
+    };
+
+    Ok(Json(entry))
+}
+
+/// Returns the token id representing a word.
+///
+#[inline(always)]
+pub async fn dictionary_num(
+    word: web::Path<String>,
+    state: Data<ServerActor>,
+) -> Result<impl Responder, ApiError> {
+    let word = word.into_inner();
+    let Ok(dict) = read_dictionary(&state.dict, &state.metrics) else {
+        return Err(ApiError::dictionary());
+    };
+    let Some(num) = dict.num(&word) else {
+        return Err(ApiError::new(
+            ErrorCode::NotFound,
+            format!("Unknown word {}.", word),
+        ));
+    };
+
+    Ok(Json(DictionaryEntry { word, num }))
+}
+
+/// Returns the number of words and statistics of the graph the dictionary searches words with,
+/// to guide capacity planning.
+///
+#[inline(always)]
+pub async fn dictionary_stats(state: Data<ServerActor>) -> Result<impl Responder, ApiError> {
+    let Ok(dict) = read_dictionary(&state.dict, &state.metrics) else {
+        return Err(ApiError::dictionary());
+    };
+
+    Ok(Json(DictionaryStats {
+        words: dict.len(),
+        filter: state.filter,
+        graph: dict.stats(),
+    }))
+}
+
+/// Exports the dictionary schema in the binary format.
+///
+#[inline(always)]
+pub async fn dictionary_export(state: Data<ServerActor>) -> Result<impl Responder, ApiError> {
+    let mut body = Vec::new();
+    {
+        let Ok(dict) = read_dictionary(&state.dict, &state.metrics) else {
+            return Err(ApiError::dictionary());
+        };
+        if let Err(e) = dict.write_schema_binary(&mut body) {
+            return Err(ApiError::new(ErrorCode::Internal, e.to_string()));
+        }
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::octet_stream())
+        .body(body))
+}
+
+/// Replaces the dictionary with a schema in the binary format, saving its words in to the repository.
+/// Only a repository holding no words and logs is imported in to, so numbers of saved logs never
+/// conflict with the imported ones, and a dictionary holding unsaved words is replaced only with `force=true`.
+/// Logs wait for the import to end before they are saved, so none is saved with words of the replaced dictionary.
+///
+#[inline(always)]
+pub async fn dictionary_import(
+    body: web::Bytes,
+    query: web::Query<ImportQuery>,
+    state: Data<ServerActor>,
+) -> Result<impl Responder, ApiError> {
+    let (mut imported, reader) =
+        match new_dictionary(state.filter, state.fold_case, Some(&mut &body[..])) {
+            Ok(imported) => imported,
+            Err(e) => return Err(ApiError::new(ErrorCode::BadRequest, e.to_string())),
+        };
+    imported.shrink_to_fit();
+
+    let _importing = state.import_gate.write().await;
+    {
+        let Ok(dict) = state.dict.read() else {
+            return Err(ApiError::dictionary());
+        };
+        if !dict.is_empty() && !query.force {
+            return Err(ApiError::new(
+                ErrorCode::Conflict,
+                "Dictionary is not empty, use force=true to replace it.",
+            ));
+        }
+    }
+    let words = match save_imported(&state.repo, &mut imported).await {
+        Ok(words) => words,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            return Err(ApiError::new(
+                ErrorCode::Conflict,
+                "Repository holds words or logs, a dictionary is imported only in to an empty repository.",
+            ));
+        }
+        Err(e) => return Err(ApiError::new(ErrorCode::Internal, e.to_string())),
+    };
+
+    let Ok(mut dict) = write_dictionary(&state.dict, &state.metrics) else {
+        return Err(ApiError::dictionary());
+    };
+    imported.set_max_log_bytes(dict.max_log_bytes());
+    *dict = imported;
+    if let (Some(prefixes), Some(reader)) = (state.prefixes.as_ref(), reader) {
+        prefixes.follow(&reader);
+    }
+
+    Ok(Json(ImportOutput { words }))
+}
+
+/// Backs up logs and the dictionary to a new snapshot file at the path relative to `backup_dir` on the server.
+/// Absolute paths, paths leaving the directory and existing files are refused.
+///
+pub async fn admin_backup(
+    input: Json<BackupInput>,
+    state: Data<ServerActor>,
+) -> Result<impl Responder, ApiError> {
+    let Some(dir) = state.setup.get_backup_dir() else {
+        return Err(ApiError::new(
+            ErrorCode::Unsupported,
+            "Backups are switched off, set backup_dir to take them.",
+        ));
+    };
+    let path = std::path::Path::new(&input.path);
+    if input.path.is_empty()
+        || !path
+            .components()
+            .all(|c| matches!(c, std::path::Component::Normal(_)))
+    {
+        return Err(ApiError::new(
+            ErrorCode::InvalidInput,
+            "Backup path must be relative to backup_dir and cannot hold `..`.",
+        ));
+    }
+    match ensure_streaming(&state.repo, &state.setup).await {
+        Ok(()) => (),
+        Err(e) if e.kind() == std::io::ErrorKind::Unsupported => {
+            return Err(ApiError::new(ErrorCode::BadRequest, e.to_string()));
+        }
+        Err(e) => return Err(ApiError::new(ErrorCode::Internal, e.to_string())),
+    }
+    let file = match tokio::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dir.join(path))
+        .await
+    {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            return Err(ApiError::new(
+                ErrorCode::Conflict,
+                "Backup file exists already.",
+            ));
+        }
+        Err(e) => {
+            return Err(ApiError::new(
+                ErrorCode::Internal,
+                format!("Cannot create backup file: {}.", e),
+            ))
+        }
+    };
+    match backup_to(&state.repo, &state.dict, file).await {
+        Ok((logs, words)) => Ok(Json(BackupOutput { logs, words })),
+        Err(e) => {
+            let _ = tokio::fs::remove_file(dir.join(path)).await;
+            Err(ApiError::from(e))
+        }
+    }
+}
+
+/// Reads the setup again from its file, `SCRIBE_` environment variables and `--set` flags,
+/// applying retention, the log level and limits of reads and saves to the running server.
+/// Responds with names of other changed fields, which take effect on restart,
+/// together with retention of a repository expiring logs by an index set on start.
+///
+pub async fn admin_reload(state: Data<ServerActor>) -> Result<impl Responder, ApiError> {
+    let reload_error = |e: std::io::Error| {
+        ApiError::new(ErrorCode::InvalidInput, "Cannot reload the setup.")
+            .with_details(serde_json::json!({ "cause": e.to_string() }))
+    };
+    let setup = settings::Setup::load(state.setup_path.as_deref(), &state.overrides)
+        .map_err(reload_error)?;
+    repository::check_settings(&setup)
+        .map_err(|e| reload_error(std::io::Error::new(std::io::ErrorKind::InvalidInput, e)))?;
+    let running = state.setup.reloaded(&setup);
+    let runtime = running.get_runtime();
+    state
+        .log_level
+        .set(&runtime.log_level)
+        .map_err(reload_error)?;
+    state.runtime.store(Arc::new(runtime));
+    state.config_digest.store(Arc::new(running.digest()));
+    let restart_required = state.setup.restart_required(&setup);
+    tracing::info!(?restart_required, "reloaded setup");
+
+    Ok(Json(ReloadOutput { restart_required }))
+}
+
+/// Saves words of the dictionary no saved log refers to yet, like words of logs waiting in the write queue,
+/// so they are known after a restart, and the trie to `trie_file`. Responds with the number of saved words.
+///
+pub async fn admin_flush(state: Data<ServerActor>) -> Result<impl Responder, ApiError> {
+    let words = {
+        let Ok(dict) = read_dictionary(&state.dict, &state.metrics) else {
+            return Err(ApiError::dictionary());
+        };
+        dict.unsaved_words()
+    };
+    if !words.is_empty() {
+        state.repo.insert_logs_with_words(&[], &words).await?;
+        let Ok(mut dict) = write_dictionary(&state.dict, &state.metrics) else {
+            return Err(ApiError::dictionary());
+        };
+        dict.mark_saved(&words);
+    }
+    if let Err(e) = save_trie(&state.setup, &state.dict) {
+        return Err(ApiError::new(
+            ErrorCode::Internal,
+            format!("Cannot save trie file: {}.", e),
+        ));
+    }
+
+    Ok(Json(WordsOutput { words: words.len() }))
+}
+
+/// Rebuilds the dictionary with a new filter holding the same words, reclaiming memory left by growth
+/// and starting word frequencies over. With `prune=true` words no stored log refers to are pruned
+/// from the filter first. Saves and reads wait for the rebuild.
+///
+pub async fn admin_rotate(
+    query: web::Query<RotateQuery>,
+    state: Data<ServerActor>,
+) -> Result<impl Responder, ApiError> {
+    let (empty, reader) = new_dictionary(state.filter, state.fold_case, None)
+        .map_err(|e| ApiError::new(ErrorCode::Internal, e.to_string()))?;
+    let (words, pruned) =
+        rotate_dictionary(&state.repo, &state.dict, &state.metrics, empty, query.prune).await?;
+    if let (Some(prefixes), Some(reader)) = (state.prefixes.as_ref(), reader) {
+        prefixes.follow(&reader);
+    }
+
+    Ok(Json(RotateOutput { words, pruned }))
+}
+
+/// Moves the dictionary in to the empty one, pruning words no stored log refers to first if prune is set,
+/// returning the number of words and of pruned words. Logs are read before the dictionary is locked.
+///
+async fn rotate_dictionary(
+    repo: &impl RepositoryProvider,
+    dict: &RwLock<dictionary::Module>,
+    metrics: &metrics::Metrics,
+    empty: dictionary::Module,
+    prune: bool,
+) -> Result<(usize, usize), ApiError> {
+    let referenced = match prune {
+        true => Some(referenced_words(repo).await?),
+        false => None,
+    };
+    let Ok(mut dict) = write_dictionary(dict, metrics) else {
+        return Err(ApiError::dictionary());
+    };
+    let pruned = referenced.map_or(0, |referenced| dict.prune(&referenced));
+    *dict = dict.rotated(empty);
+
+    Ok((dict.len(), pruned))
+}
+
+/// Returns numbers of words held by any stored log.
+///
+async fn referenced_words(
+    repo: &impl RepositoryProvider,
+) -> repository::error::Result<HashSet<Token>> {
+    let mut logs = repo.find_logs_stream(&Duration::ZERO, &Duration::from_nanos(i64::MAX as u64));
+    let mut referenced = HashSet::new();
+    while let Some(log) = logs.next().await {
+        referenced.extend(log?.1);
+    }
+
+    Ok(referenced)
+}
+
+/// Deletes logs older than the retention now instead of on the next scheduled run.
+/// Responds with the number of deleted logs, none without a retention.
+///
+pub async fn admin_expire(state: Data<ServerActor>) -> Result<impl Responder, ApiError> {
+    let retention = housekeeping::Retention::new(state.runtime.clone(), state.metrics.clone());
+    let expired = retention.expire(&state.repo).await?;
+
+    Ok(Json(ExpireOutput { expired }))
+}
+
+/// Gives space left by expired logs back to the file system, for repositories able to do it on demand.
+///
+pub async fn admin_compact(state: Data<ServerActor>) -> Result<impl Responder, ApiError> {
+    state.repo.compact().await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Returns the state of the dictionary, the write queue, retention and deduplication in one JSON document.
+///
+pub async fn admin_stats(state: Data<ServerActor>) -> Result<impl Responder, ApiError> {
+    let (dictionary, unsaved_words) = {
+        let Ok(dict) = read_dictionary(&state.dict, &state.metrics) else {
+            return Err(ApiError::dictionary());
+        };
+        (
+            DictionaryStats {
+                words: dict.len(),
+                filter: state.filter,
+                graph: dict.stats(),
+            },
+            dict.unsaved_words().len(),
+        )
+    };
+    let metrics = &state.metrics;
+
+    Ok(Json(AdminStats {
+        version: state.version.clone(),
+        dictionary,
+        unsaved_words,
+        write_queue: state.queue.as_ref().map(|_| QueueStats {
+            depth: metrics.write_queue_depth.get(),
+            rejected_logs: metrics.write_queue_rejected_logs.get(),
+            failures: metrics.write_queue_failures.get(),
+        }),
+        retention: RetentionStats {
+            seconds: state.runtime.load().retention.map(|r| r.as_secs()),
+            runs: metrics.retention_runs.get(),
+            failures: metrics.retention_failures.get(),
+            reclaimed_logs: metrics.retention_reclaimed_logs.get(),
+        },
+        dedup_repeated_logs: metrics.dedup_repeated_logs.get(),
+    }))
+}
+
+#[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
+mod tests {
+    use super::*;
+    use crate::repository::kv::WarehouseKv;
+    use scribe::trie;
+    use std::env;
+
+    #[tokio::test]
+    async fn on_rotate_with_prune_should_prune_words_no_stored_log_refers_to() {
+        let dir = env::temp_dir().join("scribe_handlers_prune");
+        let _ = std::fs::remove_dir_all(&dir);
+        let metrics = metrics::Metrics::default();
+        let Ok(repo) = WarehouseKv::new(&dir).await else {
+            assert!(false);
+            return;
+        };
+        let mut source = dictionary::Module::new(trie::Trie::new());
+        let Ok(kept) = source.serialize("kept words") else {
+            assert!(false);
+            return;
+        };
+        let Ok(_) = source.serialize("expired") else {
+            assert!(false);
+            return;
+        };
+        let words = source.unsaved_words();
+        let Ok(()) = repo
+            .insert_logs_with_words(&[(1, kept.clone())], &words)
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        source.mark_saved(&words);
+        let dict = RwLock::new(source.rotated(dictionary::Module::new(trie::Trie::new())));
+
+        let Ok((words, pruned)) = rotate_dictionary(
+            &repo,
+            &dict,
+            &metrics,
+            dictionary::Module::new(trie::Trie::new()),
+            true,
+        )
+        .await
+        else {
+            assert!(false);
+            return;
+        };
+        assert_eq!((words, pruned), (3, 1));
+        {
+            let Ok(dict) = dict.read() else {
+                assert!(false);
+                return;
+            };
+            assert!(dict.expand_prefix("exp").is_empty());
+            assert_eq!(dict.expand_prefix("kep").len(), 1);
+            assert_eq!(dict.deserialize(&kept), "kept words");
+        }
+
+        repo.close().await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,787 @@
+mod handlers;
+
+use crate::api::{self, ApiError, ErrorCode};
+use crate::repository::error::RepositoryError;
+use crate::repository::instrument::Instrumented;
+use crate::repository::interface::{LogId, RepositoryProvider};
+use crate::repository::retry::{RetryPolicy, Retrying};
+use crate::repository::Repository;
+use crate::{auth, dedup, housekeeping, logging, metrics, queue, settings};
+use actix_web::middleware::{Compress, Condition, DefaultHeaders};
+use actix_web::{web, HttpResponse, Result};
+use arc_swap::ArcSwap;
+use handlers::{
+    admin_backup, admin_compact, admin_expire, admin_flush, admin_reload, admin_rotate,
+    admin_stats, dictionary_export, dictionary_import, dictionary_num, dictionary_stats,
+    dictionary_word, elastic_info, explain, health, import_logs, metrics, read_log, read_logs,
+    save_bulk, save_gelf, save_hec, save_log, save_logs, suggest, top_words, version,
+};
+use scribe::token::Token;
+use scribe::{bloom, dawg, dictionary, transducer, trie};
+use std::collections::HashSet;
+use std::sync::{Arc, LockResult, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Largest dictionary schema accepted by the import.
+const MAX_SCHEMA_SIZE: usize = 256 * 1024 * 1024;
+
+pub struct ServerActor {
+    pub version: String,
+    pub repo: Retrying<Instrumented<Repository>>,
+    pub dict: Arc<RwLock<dictionary::Module>>,
+    pub filter: settings::FilterKind,
+    pub fold_case: bool,
+    pub prefixes: Option<trie::Reader>,
+    pub metrics: Arc<metrics::Metrics>,
+    pub dedup: Option<Arc<dedup::Deduplicator>>,
+    pub queue: Option<queue::WriteQueue>,
+    pub runtime: Arc<ArcSwap<settings::Runtime>>,
+    pub setup: Arc<settings::Setup>,
+    pub config_digest: Arc<ArcSwap<String>>,
+    pub setup_path: Option<String>,
+    pub overrides: Vec<(String, String)>,
+    pub log_level: logging::LevelHandle,
+    pub import_gate: Arc<tokio::sync::RwLock<()>>,
+    pub activity: Arc<housekeeping::Activity>,
+}
+
+impl Clone for ServerActor {
+    fn clone(&self) -> Self {
+        Self {
+            version: self.version.clone(),
+            repo: self.repo.clone(),
+            dict: self.dict.clone(),
+            filter: self.filter,
+            fold_case: self.fold_case,
+            prefixes: self.prefixes.clone(),
+            metrics: self.metrics.clone(),
+            dedup: self.dedup.clone(),
+            queue: self.queue.clone(),
+            runtime: self.runtime.clone(),
+            setup: self.setup.clone(),
+            config_digest: self.config_digest.clone(),
+            setup_path: self.setup_path.clone(),
+            overrides: self.overrides.clone(),
+            log_level: self.log_level.clone(),
+            import_gate: self.import_gate.clone(),
+            activity: self.activity.clone(),
+        }
+    }
+}
+
+/// Creates a dictionary searching words with the filter of given kind, reading the schema
+/// in the binary format in to it if given, together with a reader of prefixes if the filter publishes them.
+///
+pub fn new_dictionary(
+    kind: settings::FilterKind,
+    fold_case: bool,
+    schema: Option<&mut dyn std::io::Read>,
+) -> std::io::Result<(dictionary::Module, Option<trie::Reader>)> {
+    match kind {
+        settings::FilterKind::Trie => {
+            Ok((dictionary_of(trie::Trie::new(), fold_case, schema)?, None))
+        }
+        settings::FilterKind::BiTrie => {
+            Ok((dictionary_of(trie::BiTrie::new(), fold_case, schema)?, None))
+        }
+        settings::FilterKind::Dawg => {
+            Ok((dictionary_of(dawg::Dawg::new(), fold_case, schema)?, None))
+        }
+        settings::FilterKind::Bloom => {
+            Ok((dictionary_of(bloom::Bloom::new(), fold_case, schema)?, None))
+        }
+        settings::FilterKind::Fst => Ok((
+            dictionary_of(transducer::Fst::new(), fold_case, schema)?,
+            None,
+        )),
+        settings::FilterKind::Concurrent => {
+            let filter = trie::Concurrent::new();
+            let reader = filter.reader();
+            Ok((dictionary_of(filter, fold_case, schema)?, Some(reader)))
+        }
+        settings::FilterKind::Mapped => Ok((
+            dictionary_of(trie::Mapped::new()?, fold_case, schema)?,
+            None,
+        )),
+    }
+}
+
+/// Connects the repository of the setup retrying operations by its policy and migrates its schema.
+/// Operations are timed into the metrics.
+///
+pub async fn connect(
+    setup: &settings::Setup,
+    metrics: &metrics::Metrics,
+) -> std::io::Result<Retrying<Instrumented<Repository>>> {
+    let repo = match Repository::new(setup).await {
+        Ok(repo) => repo,
+        Err(RepositoryError::InvalidInput(e)) => {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, e));
+        }
+        Err(_) => {
+            return Err(std::io::Error::new::<String>(
+                std::io::ErrorKind::NotConnected,
+                "repository is not responding".to_string(),
+            ));
+        }
+    };
+    tracing::info!(repository = %repo, "using repository");
+    let backend = repo.to_string();
+    let repo = Retrying::new(
+        Instrumented::new(repo, &backend, metrics),
+        RetryPolicy {
+            attempts: setup.get_retry_attempts(),
+            backoff: setup.get_retry_backoff(),
+            jitter: setup.get_retry_jitter(),
+        },
+    );
+
+    if let Err(e) = repo.migrate().await {
+        return Err(std::io::Error::new::<String>(
+            std::io::ErrorKind::NotConnected,
+            e.to_string(),
+        ));
+    };
+
+    Ok(repo)
+}
+
+/// Creates the dictionary of the setup holding words saved in the repository by previous runs.
+///
+pub async fn load_dictionary(
+    repo: &impl RepositoryProvider,
+    setup: &settings::Setup,
+) -> std::io::Result<(dictionary::Module, Option<trie::Reader>)> {
+    let words = repo.find_words().await.map_err(std::io::Error::other)?;
+    if !words.is_empty() {
+        tracing::info!(words = words.len(), "loaded dictionary");
+    }
+    let (mut dict, prefixes) = match cached_dictionary(setup, words) {
+        Ok(dict) => (dict, None),
+        Err(words) => {
+            let (mut dict, prefixes) =
+                new_dictionary(setup.get_filter(), setup.get_fold_case(), None)?;
+            dict.load_words(words);
+            (dict, prefixes)
+        }
+    };
+    dict.set_max_log_bytes(setup.get_max_log_bytes());
+
+    Ok((dict, prefixes))
+}
+
+/// Returns the dictionary of the words with the trie of `trie_file` if the file holds exactly the words,
+/// giving the words back if it is not set, missing or stale, or the filter is not a trie without `fold_case`,
+/// so the trie is built by pushing the words instead.
+/// The mapped filter builds the file of the words when it is missing or stale and maps it,
+/// giving the words back only if the file cannot be written.
+///
+fn cached_dictionary(
+    setup: &settings::Setup,
+    words: Vec<(String, Token)>,
+) -> Result<dictionary::Module, Vec<(String, Token)>> {
+    let Some(path) = setup.get_trie_file() else {
+        return Err(words);
+    };
+    if setup.get_fold_case() {
+        return Err(words);
+    }
+    match setup.get_filter() {
+        settings::FilterKind::Trie => match trie::Trie::load(path) {
+            Ok(trie) if holds_words(trie.count_words(), &words, |w| trie.find_match(w)) => {
+                tracing::info!(words = words.len(), "loaded trie file");
+                Ok(dictionary::Module::with_words(trie, words))
+            }
+            Ok(_) => {
+                tracing::info!("trie file is stale, building the trie");
+                Err(words)
+            }
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    tracing::warn!(error = %e, "cannot load trie file, building the trie");
+                }
+                Err(words)
+            }
+        },
+        settings::FilterKind::Mapped => {
+            match trie::Mapped::open(path) {
+                Ok(mapped)
+                    if holds_words(mapped.count_words(), &words, |w| mapped.find_match(w)) =>
+                {
+                    tracing::info!(words = words.len(), "mapped trie file");
+                    return Ok(dictionary::Module::with_words(mapped, words));
+                }
+                Ok(_) => tracing::info!("trie file is stale, building the mapped file"),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+                Err(e) => {
+                    tracing::warn!(error = %e, "cannot map trie file, building the mapped file")
+                }
+            }
+            let mut trie = trie::Trie::new();
+            for (word, num) in words.iter() {
+                dictionary::Filter::push(&mut trie, word, *num);
+            }
+            match write_aside(path, |p| trie::Mapped::save(&trie, p))
+                .and_then(|_| trie::Mapped::open(path))
+            {
+                Ok(mapped) => {
+                    tracing::info!(words = words.len(), "built mapped trie file");
+                    Ok(dictionary::Module::with_words(mapped, words))
+                }
+                Err(e) => {
+                    tracing::warn!(error = %e, "cannot build mapped trie file, keeping words in memory");
+                    Err(words)
+                }
+            }
+        }
+        _ => Err(words),
+    }
+}
+
+/// Tells if the filter holding count words finds every word under its number.
+///
+fn holds_words(
+    count: usize,
+    words: &[(String, Token)],
+    find_match: impl Fn(&str) -> Option<Token>,
+) -> bool {
+    count == words.len()
+        && words
+            .iter()
+            .all(|(word, num)| find_match(word) == Some(*num))
+}
+
+/// Writes the file aside and moves it over the path at once, so a crash never leaves a torn file
+/// and processes mapping the old file keep reading it.
+///
+fn write_aside(
+    path: &std::path::Path,
+    write: impl FnOnce(&std::path::Path) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let written = path.with_extension("tmp");
+    write(&written)?;
+    std::fs::rename(&written, path)
+}
+
+/// Saves the trie of words of the dictionary saved in the repository to `trie_file`,
+/// written aside and moved over the file at once.
+/// Does nothing unless the filter is a trie or a mapped trie without `fold_case`.
+///
+pub fn save_trie(
+    setup: &settings::Setup,
+    dict: &RwLock<dictionary::Module>,
+) -> std::io::Result<()> {
+    let Some(path) = setup.get_trie_file() else {
+        return Ok(());
+    };
+    let kind = setup.get_filter();
+    if !matches!(
+        kind,
+        settings::FilterKind::Trie | settings::FilterKind::Mapped
+    ) || setup.get_fold_case()
+    {
+        return Ok(());
+    }
+    let trie = {
+        let Ok(dict) = dict.read() else {
+            return Err(std::io::Error::other("dictionary is not responding"));
+        };
+        let unsaved: HashSet<Token> = dict.unsaved_words().into_iter().map(|(_, n)| n).collect();
+        let mut trie = trie::Trie::new();
+        for (word, num) in dict.iter().filter(|(_, num)| !unsaved.contains(num)) {
+            dictionary::Filter::push(&mut trie, word, *num);
+        }
+        trie
+    };
+    match kind {
+        settings::FilterKind::Mapped => write_aside(path, |p| trie::Mapped::save(&trie, p)),
+        _ => write_aside(path, |p| trie.save(p)),
+    }
+}
+
+/// Creates a dictionary of the filter, keeping words case folded aside if fold_case is set.
+///
+fn dictionary_of(
+    filter: impl dictionary::Filter + 'static,
+    fold_case: bool,
+    schema: Option<&mut dyn std::io::Read>,
+) -> std::io::Result<dictionary::Module> {
+    match (fold_case, schema) {
+        (false, None) => Ok(dictionary::Module::new(filter)),
+        (false, Some(mut r)) => dictionary::Module::read_schema(&mut r, filter),
+        (true, None) => Ok(dictionary::Module::new(trie::Folded::new(filter))),
+        (true, Some(mut r)) => dictionary::Module::read_schema(&mut r, trie::Folded::new(filter)),
+    }
+}
+
+/// Answers paths no route is registered on.
+///
+pub async fn not_found(req: actix_web::HttpRequest) -> Result<HttpResponse, ApiError> {
+    Err(ApiError::new(
+        ErrorCode::NotFound,
+        format!("Unknown path {}.", req.path()),
+    ))
+}
+
+/// Returns the current time in nanoseconds since the Unix epoch.
+///
+#[inline(always)]
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as i64
+}
+
+/// Saved tells what became of a saved log.
+///
+pub enum Saved {
+    /// The log is saved under the id.
+    Id(LogId),
+    /// The log waits in the write queue.
+    Queued,
+    /// The log repeats one saved within the dedup window and is not saved again.
+    Repeated,
+}
+
+/// Validates the log and saves it at the timestamp, in nanoseconds since the Unix epoch,
+/// together with its words new to the repository. Every way logs come in saves them here,
+/// so a server receiving logs only over GELF UDP, Kafka or a tailed file is never taken for idle.
+///
+pub async fn save(state: &ServerActor, log: &str, timestamp: i64) -> Result<Saved, ApiError> {
+    state.activity.touch();
+    api::validate_log(log, state.runtime.load().max_log_tokens)?;
+    let _saving = state.import_gate.read().await;
+    let (buf, words) = {
+        let Ok(mut dict) = write_dictionary(&state.dict, &state.metrics) else {
+            return Err(ApiError::dictionary());
+        };
+        #[cfg(feature = "chaos")]
+        crate::chaos::lock_delay();
+        let serialized = tracing::info_span!("serialize")
+            .in_scope(|| state.metrics.stages.tokenize.time(|| dict.serialize(log)));
+        match serialized {
+            Ok(buf) => (buf, dict.unsaved_words()),
+            Err(e) => return Err(serialize_error(e)),
+        }
+    };
+    if deduplicated(state, &buf).await? {
+        return Ok(Saved::Repeated);
+    }
+    if let Some(queue) = &state.queue {
+        if !queue.push(timestamp, buf) {
+            return Err(ApiError::new(ErrorCode::QueueFull, "Write queue is full."));
+        }
+        return Ok(Saved::Queued);
+    }
+    let id = match state
+        .repo
+        .insert_log_with_words(&buf, timestamp, &words)
+        .await
+    {
+        Ok(id) => id,
+        Err(e) => {
+            if let Some(dedup) = &state.dedup {
+                dedup.forget(&buf);
+            }
+            return Err(ApiError::from(e));
+        }
+    };
+    mark_saved(&state.dict, &state.metrics, &words);
+
+    Ok(Saved::Id(id))
+}
+
+/// Takes the dictionary for reading in a `dictionary_lock` span, so time spent waiting for a writer shows in traces
+/// and in the lock wait stage of the metrics.
+///
+#[inline(always)]
+fn read_dictionary<'a>(
+    dict: &'a RwLock<dictionary::Module>,
+    metrics: &metrics::Metrics,
+) -> LockResult<RwLockReadGuard<'a, dictionary::Module>> {
+    tracing::info_span!("dictionary_lock", write = false)
+        .in_scope(|| metrics.stages.lock_wait.time(|| dict.read()))
+}
+
+/// Takes the dictionary for writing in a `dictionary_lock` span, so time spent waiting for readers shows in traces
+/// and in the lock wait stage of the metrics.
+///
+#[inline(always)]
+fn write_dictionary<'a>(
+    dict: &'a RwLock<dictionary::Module>,
+    metrics: &metrics::Metrics,
+) -> LockResult<RwLockWriteGuard<'a, dictionary::Module>> {
+    tracing::info_span!("dictionary_lock", write = true)
+        .in_scope(|| metrics.stages.lock_wait.time(|| dict.write()))
+}
+
+/// Maps an error of serializing a log, a log too long is refused with 413
+/// and an exhausted token id space is reported with 507.
+///
+#[inline(always)]
+fn serialize_error(e: std::io::Error) -> ApiError {
+    match e.kind() {
+        std::io::ErrorKind::FileTooLarge => {
+            ApiError::new(ErrorCode::PayloadTooLarge, e.to_string())
+        }
+        _ => ApiError::new(ErrorCode::StorageFull, e.to_string()),
+    }
+}
+
+/// Marks words saved together with logs as saved, so later logs are not saved with them again.
+///
+#[inline(always)]
+fn mark_saved(
+    dict: &RwLock<dictionary::Module>,
+    metrics: &metrics::Metrics,
+    words: &[(String, Token)],
+) {
+    if words.is_empty() {
+        return;
+    }
+    if let Ok(mut dict) = write_dictionary(dict, metrics) {
+        dict.mark_saved(words);
+    }
+}
+
+/// Handles the log if it is identical to one saved within the dedup window,
+/// skipping it or counting its occurrence. Returns true if the log is not to be inserted.
+///
+async fn deduplicated(state: &ServerActor, buf: &[Token]) -> Result<bool, ApiError> {
+    let Some(dedup) = &state.dedup else {
+        return Ok(false);
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let Some(since) = dedup.check(buf, now) else {
+        return Ok(false);
+    };
+    state.metrics.dedup_repeated_logs.add(1);
+    if dedup.mode() == settings::DedupMode::Skip {
+        return Ok(true);
+    }
+    match state.repo.count_occurrence(buf, &since).await {
+        Ok(counted) => Ok(counted),
+        Err(e) => Err(ApiError::from(e)),
+    }
+}
+
+/// Versions of the public API, each mounted under `/{version}` with its routes.
+/// A breaking change, like new query semantics, ships as a new version registering handlers of the changed routes
+/// next to the unchanged ones, so shippers stay on the version they were written for.
+///
+const API_VERSIONS: [(&str, Routes); 1] = [("v1", v1_routes)];
+
+/// Registers routes of a version of the public API, compressing large responses if the flag is set.
+///
+type Routes = fn(&mut web::ServiceConfig, bool);
+
+/// Registers every version of the public API under its prefix, and version 1 on the legacy unversioned paths,
+/// kept until shippers move to `/v1` and marked with the `Deprecation` header meanwhile.
+/// Legacy paths match any path, so they are registered after every other route.
+///
+pub fn public_routes(cfg: &mut web::ServiceConfig, compress: bool) {
+    cfg.service(web::resource("/gelf").route(web::post().to(save_gelf)))
+        .service(
+            web::resource(["/services/collector", "/services/collector/event"])
+                .route(web::post().to(save_hec)),
+        )
+        .service(web::scope("/elastic").configure(elastic_routes));
+    for (version, routes) in API_VERSIONS {
+        cfg.service(web::scope(&format!("/{}", version)).configure(|cfg| routes(cfg, compress)));
+    }
+    cfg.service(
+        web::scope("")
+            .wrap(DefaultHeaders::new().add(("Deprecation", "true")))
+            .configure(|cfg| v1_routes(cfg, compress)),
+    );
+}
+
+/// Registers the Elasticsearch compatible routes Filebeat ships to, under the `/elastic` scope
+/// so an index of the path never shadows a route of the public API.
+///
+fn elastic_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource(["", "/"]).route(web::get().to(elastic_info)))
+        .service(
+            web::resource(["/_bulk", "/{index}/_bulk"])
+                .route(web::post().to(save_bulk))
+                .route(web::put().to(save_bulk)),
+        );
+}
+
+/// Registers version 1 of the public ingest and query API.
+///
+/// Routes are registered as resources with method routes, so not matching methods get
+/// `405 Method Not Allowed` with the Allow header that methods::handle relies on.
+/// With compress, read logs and the dictionary export are encoded as the client accepts.
+///
+fn v1_routes(cfg: &mut web::ServiceConfig, compress: bool) {
+    cfg.service(web::resource("/version").route(web::get().to(version)))
+        .service(web::resource("/save").route(web::post().to(save_log)))
+        .service(web::resource("/save/batch").route(web::post().to(save_logs)))
+        .service(web::resource("/import").route(web::post().to(import_logs)))
+        .service(
+            web::resource("/read")
+                .wrap(Condition::new(compress, Compress::default()))
+                .route(web::post().to(read_logs)),
+        )
+        .service(web::resource("/log/{id}").route(web::get().to(read_log)))
+        .service(web::resource("/top").route(web::post().to(top_words)))
+        .service(web::resource("/suggest").route(web::get().to(suggest)))
+        .service(web::resource("/explain").route(web::get().to(explain)))
+        .service(web::resource("/dictionary/word/{num}").route(web::get().to(dictionary_word)))
+        .service(web::resource("/dictionary/num/{word}").route(web::get().to(dictionary_num)))
+        .service(web::resource("/dictionary/stats").route(web::get().to(dictionary_stats)))
+        .service(
+            web::resource("/dictionary/export")
+                .wrap(Condition::new(compress, Compress::default()))
+                .route(web::get().to(dictionary_export)),
+        )
+        .service(
+            web::resource("/dictionary/import")
+                .app_data(web::PayloadConfig::new(MAX_SCHEMA_SIZE))
+                .route(web::post().to(dictionary_import)),
+        );
+}
+
+/// Registers the admin API under the `/admin` scope.
+/// With a token, every route but health requires it as a bearer token, so probes need none.
+/// Without a token the routes acting on the server are registered only if `open` is set, as on `admin_listen`,
+/// so a public listener answers them with 404 instead of serving anyone.
+///
+pub fn admin_routes(cfg: &mut web::ServiceConfig, token: Option<Arc<str>>, open: bool) {
+    let scope = web::scope("/admin").service(web::resource("/health").route(web::get().to(health)));
+    if token.is_none() && !open {
+        cfg.service(scope);
+        return;
+    }
+    cfg.service(
+        scope.service(
+            web::scope("")
+                .wrap_fn(move |req, srv| auth::authorize(req, srv, token.as_deref()))
+                .service(web::resource("/metrics").route(web::get().to(metrics)))
+                .service(web::resource("/stats").route(web::get().to(admin_stats)))
+                .service(web::resource("/backup").route(web::post().to(admin_backup)))
+                .service(web::resource("/reload").route(web::post().to(admin_reload)))
+                .service(web::resource("/expire").route(web::post().to(admin_expire)))
+                .service(web::resource("/compact").route(web::post().to(admin_compact)))
+                .service(web::resource("/dictionary/flush").route(web::post().to(admin_flush)))
+                .service(web::resource("/dictionary/rotate").route(web::post().to(admin_rotate))),
+        ),
+    );
+}
+
+#[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
+mod tests {
+    use super::*;
+    use crate::repository::kv::WarehouseKv;
+    use actix_web::App;
+    use std::env;
+
+    #[actix_web::test]
+    async fn on_admin_routes_without_token_on_public_listener_should_serve_only_health() {
+        let app = actix_web::test::init_service(
+            App::new()
+                .configure(|cfg| admin_routes(cfg, None, false))
+                .default_service(web::to(not_found)),
+        )
+        .await;
+
+        let res = actix_web::test::call_service(
+            &app,
+            actix_web::test::TestRequest::get()
+                .uri("/admin/health")
+                .to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::OK);
+
+        for path in [
+            "/admin/dictionary/rotate",
+            "/admin/dictionary/flush",
+            "/admin/expire",
+            "/admin/reload",
+            "/admin/backup",
+            "/admin/compact",
+        ] {
+            let res = actix_web::test::call_service(
+                &app,
+                actix_web::test::TestRequest::post().uri(path).to_request(),
+            )
+            .await;
+            assert_eq!(
+                res.status(),
+                actix_web::http::StatusCode::NOT_FOUND,
+                "{}",
+                path
+            );
+        }
+        let res = actix_web::test::call_service(
+            &app,
+            actix_web::test::TestRequest::get()
+                .uri("/admin/stats")
+                .to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn on_load_dictionary_should_load_trie_file_and_build_the_trie_if_it_is_missing_or_stale()
+    {
+        let dir = env::temp_dir().join("scribe_server_trie_file");
+        let _ = std::fs::remove_dir_all(&dir);
+        let Ok(()) = std::fs::create_dir_all(&dir) else {
+            assert!(false);
+            return;
+        };
+        let path = dir.join("words.trie");
+        let Ok(setup) = serde_yaml::from_str::<settings::Setup>(&format!(
+            "ip: 0.0.0.0\nport: 8000\ndb_url: \"\"\nfilter: trie\ntrie_file: {}\n",
+            path.display()
+        )) else {
+            assert!(false);
+            return;
+        };
+        let Ok(repo) = WarehouseKv::new(dir.join("kv")).await else {
+            assert!(false);
+            return;
+        };
+        let words = vec![
+            ("alpha".to_string(), Token::from(1)),
+            ("beta".to_string(), Token::from(2)),
+        ];
+        let Ok(()) = repo.insert_logs_with_words(&[], &words).await else {
+            assert!(false);
+            return;
+        };
+
+        let Ok((dict, _)) = load_dictionary(&repo, &setup).await else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(dict.expand_prefix("al").len(), 1);
+        let dict = RwLock::new(dict);
+        let Ok(()) = save_trie(&setup, &dict) else {
+            assert!(false);
+            return;
+        };
+        assert!(cached_dictionary(&setup, words.clone()).is_ok());
+
+        let Ok(()) = repo
+            .insert_logs_with_words(&[], &[("gamma".to_string(), Token::from(3))])
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        let Ok(stored) = repo.find_words().await else {
+            assert!(false);
+            return;
+        };
+        assert!(cached_dictionary(&setup, stored).is_err());
+        let Ok((dict, _)) = load_dictionary(&repo, &setup).await else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(dict.expand_prefix("ga").len(), 1);
+        assert_eq!(dict.num("alpha"), Some(Token::from(1)));
+
+        let Ok(()) = std::fs::remove_file(&path) else {
+            assert!(false);
+            return;
+        };
+        assert!(cached_dictionary(&setup, words).is_err());
+
+        repo.close().await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[actix_web::test]
+    async fn on_load_dictionary_with_mapped_filter_should_build_the_mapped_file_and_map_it_on_start(
+    ) {
+        let dir = env::temp_dir().join("scribe_server_mapped_file");
+        let _ = std::fs::remove_dir_all(&dir);
+        let Ok(()) = std::fs::create_dir_all(&dir) else {
+            assert!(false);
+            return;
+        };
+        let path = dir.join("words.trie");
+        let Ok(setup) = serde_yaml::from_str::<settings::Setup>(&format!(
+            "ip: 0.0.0.0\nport: 8000\ndb_url: \"\"\nfilter: mapped\ntrie_file: {}\n",
+            path.display()
+        )) else {
+            assert!(false);
+            return;
+        };
+        let Ok(repo) = WarehouseKv::new(dir.join("kv")).await else {
+            assert!(false);
+            return;
+        };
+        let words = vec![
+            ("alpha".to_string(), Token::from(1)),
+            ("beta".to_string(), Token::from(2)),
+        ];
+        let Ok(()) = repo.insert_logs_with_words(&[], &words).await else {
+            assert!(false);
+            return;
+        };
+
+        let Ok((dict, _)) = load_dictionary(&repo, &setup).await else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(dict.expand_prefix("al").len(), 1);
+        let Ok(mapped) = trie::Mapped::open(&path) else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(mapped.count_words(), 2);
+        assert_eq!(mapped.find_match("beta"), Some(Token::from(2)));
+
+        let Ok(()) = repo
+            .insert_logs_with_words(&[], &[("gamma".to_string(), Token::from(3))])
+            .await
+        else {
+            assert!(false);
+            return;
+        };
+        let Ok((dict, _)) = load_dictionary(&repo, &setup).await else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(dict.expand_prefix("ga").len(), 1);
+        assert_eq!(dict.num("alpha"), Some(Token::from(1)));
+        let Ok(mapped) = trie::Mapped::open(&path) else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(mapped.count_words(), 3);
+
+        let Ok((mut dict, _)) = new_dictionary(settings::FilterKind::Mapped, false, None) else {
+            assert!(false);
+            return;
+        };
+        let Ok(stored) = repo.find_words().await else {
+            assert!(false);
+            return;
+        };
+        dict.load_words(stored);
+        let Ok(()) = save_trie(&setup, &RwLock::new(dict)) else {
+            assert!(false);
+            return;
+        };
+        let Ok(mapped) = trie::Mapped::open(&path) else {
+            assert!(false);
+            return;
+        };
+        assert_eq!(mapped.count_words(), 3);
+        assert_eq!(mapped.find_match("gamma"), Some(Token::from(3)));
+
+        repo.close().await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,376 @@
+//! Snapshot is the backend neutral format logs and the dictionary are exported in and imported from,
+//! so logs are moved between repositories of any kind. All numbers are little endian.
+//!
+//! | bytes | content                                                         |
+//! |-------|-----------------------------------------------------------------|
+//! | 4     | magic bytes `SCBK`                                              |
+//! | 1     | format version, `1`                                             |
+//! | 1     | token width in bytes, `4` or `8` with the `wide-tokens` feature |
+//! | 8     | offset of the dictionary schema from the start                  |
+//!
+//! The header is followed by a record of each log, oldest first:
+//!
+//! | bytes | content                                          |
+//! |-------|--------------------------------------------------|
+//! | 1     | tag `1`                                          |
+//! | 8     | timestamp in nanoseconds since the epoch, signed |
+//! | 4     | length of the tokens                             |
+//! | n     | tokens in the encoding of `Token::encode`        |
+//!
+//! Records end with tag `0` and the 8 byte count of logs, followed by the dictionary schema
+//! in the binary format of `Module::write_schema_binary` up to the end.
+
+use crate::repository::error::Result;
+use crate::repository::interface::RepositoryProvider;
+use futures::StreamExt;
+use scribe::token::Token;
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom};
+use std::time::Duration;
+use tokio::io::{AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+
+const SNAPSHOT_MAGIC: &[u8; 4] = b"SCBK";
+const SNAPSHOT_VERSION: u8 = 1;
+const HEADER_SIZE: u64 = 14;
+const TAG_END: u8 = 0;
+const TAG_LOG: u8 = 1;
+const IMPORT_BATCH: usize = 1000;
+const ALL_TIME: Duration = Duration::from_nanos(i64::MAX as u64);
+/// Longest tokens of a log record, far above tokens of any log the server accepts,
+/// so a damaged length does not allocate gigabytes.
+const MAX_RECORD_LEN: u64 = 64 * 1024 * 1024;
+
+/// Checks the repository streams logs by reading the first log of an empty time range,
+/// returning the error of `find_logs_stream` if it does not, so no snapshot is started
+/// for a repository failing once logs are read.
+///
+pub async fn check_streaming(repo: &impl RepositoryProvider) -> Result<()> {
+    match repo
+        .find_logs_stream(&Duration::ZERO, &Duration::ZERO)
+        .next()
+        .await
+    {
+        Some(Err(e)) => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Writes the snapshot of every log of the repository and the dictionary schema.
+/// The schema is taken once logs are written and placed after them, the header points at it,
+/// so it holds every word of exported logs even if logs are saved meanwhile.
+/// Returns the number of exported logs.
+///
+pub async fn write<W: AsyncWrite + AsyncSeek + Unpin>(
+    repo: &impl RepositoryProvider,
+    w: &mut W,
+    schema: impl FnOnce() -> std::io::Result<Vec<u8>>,
+) -> Result<u64> {
+    w.write_all(SNAPSHOT_MAGIC).await?;
+    w.write_all(&[SNAPSHOT_VERSION, Token::WIDTH as u8]).await?;
+    w.write_all(&0u64.to_le_bytes()).await?;
+
+    let mut logs = repo.find_logs_stream(&Duration::ZERO, &ALL_TIME);
+    let mut count = 0u64;
+    while let Some(log) = logs.next().await {
+        let (timestamp, tokens) = log?;
+        let data = Token::encode(&tokens);
+        w.write_all(&[TAG_LOG]).await?;
+        w.write_all(&timestamp.to_le_bytes()).await?;
+        w.write_all(&(data.len() as u32).to_le_bytes()).await?;
+        w.write_all(&data).await?;
+        count += 1;
+    }
+    w.write_all(&[TAG_END]).await?;
+    w.write_all(&count.to_le_bytes()).await?;
+
+    let offset = w.stream_position().await?;
+    w.write_all(&schema()?).await?;
+    w.seek(SeekFrom::Start(HEADER_SIZE - 8)).await?;
+    w.write_all(&offset.to_le_bytes()).await?;
+    w.seek(SeekFrom::End(0)).await?;
+    w.flush().await?;
+
+    Ok(count)
+}
+
+/// Snapshot reads logs of a snapshot written by `write`.
+///
+pub struct Snapshot<R: Read + Seek> {
+    r: R,
+    read: u64,
+}
+
+impl<R: Read + Seek> Snapshot<R> {
+    /// Opens the snapshot, returning it together with the dictionary schema in the binary format
+    /// the caller creates the dictionary of before logs are imported.
+    ///
+    pub fn open(mut r: R) -> std::io::Result<(Self, Vec<u8>)> {
+        let mut header = [0; HEADER_SIZE as usize];
+        r.read_exact(&mut header)?;
+        if &header[..4] != SNAPSHOT_MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "not a scribe snapshot"));
+        }
+        if header[4] != SNAPSHOT_VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unsupported snapshot version {}", header[4]),
+            ));
+        }
+        if header[5] as usize != Token::WIDTH {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "snapshot token width {} does not match {}",
+                    header[5],
+                    Token::WIDTH
+                ),
+            ));
+        }
+        let offset = u64::from_le_bytes(header[6..].try_into().unwrap_or_default());
+        if offset < HEADER_SIZE {
+            return Err(Error::new(ErrorKind::InvalidData, "snapshot is incomplete"));
+        }
+        r.seek(SeekFrom::Start(offset))?;
+        let mut schema = Vec::new();
+        r.read_to_end(&mut schema)?;
+        r.seek(SeekFrom::Start(HEADER_SIZE))?;
+
+        Ok((Self { r, read: 0 }, schema))
+    }
+
+    /// Inserts logs of the snapshot in to the repository in batches, the first batch together with the words,
+    /// so imported logs never refer to words that were not saved. Returns the number of imported logs.
+    /// Batches inserted before a damaged record is met stay in the repository.
+    ///
+    pub async fn insert_into(
+        mut self,
+        repo: &impl RepositoryProvider,
+        words: &[(String, Token)],
+    ) -> Result<u64> {
+        let mut words = words;
+        let mut batch = Vec::with_capacity(IMPORT_BATCH);
+        while let Some(log) = self.next_log()? {
+            batch.push(log);
+            if batch.len() == IMPORT_BATCH {
+                repo.insert_logs_with_words(&batch, words).await?;
+                batch.clear();
+                words = &[];
+            }
+        }
+        if !batch.is_empty() || !words.is_empty() {
+            repo.insert_logs_with_words(&batch, words).await?;
+        }
+
+        Ok(self.read)
+    }
+
+    /// Reads the next log with its timestamp, None once the end of logs is read.
+    /// Returns an error if the snapshot ends early or its count of logs does not match.
+    ///
+    fn next_log(&mut self) -> std::io::Result<Option<(i64, Vec<Token>)>> {
+        let mut tag = [0; 1];
+        self.r.read_exact(&mut tag)?;
+        match tag[0] {
+            TAG_LOG => (),
+            TAG_END => {
+                let mut count = [0; 8];
+                self.r.read_exact(&mut count)?;
+                if u64::from_le_bytes(count) != self.read {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "snapshot count of logs does not match",
+                    ));
+                }
+                return Ok(None);
+            }
+            tag => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("unknown snapshot record {}", tag),
+                ))
+            }
+        }
+        let mut timestamp = [0; 8];
+        self.r.read_exact(&mut timestamp)?;
+        let mut len = [0; 4];
+        self.r.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len) as u64;
+        if len > MAX_RECORD_LEN {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("snapshot log record of {} bytes is too large", len),
+            ));
+        }
+        // Reads through take, so the buffer grows with the bytes actually there
+        // instead of trusting the length of a truncated record.
+        let mut data = Vec::new();
+        (&mut self.r).take(len).read_to_end(&mut data)?;
+        if data.len() as u64 != len {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "snapshot log record is truncated",
+            ));
+        }
+        self.read += 1;
+
+        Ok(Some((i64::from_le_bytes(timestamp), Token::decode(&data)?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::error::RepositoryError;
+    use crate::repository::interface::{Log, LogId};
+    use crate::repository::sql::{DatabaseStorage, WarehouseSql};
+    use crate::repository::PoolConfig;
+    use std::io::Cursor;
+
+    /// Unstreamed keeps logs in SQLite without streaming them, as a repository keeping
+    /// the default `find_logs_stream` does.
+    ///
+    #[derive(Clone)]
+    struct Unstreamed(WarehouseSql);
+
+    impl RepositoryProvider for Unstreamed {
+        async fn migrate(&self) -> Result<()> {
+            self.0.migrate().await
+        }
+
+        async fn insert_log_at(&self, input: &[Token], timestamp: i64) -> Result<LogId> {
+            self.0.insert_log_at(input, timestamp).await
+        }
+
+        async fn insert_log_with_words(
+            &self,
+            input: &[Token],
+            timestamp: i64,
+            words: &[(String, Token)],
+        ) -> Result<LogId> {
+            self.0.insert_log_with_words(input, timestamp, words).await
+        }
+
+        async fn insert_logs_with_words(
+            &self,
+            inputs: &[(i64, Vec<Token>)],
+            words: &[(String, Token)],
+        ) -> Result<()> {
+            self.0.insert_logs_with_words(inputs, words).await
+        }
+
+        async fn find_words(&self) -> Result<Vec<(String, Token)>> {
+            self.0.find_words().await
+        }
+
+        async fn find_logs(&self, from: &Duration, to: &Duration) -> Result<Vec<Log>> {
+            self.0.find_logs(from, to).await
+        }
+
+        async fn close(&self) {
+            self.0.close().await
+        }
+    }
+
+    async fn warehouse() -> WarehouseSql {
+        let repo = WarehouseSql::new(DatabaseStorage::Ram, &PoolConfig::default())
+            .await
+            .unwrap();
+        repo.migrate().await.unwrap();
+        repo
+    }
+
+    #[tokio::test]
+    async fn on_import_should_load_logs_and_words_of_the_export() {
+        let source = warehouse().await;
+        let words = vec![
+            ("one".to_string(), Token::from(1)),
+            ("two".to_string(), Token::from(2)),
+        ];
+        let logs: Vec<(i64, Vec<Token>)> = (0..2500)
+            .map(|i| (i, vec![Token::from(1 + i as u32 % 2)]))
+            .collect();
+        source.insert_logs_with_words(&logs, &words).await.unwrap();
+
+        let mut exported = Cursor::new(Vec::new());
+        let count = source
+            .export(&mut exported, || Ok(b"schema".to_vec()))
+            .await
+            .unwrap();
+        assert_eq!(count, 2500);
+
+        exported.set_position(0);
+        let (snapshot, schema) = Snapshot::open(exported).unwrap();
+        assert_eq!(schema, b"schema");
+        let target = warehouse().await;
+        assert_eq!(target.import(snapshot, &words).await.unwrap(), 2500);
+
+        let restored = target
+            .find_logs(&Duration::ZERO, &Duration::from_nanos(3000))
+            .await
+            .unwrap();
+        assert_eq!(
+            restored.into_iter().map(|l| l.data).collect::<Vec<_>>(),
+            logs.into_iter().map(|(_, data)| data).collect::<Vec<_>>()
+        );
+        assert_eq!(target.find_words().await.unwrap(), words);
+    }
+
+    #[tokio::test]
+    async fn on_import_of_truncated_snapshot_should_fail() {
+        let source = warehouse().await;
+        source
+            .insert_logs(&[(1, vec![Token::from(1)]), (2, vec![Token::from(2)])])
+            .await
+            .unwrap();
+        let mut exported = Cursor::new(Vec::new());
+        source
+            .export(&mut exported, || Ok(Vec::new()))
+            .await
+            .unwrap();
+
+        let mut truncated = exported.into_inner();
+        truncated.truncate(truncated.len() - 10);
+        let (snapshot, _) = Snapshot::open(Cursor::new(truncated)).unwrap();
+        assert!(warehouse().await.import(snapshot, &[]).await.is_err());
+
+        assert!(Snapshot::open(Cursor::new(b"not a snapshot".to_vec())).is_err());
+    }
+
+    #[tokio::test]
+    async fn on_import_of_record_with_too_large_length_should_fail() {
+        let source = warehouse().await;
+        source
+            .insert_logs(&[(1, vec![Token::from(1)])])
+            .await
+            .unwrap();
+        let mut exported = Cursor::new(Vec::new());
+        source
+            .export(&mut exported, || Ok(Vec::new()))
+            .await
+            .unwrap();
+
+        let mut damaged = exported.into_inner();
+        let at = HEADER_SIZE as usize + 1 + 8;
+        damaged[at..at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let (mut snapshot, _) = Snapshot::open(Cursor::new(damaged)).unwrap();
+        assert_eq!(
+            snapshot.next_log().unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+    }
+
+    #[tokio::test]
+    async fn on_check_streaming_should_refuse_repository_not_streaming_logs() {
+        let source = warehouse().await;
+        source
+            .insert_logs(&[(1, vec![Token::from(1)])])
+            .await
+            .unwrap();
+        assert!(check_streaming(&source).await.is_ok());
+
+        let unstreamed = Unstreamed(source);
+        assert!(matches!(
+            check_streaming(&unstreamed).await,
+            Err(RepositoryError::Unsupported(_))
+        ));
+    }
+}
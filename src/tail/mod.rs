@@ -0,0 +1,389 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::io::{self, SeekFrom};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// Time between looks at the files for new lines, rotation and new files matching the patterns.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Bytes read from a file at once.
+const READ_BYTES: usize = 64 * 1024;
+
+/// Longest line, a longer one is saved in parts of this length.
+const MAX_LINE_BYTES: usize = 1 << 20;
+
+/// Position is how far the file of the inode is saved.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Position {
+    inode: u64,
+    offset: u64,
+}
+
+/// Positions is the file holding the position of each tailed file, so tailing resumes where it stopped.
+///
+#[derive(Debug, Clone)]
+pub struct Positions {
+    path: PathBuf,
+}
+
+impl Positions {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Reads the positions of the file, none when there is no file yet.
+    ///
+    pub fn load(&self) -> io::Result<BTreeMap<PathBuf, Position>> {
+        match std::fs::read(&self.path) {
+            Ok(json) => serde_json::from_slice(&json)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes the positions to the file, replacing the file at once so a crash never leaves half of them.
+    ///
+    pub fn store(&self, positions: &BTreeMap<PathBuf, Position>) -> io::Result<()> {
+        let json = serde_json::to_vec(positions).map_err(io::Error::other)?;
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        std::fs::write(&tmp, json)?;
+        std::fs::rename(&tmp, &self.path)
+    }
+}
+
+/// Returns the files matching the patterns, sorted. A pattern may hold `*` and `?` in its file name,
+/// a pattern without them names the file itself.
+///
+pub fn expand(patterns: &[String]) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    for pattern in patterns {
+        let pattern = Path::new(pattern);
+        let Some(name) = pattern.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if !name.contains(['*', '?']) {
+            paths.push(pattern.to_path_buf());
+            continue;
+        }
+        let dir = match pattern.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if entry.file_type().is_ok_and(|t| t.is_file())
+                && entry
+                    .file_name()
+                    .to_str()
+                    .is_some_and(|file| matches(name.as_bytes(), file.as_bytes()))
+            {
+                paths.push(dir.join(entry.file_name()));
+            }
+        }
+    }
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// Returns true if the name matches the pattern, where `*` stands for any run of bytes and `?` for one byte.
+///
+fn matches(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(b'?') => {
+                p += 1;
+                n += 1;
+            }
+            Some(b) if *b == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((sp, sn)) => {
+                    star = Some((sp, sn + 1));
+                    p = sp + 1;
+                    n = sn + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|b| *b == b'*')
+}
+
+/// Tailed is a file being followed, with bytes read from it and not yet saved.
+///
+struct Tailed {
+    file: File,
+    inode: u64,
+    offset: u64,
+    pending: Vec<u8>,
+}
+
+impl Tailed {
+    async fn open(path: &Path, inode: u64, offset: u64) -> io::Result<Self> {
+        let mut file = File::open(path).await?;
+        file.seek(SeekFrom::Start(offset)).await?;
+        Ok(Self {
+            file,
+            inode,
+            offset,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Reads the file to its end, passing each whole line to save and moving the offset past it once saved.
+    /// Returns false if a line cannot be saved, keeping it to be saved again on the next call.
+    /// The trailing bytes of a file that is done with are saved as a line too.
+    ///
+    async fn drain<S, F>(&mut self, path: &Path, done: bool, save: &mut S) -> io::Result<bool>
+    where
+        S: FnMut(String) -> F,
+        F: Future<Output = bool>,
+    {
+        let mut buf = vec![0; READ_BYTES];
+        loop {
+            if !self.save_lines(path, done, save).await {
+                return Ok(false);
+            }
+            let read = self.file.read(&mut buf).await?;
+            if read == 0 {
+                return Ok(self.save_lines(path, done, save).await);
+            }
+            self.pending.extend_from_slice(&buf[..read]);
+        }
+    }
+
+    async fn save_lines<S, F>(&mut self, path: &Path, done: bool, save: &mut S) -> bool
+    where
+        S: FnMut(String) -> F,
+        F: Future<Output = bool>,
+    {
+        loop {
+            let (line, consumed) = match self.pending.iter().position(|b| *b == b'\n') {
+                Some(end) => (&self.pending[..end], end + 1),
+                None if self.pending.len() >= MAX_LINE_BYTES => {
+                    (&self.pending[..MAX_LINE_BYTES], MAX_LINE_BYTES)
+                }
+                None if done && !self.pending.is_empty() => (&self.pending[..], self.pending.len()),
+                None => return true,
+            };
+            let line = String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(line));
+            if !line.trim().is_empty() && !save(format!("{} path={}", line, path.display())).await {
+                return false;
+            }
+            self.pending.drain(..consumed);
+            self.offset += consumed as u64;
+        }
+    }
+}
+
+/// Follows the files matching the patterns, passing each line, followed by the `path=` word of its file, to save.
+/// Files are read from the position kept in the positions file, files without one from their end when found
+/// at start and from their start when they appear later. A file replaced by one of a new inode is read to its end
+/// before the new one is read, a file cut shorter than its position is read again from its start.
+/// A line that save returns false for is saved again later. Runs for as long as the server does.
+///
+pub async fn follow<S, F>(patterns: Vec<String>, positions: Positions, mut save: S)
+where
+    S: FnMut(String) -> F,
+    F: Future<Output = bool>,
+{
+    let mut saved = positions.load().unwrap_or_else(|e| {
+        tracing::error!(error = %e, "cannot read tail positions, reading files from their end");
+        BTreeMap::new()
+    });
+    let mut tailed: HashMap<PathBuf, Tailed> = HashMap::new();
+    let mut started = false;
+    loop {
+        let paths = expand(&patterns);
+        for path in &paths {
+            if let Err(e) = tail(path, &mut tailed, &saved, started, &mut save).await {
+                tracing::warn!(error = %e, path = %path.display(), "cannot tail file");
+                tailed.remove(path);
+            }
+        }
+        let gone: Vec<PathBuf> = tailed
+            .keys()
+            .filter(|path| !paths.contains(path))
+            .cloned()
+            .collect();
+        for path in gone {
+            if let Some(mut file) = tailed.remove(&path) {
+                if let Err(e) = file.drain(&path, true, &mut save).await {
+                    tracing::warn!(error = %e, path = %path.display(), "cannot read removed file");
+                }
+            }
+        }
+        started = true;
+
+        let current: BTreeMap<PathBuf, Position> = tailed
+            .iter()
+            .map(|(path, file)| {
+                let position = Position {
+                    inode: file.inode,
+                    offset: file.offset,
+                };
+                (path.clone(), position)
+            })
+            .collect();
+        if current != saved {
+            match positions.store(&current) {
+                Ok(()) => saved = current,
+                Err(e) => tracing::error!(error = %e, "cannot write tail positions"),
+            }
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Reads new lines of the file at path, opening it when it is not tailed yet and following its rotation.
+///
+async fn tail<S, F>(
+    path: &Path,
+    tailed: &mut HashMap<PathBuf, Tailed>,
+    saved: &BTreeMap<PathBuf, Position>,
+    started: bool,
+    save: &mut S,
+) -> io::Result<()>
+where
+    S: FnMut(String) -> F,
+    F: Future<Output = bool>,
+{
+    let meta = match tokio::fs::metadata(path).await {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let (inode, len) = (meta.ino(), meta.len());
+
+    if let Some(file) = tailed.get_mut(path) {
+        if file.inode != inode {
+            file.drain(path, true, save).await?;
+            tracing::info!(path = %path.display(), "following rotated file");
+            *file = Tailed::open(path, inode, 0).await?;
+        } else if len < file.offset {
+            tracing::info!(path = %path.display(), "reading truncated file from its start");
+            *file = Tailed::open(path, inode, 0).await?;
+        }
+        file.drain(path, false, save).await?;
+        return Ok(());
+    }
+
+    let offset = match saved.get(path) {
+        Some(position) if position.inode == inode && position.offset <= len => position.offset,
+        _ if started => 0,
+        _ => len,
+    };
+    let mut file = Tailed::open(path, inode, offset).await?;
+    tracing::info!(path = %path.display(), offset, "tailing file");
+    file.drain(path, false, save).await?;
+    tailed.insert(path.to_path_buf(), file);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn on_matches_should_match_stars_and_question_marks_of_file_names() {
+        for (pattern, name, expected) in [
+            ("*.log", "app.log", true),
+            ("*.log", "app.log.1", false),
+            ("app-?.log", "app-1.log", true),
+            ("app-?.log", "app-12.log", false),
+            ("*access*", "nginx-access.log", true),
+            ("a*b*c", "abxbc", true),
+            ("a*b*c", "abxbd", false),
+            ("*", "", true),
+        ] {
+            assert_eq!(
+                matches(pattern.as_bytes(), name.as_bytes()),
+                expected,
+                "{} {}",
+                pattern,
+                name
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn on_follow_should_read_appended_rotated_and_truncated_files_once() {
+        let dir = std::env::temp_dir().join(format!("scribe-tail-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("app.log");
+        std::fs::write(&log, "before start\n").unwrap();
+
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let saved = lines.clone();
+        let follower = actix_web::rt::spawn(follow(
+            vec![dir.join("*.log").to_string_lossy().into_owned()],
+            Positions::new(dir.join("positions")),
+            move |line| {
+                saved.lock().unwrap().push(line);
+                async { true }
+            },
+        ));
+        let wait = || tokio::time::sleep(POLL_INTERVAL * 3);
+        let append = |path: &Path, text: &str| {
+            let mut file = std::fs::OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(path)
+                .unwrap();
+            std::io::Write::write_all(&mut file, text.as_bytes()).unwrap();
+        };
+
+        wait().await;
+        append(&log, "first\nsecond\r\nhalf");
+        wait().await;
+        append(&log, " done\n");
+        wait().await;
+        std::fs::rename(&log, dir.join("app.log.1")).unwrap();
+        append(&dir.join("app.log.1"), "late\n");
+        append(&log, "rotated\n");
+        wait().await;
+        std::fs::write(&log, "").unwrap();
+        wait().await;
+        append(&log, "truncated\n");
+        wait().await;
+        follower.abort();
+
+        let path = log.display();
+        assert_eq!(
+            *lines.lock().unwrap(),
+            [
+                format!("first path={}", path),
+                format!("second path={}", path),
+                format!("half done path={}", path),
+                format!("late path={}", path),
+                format!("rotated path={}", path),
+                format!("truncated path={}", path),
+            ]
+        );
+        let positions = Positions::new(dir.join("positions")).load().unwrap();
+        assert_eq!(positions.get(&log).map(|p| p.offset), Some(10));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, UNIX_EPOCH};
+
+const NOW: &str = "now";
+
+/// TimeExpr is a point in time of a query, written as nanoseconds since the Unix epoch,
+/// an RFC 3339 date like `2024-05-01T12:00:00Z`, `now`, or now shifted by a duration like `now-15m` or `now+1h`.
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TimeExpr {
+    Nanos(u64),
+    Text(String),
+}
+
+impl TimeExpr {
+    /// Returns the time since the Unix epoch the expression stands for, relative expressions counted from now.
+    ///
+    pub fn resolve(&self, now: Duration) -> Result<Duration, String> {
+        match self {
+            Self::Nanos(nanos) => Ok(Duration::from_nanos(*nanos)),
+            Self::Text(text) => parse(text, now),
+        }
+    }
+}
+
+/// Parses a point in time written as nanoseconds, an RFC 3339 date or an expression relative to now,
+/// see TimeExpr. Durations are written like `15m`, `1h 30m` or `2days`.
+///
+pub fn parse(s: &str, now: Duration) -> Result<Duration, String> {
+    let s = s.trim();
+    if let Ok(nanos) = s.parse::<u64>() {
+        return Ok(Duration::from_nanos(nanos));
+    }
+    if let Some(shift) = s.strip_prefix(NOW) {
+        let shift = shift.trim_start();
+        if shift.is_empty() {
+            return Ok(now);
+        }
+        let span = |span: &str| {
+            humantime::parse_duration(span.trim())
+                .map_err(|e| format!("cannot parse duration of [ {} ]: {}", s, e))
+        };
+        if let Some(back) = shift.strip_prefix('-') {
+            return Ok(now.saturating_sub(span(back)?));
+        }
+        if let Some(ahead) = shift.strip_prefix('+') {
+            return Ok(now.saturating_add(span(ahead)?));
+        }
+        return Err(format!("[ {} ] is to be shifted with - or +", s));
+    }
+    let time = humantime::parse_rfc3339_weak(s)
+        .map_err(|e| format!("cannot parse date [ {} ]: {}", s, e))?;
+    time.duration_since(UNIX_EPOCH)
+        .map_err(|_| format!("[ {} ] is before the Unix epoch", s))
+}
+
+/// Formats the timestamp in nanoseconds since the Unix epoch as an RFC 3339 date in UTC,
+/// which parse reads back to the same timestamp.
+///
+pub fn format(timestamp: i64) -> String {
+    let time = UNIX_EPOCH + Duration::from_nanos(timestamp.max(0) as u64);
+    humantime::format_rfc3339_nanos(time).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW_SINCE_EPOCH: Duration = Duration::from_secs(1_714_564_800);
+
+    #[test]
+    fn on_parse_should_read_nanos_dates_and_expressions_relative_to_now() {
+        assert_eq!(
+            parse("1500", NOW_SINCE_EPOCH),
+            Ok(Duration::from_nanos(1500))
+        );
+        assert_eq!(parse("now", NOW_SINCE_EPOCH), Ok(NOW_SINCE_EPOCH));
+        assert_eq!(
+            parse("now-15m", NOW_SINCE_EPOCH),
+            Ok(NOW_SINCE_EPOCH - Duration::from_secs(15 * 60))
+        );
+        assert_eq!(
+            parse("now + 1h 30m", NOW_SINCE_EPOCH),
+            Ok(NOW_SINCE_EPOCH + Duration::from_secs(90 * 60))
+        );
+        assert_eq!(
+            parse("2024-05-01T12:00:00Z", NOW_SINCE_EPOCH),
+            Ok(Duration::from_secs(1_714_564_800))
+        );
+        assert_eq!(
+            parse("2024-05-01T12:00:00.5Z", NOW_SINCE_EPOCH),
+            Ok(Duration::from_millis(1_714_564_800_500))
+        );
+    }
+
+    #[test]
+    fn on_parse_should_reject_malformed_expressions() {
+        for s in [
+            "yesterday",
+            "now*2h",
+            "now-",
+            "now-15 parsecs",
+            "now€1h",
+            "1969-12-31T00:00:00Z",
+        ] {
+            assert!(parse(s, NOW_SINCE_EPOCH).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn on_format_should_write_dates_parse_reads_back() {
+        let timestamp = 1_714_564_800_000_000_500;
+        assert_eq!(format(timestamp), "2024-05-01T12:00:00.000000500Z");
+        assert_eq!(
+            parse(&format(timestamp), NOW_SINCE_EPOCH),
+            Ok(Duration::from_nanos(timestamp as u64))
+        );
+    }
+
+    #[test]
+    fn on_deserialize_should_take_numbers_and_strings() {
+        let expr: TimeExpr = serde_json::from_str("1500").unwrap();
+        assert_eq!(
+            expr.resolve(NOW_SINCE_EPOCH),
+            Ok(Duration::from_nanos(1500))
+        );
+        let expr: TimeExpr = serde_json::from_str("\"now-1h\"").unwrap();
+        assert_eq!(
+            expr.resolve(NOW_SINCE_EPOCH),
+            Ok(NOW_SINCE_EPOCH - Duration::from_secs(3600))
+        );
+    }
+}
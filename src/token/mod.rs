@@ -0,0 +1,336 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+use std::io::{Error, ErrorKind, Result};
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+#[cfg(not(feature = "wide-tokens"))]
+type Raw = u32;
+#[cfg(feature = "wide-tokens")]
+type Raw = u64;
+
+/// Format byte of logs encoded with 4 bytes little endian tokens.
+const FORMAT_LE_32: u8 = 2;
+/// Format byte of logs encoded with 8 bytes little endian tokens.
+const FORMAT_LE_64: u8 = 3;
+/// Width of tokens of logs stored without a format byte, the only width of the v1 format.
+const LEGACY_WIDTH: usize = 4;
+
+#[cfg(not(feature = "wide-tokens"))]
+const FORMAT: u8 = FORMAT_LE_32;
+#[cfg(feature = "wide-tokens")]
+const FORMAT: u8 = FORMAT_LE_64;
+
+/// Token is the numeric representation of a word in the dictionary.
+/// It is 4 bytes wide by default and 8 bytes wide with the `wide-tokens` feature.
+/// Width changes the binary format of stored logs, so it has to stay the same for the life of a database.
+///
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Token(Raw);
+
+impl Token {
+    /// Number of bytes a token takes in the binary format.
+    ///
+    pub const WIDTH: usize = std::mem::size_of::<Raw>();
+
+    /// Returns the token following this one,
+    /// or an error if the token id space is exhausted.
+    ///
+    #[inline(always)]
+    pub fn next(self) -> Result<Self> {
+        match self.0.checked_add(1) {
+            Some(n) => Ok(Self(n)),
+            None => Err(Error::new(
+                ErrorKind::StorageFull,
+                format!("token id space of {} bytes is exhausted", Self::WIDTH),
+            )),
+        }
+    }
+
+    /// Returns the little endian bytes of the token, used where the byte order has to be portable.
+    ///
+    #[inline(always)]
+    pub fn to_le_bytes(self) -> [u8; Self::WIDTH] {
+        self.0.to_le_bytes()
+    }
+
+    /// Creates a token from its little endian bytes.
+    ///
+    #[inline(always)]
+    pub fn from_le_bytes(bytes: [u8; Self::WIDTH]) -> Self {
+        Self(Raw::from_le_bytes(bytes))
+    }
+
+    /// Encodes tokens in to the binary format,
+    /// a format byte naming the token width followed by little endian tokens,
+    /// so stored logs read the same on any architecture and with any width they fit in.
+    ///
+    #[inline]
+    pub fn encode(tokens: &[Token]) -> Vec<u8> {
+        let mut data = Vec::with_capacity(1 + tokens.len() * Self::WIDTH);
+        data.push(FORMAT);
+        for t in tokens {
+            data.extend_from_slice(&t.0.to_le_bytes());
+        }
+        data
+    }
+
+    /// Decodes tokens from the binary format.
+    /// Data without the format byte is the legacy v1 format of 4 bytes tokens written in native byte order,
+    /// told apart by length as legacy data is always a multiple of 4 bytes and formatted data never is.
+    /// Tokens narrower than the build are widened, so a `wide-tokens` build reads logs of a default build.
+    /// Returns an error if data length does not match the width of its format,
+    /// or if the data holds 8 bytes tokens and the build is not built with `wide-tokens`.
+    ///
+    #[inline]
+    pub fn decode(data: &[u8]) -> Result<Vec<Token>> {
+        if data.len().is_multiple_of(LEGACY_WIDTH) {
+            return Self::decode_with(data, |b: [u8; 4]| Ok(Self::from(u32::from_ne_bytes(b))));
+        }
+        match data[0] {
+            FORMAT_LE_32 => Self::decode_with(&data[1..], |b: [u8; 4]| {
+                Ok(Self::from(u32::from_le_bytes(b)))
+            }),
+            FORMAT_LE_64 => Self::decode_with(&data[1..], |b: [u8; 8]| {
+                Self::try_from(u64::from_le_bytes(b))
+            }),
+            format => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unknown token format {}", format),
+            )),
+        }
+    }
+
+    #[inline(always)]
+    fn decode_with<const N: usize>(
+        data: &[u8],
+        token: fn([u8; N]) -> Result<Self>,
+    ) -> Result<Vec<Token>> {
+        if !data.len().is_multiple_of(N) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("data length is not a multiple of {} bytes", N),
+            ));
+        }
+        data.chunks_exact(N)
+            .map(|c| {
+                let mut bytes = [0; N];
+                bytes.copy_from_slice(c);
+                token(bytes)
+            })
+            .collect()
+    }
+}
+
+impl From<u32> for Token {
+    #[allow(clippy::useless_conversion)]
+    fn from(n: u32) -> Self {
+        Self(n.into())
+    }
+}
+
+impl TryFrom<u64> for Token {
+    type Error = Error;
+
+    /// Converts an 8 bytes token, refused by a build without `wide-tokens` if it does not fit.
+    ///
+    #[allow(clippy::useless_conversion)]
+    fn try_from(n: u64) -> Result<Self> {
+        match Raw::try_from(n) {
+            Ok(n) => Ok(Self(n)),
+            Err(_) => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "token {} of 8 bytes does not fit in to {} bytes token, build with the wide-tokens feature",
+                    n,
+                    Self::WIDTH
+                ),
+            )),
+        }
+    }
+}
+
+impl From<Token> for i64 {
+    /// Converts token to a database integer, ids above i64::MAX wrap around and round trip back.
+    ///
+    fn from(t: Token) -> Self {
+        t.0 as i64
+    }
+}
+
+impl TryFrom<i64> for Token {
+    type Error = Error;
+
+    fn try_from(n: i64) -> Result<Self> {
+        #[cfg(feature = "wide-tokens")]
+        return Ok(Self(n as u64));
+        #[cfg(not(feature = "wide-tokens"))]
+        match Raw::try_from(n) {
+            Ok(n) => Ok(Self(n)),
+            Err(_) => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "number {} does not fit in to {} bytes token",
+                    n,
+                    Self::WIDTH
+                ),
+            )),
+        }
+    }
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for Token {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(Self(s.parse()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn on_encode_decode_should_round_trip_tokens() {
+        let tokens: Vec<Token> = vec![Token::from(1), Token::from(u32::MAX), Token(Raw::MAX)];
+
+        let data = Token::encode(&tokens);
+
+        assert_eq!(data.len(), 1 + tokens.len() * Token::WIDTH);
+        assert_eq!(Token::decode(&data).unwrap(), tokens);
+    }
+
+    #[test]
+    fn on_encode_should_write_little_endian_tokens_after_the_format_byte() {
+        let data = Token::encode(&[Token::from(1), Token::from(0x0102)]);
+
+        let mut expected = vec![FORMAT];
+        expected.extend_from_slice(&Token::from(1).to_le_bytes());
+        expected.extend_from_slice(&Token::from(0x0102).to_le_bytes());
+        assert_eq!(data, expected);
+        assert_eq!(&data[1..3], &[1, 0]);
+    }
+
+    #[test]
+    fn on_decode_should_read_legacy_native_endian_data() {
+        let data: Vec<u8> = [7u32, u32::MAX]
+            .iter()
+            .flat_map(|n| n.to_ne_bytes())
+            .collect();
+
+        assert_eq!(
+            Token::decode(&data).unwrap(),
+            [Token::from(7), Token::from(u32::MAX)]
+        );
+        assert_eq!(Token::decode(&[]).unwrap(), []);
+        assert_eq!(Token::decode(&[FORMAT]).unwrap(), []);
+    }
+
+    #[test]
+    fn on_decode_should_read_little_endian_data_of_the_build_width() {
+        let tokens = [Token::from(7), Token(Raw::MAX)];
+        let mut data = vec![FORMAT];
+        data.extend(tokens.iter().flat_map(|t| t.to_le_bytes()));
+
+        assert_eq!(Token::decode(&data).unwrap(), tokens);
+    }
+
+    #[test]
+    fn on_decode_should_widen_4_bytes_tokens() {
+        let mut data = vec![FORMAT_LE_32];
+        data.extend([7u32, u32::MAX].iter().flat_map(|n| n.to_le_bytes()));
+
+        assert_eq!(
+            Token::decode(&data).unwrap(),
+            [Token::from(7), Token::from(u32::MAX)]
+        );
+    }
+
+    #[test]
+    fn on_decode_should_read_8_bytes_tokens_fitting_the_build_width() {
+        let mut data = vec![FORMAT_LE_64];
+        data.extend([7u64, u32::MAX as u64].iter().flat_map(|n| n.to_le_bytes()));
+
+        assert_eq!(
+            Token::decode(&data).unwrap(),
+            [Token::from(7), Token::from(u32::MAX)]
+        );
+
+        data.extend(u64::MAX.to_le_bytes());
+        #[cfg(not(feature = "wide-tokens"))]
+        assert_eq!(
+            Token::decode(&data).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+        #[cfg(feature = "wide-tokens")]
+        assert_eq!(Token::decode(&data).unwrap()[2], Token(Raw::MAX));
+    }
+
+    #[test]
+    fn on_decode_should_reject_unknown_format() {
+        let mut data = Token::encode(&[Token::from(1)]);
+        data[0] = 0xff;
+
+        assert_eq!(
+            Token::decode(&data).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn on_decode_should_read_v1_fixture() {
+        let data = std::fs::read("./assets/fixtures/v1/log.bin").unwrap();
+
+        assert_eq!(
+            Token::decode(&data).unwrap(),
+            [1, 2, 3, 4].map(Token::from).to_vec()
+        );
+    }
+
+    #[test]
+    fn on_decode_should_read_v2_fixture() {
+        let data = std::fs::read("./assets/fixtures/v2/log.bin").unwrap();
+
+        assert_eq!(
+            Token::decode(&data).unwrap(),
+            [1, 2, 3, 4].map(Token::from).to_vec()
+        );
+    }
+
+    #[test]
+    fn on_decode_should_reject_truncated_data() {
+        let mut data = Token::encode(&[Token::from(1), Token::from(2)]);
+        data.truncate(data.len() - 2);
+
+        assert_eq!(
+            Token::decode(&data).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn on_next_should_fail_when_space_is_exhausted() {
+        assert_eq!(Token::default().next().unwrap(), Token::from(1));
+        assert_eq!(
+            Token(Raw::MAX).next().unwrap_err().kind(),
+            ErrorKind::StorageFull
+        );
+    }
+
+    #[test]
+    fn on_i64_conversion_should_round_trip_tokens() {
+        for t in [Token::default(), Token::from(u32::MAX), Token(Raw::MAX)] {
+            assert_eq!(Token::try_from(i64::from(t)).unwrap(), t);
+        }
+    }
+}
@@ -0,0 +1,423 @@
+use crate::bloom::{glob, within_distance};
+use crate::dictionary::{Filter, Stats};
+use crate::token::Token;
+use crate::trie::{fold, Trie};
+use fst::automaton::{Automaton, Str};
+use fst::{IntoStreamer, Map, MapBuilder, Streamer};
+use std::cmp::Reverse;
+use std::collections::HashSet;
+
+/// Fst is a finite state transducer mapping words to their rank in lexicographic order,
+/// which indexes the tokens and counts. Words sharing prefixes and suffixes share states
+/// and the transducer is one byte buffer, so millions of words take a fraction of the memory of a trie.
+/// Prefix and range queries follow the transducer, other queries stream every word.
+///
+/// The transducer is frozen, like the `Dawg` words pushed afterwards go to a small trie overlay
+/// and removed words are hidden until `shrink_to_fit` builds a new transducer of all the words.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Fst {
+    map: Map<Vec<u8>>,
+    tokens: Vec<Token>,
+    counts: Vec<u64>,
+    overlay: Trie,
+    removed: HashSet<Token>,
+}
+
+impl Fst {
+    /// Creates an empty transducer, words pushed to it are held by the overlay until `shrink_to_fit`.
+    ///
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn build(mut words: Vec<(String, Token, u64)>) -> fst::Result<Self> {
+        words.reverse();
+        words.sort_by(|a, b| a.0.cmp(&b.0));
+        words.dedup_by(|a, b| a.0 == b.0);
+
+        let mut builder = MapBuilder::memory();
+        for (rank, (word, _, _)) in words.iter().enumerate() {
+            builder.insert(word, rank as u64)?;
+        }
+        let (tokens, counts) = words.into_iter().map(|(_, t, c)| (t, c)).unzip();
+        Ok(Self {
+            map: builder.into_map(),
+            tokens,
+            counts,
+            overlay: Trie::new(),
+            removed: HashSet::new(),
+        })
+    }
+
+    /// Returns an estimate of bytes allocated on the heap by the transducer and the overlay.
+    ///
+    pub fn heap_size(&self) -> usize {
+        self.map.as_fst().as_bytes().len()
+            + self.tokens.capacity() * std::mem::size_of::<Token>()
+            + self.counts.capacity() * std::mem::size_of::<u64>()
+            + self.overlay.heap_size()
+            + self.removed.capacity() * std::mem::size_of::<Token>()
+    }
+
+    #[inline(always)]
+    fn emit(&self, rank: u64, nums: &mut HashSet<Token>) {
+        let num = self.tokens[rank as usize];
+        if !self.removed.contains(&num) {
+            nums.insert(num);
+        }
+    }
+
+    /// Streams every word of the transducer, collecting nums of words the predicate keeps.
+    ///
+    fn scan(&self, nums: &mut HashSet<Token>, keep: impl Fn(&str) -> bool) {
+        let mut stream = self.map.stream();
+        while let Some((word, rank)) = stream.next() {
+            if std::str::from_utf8(word).is_ok_and(&keep) {
+                self.emit(rank, nums);
+            }
+        }
+    }
+}
+
+impl Filter for Fst {
+    /// Pushes the word to the overlay, hiding the word in the transducer if it had another token.
+    ///
+    fn push(&mut self, s: &str, num: Token) {
+        if let Some(rank) = self.map.get(s) {
+            if self.tokens[rank as usize] != num {
+                self.removed.insert(self.tokens[rank as usize]);
+            }
+        }
+        self.overlay.push(s, num);
+    }
+
+    /// Removes the word from the overlay or hides it in the transducer until it is rebuilt.
+    /// Returns false if the string is not held under that num.
+    ///
+    fn remove(&mut self, s: &str, num: Token) -> bool {
+        if self.overlay.remove(s, num) {
+            return true;
+        }
+        self.map
+            .get(s)
+            .is_some_and(|rank| self.tokens[rank as usize] == num)
+            && self.removed.insert(num)
+    }
+
+    /// Returns the size of the transducer and the overlay, the transducer states are not exposed to walk.
+    ///
+    fn stats(&self) -> Option<Stats> {
+        Some(Stats {
+            bytes: self.heap_size(),
+            ..Stats::default()
+        })
+    }
+
+    /// Adds occurrences to the count of the word in the overlay or in the transducer.
+    ///
+    fn count(&mut self, s: &str, by: u64) {
+        if self.overlay.find_match(s).is_some() {
+            self.overlay.count(s, by);
+            return;
+        }
+        if let Some(rank) = self.map.get(s) {
+            self.counts[rank as usize] += by;
+        }
+    }
+
+    /// Finds up to k most frequent words with matching prefix.
+    /// The transducer keeps no counts of its states, so every word under the prefix is visited.
+    ///
+    fn find_prefix_top(&self, s: &str, k: usize) -> Vec<(Token, u64)> {
+        let mut top = self.overlay.find_prefix_top(s, k);
+        let mut stream = self.map.search(Str::new(s).starts_with()).into_stream();
+        while let Some((_, rank)) = stream.next() {
+            let num = self.tokens[rank as usize];
+            if !self.removed.contains(&num) {
+                top.push((num, self.counts[rank as usize]));
+            }
+        }
+        top.sort_unstable_by_key(|(num, count)| (Reverse(*count), *num));
+        top.truncate(k);
+        top
+    }
+
+    fn find_prefix(&self, s: &str) -> HashSet<Token> {
+        let mut nums = self.overlay.find_prefix(s);
+        let mut stream = self.map.search(Str::new(s).starts_with()).into_stream();
+        while let Some((_, rank)) = stream.next() {
+            self.emit(rank, &mut nums);
+        }
+        nums
+    }
+
+    /// Finds all words with matching prefix of the transducer and the overlay in lexicographic order.
+    ///
+    fn find_prefix_words(&self, s: &str) -> Vec<(String, Token)> {
+        let mut words = self.overlay.find_prefix_words(s);
+        let mut stream = self.map.search(Str::new(s).starts_with()).into_stream();
+        while let Some((word, rank)) = stream.next() {
+            let num = self.tokens[rank as usize];
+            if self.removed.contains(&num) {
+                continue;
+            }
+            if let Ok(word) = std::str::from_utf8(word) {
+                words.push((word.to_string(), num));
+            }
+        }
+        words.sort_unstable();
+        words
+    }
+
+    fn find_prefix_case_insensitive(&self, s: &str) -> HashSet<Token> {
+        let folded: Vec<char> = s.chars().flat_map(fold).collect();
+        let mut nums = self.overlay.find_prefix_case_insensitive(s);
+        self.scan(&mut nums, |w| {
+            let mut chars = w.chars().flat_map(fold);
+            folded.iter().all(|c| chars.next() == Some(*c))
+        });
+        nums
+    }
+
+    fn find_fuzzy(&self, s: &str, distance: u8) -> HashSet<Token> {
+        let word: Vec<char> = s.chars().collect();
+        let mut nums = self.overlay.find_fuzzy(s, distance);
+        self.scan(&mut nums, |w| {
+            within_distance(&word, w, distance as usize, true)
+        });
+        nums
+    }
+
+    fn find_prefix_fuzzy(&self, s: &str, distance: u8) -> HashSet<Token> {
+        let word: Vec<char> = s.chars().collect();
+        let mut nums = self.overlay.find_prefix_fuzzy(s, distance);
+        self.scan(&mut nums, |w| {
+            within_distance(&word, w, distance as usize, false)
+        });
+        nums
+    }
+
+    fn find_glob(&self, pattern: &str) -> HashSet<Token> {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut nums = self.overlay.find_glob(pattern);
+        self.scan(&mut nums, |w| {
+            glob(&chars, &w.chars().collect::<Vec<char>>())
+        });
+        nums
+    }
+
+    fn find_suffix(&self, s: &str) -> HashSet<Token> {
+        let mut nums = self.overlay.find_suffix(s);
+        self.scan(&mut nums, |w| w.ends_with(s));
+        nums
+    }
+
+    /// Finds all index nums of words between the bounds by a range walk of the transducer.
+    ///
+    fn find_range(&self, from: &str, to: &str) -> HashSet<Token> {
+        let mut nums = self.overlay.find_range(from, to);
+        let mut stream = self.map.range().ge(from).lt(to).into_stream();
+        while let Some((_, rank)) = stream.next() {
+            self.emit(rank, &mut nums);
+        }
+        nums
+    }
+
+    /// Builds a new transducer of the transducer and the overlay words, leaving out removed words.
+    /// The current transducer is kept if building fails.
+    ///
+    fn shrink_to_fit(&mut self) {
+        if self.overlay.iter_words().next().is_none() && self.removed.is_empty() {
+            return;
+        }
+        let mut words = Vec::with_capacity(self.tokens.len());
+        let mut stream = self.map.stream();
+        while let Some((word, rank)) = stream.next() {
+            let num = self.tokens[rank as usize];
+            if self.removed.contains(&num) {
+                continue;
+            }
+            let Ok(word) = std::str::from_utf8(word) else {
+                continue;
+            };
+            words.push((word.to_string(), num, self.counts[rank as usize]));
+        }
+        for (word, num) in self.overlay.iter_words() {
+            let count = self.overlay.find_count(&word);
+            words.push((word, num, count));
+        }
+        if let Ok(mut fst) = Self::build(words) {
+            fst.tokens.shrink_to_fit();
+            fst.counts.shrink_to_fit();
+            *self = fst;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl Fst {
+        /// Builds the transducer of the words, a repeated word keeps its last token.
+        ///
+        fn from_words<S: Into<String>>(
+            words: impl IntoIterator<Item = (S, Token)>,
+        ) -> fst::Result<Self> {
+            Self::build(words.into_iter().map(|(w, t)| (w.into(), t, 0)).collect())
+        }
+
+        /// Find matching string in the transducer or the overlay returning it index num if found or None otherwise.
+        ///
+        fn find_match(&self, s: &str) -> Option<Token> {
+            if let Some(num) = self.overlay.find_match(s) {
+                return Some(num);
+            }
+            let num = self.tokens[self.map.get(s)? as usize];
+            match self.removed.contains(&num) {
+                true => None,
+                false => Some(num),
+            }
+        }
+    }
+
+    const WORDS: [&str; 10] = [
+        "connection",
+        "connections",
+        "connected",
+        "collected",
+        "refused",
+        "refusal",
+        "Straße",
+        "config.json",
+        "data.json",
+        "żółw",
+    ];
+
+    fn filters() -> (Trie, Fst) {
+        let mut trie = Trie::new();
+        let mut words = Vec::new();
+        for (i, w) in WORDS.iter().enumerate() {
+            trie.push(w, Token::from(i as u32));
+            words.push((*w, Token::from(i as u32)));
+        }
+        (trie, Fst::from_words(words).unwrap())
+    }
+
+    #[test]
+    fn on_find_should_match_the_trie() {
+        let (trie, fst) = filters();
+
+        for (i, w) in WORDS.iter().enumerate() {
+            assert_eq!(fst.find_match(w), Some(Token::from(i as u32)));
+        }
+        for prefix in ["", "con", "conn", "connection", "ref", "ż", "x", "STR"] {
+            assert_eq!(fst.find_prefix(prefix), trie.find_prefix(prefix));
+            assert_eq!(
+                fst.find_prefix_words(prefix),
+                trie.find_prefix_words(prefix)
+            );
+            assert_eq!(
+                fst.find_prefix_case_insensitive(prefix),
+                trie.find_prefix_case_insensitive(prefix)
+            );
+        }
+        for (word, distance) in [("conected", 1), ("refusd", 1), ("zolw", 3), ("data", 2)] {
+            assert_eq!(
+                fst.find_fuzzy(word, distance),
+                trie.find_fuzzy(word, distance)
+            );
+            assert_eq!(
+                fst.find_prefix_fuzzy(word, distance),
+                trie.find_prefix_fuzzy(word, distance)
+            );
+        }
+        for pattern in ["con*", "*.json", "re?us*", "*", "c*d"] {
+            assert_eq!(fst.find_glob(pattern), trie.find_glob(pattern));
+        }
+        for suffix in ["ed", ".json", "w"] {
+            assert_eq!(fst.find_suffix(suffix), trie.find_suffix(suffix));
+        }
+    }
+
+    #[test]
+    fn on_find_range_should_find_words_between_bounds() {
+        let (_, mut fst) = filters();
+        fst.push("config.yaml", Token::from(100));
+
+        assert_eq!(
+            fst.find_range("con", "cono"),
+            HashSet::from_iter([0, 1, 2, 7, 100].map(Token::from))
+        );
+        assert_eq!(
+            fst.find_range("data", "refused"),
+            HashSet::from_iter([8, 5].map(Token::from))
+        );
+        assert!(fst.find_range("x", "y").is_empty());
+
+        let (trie, fst) = filters();
+        for (from, to) in [
+            ("con", "cono"),
+            ("data", "refused"),
+            ("", "d"),
+            ("ref", "ref"),
+            ("x", "y"),
+        ] {
+            assert_eq!(
+                fst.find_range(from, to),
+                trie.find_range(from, to),
+                "{}..{}",
+                from,
+                to
+            );
+        }
+    }
+
+    #[test]
+    fn on_push_and_remove_should_use_the_overlay_until_shrink() {
+        let (_, mut fst) = filters();
+        let bytes = fst.map.as_fst().as_bytes().len();
+
+        fst.push("connecting", Token::from(100));
+        fst.push("refused", Token::from(101));
+        assert!(fst.remove("refusal", Token::from(5)));
+        assert!(!fst.remove("refusal", Token::from(5)));
+        assert!(!fst.remove("collected", Token::from(0)));
+        fst.count("refused", 3);
+        fst.count("connected", 2);
+
+        assert_eq!(fst.map.as_fst().as_bytes().len(), bytes);
+        assert_eq!(fst.find_match("connecting"), Some(Token::from(100)));
+        assert_eq!(fst.find_match("refused"), Some(Token::from(101)));
+        assert_eq!(fst.find_match("refusal"), None);
+        assert_eq!(fst.find_prefix("refus"), HashSet::from([Token::from(101)]));
+
+        fst.shrink_to_fit();
+
+        assert!(fst.overlay.iter_words().next().is_none() && fst.removed.is_empty());
+        assert_eq!(fst.find_match("connecting"), Some(Token::from(100)));
+        assert_eq!(fst.find_match("refused"), Some(Token::from(101)));
+        assert_eq!(fst.find_match("refusal"), None);
+        assert_eq!(fst.tokens.len(), WORDS.len());
+        assert_eq!(
+            fst.find_prefix_top("", 2),
+            vec![(Token::from(101), 3), (Token::from(2), 2)]
+        );
+    }
+
+    #[test]
+    fn on_build_should_take_less_memory_than_the_trie() {
+        let mut trie = Trie::new();
+        let mut words = Vec::new();
+        for i in 0..100000u32 {
+            let w = format!("host-{}.eu-west-{}.internal", i, i % 3);
+            trie.push(&w, Token::from(i));
+            words.push((w, Token::from(i)));
+        }
+        let fst = Fst::from_words(words).unwrap();
+
+        assert!(fst.heap_size() * 4 < trie.heap_size());
+        assert_eq!(fst.find_prefix("host-9999").len(), 11);
+    }
+}
@@ -0,0 +1,179 @@
+use super::{unit, Trie};
+use crate::dictionary::{Filter, Stats};
+use crate::token::Token;
+use std::collections::HashSet;
+
+/// BiTrie keeps a second trie of reversed words next to the forward one,
+/// so suffix queries walk a prefix of the reversed trie instead of visiting every word.
+/// It takes about twice the memory of a single trie.
+///
+#[derive(Debug, Clone, Default)]
+pub struct BiTrie {
+    forward: Trie,
+    reversed: Trie,
+}
+
+impl BiTrie {
+    /// Creates new empty BiTrie.
+    ///
+    #[inline(always)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[inline(always)]
+fn reverse(s: &str) -> String {
+    s.chars().rev().collect()
+}
+
+/// Reverses the bytes unit by unit, so UTF-8 chars are reversed the same way as by `reverse`.
+///
+#[inline(always)]
+fn reverse_bytes(s: &[u8]) -> Vec<u8> {
+    let mut units = Vec::new();
+    let mut rest = s;
+    while let Some((_, len)) = unit(rest) {
+        units.push(&rest[..len]);
+        rest = &rest[len..];
+    }
+    units.into_iter().rev().flatten().copied().collect()
+}
+
+impl Filter for BiTrie {
+    fn push(&mut self, s: &str, num: Token) {
+        self.forward.push(s, num);
+        self.reversed.push(&reverse(s), num);
+    }
+
+    fn remove(&mut self, s: &str, num: Token) -> bool {
+        let removed = self.forward.remove(s, num);
+        self.reversed.remove(&reverse(s), num);
+        removed
+    }
+
+    fn push_bytes(&mut self, s: &[u8], num: Token) {
+        self.forward.push_bytes(s, num);
+        self.reversed.push_bytes(&reverse_bytes(s), num);
+    }
+
+    fn remove_bytes(&mut self, s: &[u8], num: Token) -> bool {
+        let removed = self.forward.remove_bytes(s, num);
+        self.reversed.remove_bytes(&reverse_bytes(s), num);
+        removed
+    }
+
+    fn find_prefix_bytes(&self, s: &[u8]) -> HashSet<Token> {
+        self.forward.find_prefix_bytes(s)
+    }
+
+    fn count(&mut self, s: &str, by: u64) {
+        self.forward.count(s, by);
+    }
+
+    fn find_prefix_top(&self, s: &str, k: usize) -> Vec<(Token, u64)> {
+        self.forward.find_prefix_top(s, k)
+    }
+
+    fn find_prefix(&self, s: &str) -> HashSet<Token> {
+        self.forward.find_prefix(s)
+    }
+
+    /// Returns the shape of the forward tree with nodes and bytes of both trees.
+    ///
+    fn stats(&self) -> Option<Stats> {
+        let mut stats = self.forward.stats();
+        let reversed = self.reversed.stats();
+        stats.nodes += reversed.nodes;
+        stats.bytes += reversed.bytes;
+        Some(stats)
+    }
+
+    fn find_prefix_limited(&self, s: &str, max: usize) -> Option<HashSet<Token>> {
+        self.forward.find_prefix_limited(s, max)
+    }
+
+    fn find_prefix_words(&self, s: &str) -> Vec<(String, Token)> {
+        self.forward.find_prefix_words(s)
+    }
+
+    fn find_prefix_case_insensitive(&self, s: &str) -> HashSet<Token> {
+        self.forward.find_prefix_case_insensitive(s)
+    }
+
+    fn find_fuzzy(&self, s: &str, distance: u8) -> HashSet<Token> {
+        self.forward.find_fuzzy(s, distance)
+    }
+
+    fn find_prefix_fuzzy(&self, s: &str, distance: u8) -> HashSet<Token> {
+        self.forward.find_prefix_fuzzy(s, distance)
+    }
+
+    fn find_glob(&self, pattern: &str) -> HashSet<Token> {
+        self.forward.find_glob(pattern)
+    }
+
+    /// Finds all index nums of words ending with the suffix by a prefix walk of the reversed trie.
+    ///
+    fn find_suffix(&self, s: &str) -> HashSet<Token> {
+        self.reversed.find_prefix(&reverse(s))
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.forward.shrink_to_fit();
+        self.reversed.shrink_to_fit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn on_find_suffix_should_match_scan_of_forward_trie() {
+        let mut bi = BiTrie::new();
+        let words = ["config.json", "data.json", "json", "data.yaml", "żółć.json"];
+        words
+            .iter()
+            .enumerate()
+            .for_each(|(i, w)| bi.push(w, Token::from(i as u32)));
+
+        for suffix in [".json", "json", "ć.json", ".yaml", "", ".toml"] {
+            assert_eq!(
+                bi.find_suffix(suffix),
+                bi.forward.find_suffix(suffix),
+                "suffix {}",
+                suffix
+            );
+        }
+        assert_eq!(
+            bi.find_suffix(".json"),
+            HashSet::from_iter([0, 1, 4].map(Token::from))
+        );
+        assert_eq!(bi.find_prefix("data"), bi.forward.find_prefix("data"));
+    }
+
+    #[test]
+    fn on_push_bytes_should_index_suffix_of_binary_words() {
+        let mut bi = BiTrie::new();
+        bi.push_bytes(b"\xffdata.json", Token::from(1));
+        bi.push_bytes("żółć.json".as_bytes(), Token::from(2));
+        bi.push_bytes(b"data\xfe", Token::from(3));
+
+        assert_eq!(
+            bi.find_suffix(".json"),
+            HashSet::from_iter([1, 2].map(Token::from))
+        );
+        assert_eq!(bi.find_suffix("ć.json"), HashSet::from([Token::from(2)]));
+        assert_eq!(
+            bi.find_prefix_bytes(b"data"),
+            HashSet::from([Token::from(3)])
+        );
+        assert!(bi.remove_bytes(b"\xffdata.json", Token::from(1)));
+        assert_eq!(bi.find_suffix(".json"), HashSet::from([Token::from(2)]));
+        assert!(bi
+            .reversed
+            .iter_words()
+            .all(|(_, num)| num != Token::from(1)));
+    }
+}
@@ -0,0 +1,274 @@
+use super::Trie;
+use crate::dictionary::{Filter, Stats};
+use crate::token::Token;
+use arc_swap::ArcSwap;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Number of words pushed in to the delta before it is folded in to a new base.
+const MAX_DELTA: usize = 1024;
+
+/// Snapshot is an immutable view of the graph published for readers,
+/// the base is rebuilt rarely and the delta holds words pushed since, so publishing a word copies only the delta.
+///
+#[derive(Debug, Default)]
+struct Snapshot {
+    base: Arc<Trie>,
+    delta: Arc<Trie>,
+}
+
+impl Snapshot {
+    fn find_prefix(&self, s: &str, nums: &mut HashSet<Token>) {
+        nums.extend(self.base.find_prefix(s));
+        nums.extend(self.delta.find_prefix(s));
+    }
+}
+
+/// Concurrent is a radix tree filter that publishes immutable snapshots of itself,
+/// so prefix queries made through a `Reader` never wait for the dictionary lock held by ingestion.
+/// All other queries are answered by the tree itself under the dictionary lock.
+/// It takes about twice the memory of a single tree, since the published base is a copy of the tree.
+///
+#[derive(Debug)]
+pub struct Concurrent {
+    trie: Trie,
+    base: Arc<Trie>,
+    delta: Trie,
+    delta_len: usize,
+    published: Arc<ArcSwap<Snapshot>>,
+}
+
+/// Reader answers prefix queries from the latest published snapshot without taking any lock.
+/// It can be switched to follow another filter, when the dictionary it was made for is replaced.
+///
+#[derive(Debug, Clone)]
+pub struct Reader {
+    published: Arc<ArcSwap<Arc<ArcSwap<Snapshot>>>>,
+}
+
+impl Default for Concurrent {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Concurrent {
+    /// Creates new empty filter with nothing published yet.
+    ///
+    pub fn new() -> Self {
+        Self {
+            trie: Trie::new(),
+            base: Arc::new(Trie::new()),
+            delta: Trie::new(),
+            delta_len: 0,
+            published: Arc::new(ArcSwap::from_pointee(Snapshot::default())),
+        }
+    }
+
+    /// Returns a reader of snapshots published by this filter.
+    ///
+    pub fn reader(&self) -> Reader {
+        Reader {
+            published: Arc::new(ArcSwap::new(Arc::new(self.published.clone()))),
+        }
+    }
+
+    /// Publishes the base with the current delta.
+    ///
+    fn publish(&mut self) {
+        self.published.store(Arc::new(Snapshot {
+            base: self.base.clone(),
+            delta: Arc::new(self.delta.clone()),
+        }));
+    }
+
+    /// Publishes a copy of the whole tree as the new base with an empty delta.
+    ///
+    fn rebase(&mut self) {
+        self.base = Arc::new(self.trie.clone());
+        self.delta = Trie::new();
+        self.delta_len = 0;
+        self.publish();
+    }
+}
+
+impl Reader {
+    /// Finds all index nums with any of the prefixes in the latest published snapshot.
+    ///
+    pub fn find_prefix_any(&self, prefixes: &[&str]) -> HashSet<Token> {
+        let published = self.published.load();
+        let snapshot = published.load();
+        let mut nums = HashSet::new();
+        for prefix in prefixes.iter() {
+            snapshot.find_prefix(prefix, &mut nums);
+        }
+        nums
+    }
+
+    /// Switches the reader to snapshots published by the reader of another filter.
+    ///
+    pub fn follow(&self, other: &Reader) {
+        self.published.store(other.published.load_full());
+    }
+}
+
+impl Filter for Concurrent {
+    /// Pushes the word in to the tree and publishes it in the delta,
+    /// folding the delta in to a new base once it grows too large.
+    ///
+    fn push(&mut self, s: &str, num: Token) {
+        self.trie.push(s, num);
+        self.delta_len += 1;
+        if self.delta_len >= MAX_DELTA {
+            self.rebase();
+            return;
+        }
+        self.delta.push(s, num);
+        self.publish();
+    }
+
+    /// Removes the word from the tree and publishes a new base without it.
+    ///
+    fn remove(&mut self, s: &str, num: Token) -> bool {
+        if !self.trie.remove(s, num) {
+            return false;
+        }
+        self.rebase();
+        true
+    }
+
+    fn count(&mut self, s: &str, by: u64) {
+        self.trie.count(s, by);
+    }
+
+    fn find_prefix_top(&self, s: &str, k: usize) -> Vec<(Token, u64)> {
+        self.trie.find_prefix_top(s, k)
+    }
+
+    fn find_prefix(&self, s: &str) -> HashSet<Token> {
+        self.trie.find_prefix(s)
+    }
+
+    /// Returns the shape of the tree with bytes of the published snapshot added.
+    ///
+    fn stats(&self) -> Option<Stats> {
+        let mut stats = self.trie.stats();
+        stats.bytes += self.base.heap_size() + self.delta.heap_size();
+        Some(stats)
+    }
+
+    fn find_prefix_limited(&self, s: &str, max: usize) -> Option<HashSet<Token>> {
+        self.trie.find_prefix_limited(s, max)
+    }
+
+    fn find_prefix_words(&self, s: &str) -> Vec<(String, Token)> {
+        self.trie.find_prefix_words(s)
+    }
+
+    fn find_prefix_case_insensitive(&self, s: &str) -> HashSet<Token> {
+        self.trie.find_prefix_case_insensitive(s)
+    }
+
+    fn find_fuzzy(&self, s: &str, distance: u8) -> HashSet<Token> {
+        self.trie.find_fuzzy(s, distance)
+    }
+
+    fn find_prefix_fuzzy(&self, s: &str, distance: u8) -> HashSet<Token> {
+        self.trie.find_prefix_fuzzy(s, distance)
+    }
+
+    fn find_glob(&self, pattern: &str) -> HashSet<Token> {
+        self.trie.find_glob(pattern)
+    }
+
+    fn find_suffix(&self, s: &str) -> HashSet<Token> {
+        self.trie.find_suffix(s)
+    }
+
+    /// Compacts the tree and publishes it as the new base.
+    ///
+    fn shrink_to_fit(&mut self) {
+        self.trie.shrink_to_fit();
+        self.rebase();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::RwLock;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn on_push_should_publish_words_to_readers() {
+        let mut filter = Concurrent::new();
+        let reader = filter.reader();
+
+        for i in 0..(MAX_DELTA * 2 + 10) {
+            filter.push(&format!("word{}", i), Token::from(i as u32));
+            assert!(reader
+                .find_prefix_any(&[&format!("word{}", i)])
+                .contains(&Token::from(i as u32)));
+        }
+        assert_eq!(
+            reader.find_prefix_any(&["word"]),
+            filter.find_prefix("word")
+        );
+        assert_eq!(filter.delta_len, 10);
+
+        assert!(filter.remove("word1", Token::from(1)));
+        assert_eq!(
+            reader.find_prefix_any(&["word1", "word2"]),
+            filter
+                .find_prefix("word1")
+                .union(&filter.find_prefix("word2"))
+                .cloned()
+                .collect()
+        );
+    }
+
+    #[test]
+    fn on_follow_should_read_snapshots_of_the_other_filter() {
+        let mut first = Concurrent::new();
+        let mut second = Concurrent::new();
+        let reader = first.reader();
+        first.push("first", Token::from(1));
+        second.push("second", Token::from(2));
+
+        reader.follow(&second.reader());
+        second.push("secondary", Token::from(3));
+
+        assert!(reader.find_prefix_any(&["first"]).is_empty());
+        assert_eq!(
+            reader.find_prefix_any(&["sec"]),
+            HashSet::from_iter([2, 3].map(Token::from))
+        );
+    }
+
+    #[test]
+    fn on_find_prefix_any_should_not_wait_for_a_held_write_lock() {
+        let filter = RwLock::new(Concurrent::new());
+        let reader = filter.read().unwrap().reader();
+        let done = Arc::new(AtomicBool::new(false));
+
+        let mut guard = filter.write().unwrap();
+        guard.push("locked", Token::from(1));
+        let handle = {
+            let done = done.clone();
+            std::thread::spawn(move || {
+                let found = reader.find_prefix_any(&["lock"]);
+                done.store(true, Ordering::SeqCst);
+                found
+            })
+        };
+        let start = Instant::now();
+        while !done.load(Ordering::SeqCst) && start.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(done.load(Ordering::SeqCst));
+        drop(guard);
+
+        assert_eq!(handle.join().unwrap(), HashSet::from([Token::from(1)]));
+    }
+}
@@ -0,0 +1,215 @@
+use super::{fold, Trie};
+use crate::dictionary::{Filter, Stats};
+use crate::token::Token;
+use std::collections::HashSet;
+
+/// Folded keeps a tree of case folded words next to any filter, so case insensitive prefix queries
+/// are a plain prefix walk of the folded tree instead of folding every label on the way.
+/// Words differing only in case fold to the same key, so each key of the folded tree holds
+/// nums of all the original forms as its payload, while the original forms stay in the inner filter.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Folded<F> {
+    inner: F,
+    folded: Trie<Vec<Token>>,
+}
+
+impl<F: Filter> Folded<F> {
+    /// Creates new Folded around given filter, which is expected to be empty.
+    ///
+    pub fn new(inner: F) -> Self {
+        Self {
+            inner,
+            folded: Trie::default(),
+        }
+    }
+}
+
+#[inline(always)]
+fn folded(s: &str) -> String {
+    s.chars().flat_map(fold).collect()
+}
+
+impl<F: Filter> Filter for Folded<F> {
+    /// Pushes the word to the inner filter and its num to the payload of its case folded key.
+    /// The key is numbered by the first original form pushed, the number only marks the key as held.
+    ///
+    fn push(&mut self, s: &str, num: Token) {
+        self.inner.push(s, num);
+        let key = folded(s);
+        if self.folded.find_match(&key).is_none() {
+            self.folded.push(&key, num);
+        }
+        if let Some(nums) = self.folded.value_mut(&key) {
+            if !nums.contains(&num) {
+                nums.push(num);
+            }
+        }
+    }
+
+    /// Removes the word from the inner filter and its num from the payload of its key,
+    /// dropping the folded key once no original form is left.
+    ///
+    fn remove(&mut self, s: &str, num: Token) -> bool {
+        if !self.inner.remove(s, num) {
+            return false;
+        }
+        let key = folded(s);
+        let Some(nums) = self.folded.value_mut(&key) else {
+            return true;
+        };
+        nums.retain(|n| *n != num);
+        if nums.is_empty() {
+            if let Some(marker) = self.folded.find_match(&key) {
+                self.folded.remove(&key, marker);
+            }
+        }
+        true
+    }
+
+    fn count(&mut self, s: &str, by: u64) {
+        self.inner.count(s, by);
+    }
+
+    fn find_prefix_top(&self, s: &str, k: usize) -> Vec<(Token, u64)> {
+        self.inner.find_prefix_top(s, k)
+    }
+
+    fn find_prefix(&self, s: &str) -> HashSet<Token> {
+        self.inner.find_prefix(s)
+    }
+
+    fn find_prefix_limited(&self, s: &str, max: usize) -> Option<HashSet<Token>> {
+        self.inner.find_prefix_limited(s, max)
+    }
+
+    fn find_prefix_words(&self, s: &str) -> Vec<(String, Token)> {
+        self.inner.find_prefix_words(s)
+    }
+
+    /// Finds all index nums of words with case insensitive prefix by a prefix walk of the folded tree.
+    ///
+    fn find_prefix_case_insensitive(&self, s: &str) -> HashSet<Token> {
+        self.folded
+            .find_prefix_values(&folded(s))
+            .into_iter()
+            .flatten()
+            .copied()
+            .collect()
+    }
+
+    fn find_fuzzy(&self, s: &str, distance: u8) -> HashSet<Token> {
+        self.inner.find_fuzzy(s, distance)
+    }
+
+    fn find_prefix_fuzzy(&self, s: &str, distance: u8) -> HashSet<Token> {
+        self.inner.find_prefix_fuzzy(s, distance)
+    }
+
+    fn find_glob(&self, pattern: &str) -> HashSet<Token> {
+        self.inner.find_glob(pattern)
+    }
+
+    fn find_suffix(&self, s: &str) -> HashSet<Token> {
+        self.inner.find_suffix(s)
+    }
+
+    fn find_range(&self, from: &str, to: &str) -> HashSet<Token> {
+        self.inner.find_range(from, to)
+    }
+
+    /// Returns the stats of the inner filter with nodes and bytes of the folded tree.
+    ///
+    fn stats(&self) -> Option<Stats> {
+        let mut stats = self.inner.stats()?;
+        let folded = self.folded.stats();
+        stats.nodes += folded.nodes;
+        stats.bytes += folded.bytes
+            + self
+                .folded
+                .find_prefix_values("")
+                .into_iter()
+                .map(|nums| nums.capacity() * std::mem::size_of::<Token>())
+                .sum::<usize>();
+        Some(stats)
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.inner.shrink_to_fit();
+        self.folded.shrink_to_fit();
+        for nums in self.folded.values_mut() {
+            nums.shrink_to_fit();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn on_find_prefix_case_insensitive_should_match_the_trie() {
+        let mut trie = Trie::new();
+        let mut f = Folded::new(Trie::new());
+        let words = [
+            "Error",
+            "error",
+            "ERRORS",
+            "Straße",
+            "STRASSE",
+            "ﬁle",
+            "FILE",
+            "Σίσυφος",
+            "warn",
+        ];
+        for (i, w) in words.iter().enumerate() {
+            trie.push(w, Token::from(i as u32));
+            f.push(w, Token::from(i as u32));
+        }
+
+        for prefix in [
+            "err",
+            "ERROR",
+            "errors",
+            "strass",
+            "straß",
+            "fi",
+            "σίσυφοσ",
+            "w",
+            "x",
+            "",
+        ] {
+            assert_eq!(
+                f.find_prefix_case_insensitive(prefix),
+                trie.find_prefix_case_insensitive(prefix),
+                "prefix {}",
+                prefix
+            );
+        }
+        assert_eq!(f.find_prefix("E"), trie.find_prefix("E"));
+    }
+
+    #[test]
+    fn on_remove_should_drop_the_folded_key_with_the_last_original_form() {
+        let mut f = Folded::new(Trie::new());
+        f.push("Error", Token::from(1));
+        f.push("error", Token::from(2));
+
+        assert!(!f.remove("ERROR", Token::from(1)));
+        assert!(f.remove("Error", Token::from(1)));
+        assert_eq!(
+            f.find_prefix_case_insensitive("ERR"),
+            HashSet::from([Token::from(2)])
+        );
+        assert!(f.remove("error", Token::from(2)));
+        assert!(f.find_prefix_case_insensitive("err").is_empty());
+        assert_eq!(f.folded.find_match("error"), None);
+
+        f.push("Warning", Token::from(3));
+        assert_eq!(f.folded.count_words(), 1);
+        assert_eq!(
+            f.find_prefix_case_insensitive("warn"),
+            HashSet::from([Token::from(3)])
+        );
+    }
+}
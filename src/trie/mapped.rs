@@ -0,0 +1,728 @@
+use super::{fold, Trie, Walk, ROOT};
+use crate::bloom::{glob, within_distance};
+use crate::dictionary::{Filter, Stats};
+use crate::token::Token;
+use memmap2::{Mmap, MmapMut};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Result, Write};
+use std::path::Path;
+
+const MAPPED_MAGIC: &[u8; 4] = b"SCRM";
+const MAPPED_VERSION: u8 = 1;
+const HEADER_SIZE: usize = 20;
+const NODE_SIZE: usize = 1 + Token::WIDTH + 8 + 4 + 4;
+const EDGE_SIZE: usize = 16;
+const HAS_NUM: u8 = 1;
+
+/// Mapped is a read only trie laid out flat in a file and memory mapped,
+/// so processes opening the same file share one copy of the graph in the page cache
+/// and a restarted process finds its words without pushing them again.
+///
+/// The file starts with `SCRM` magic, format version, token width, two reserved bytes
+/// and little endian u32 numbers of nodes, edges and label bytes. Then come nodes in breadth first order,
+/// every node being a flags byte, its token, its count and the range of its edges,
+/// then edges as the first char of the label, label start, label length and the child node,
+/// and at last the UTF-8 labels. Edges of a node are sorted by their first char and children go after their parents.
+///
+/// The file is never written by the filter, like the `Dawg` words pushed afterwards go to a small trie overlay,
+/// removed words are hidden and counts are kept aside, until a new file is saved.
+///
+#[derive(Debug)]
+pub struct Mapped {
+    map: Mmap,
+    nodes: u32,
+    edges: u32,
+    counts: HashMap<u32, u64>,
+    overlay: Trie,
+    removed: HashSet<Token>,
+}
+
+/// MappedNode is a node record read from the file.
+///
+#[derive(Debug, Clone, Copy)]
+struct MappedNode {
+    num: Option<Token>,
+    count: u64,
+    first: u32,
+    len: u32,
+}
+
+/// MappedEdge is an edge record read from the file.
+///
+#[derive(Debug, Clone, Copy)]
+struct MappedEdge {
+    first: u32,
+    start: u32,
+    len: u32,
+    node: u32,
+}
+
+#[inline(always)]
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    let mut b = [0; 4];
+    b.copy_from_slice(&bytes[at..at + 4]);
+    u32::from_le_bytes(b)
+}
+
+#[inline(always)]
+fn u64_at(bytes: &[u8], at: usize) -> u64 {
+    let mut b = [0; 8];
+    b.copy_from_slice(&bytes[at..at + 8]);
+    u64::from_le_bytes(b)
+}
+
+impl Mapped {
+    /// Saves the trie to a file to be opened with `open`.
+    ///
+    pub fn save(trie: &Trie, path: impl AsRef<Path>) -> Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        Self::write(trie, &mut w)?;
+        w.flush()
+    }
+
+    /// Writes the trie in the flat layout, numbering nodes in breadth first order,
+    /// so edges of every node are stored next to each other.
+    ///
+    pub fn write(trie: &Trie, w: &mut impl Write) -> Result<()> {
+        let mut order = vec![ROOT];
+        let mut index = vec![0; trie.nodes.len()];
+        let mut i = 0;
+        while let Some(node) = order.get(i) {
+            for edge in trie.nodes[*node as usize].edges.iter() {
+                index[edge.node as usize] = order.len() as u32;
+                order.push(edge.node);
+            }
+            i += 1;
+        }
+        let edges = order
+            .iter()
+            .map(|n| trie.nodes[*n as usize].edges.len())
+            .sum::<usize>();
+        let labels = order
+            .iter()
+            .flat_map(|n| trie.nodes[*n as usize].edges.iter())
+            .map(|e| e.len as usize)
+            .sum::<usize>();
+        if labels > u32::MAX as usize {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "labels do not fit a mapped trie",
+            ));
+        }
+        let utf8 = order
+            .iter()
+            .flat_map(|n| trie.nodes[*n as usize].edges.iter())
+            .all(|e| std::str::from_utf8(e.label(&trie.labels)).is_ok());
+        if !utf8 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "words that are not UTF-8 can not be mapped",
+            ));
+        }
+
+        w.write_all(MAPPED_MAGIC)?;
+        w.write_all(&[MAPPED_VERSION, Token::WIDTH as u8, 0, 0])?;
+        w.write_all(&(order.len() as u32).to_le_bytes())?;
+        w.write_all(&(edges as u32).to_le_bytes())?;
+        w.write_all(&(labels as u32).to_le_bytes())?;
+
+        let mut first = 0u32;
+        for node in order.iter() {
+            let node = &trie.nodes[*node as usize];
+            let num = node.num.unwrap_or_default();
+            w.write_all(&[if node.num.is_some() { HAS_NUM } else { 0 }])?;
+            w.write_all(&num.to_le_bytes())?;
+            w.write_all(&node.count.to_le_bytes())?;
+            w.write_all(&first.to_le_bytes())?;
+            w.write_all(&(node.edges.len() as u32).to_le_bytes())?;
+            first += node.edges.len() as u32;
+        }
+        let mut start = 0u32;
+        for node in order.iter() {
+            for edge in trie.nodes[*node as usize].edges.iter() {
+                w.write_all(&edge.first.to_le_bytes())?;
+                w.write_all(&start.to_le_bytes())?;
+                w.write_all(&edge.len.to_le_bytes())?;
+                w.write_all(&index[edge.node as usize].to_le_bytes())?;
+                start += edge.len;
+            }
+        }
+        for node in order.iter() {
+            for edge in trie.nodes[*node as usize].edges.iter() {
+                w.write_all(edge.label(&trie.labels))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Opens the file saved with `save`, checking every record once,
+    /// so queries can not run out of the file or in to a cycle.
+    ///
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: the file is opened read only and is expected to not be truncated while mapped,
+        // which is the contract of every file shared this way.
+        let map = unsafe { Mmap::map(&file)? };
+        Self::of_map(map)
+    }
+
+    /// Creates an empty mapped trie in anonymous memory, holding words pushed to it in the overlay
+    /// until they are saved to a file and the file is opened.
+    ///
+    pub fn new() -> Result<Self> {
+        let mut bytes = Vec::new();
+        Self::write(&Trie::new(), &mut bytes)?;
+        let mut map = MmapMut::map_anon(bytes.len())?;
+        map.copy_from_slice(&bytes);
+        Self::of_map(map.make_read_only()?)
+    }
+
+    fn of_map(map: Mmap) -> Result<Self> {
+        if map.len() < HEADER_SIZE || &map[..4] != MAPPED_MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "not a mapped trie file"));
+        }
+        if map[4] != MAPPED_VERSION || map[5] as usize != Token::WIDTH {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "unsupported mapped trie file version {} with token width {}",
+                    map[4], map[5]
+                ),
+            ));
+        }
+        let mapped = Self {
+            nodes: u32_at(&map, 8),
+            edges: u32_at(&map, 12),
+            map,
+            counts: HashMap::new(),
+            overlay: Trie::new(),
+            removed: HashSet::new(),
+        };
+        mapped.check()?;
+        Ok(mapped)
+    }
+
+    fn check(&self) -> Result<()> {
+        let labels = u32_at(&self.map, 16) as usize;
+        let size = HEADER_SIZE
+            + self.nodes as usize * NODE_SIZE
+            + self.edges as usize * EDGE_SIZE
+            + labels;
+        if self.nodes == 0 || self.map.len() != size {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "mapped trie file size does not match its header",
+            ));
+        }
+        let Ok(labels) = std::str::from_utf8(&self.map[self.map.len() - labels..]) else {
+            return Err(Error::new(ErrorKind::InvalidData, "labels are not UTF-8"));
+        };
+        for n in 0..self.nodes {
+            let node = self.node(n);
+            if node.first as u64 + node.len as u64 > self.edges as u64 {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "node edges are out of the file",
+                ));
+            }
+            let mut prev = None;
+            for e in node.first..node.first + node.len {
+                let edge = self.edge(e);
+                let end = edge.start as usize + edge.len as usize;
+                if edge.node <= n
+                    || edge.node >= self.nodes
+                    || edge.len == 0
+                    || end > labels.len()
+                    || !labels.is_char_boundary(edge.start as usize)
+                    || !labels.is_char_boundary(end)
+                    || labels[edge.start as usize..].chars().next().map(u32::from)
+                        != Some(edge.first)
+                    || prev.is_some_and(|p| p >= edge.first)
+                {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "edges are out of the file or not sorted",
+                    ));
+                }
+                prev = Some(edge.first);
+            }
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn node(&self, node: u32) -> MappedNode {
+        let at = HEADER_SIZE + node as usize * NODE_SIZE;
+        let flags = self.map[at];
+        let mut num = [0; Token::WIDTH];
+        num.copy_from_slice(&self.map[at + 1..at + 1 + Token::WIDTH]);
+        let at = at + 1 + Token::WIDTH;
+        MappedNode {
+            num: match flags & HAS_NUM {
+                0 => None,
+                _ => Some(Token::from_le_bytes(num)),
+            },
+            count: u64_at(&self.map, at),
+            first: u32_at(&self.map, at + 8),
+            len: u32_at(&self.map, at + 12),
+        }
+    }
+
+    #[inline(always)]
+    fn edge(&self, edge: u32) -> MappedEdge {
+        let at = HEADER_SIZE + self.nodes as usize * NODE_SIZE + edge as usize * EDGE_SIZE;
+        MappedEdge {
+            first: u32_at(&self.map, at),
+            start: u32_at(&self.map, at + 4),
+            len: u32_at(&self.map, at + 8),
+            node: u32_at(&self.map, at + 12),
+        }
+    }
+
+    #[inline(always)]
+    fn label(&self, edge: &MappedEdge) -> &str {
+        let at = HEADER_SIZE + self.nodes as usize * NODE_SIZE + self.edges as usize * EDGE_SIZE;
+        let start = at + edge.start as usize;
+        // Labels are checked to be UTF-8 split on char boundaries when the file is opened.
+        std::str::from_utf8(&self.map[start..start + edge.len as usize]).unwrap_or_default()
+    }
+
+    fn find_edge(&self, node: u32, c: char) -> Option<MappedEdge> {
+        let node = self.node(node);
+        let (mut lo, mut hi) = (node.first, node.first + node.len);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let edge = self.edge(mid);
+            match edge.first.cmp(&u32::from(c)) {
+                std::cmp::Ordering::Equal => return Some(edge),
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+            }
+        }
+        None
+    }
+
+    /// Walks the graph returning the node every word starting with the string is under
+    /// together with the path spelled to it, which goes past the string if it ends in the middle of a label.
+    ///
+    fn walk_path(&self, s: &str) -> Option<(u32, String)> {
+        let mut curr = ROOT;
+        let mut path = String::with_capacity(s.len());
+        let mut rest = s;
+        while let Some(c) = rest.chars().next() {
+            let edge = self.find_edge(curr, c)?;
+            let label = self.label(&edge);
+            match rest.strip_prefix(label) {
+                Some(r) => rest = r,
+                None if label.starts_with(rest) => rest = "",
+                None => return None,
+            }
+            path.push_str(label);
+            curr = edge.node;
+        }
+        Some((curr, path))
+    }
+
+    fn walk(&self, s: &str) -> Walk {
+        let mut curr = ROOT;
+        let mut rest = s;
+        while let Some(c) = rest.chars().next() {
+            let Some(edge) = self.find_edge(curr, c) else {
+                return Walk::Missing;
+            };
+            let label = self.label(&edge);
+            if let Some(r) = rest.strip_prefix(label) {
+                rest = r;
+                curr = edge.node;
+                continue;
+            }
+            if label.starts_with(rest) {
+                return Walk::Edge(edge.node);
+            }
+            return Walk::Missing;
+        }
+        Walk::Node(curr)
+    }
+
+    /// Find matching string in the file or the overlay returning it index num if found or None otherwise.
+    ///
+    pub fn find_match(&self, s: &str) -> Option<Token> {
+        if let Some(num) = self.overlay.find_match(s) {
+            return Some(num);
+        }
+        self.find_mapped(s)
+            .filter(|num| !self.removed.contains(num))
+    }
+
+    /// Returns the number of words held in the file and the overlay that are not removed.
+    ///
+    pub fn count_words(&self) -> usize {
+        let mapped = (0..self.nodes)
+            .filter_map(|n| self.node(n).num)
+            .filter(|num| !self.removed.contains(num))
+            .count();
+        mapped + self.overlay.count_words()
+    }
+
+    fn find_mapped(&self, s: &str) -> Option<Token> {
+        match self.walk(s) {
+            Walk::Node(node) => self.node(node).num,
+            _ => None,
+        }
+    }
+
+    /// Returns bytes of the mapped file together with an estimate of bytes allocated on the heap aside of it.
+    ///
+    pub fn heap_size(&self) -> usize {
+        self.map.len()
+            + self.counts.capacity() * std::mem::size_of::<(u32, u64)>()
+            + self.overlay.heap_size()
+            + self.removed.capacity() * std::mem::size_of::<Token>()
+    }
+
+    #[inline(always)]
+    fn count_of(&self, node: u32) -> u64 {
+        self.node(node).count + self.counts.get(&node).copied().unwrap_or_default()
+    }
+
+    /// Calls f with every word under the node that is not removed, its num and its node,
+    /// path holding the part of the word above the node.
+    ///
+    fn visit(&self, node: u32, path: &mut String, f: &mut impl FnMut(&str, Token, u32)) {
+        let n = self.node(node);
+        if let Some(num) = n.num {
+            if !self.removed.contains(&num) {
+                f(path, num, node);
+            }
+        }
+        for e in n.first..n.first + n.len {
+            let edge = self.edge(e);
+            let len = path.len();
+            path.push_str(self.label(&edge));
+            self.visit(edge.node, path, f);
+            path.truncate(len);
+        }
+    }
+
+    /// Appends nums of the node and its descendants, giving up with false once there are more than max.
+    ///
+    fn append_limited(&self, node: u32, nums: &mut HashSet<Token>, max: usize) -> bool {
+        let n = self.node(node);
+        if let Some(num) = n.num {
+            if !self.removed.contains(&num) {
+                if nums.len() >= max {
+                    return false;
+                }
+                nums.insert(num);
+            }
+        }
+        (n.first..n.first + n.len).all(|e| self.append_limited(self.edge(e).node, nums, max))
+    }
+
+    /// Visits every word of the file, collecting nums of words the predicate keeps.
+    ///
+    fn scan(&self, nums: &mut HashSet<Token>, keep: impl Fn(&str) -> bool) {
+        self.visit(ROOT, &mut String::new(), &mut |w, num, _| {
+            if keep(w) {
+                nums.insert(num);
+            }
+        });
+    }
+}
+
+impl Filter for Mapped {
+    /// Pushes the word to the overlay unless the file holds it under the same num,
+    /// hiding the word in the file if it had another token.
+    ///
+    fn push(&mut self, s: &str, num: Token) {
+        match self.find_mapped(s) {
+            Some(mapped) if mapped == num => {
+                self.removed.remove(&num);
+                return;
+            }
+            Some(mapped) => {
+                self.removed.insert(mapped);
+            }
+            None => (),
+        }
+        self.overlay.push(s, num);
+    }
+
+    /// Removes the word from the overlay or hides it in the file.
+    /// Returns false if the string is not held under that num.
+    ///
+    fn remove(&mut self, s: &str, num: Token) -> bool {
+        if self.overlay.remove(s, num) {
+            return true;
+        }
+        self.find_mapped(s) == Some(num) && self.removed.insert(num)
+    }
+
+    /// Adds occurrences to the count of the word in the overlay or to the count kept aside of the file.
+    ///
+    fn count(&mut self, s: &str, by: u64) {
+        if self.overlay.find_match(s).is_some() {
+            self.overlay.count(s, by);
+            return;
+        }
+        if let Walk::Node(node) = self.walk(s) {
+            if self.node(node).num.is_some() {
+                *self.counts.entry(node).or_insert(0) += by;
+            }
+        }
+    }
+
+    /// Finds up to k most frequent words with matching prefix.
+    /// The file keeps no highest counts of subtrees, so every word under the prefix is visited.
+    ///
+    fn find_prefix_top(&self, s: &str, k: usize) -> Vec<(Token, u64)> {
+        let mut top = self.overlay.find_prefix_top(s, k);
+        if let Walk::Node(node) | Walk::Edge(node) = self.walk(s) {
+            self.visit(node, &mut String::new(), &mut |_, num, node| {
+                top.push((num, self.count_of(node)))
+            });
+        }
+        top.sort_unstable_by_key(|(num, count)| (Reverse(*count), *num));
+        top.truncate(k);
+        top
+    }
+
+    fn find_prefix(&self, s: &str) -> HashSet<Token> {
+        let mut nums = self.overlay.find_prefix(s);
+        if let Walk::Node(node) | Walk::Edge(node) = self.walk(s) {
+            self.visit(node, &mut String::new(), &mut |_, num, _| {
+                nums.insert(num);
+            });
+        }
+        nums
+    }
+
+    /// Finds index nums with matching string prefix, stopping the walk once there are more than max.
+    ///
+    fn find_prefix_limited(&self, s: &str, max: usize) -> Option<HashSet<Token>> {
+        let mut nums = self.overlay.find_prefix_limited(s, max)?;
+        match self.walk(s) {
+            Walk::Node(node) | Walk::Edge(node) => {
+                self.append_limited(node, &mut nums, max).then_some(nums)
+            }
+            Walk::Missing => Some(nums),
+        }
+    }
+
+    /// Finds all words with matching prefix of the file and the overlay in lexicographic order.
+    ///
+    fn find_prefix_words(&self, s: &str) -> Vec<(String, Token)> {
+        let mut words = self.overlay.find_prefix_words(s);
+        if let Some((node, mut path)) = self.walk_path(s) {
+            self.visit(node, &mut path, &mut |w, num, _| {
+                words.push((w.to_string(), num))
+            });
+        }
+        words.sort_unstable();
+        words
+    }
+
+    fn find_prefix_case_insensitive(&self, s: &str) -> HashSet<Token> {
+        let folded: Vec<char> = s.chars().flat_map(fold).collect();
+        let mut nums = self.overlay.find_prefix_case_insensitive(s);
+        self.scan(&mut nums, |w| {
+            let mut chars = w.chars().flat_map(fold);
+            folded.iter().all(|c| chars.next() == Some(*c))
+        });
+        nums
+    }
+
+    fn find_fuzzy(&self, s: &str, distance: u8) -> HashSet<Token> {
+        let word: Vec<char> = s.chars().collect();
+        let mut nums = self.overlay.find_fuzzy(s, distance);
+        self.scan(&mut nums, |w| {
+            within_distance(&word, w, distance as usize, true)
+        });
+        nums
+    }
+
+    fn find_prefix_fuzzy(&self, s: &str, distance: u8) -> HashSet<Token> {
+        let word: Vec<char> = s.chars().collect();
+        let mut nums = self.overlay.find_prefix_fuzzy(s, distance);
+        self.scan(&mut nums, |w| {
+            within_distance(&word, w, distance as usize, false)
+        });
+        nums
+    }
+
+    fn find_glob(&self, pattern: &str) -> HashSet<Token> {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut nums = self.overlay.find_glob(pattern);
+        self.scan(&mut nums, |w| {
+            glob(&chars, &w.chars().collect::<Vec<char>>())
+        });
+        nums
+    }
+
+    fn find_suffix(&self, s: &str) -> HashSet<Token> {
+        let mut nums = self.overlay.find_suffix(s);
+        self.scan(&mut nums, |w| w.ends_with(s));
+        nums
+    }
+
+    /// Returns the shape of the mapped graph, bytes count the file and the heap aside of it.
+    ///
+    fn stats(&self) -> Option<Stats> {
+        let (mut max_depth, mut inner) = (0, 0);
+        let mut stack = vec![(ROOT, 0)];
+        while let Some((node, depth)) = stack.pop() {
+            let n = self.node(node);
+            max_depth = max_depth.max(depth);
+            if n.len > 0 {
+                inner += 1;
+            }
+            stack.extend((n.first..n.first + n.len).map(|e| (self.edge(e).node, depth + 1)));
+        }
+        Some(Stats {
+            nodes: self.nodes as usize,
+            max_depth,
+            branching: match inner {
+                0 => 0.0,
+                _ => self.edges as f64 / inner as f64,
+            },
+            bytes: self.heap_size(),
+        })
+    }
+
+    /// Shrinks the overlay, the file is shared and stays as it is.
+    ///
+    fn shrink_to_fit(&mut self) {
+        self.overlay.shrink_to_fit();
+        self.counts.shrink_to_fit();
+        self.removed.shrink_to_fit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("{}_{}.scrm", name, std::process::id()))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    fn trie_of(words: &[&str]) -> Trie {
+        let mut trie = Trie::new();
+        for (i, w) in words.iter().enumerate() {
+            trie.push(w, Token::from(i as u32));
+            trie.count(w, i as u64);
+        }
+        trie
+    }
+
+    #[test]
+    fn on_open_of_saved_trie_should_answer_queries_like_the_trie() {
+        let words = [
+            "aba",
+            "abacus",
+            "abac",
+            "ab",
+            "ole",
+            "oleum",
+            "Żółw",
+            "żółć",
+            "config.json",
+            "",
+        ];
+        let trie = trie_of(&words);
+        let path = path("mapped_queries");
+        Mapped::save(&trie, &path).unwrap();
+        let mapped = Mapped::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        for (i, w) in words.iter().enumerate() {
+            assert_eq!(mapped.find_match(w), Some(Token::from(i as u32)));
+        }
+        for w in ["a", "abacu", "olx", "żół"] {
+            assert_eq!(mapped.find_match(w), None);
+        }
+        for prefix in ["ab", "aba", "abacu", "o", "ż", "Ż", "x", ""] {
+            assert_eq!(mapped.find_prefix(prefix), trie.find_prefix(prefix));
+            assert_eq!(
+                mapped.find_prefix_words(prefix),
+                trie.find_prefix_words(prefix)
+            );
+            assert_eq!(
+                mapped.find_prefix_case_insensitive(prefix),
+                trie.find_prefix_case_insensitive(prefix)
+            );
+            assert_eq!(
+                mapped.find_prefix_limited(prefix, 2),
+                trie.find_prefix_limited(prefix, 2)
+            );
+            assert_eq!(
+                mapped.find_prefix_top(prefix, 3),
+                trie.find_prefix_top(prefix, 3)
+            );
+        }
+        assert_eq!(mapped.find_fuzzy("olum", 1), trie.find_fuzzy("olum", 1));
+        assert_eq!(
+            mapped.find_prefix_fuzzy("abk", 1),
+            trie.find_prefix_fuzzy("abk", 1)
+        );
+        assert_eq!(mapped.find_glob("a*c?s"), trie.find_glob("a*c?s"));
+        assert_eq!(mapped.find_suffix(".json"), trie.find_suffix(".json"));
+        assert_eq!(
+            mapped.stats().map(|s| (s.nodes, s.max_depth)),
+            Some((trie.stats().nodes, trie.stats().max_depth))
+        );
+    }
+
+    #[test]
+    fn on_push_and_remove_should_change_the_overlay_and_leave_the_file() {
+        let trie = trie_of(&["error", "errors", "warn"]);
+        let path = path("mapped_overlay");
+        Mapped::save(&trie, &path).unwrap();
+        let mut mapped = Mapped::open(&path).unwrap();
+
+        mapped.push("error", Token::from(0));
+        assert_eq!(mapped.overlay.find_match("error"), None);
+        mapped.push("erratic", Token::from(3));
+        assert!(mapped.remove("errors", Token::from(1)));
+        assert!(!mapped.remove("errors", Token::from(1)));
+        assert!(!mapped.remove("warn", Token::from(0)));
+        mapped.count("warn", 7);
+        assert_eq!(
+            mapped.find_prefix("err"),
+            HashSet::from_iter([0, 3].map(Token::from))
+        );
+        assert_eq!(mapped.find_prefix_top("", 1), vec![(Token::from(2), 9)]);
+
+        mapped.push("warn", Token::from(4));
+        assert_eq!(mapped.find_match("warn"), Some(Token::from(4)));
+        assert!(!mapped.find_prefix("w").contains(&Token::from(2)));
+
+        let reopened = Mapped::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(reopened.find_match("errors"), Some(Token::from(1)));
+        assert_eq!(reopened.find_match("erratic"), None);
+    }
+
+    #[test]
+    fn on_open_of_broken_file_should_fail() {
+        let path = path("mapped_broken");
+        Mapped::save(&trie_of(&["abc", "abd"]), &path).unwrap();
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes.pop();
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(Mapped::open(&path).is_err());
+
+        Mapped::save(&trie_of(&["abc", "abd"]), &path).unwrap();
+        let mut bytes = std::fs::read(&path).unwrap();
+        let at = HEADER_SIZE + u32_at(&bytes, 8) as usize * NODE_SIZE + 12;
+        bytes[at..at + 4].copy_from_slice(&0u32.to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(Mapped::open(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,123 +1,1143 @@
-use crate::dictionary::Filter;
-use std::collections::{HashMap, HashSet};
+use crate::dictionary::{Filter, Stats};
+use crate::token::Token;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Result, Write};
+use std::path::Path;
 
-/// Node is a part of tries graph.
-/// First node is a root of the tree and Contains None number and \0 char.
+mod bitrie;
+mod concurrent;
+mod folded;
+mod mapped;
+
+pub use bitrie::BiTrie;
+pub use concurrent::{Concurrent, Reader};
+pub use folded::Folded;
+pub use mapped::Mapped;
+
+const FILE_MAGIC: &[u8; 4] = b"SCRT";
+const FILE_VERSION: u8 = 1;
+const HAS_NUM: u8 = 1;
+const ROOT: u32 = 0;
+
+/// Trie is a radix tree graph.
+/// Edges are labeled with whole runs of characters, so chains of nodes with a single child
+/// are compressed in to one edge and only words and branching points get a node.
+/// Nodes live in an arena and point to children by u32 index, labels are slices of one shared
+/// byte buffer, so the graph takes a few large allocations instead of a couple per node.
+/// Words are bytes, labels are split on units which are UTF-8 chars or single bytes not starting one,
+/// so binary words are kept as they are while UTF-8 words split on chars for queries made of chars.
+/// First node is a root of the tree and contains None number.
 /// Root node task is to be the entry point in to the graph.
+/// Every word may carry a payload of type V next to its num, like the time it was last seen,
+/// the default unit payload takes no memory.
 ///
 #[derive(Debug, Clone)]
-pub struct Node {
-    num: Option<u32>,
-    nodes: HashMap<char, Box<Node>>,
+pub struct Trie<V = ()> {
+    nodes: Vec<Node<V>>,
+    labels: Vec<u8>,
+    free: Vec<u32>,
+}
+
+/// Node is a part of the graph, it holds a number and a payload if a word ends in it together with the count of the word
+/// and the highest count of a word in the graph below, so the most frequent words are found without a full walk.
+///
+#[derive(Debug, Clone, Default)]
+struct Node<V> {
+    num: Option<Token>,
+    value: V,
+    count: u64,
+    best: u64,
+    edges: Vec<Edge>,
+}
+
+/// Edge leads to a child node, edges of a node are sorted by the first unit of the label
+/// and no two of them start with the same unit.
+/// The first unit is kept next to the label, so searching edges does not touch labels.
+///
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+    first: u32,
+    start: u32,
+    len: u32,
+    node: u32,
+}
+
+impl Edge {
+    fn new(labels: &[u8], start: usize, len: usize, node: u32) -> Self {
+        Self {
+            first: unit(&labels[start..]).map(|(u, _)| u).unwrap_or_default(),
+            start: start as u32,
+            len: len as u32,
+            node,
+        }
+    }
+
+    #[inline(always)]
+    fn label<'a>(&self, labels: &'a [u8]) -> &'a [u8] {
+        &labels[self.start as usize..(self.start + self.len) as usize]
+    }
+}
+
+/// Candidate is an entry of the best first search for the most frequent words, ordered by count.
+/// A word goes before a node of the same count, since nothing below the node counts more,
+/// so words of the same count are found in no particular order.
+///
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Candidate(u64, Kind);
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    Node(u32),
+    Word(Reverse<Token>),
+}
+
+/// Walk is the place where a string ends in the graph.
+///
+enum Walk {
+    /// String ends exactly at the node.
+    Node(u32),
+    /// String ends in the middle of the label of the edge leading to the node.
+    Edge(u32),
+    /// String leaves the graph.
+    Missing,
+}
+
+/// Words iterates over words of the graph with their index nums in lexicographic order.
+/// Edges of a node are sorted by their first unit, so a depth first walk
+/// yields a word before all the words it is a prefix of. Bytes of words that are not UTF-8 are replaced
+/// with U+FFFD.
+///
+pub struct Words<'a, V = ()> {
+    trie: &'a Trie<V>,
+    path: Vec<u8>,
+    stack: Vec<Frame>,
+    root: Option<Token>,
+}
+
+/// Frame is a node on the path of the walk with the next edge to follow and the length of the path at the node.
+///
+struct Frame {
+    node: u32,
+    edge: usize,
+    len: usize,
 }
 
-impl Node {
-    /// Crates a new root node that is an entrance to the graph.
+/// Loading is a node read from a file with the count of its edges, the edges read so far
+/// and the label of the edge to the child being read.
+///
+struct Loading {
+    node: u32,
+    count: usize,
+    edges: Vec<Edge>,
+    start: usize,
+    len: usize,
+}
+
+impl<V> Iterator for Words<'_, V> {
+    type Item = (String, Token);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(num) = self.root.take() {
+            return Some((String::from_utf8_lossy(&self.path).into_owned(), num));
+        }
+        while let Some(frame) = self.stack.last_mut() {
+            let Some(edge) = self.trie.nodes[frame.node as usize].edges.get(frame.edge) else {
+                self.stack.pop();
+                continue;
+            };
+            frame.edge += 1;
+            self.path.truncate(frame.len);
+            self.path.extend_from_slice(edge.label(&self.trie.labels));
+            self.stack.push(Frame {
+                node: edge.node,
+                edge: 0,
+                len: self.path.len(),
+            });
+            if let Some(num) = self.trie.nodes[edge.node as usize].num {
+                return Some((String::from_utf8_lossy(&self.path).into_owned(), num));
+            }
+        }
+        None
+    }
+}
+
+impl<V: Default> Default for Trie<V> {
+    fn default() -> Self {
+        Self {
+            nodes: vec![Node::default()],
+            labels: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+}
+
+impl Trie {
+    /// Crates a new graph holding only the root node, with no payload of words.
+    /// A graph with payloads is created with `default`.
     ///
     pub fn new() -> Self {
-        Self {
-            num: None,
-            nodes: HashMap::new(),
+        Self::default()
+    }
+
+    /// Loads the graph saved with `save`.
+    ///
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::read_from(&mut BufReader::new(File::open(path)?))
+    }
+
+    /// Reads the graph written with `write_to`, payloads of words are not saved.
+    ///
+    pub fn read_from(r: &mut impl Read) -> Result<Self> {
+        let mut header = [0; 6];
+        r.read_exact(&mut header)?;
+        if &header[..4] != FILE_MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "not a trie file"));
+        }
+        if header[4] != FILE_VERSION || header[5] as usize != Token::WIDTH {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "unsupported trie file version {} with token width {}",
+                    header[4], header[5]
+                ),
+            ));
         }
+        let mut trie = Self {
+            nodes: Vec::new(),
+            labels: Vec::new(),
+            free: Vec::new(),
+        };
+        trie.read_node(r)?;
+        Ok(trie)
     }
+}
 
+impl<V: Default + Clone> Trie<V> {
     /// Find matching string in the trie graph returning it index num if found or None otherwise.
     ///
-    pub fn find_match(&self, s: &str) -> Option<u32> {
-        let mut curr = self;
-        for c in s.chars() {
-            curr = curr.nodes.get(&c)?;
+    pub fn find_match(&self, s: &str) -> Option<Token> {
+        self.find_match_bytes(s.as_bytes())
+    }
+
+    /// Find matching bytes in the trie graph returning it index num if found or None otherwise.
+    ///
+    pub fn find_match_bytes(&self, s: &[u8]) -> Option<Token> {
+        match self.walk(s) {
+            Walk::Node(node) => self.nodes[node as usize].num,
+            _ => None,
+        }
+    }
+
+    /// Returns payloads of all words with matching string prefix, in depth first order of the graph.
+    ///
+    pub fn find_prefix_values(&self, s: &str) -> Vec<&V> {
+        let mut values = Vec::new();
+        let mut stack = match self.walk(s.as_bytes()) {
+            Walk::Node(node) | Walk::Edge(node) => vec![node],
+            Walk::Missing => return values,
+        };
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node as usize];
+            if node.num.is_some() {
+                values.push(&node.value);
+            }
+            stack.extend(node.edges.iter().rev().map(|edge| edge.node));
+        }
+        values
+    }
+
+    /// Returns payloads of all words of the graph to be changed in place, in no particular order.
+    ///
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        self.nodes
+            .iter_mut()
+            .filter(|node| node.num.is_some())
+            .map(|node| &mut node.value)
+    }
+
+    /// Returns the payload of the word to be changed in place, or None if the word is not in the trie graph.
+    ///
+    pub fn value_mut(&mut self, s: &str) -> Option<&mut V> {
+        match self.walk(s.as_bytes()) {
+            Walk::Node(node) => {
+                let node = &mut self.nodes[node as usize];
+                node.num.map(|_| &mut node.value)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the count of the string in the trie graph, zero if it is not found.
+    ///
+    pub fn find_count(&self, s: &str) -> u64 {
+        match self.walk(s.as_bytes()) {
+            Walk::Node(node) => self.nodes[node as usize].count,
+            _ => 0,
+        }
+    }
+
+    /// Returns the number of words of the graph.
+    ///
+    pub fn count_words(&self) -> usize {
+        self.nodes.iter().filter(|n| n.num.is_some()).count()
+    }
+
+    /// Returns an iterator over all words of the graph with their index nums in lexicographic order.
+    ///
+    pub fn iter_words(&self) -> Words<'_, V> {
+        self.words_below(ROOT, Vec::new())
+    }
+
+    /// Returns an iterator over words of the node and its descendants, path being the word spelled to the node.
+    ///
+    fn words_below(&self, node: u32, path: Vec<u8>) -> Words<'_, V> {
+        Words {
+            trie: self,
+            stack: vec![Frame {
+                node,
+                edge: 0,
+                len: path.len(),
+            }],
+            path,
+            root: self.nodes[node as usize].num,
+        }
+    }
+
+    /// Saves the graph to a file in a compact binary layout.
+    ///
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write_to(&mut w)?;
+        w.flush()
+    }
+
+    /// Writes the graph starting with `SCRT` magic, format version and token width,
+    /// followed by nodes in depth first order. Every node is a flags byte,
+    /// its little endian token if it holds a word, little endian u32 number of edges
+    /// and then every edge as little endian u32 label length, label bytes and the child node.
+    ///
+    pub fn write_to(&self, w: &mut impl Write) -> Result<()> {
+        w.write_all(FILE_MAGIC)?;
+        w.write_all(&[FILE_VERSION, Token::WIDTH as u8])?;
+        self.write_node(ROOT, w)
+    }
+
+    fn write_node(&self, node: u32, w: &mut impl Write) -> Result<()> {
+        self.write_header(node, w)?;
+        let mut stack = vec![(node, 0)];
+        while let Some((node, next)) = stack.last_mut() {
+            let Some(edge) = self.nodes[*node as usize].edges.get(*next) else {
+                stack.pop();
+                continue;
+            };
+            *next += 1;
+            w.write_all(&edge.len.to_le_bytes())?;
+            w.write_all(edge.label(&self.labels))?;
+            self.write_header(edge.node, w)?;
+            stack.push((edge.node, 0));
+        }
+        Ok(())
+    }
+
+    fn write_header(&self, node: u32, w: &mut impl Write) -> Result<()> {
+        let node = &self.nodes[node as usize];
+        match node.num {
+            Some(num) => {
+                w.write_all(&[HAS_NUM])?;
+                w.write_all(&num.to_le_bytes())?;
+            }
+            None => w.write_all(&[0])?,
+        }
+        w.write_all(&(node.edges.len() as u32).to_le_bytes())
+    }
+
+    fn read_node(&mut self, r: &mut impl Read) -> Result<u32> {
+        let root = self.read_header(r)?;
+        let node = root.node;
+        let mut stack = vec![root];
+        let mut len = [0; 4];
+        while let Some(loading) = stack.last_mut() {
+            if loading.edges.len() == loading.count {
+                let (child, edges) = (loading.node, std::mem::take(&mut loading.edges));
+                stack.pop();
+                self.nodes[child as usize].edges = edges;
+                let Some(parent) = stack.last_mut() else {
+                    break;
+                };
+                let edge = Edge::new(&self.labels, parent.start, parent.len, child);
+                if parent.edges.last().is_some_and(|e| e.first >= edge.first) {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "edges are empty or not sorted",
+                    ));
+                }
+                parent.edges.push(edge);
+                continue;
+            }
+            r.read_exact(&mut len)?;
+            let mut label = Vec::new();
+            r.take(u32::from_le_bytes(len) as u64)
+                .read_to_end(&mut label)?;
+            if label.len() != u32::from_le_bytes(len) as usize {
+                return Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "trie file is truncated",
+                ));
+            }
+            if label.is_empty() {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "edges are empty or not sorted",
+                ));
+            }
+            loading.start = self.labels.len();
+            loading.len = label.len();
+            self.labels.extend_from_slice(&label);
+            let child = self.read_header(r)?;
+            stack.push(child);
+        }
+        Ok(node)
+    }
+
+    fn read_header(&mut self, r: &mut impl Read) -> Result<Loading> {
+        let mut flags = [0; 1];
+        r.read_exact(&mut flags)?;
+        let num = match flags[0] & HAS_NUM {
+            0 => None,
+            _ => {
+                let mut num = [0; Token::WIDTH];
+                r.read_exact(&mut num)?;
+                Some(Token::from_le_bytes(num))
+            }
+        };
+        let node = self.alloc(num);
+        let mut len = [0; 4];
+        r.read_exact(&mut len)?;
+        let count = u32::from_le_bytes(len) as usize;
+        Ok(Loading {
+            node,
+            count,
+            edges: Vec::with_capacity(count.min(u8::MAX as usize)),
+            start: 0,
+            len: 0,
+        })
+    }
+
+    /// Returns an estimate of bytes allocated on the heap by the graph.
+    ///
+    pub fn heap_size(&self) -> usize {
+        self.nodes.capacity() * std::mem::size_of::<Node<V>>()
+            + self
+                .nodes
+                .iter()
+                .map(|n| n.edges.capacity() * std::mem::size_of::<Edge>())
+                .sum::<usize>()
+            + self.labels.capacity()
+            + self.free.capacity() * std::mem::size_of::<u32>()
+    }
+
+    /// Returns the number of nodes reachable from the root, the depth of the deepest of them,
+    /// the average branching factor of nodes with children and the estimated bytes on the heap.
+    ///
+    pub fn stats(&self) -> Stats {
+        let (mut nodes, mut max_depth, mut edges, mut inner) = (0, 0, 0, 0);
+        let mut stack = vec![(ROOT, 0)];
+        while let Some((node, depth)) = stack.pop() {
+            let node = &self.nodes[node as usize];
+            nodes += 1;
+            max_depth = max_depth.max(depth);
+            if !node.edges.is_empty() {
+                inner += 1;
+                edges += node.edges.len();
+            }
+            stack.extend(node.edges.iter().map(|e| (e.node, depth + 1)));
+        }
+        Stats {
+            nodes,
+            max_depth,
+            branching: match inner {
+                0 => 0.0,
+                _ => edges as f64 / inner as f64,
+            },
+            bytes: self.heap_size(),
+        }
+    }
+
+    /// Takes a node from the arena, reusing a slot of a removed node if there is one.
+    ///
+    fn alloc(&mut self, num: Option<Token>) -> u32 {
+        if let Some(node) = self.free.pop() {
+            self.nodes[node as usize].num = num;
+            return node;
+        }
+        self.nodes.push(Node {
+            num,
+            ..Default::default()
+        });
+        (self.nodes.len() - 1) as u32
+    }
+
+    /// Returns the node to the arena to be reused.
+    ///
+    fn release(&mut self, node: u32) {
+        self.nodes[node as usize] = Node::default();
+        self.free.push(node);
+    }
+
+    /// Appends the label to the shared buffer, returning the edge leading to the node.
+    ///
+    fn new_edge(&mut self, label: &[u8], node: u32) -> Edge {
+        let start = self.labels.len();
+        self.labels.extend_from_slice(label);
+        Edge::new(&self.labels, start, label.len(), node)
+    }
+
+    #[inline(always)]
+    fn edge(&self, node: u32, c: u32) -> std::result::Result<usize, usize> {
+        self.nodes[node as usize]
+            .edges
+            .binary_search_by(|e| e.first.cmp(&c))
+    }
+
+    /// Walks the graph like `walk`, returning the node every word starting with the string is under
+    /// together with the path spelled to it, which goes past the string if it ends in the middle of a label.
+    ///
+    fn walk_path(&self, s: &[u8]) -> Option<(u32, Vec<u8>)> {
+        let mut curr = ROOT;
+        let mut path = Vec::with_capacity(s.len());
+        let mut rest = s;
+        while let Some((c, _)) = unit(rest) {
+            let edge = &self.nodes[curr as usize].edges[self.edge(curr, c).ok()?];
+            let label = edge.label(&self.labels);
+            match rest.strip_prefix(label) {
+                Some(r) => rest = r,
+                None if label.starts_with(rest) => rest = &[],
+                None => return None,
+            }
+            path.extend_from_slice(label);
+            curr = edge.node;
+        }
+        Some((curr, path))
+    }
+
+    fn walk(&self, s: &[u8]) -> Walk {
+        let mut curr = ROOT;
+        let mut rest = s;
+        while let Some((c, _)) = unit(rest) {
+            let Ok(i) = self.edge(curr, c) else {
+                return Walk::Missing;
+            };
+            let edge = &self.nodes[curr as usize].edges[i];
+            let label = edge.label(&self.labels);
+            if let Some(r) = rest.strip_prefix(label) {
+                rest = r;
+                curr = edge.node;
+                continue;
+            }
+            if label.starts_with(rest) {
+                return Walk::Edge(edge.node);
+            }
+            return Walk::Missing;
+        }
+        Walk::Node(curr)
+    }
+
+    /// Walks the graph simulating a Levenshtein automaton of the word,
+    /// where row holds edit distances of the path so far to every prefix of the word.
+    /// Branches are cut once no prefix is within the distance anymore.
+    ///
+    fn walk_fuzzy(
+        &self,
+        node: u32,
+        word: &[char],
+        row: &[usize],
+        distance: usize,
+        nums: &mut HashSet<Token>,
+    ) {
+        'edges: for edge in self.nodes[node as usize].edges.iter() {
+            let mut row = row.to_vec();
+            for c in chars(edge.label(&self.labels)) {
+                let mut curr = Vec::with_capacity(row.len());
+                curr.push(row[0] + 1);
+                for i in 1..row.len() {
+                    let substitution = row[i - 1] + usize::from(word[i - 1] != c);
+                    curr.push((curr[i - 1] + 1).min(row[i] + 1).min(substitution));
+                }
+                if curr.iter().min().is_none_or(|d| *d > distance) {
+                    continue 'edges;
+                }
+                row = curr;
+            }
+            if row[word.len()] <= distance {
+                if let Some(num) = self.nodes[edge.node as usize].num {
+                    nums.insert(num);
+                }
+            }
+            self.walk_fuzzy(edge.node, word, &row, distance, nums);
+        }
+    }
+
+    /// Walks the graph simulating a Levenshtein automaton of the word like `walk_fuzzy`,
+    /// but once the path is within the distance of the whole word every word below it matches,
+    /// so the subtree is collected without walking the automaton any further.
+    ///
+    fn walk_prefix_fuzzy(
+        &self,
+        node: u32,
+        word: &[char],
+        row: &[usize],
+        distance: usize,
+        nums: &mut HashSet<Token>,
+    ) {
+        'edges: for edge in self.nodes[node as usize].edges.iter() {
+            let mut row = row.to_vec();
+            for c in chars(edge.label(&self.labels)) {
+                let mut curr = Vec::with_capacity(row.len());
+                curr.push(row[0] + 1);
+                for i in 1..row.len() {
+                    let substitution = row[i - 1] + usize::from(word[i - 1] != c);
+                    curr.push((curr[i - 1] + 1).min(row[i] + 1).min(substitution));
+                }
+                if curr[word.len()] <= distance {
+                    self.append_node(edge.node, nums);
+                    continue 'edges;
+                }
+                if curr.iter().min().is_none_or(|d| *d > distance) {
+                    continue 'edges;
+                }
+                row = curr;
+            }
+            self.walk_prefix_fuzzy(edge.node, word, &row, distance, nums);
+        }
+    }
+
+    /// Collects nums of words whose case folded form starts with the folded chars.
+    /// A label char may fold in to more than one char, so the folded query is matched
+    /// against the folded label and may end in the middle of a folded char.
+    ///
+    fn walk_case_insensitive(&self, node: u32, folded: &[char], nums: &mut HashSet<Token>) {
+        if folded.is_empty() {
+            self.append_node(node, nums);
+            return;
+        }
+        'edges: for edge in self.nodes[node as usize].edges.iter() {
+            let mut rest = folded;
+            for c in chars(edge.label(&self.labels)).flat_map(fold) {
+                let Some((first, r)) = rest.split_first() else {
+                    self.append_node(edge.node, nums);
+                    continue 'edges;
+                };
+                if c != *first {
+                    continue 'edges;
+                }
+                rest = r;
+            }
+            self.walk_case_insensitive(edge.node, rest, nums);
+        }
+    }
+
+    /// Collects nums of words matching the glob pattern from this node on,
+    /// where `*` matches any run of characters and `?` matches a single character.
+    ///
+    fn walk_glob(&self, node: u32, pattern: &[char], nums: &mut HashSet<Token>) {
+        let edges = &self.nodes[node as usize].edges;
+        match pattern.first() {
+            None => {
+                if let Some(num) = self.nodes[node as usize].num {
+                    nums.insert(num);
+                }
+            }
+            Some('*') => {
+                self.walk_glob(node, &pattern[1..], nums);
+                for edge in edges.iter() {
+                    let label: Vec<char> = chars(edge.label(&self.labels)).collect();
+                    self.glob_edge(edge.node, &label, pattern, nums);
+                }
+            }
+            // Bytes not starting a char are seen as U+FFFD, they are not found by the unit of the char.
+            Some('?' | '\u{FFFD}') => {
+                for edge in edges.iter() {
+                    let label: Vec<char> = chars(edge.label(&self.labels)).collect();
+                    self.glob_edge(edge.node, &label, pattern, nums);
+                }
+            }
+            Some(c) => {
+                if let Ok(i) = self.edge(node, u32::from(*c)) {
+                    let label: Vec<char> = chars(edges[i].label(&self.labels)).collect();
+                    self.glob_edge(edges[i].node, &label, pattern, nums);
+                }
+            }
         }
-        curr.num
     }
 
-    fn append_inner(&self, nums: &mut HashSet<u32>) {
-        for (_, next) in self.nodes.iter() {
-            if let Some(num) = next.num {
+    /// Matches the rest of the label against the glob pattern, continuing in to the node once the label is consumed.
+    ///
+    fn glob_edge(&self, node: u32, label: &[char], pattern: &[char], nums: &mut HashSet<Token>) {
+        let Some((c, rest)) = label.split_first() else {
+            self.walk_glob(node, pattern, nums);
+            return;
+        };
+        match pattern.first() {
+            None => (),
+            Some('*') => {
+                self.glob_edge(node, label, &pattern[1..], nums);
+                self.glob_edge(node, rest, pattern, nums);
+            }
+            Some('?') => self.glob_edge(node, rest, &pattern[1..], nums),
+            Some(p) if p == c => self.glob_edge(node, rest, &pattern[1..], nums),
+            Some(_) => (),
+        }
+    }
+
+    /// Collects nums of all words ending with the suffix, rebuilding each word on the path.
+    ///
+    fn walk_suffix(&self, node: u32, path: &mut Vec<u8>, suffix: &[u8], nums: &mut HashSet<Token>) {
+        let node = &self.nodes[node as usize];
+        if let Some(num) = node.num {
+            if path.ends_with(suffix) {
                 nums.insert(num);
             }
-            next.append_inner(nums);
+        }
+        for edge in node.edges.iter() {
+            let len = path.len();
+            path.extend_from_slice(edge.label(&self.labels));
+            self.walk_suffix(edge.node, path, suffix, nums);
+            path.truncate(len);
+        }
+    }
+
+    /// Removes the word from the graph if it holds the num.
+    /// Edges left with an empty node are pruned and a node left with no num and a single edge
+    /// is merged with it, so the graph stays compressed as if the word was never pushed.
+    /// Pruned nodes go back to the arena, a merged label is appended to the shared buffer
+    /// unless both parts lie next to each other, the bytes it leaves behind are dropped by `shrink_to_fit`.
+    ///
+    fn remove_inner(&mut self, node: u32, s: &[u8], num: Token) -> bool {
+        let Some((c, _)) = unit(s) else {
+            if self.nodes[node as usize].num != Some(num) {
+                return false;
+            }
+            self.nodes[node as usize].num = None;
+            self.nodes[node as usize].value = V::default();
+            self.nodes[node as usize].count = 0;
+            self.update_best(node);
+            return true;
+        };
+        let Ok(i) = self.edge(node, c) else {
+            return false;
+        };
+        let edge = self.nodes[node as usize].edges[i];
+        let Some(rest) = s.strip_prefix(edge.label(&self.labels)) else {
+            return false;
+        };
+        if !self.remove_inner(edge.node, rest, num) {
+            return false;
+        }
+        let child = &self.nodes[edge.node as usize];
+        if child.num.is_none() {
+            match child.edges[..] {
+                [] => {
+                    self.nodes[node as usize].edges.remove(i);
+                    self.release(edge.node);
+                }
+                [tail] => {
+                    let merged = if edge.start + edge.len == tail.start {
+                        Edge {
+                            len: edge.len + tail.len,
+                            node: tail.node,
+                            ..edge
+                        }
+                    } else {
+                        let label = [edge.label(&self.labels), tail.label(&self.labels)].concat();
+                        self.new_edge(&label, tail.node)
+                    };
+                    self.nodes[node as usize].edges[i] = merged;
+                    self.release(edge.node);
+                }
+                _ => (),
+            }
+        }
+        self.update_best(node);
+        true
+    }
+
+    /// Sets the highest count below the node from its own count and the highest counts of its children.
+    ///
+    fn update_best(&mut self, node: u32) {
+        let n = &self.nodes[node as usize];
+        let best = n
+            .edges
+            .iter()
+            .map(|e| self.nodes[e.node as usize].best)
+            .fold(n.count, u64::max);
+        self.nodes[node as usize].best = best;
+    }
+
+    /// Copies the graph in to a new arena in depth first order,
+    /// leaving out removed nodes and labels no edge points to.
+    ///
+    fn compacted(&self) -> Self {
+        let mut trie = Self {
+            nodes: Vec::with_capacity(self.nodes.len() - self.free.len()),
+            labels: Vec::with_capacity(self.labels.len()),
+            free: Vec::new(),
+        };
+        trie.copy_node(self, ROOT);
+        trie
+    }
+
+    fn copy_node(&mut self, from: &Self, node: u32) -> u32 {
+        let src = &from.nodes[node as usize];
+        let copy = self.alloc(src.num);
+        self.nodes[copy as usize].value = src.value.clone();
+        self.nodes[copy as usize].count = src.count;
+        self.nodes[copy as usize].best = src.best;
+        let mut edges = Vec::with_capacity(src.edges.len());
+        for edge in src.edges.iter() {
+            let start = self.labels.len();
+            self.labels.extend_from_slice(edge.label(&from.labels));
+            let child = self.copy_node(from, edge.node);
+            edges.push(Edge {
+                start: start as u32,
+                node: child,
+                ..*edge
+            });
+        }
+        self.nodes[copy as usize].edges = edges;
+        copy
+    }
+
+    fn append_node(&self, node: u32, nums: &mut impl Extend<Token>) {
+        if let Some(num) = self.nodes[node as usize].num {
+            nums.extend([num]);
+        }
+        self.append_inner(node, nums);
+    }
+
+    /// Appends nums of the node and its descendants, giving up with false once there are more than max.
+    ///
+    fn append_limited(&self, node: u32, nums: &mut HashSet<Token>, max: usize) -> bool {
+        let node = &self.nodes[node as usize];
+        if let Some(num) = node.num {
+            if nums.len() >= max {
+                return false;
+            }
+            nums.insert(num);
+        }
+        node.edges
+            .iter()
+            .all(|edge| self.append_limited(edge.node, nums, max))
+    }
+
+    fn append_inner(&self, node: u32, nums: &mut impl Extend<Token>) {
+        for edge in self.nodes[node as usize].edges.iter() {
+            self.append_node(edge.node, nums);
         }
     }
 }
 
-impl Filter for Node {
-    /// Push string in to the trie graph giving it a num index.
+/// Returns the full case folding of the char, approximated by a lowercase, uppercase, lowercase round,
+/// so `ß` and `ẞ` fold to `ss`, `ς` and `Σ` fold to `σ` and ligatures like `ﬁ` fold to `fi`.
+///
+#[inline(always)]
+pub(crate) fn fold(c: char) -> impl Iterator<Item = char> {
+    c.to_lowercase()
+        .flat_map(char::to_uppercase)
+        .flat_map(char::to_lowercase)
+}
+
+/// Returns the number the first unit of the bytes is known by, with its length in bytes.
+/// A unit is a UTF-8 char or a single byte not starting one, chars are numbered by their scalar value
+/// and bytes past all chars, so UTF-8 words are split and ordered by chars.
+///
+#[inline(always)]
+fn unit(bytes: &[u8]) -> Option<(u32, usize)> {
+    let first = *bytes.first()?;
+    let width = match first {
+        0x00..=0x7F => return Some((u32::from(first), 1)),
+        0xC2..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF4 => 4,
+        _ => 0,
+    };
+    match bytes
+        .get(..width)
+        .and_then(|b| std::str::from_utf8(b).ok())
+        .and_then(|s| s.chars().next())
+    {
+        Some(c) => Some((u32::from(c), width)),
+        None => Some((char::MAX as u32 + 1 + u32::from(first), 1)),
+    }
+}
+
+/// Returns chars of the label, with U+FFFD for every byte that does not start a char.
+///
+#[inline(always)]
+fn chars(label: &[u8]) -> impl Iterator<Item = char> + '_ {
+    let mut rest = label;
+    std::iter::from_fn(move || {
+        let (u, len) = unit(rest)?;
+        rest = &rest[len..];
+        Some(char::from_u32(u).unwrap_or(char::REPLACEMENT_CHARACTER))
+    })
+}
+
+/// Returns the length in bytes of the common prefix of both byte strings, made of whole units.
+///
+#[inline(always)]
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    let mut len = 0;
+    while let (Some(x), Some(y)) = (unit(&a[len..]), unit(&b[len..])) {
+        if x != y {
+            break;
+        }
+        len += x.1;
+    }
+    len
+}
+
+impl<V: Default + Clone + Send + Sync> Filter for Trie<V> {
+    fn push(&mut self, s: &str, num: Token) {
+        self.push_bytes(s.as_bytes(), num);
+    }
+
+    /// Push bytes in to the trie graph giving them a num index.
     /// Num index shall be unique and it is not the case of trie to validate it uniqueness.
+    /// An edge sharing only a part of its label with the bytes is split at the end of the common part,
+    /// both parts keep pointing in to the same bytes of the shared label buffer.
     ///
-    fn push(&mut self, s: &str, num: u32) {
-        let mut curr = self;
-        for c in s.chars() {
-            curr = curr.nodes.entry(c).or_insert_with(|| Box::new(Node::new()));
+    fn push_bytes(&mut self, s: &[u8], num: Token) {
+        let mut curr = ROOT;
+        let mut rest = s;
+        while let Some((c, _)) = unit(rest) {
+            let i = match self.edge(curr, c) {
+                Ok(i) => i,
+                Err(i) => {
+                    let node = self.alloc(Some(num));
+                    let edge = self.new_edge(rest, node);
+                    self.nodes[curr as usize].edges.insert(i, edge);
+                    return;
+                }
+            };
+            let edge = self.nodes[curr as usize].edges[i];
+            let common = common_prefix(edge.label(&self.labels), rest);
+            if common < edge.len as usize {
+                let tail = Edge::new(
+                    &self.labels,
+                    edge.start as usize + common,
+                    edge.len as usize - common,
+                    edge.node,
+                );
+                let mid = self.alloc(None);
+                self.nodes[mid as usize].best = self.nodes[edge.node as usize].best;
+                self.nodes[mid as usize].edges.push(tail);
+                let edge = &mut self.nodes[curr as usize].edges[i];
+                edge.len = common as u32;
+                edge.node = mid;
+            }
+            rest = &rest[common..];
+            curr = self.nodes[curr as usize].edges[i].node;
         }
-        curr.num = Some(num);
+        self.nodes[curr as usize].num = Some(num);
     }
 
-    /// Finds all index nums with matching string prefix.
+    /// Removes string with the num index from the trie graph, pruning branches left empty.
+    /// Returns false if the string is not in the graph under that num.
     ///
-    fn find_prefix(&self, s: &str) -> HashSet<u32> {
-        let mut curr = self;
-        let mut nums = HashSet::new();
-        for c in s.chars() {
-            let Some(node) = curr.nodes.get(&c) else {
-                return nums;
+    fn remove(&mut self, s: &str, num: Token) -> bool {
+        self.remove_bytes(s.as_bytes(), num)
+    }
+
+    fn remove_bytes(&mut self, s: &[u8], num: Token) -> bool {
+        self.remove_inner(ROOT, s, num)
+    }
+
+    /// Adds occurrences to the count of the word, raising the highest count of every node on its path.
+    ///
+    fn count(&mut self, s: &str, by: u64) {
+        let mut path = Vec::with_capacity(8);
+        let mut curr = ROOT;
+        let mut rest = s.as_bytes();
+        while let Some((c, _)) = unit(rest) {
+            let Ok(i) = self.edge(curr, c) else {
+                return;
             };
-            curr = node;
+            let edge = &self.nodes[curr as usize].edges[i];
+            let Some(r) = rest.strip_prefix(edge.label(&self.labels)) else {
+                return;
+            };
+            path.push(curr);
+            rest = r;
+            curr = edge.node;
         }
-        if let Some(num) = curr.num {
-            nums.insert(num);
+        let node = &mut self.nodes[curr as usize];
+        if node.num.is_none() {
+            return;
+        }
+        node.count += by;
+        let count = node.count;
+        node.best = node.best.max(count);
+        for n in path {
+            let n = &mut self.nodes[n as usize];
+            n.best = n.best.max(count);
+        }
+    }
+
+    /// Finds up to k index nums with matching string prefix together with their counts, most frequent first.
+    /// Nodes are visited best first by the highest count below them, so only branches
+    /// that may still hold one of the k most frequent words are walked. Words of the same count come in no particular order.
+    ///
+    fn find_prefix_top(&self, s: &str, k: usize) -> Vec<(Token, u64)> {
+        let mut top = Vec::with_capacity(k.min(u8::MAX as usize));
+        let node = match self.walk(s.as_bytes()) {
+            Walk::Node(node) | Walk::Edge(node) => node,
+            Walk::Missing => return top,
+        };
+        let mut heap = BinaryHeap::new();
+        heap.push(Candidate(self.nodes[node as usize].best, Kind::Node(node)));
+        while top.len() < k {
+            let Some(Candidate(count, kind)) = heap.pop() else {
+                break;
+            };
+            match kind {
+                Kind::Word(Reverse(num)) => top.push((num, count)),
+                Kind::Node(node) => {
+                    let node = &self.nodes[node as usize];
+                    if let Some(num) = node.num {
+                        heap.push(Candidate(node.count, Kind::Word(Reverse(num))));
+                    }
+                    for edge in node.edges.iter() {
+                        let best = self.nodes[edge.node as usize].best;
+                        heap.push(Candidate(best, Kind::Node(edge.node)));
+                    }
+                }
+            }
         }
+        top
+    }
 
-        curr.append_inner(&mut nums);
+    /// Finds all index nums with matching string prefix.
+    ///
+    fn find_prefix(&self, s: &str) -> HashSet<Token> {
+        self.find_prefix_bytes(s.as_bytes())
+    }
+
+    /// Finds all index nums with matching byte prefix.
+    ///
+    fn find_prefix_bytes(&self, s: &[u8]) -> HashSet<Token> {
+        let mut nums = HashSet::new();
+        let node = match self.walk(s) {
+            Walk::Node(node) | Walk::Edge(node) => node,
+            Walk::Missing => return nums,
+        };
+        self.append_node(node, &mut nums);
         nums
     }
 
-    fn find_prefix_case_insensitive(&self, s: &str) -> HashSet<u32> {
+    /// Finds index nums with matching string prefix, stopping the walk once there are more than max.
+    ///
+    fn find_prefix_limited(&self, s: &str, max: usize) -> Option<HashSet<Token>> {
+        let mut nums = HashSet::new();
+        let node = match self.walk(s.as_bytes()) {
+            Walk::Node(node) | Walk::Edge(node) => node,
+            Walk::Missing => return Some(nums),
+        };
+        self.append_limited(node, &mut nums, max).then_some(nums)
+    }
+
+    /// Finds all words with matching string prefix together with their index nums in lexicographic order.
+    ///
+    fn find_prefix_words(&self, s: &str) -> Vec<(String, Token)> {
+        match self.walk_path(s.as_bytes()) {
+            Some((node, path)) => self.words_below(node, path).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn stats(&self) -> Option<Stats> {
+        Some(Trie::stats(self))
+    }
+
+    /// Compacts the arena and the label buffer, laying the graph out in depth first order,
+    /// and releases spare capacity.
+    ///
+    fn shrink_to_fit(&mut self) {
+        *self = self.compacted();
+        for node in self.nodes.iter_mut() {
+            node.edges.shrink_to_fit();
+        }
+        self.nodes.shrink_to_fit();
+        self.labels.shrink_to_fit();
+    }
+
+    fn find_prefix_case_insensitive(&self, s: &str) -> HashSet<Token> {
+        let folded: Vec<char> = s.chars().flat_map(fold).collect();
         let mut result = HashSet::new();
+        self.walk_case_insensitive(ROOT, &folded, &mut result);
+        result
+    }
 
-        for (idx, char) in s.chars().enumerate() {
-            let Some(upper) = char.to_uppercase().next() else {
-                return result;
-            };
-            if let Some(node) = self.nodes.get(&upper) {
-                if let Some(num) = node.num {
-                    result.insert(num);
-                }
-                node.find_prefix_case_insensitive(&s[idx + 1..])
-                    .iter()
-                    .for_each(|el| {
-                        result.insert(*el);
-                    });
+    /// Finds all index nums of words matching the glob pattern,
+    /// where `*` matches any run of characters and `?` matches a single character.
+    ///
+    fn find_glob(&self, pattern: &str) -> HashSet<Token> {
+        let mut chars: Vec<char> = Vec::with_capacity(pattern.len());
+        for c in pattern.chars() {
+            if !(c == '*' && chars.last() == Some(&'*')) {
+                chars.push(c);
             }
+        }
+        let mut nums = HashSet::new();
+        self.walk_glob(ROOT, &chars, &mut nums);
+        nums
+    }
 
-            let Some(lower) = char.to_lowercase().next() else {
-                return result;
-            };
-            if let Some(node) = self.nodes.get(&lower) {
-                if let Some(num) = node.num {
-                    result.insert(num);
-                }
-                node.find_prefix_case_insensitive(&s[idx + 1..])
-                    .iter()
-                    .for_each(|el| {
-                        result.insert(*el);
-                    });
+    /// Finds all index nums of words ending with the suffix.
+    /// The graph is ordered by prefixes, so this visits every word, use `BiTrie` to have it indexed.
+    ///
+    fn find_suffix(&self, s: &str) -> HashSet<Token> {
+        let mut nums = HashSet::new();
+        self.walk_suffix(ROOT, &mut Vec::new(), s.as_bytes(), &mut nums);
+        nums
+    }
+
+    /// Finds all index nums of words within given edit distance.
+    ///
+    fn find_fuzzy(&self, s: &str, distance: u8) -> HashSet<Token> {
+        let word: Vec<char> = s.chars().collect();
+        let row: Vec<usize> = (0..=word.len()).collect();
+        let mut nums = HashSet::new();
+        if word.len() <= distance as usize {
+            if let Some(num) = self.nodes[ROOT as usize].num {
+                nums.insert(num);
             }
         }
+        self.walk_fuzzy(ROOT, &word, &row, distance as usize, &mut nums);
+        nums
+    }
 
-        if s.is_empty() {
-            self.append_inner(&mut result);
+    /// Finds all index nums of words starting with a string within given edit distance of the string.
+    ///
+    fn find_prefix_fuzzy(&self, s: &str, distance: u8) -> HashSet<Token> {
+        let word: Vec<char> = s.chars().collect();
+        let row: Vec<usize> = (0..=word.len()).collect();
+        let mut nums = HashSet::new();
+        if word.len() <= distance as usize {
+            self.append_node(ROOT, &mut nums);
+            return nums;
         }
-
-        result
+        self.walk_prefix_fuzzy(ROOT, &word, &row, distance as usize, &mut nums);
+        nums
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
 mod tests {
     use super::*;
     use rand::{distributions::Alphanumeric, thread_rng, Rng};
-    use std::time::Instant;
 
     const BENCH_LOOP_SIZE: usize = 100000;
     const BENCH_WORD_SIZE: usize = 12;
@@ -149,6 +1169,14 @@ mod tests {
         "aloes",
     ];
 
+    fn labels(trie: &Trie, node: u32) -> Vec<&str> {
+        trie.nodes[node as usize]
+            .edges
+            .iter()
+            .map(|e| std::str::from_utf8(e.label(&trie.labels)).unwrap())
+            .collect()
+    }
+
     fn create_random_str(size: usize) -> String {
         thread_rng()
             .sample_iter(&Alphanumeric)
@@ -159,143 +1187,78 @@ mod tests {
 
     #[test]
     fn on_find_match_of_pushed_words_should_find_all_matching_words() {
-        let mut root = Node::new();
+        let mut root = Trie::new();
         TEST_WORDS_PUSH
             .iter()
             .enumerate()
-            .for_each(|w| root.push(w.1, w.0 as u32));
+            .for_each(|w| root.push(w.1, Token::from(w.0 as u32)));
 
         for (i, w) in TEST_WORDS_PUSH.iter().enumerate() {
-            assert_eq!(root.find_match(w).unwrap(), i as u32);
+            assert_eq!(root.find_match(w).unwrap(), Token::from(i as u32));
         }
     }
 
     #[test]
     fn on_find_match_of_not_pushed_words_should_find_no_matching_words() {
-        let mut root = Node::new();
+        let mut root = Trie::new();
         for (i, w) in TEST_WORDS_PUSH.iter().enumerate() {
-            root.push(w, i as u32);
+            root.push(w, Token::from(i as u32));
         }
 
-        for (_, w) in TEST_WORDS_NOT_PUSH.iter().enumerate() {
+        for w in TEST_WORDS_NOT_PUSH.iter() {
             assert!(root.find_match(w).is_none());
         }
     }
 
     #[test]
     fn on_find_prefix_should_find_all_matching_words_case_sensitive() {
-        let mut root = Node::new();
+        let mut root = Trie::new();
         for (i, w) in TEST_WORDS_PUSH.iter().enumerate() {
-            root.push(w, i as u32);
+            root.push(w, Token::from(i as u32));
         }
 
         let result = root.find_prefix(TEST_WORDS_PUSH[5]);
 
         assert_eq!(result.len(), 6);
         for i in result.iter() {
-            if *i as usize > TEST_WORDS_PUSH.len() {
+            if *i > Token::from(TEST_WORDS_PUSH.len() as u32) {
                 assert!(false);
                 return;
             }
-            let w = TEST_WORDS_PUSH[*i as usize];
-            assert_eq!(w[0..2], TEST_WORDS_PUSH[5][0..2]);
-        }
-    }
-
-    #[test]
-    fn bench_push() {
-        let mut words = Vec::new();
-        for _ in 0..BENCH_LOOP_SIZE {
-            words.push(create_random_str(BENCH_WORD_SIZE));
         }
-
-        let mut root = Node::new();
-
-        let start = Instant::now();
-        for (i, w) in words.iter().enumerate() {
-            root.push(w, i as u32);
+        for (i, w) in TEST_WORDS_PUSH.iter().enumerate() {
+            if result.contains(&Token::from(i as u32)) {
+                assert_eq!(w[0..2], TEST_WORDS_PUSH[5][0..2]);
+            }
         }
-
-        let duration = start.elapsed();
-
-        println!(
-            "Time elapsed in test_bench_push is: {:?} for one word in {} words of length {}, total {:?}.",
-            duration / BENCH_LOOP_SIZE as u32,
-            words.len(),
-            BENCH_WORD_SIZE,
-            duration
-        );
     }
 
     #[test]
-    fn bench_find_match() {
+    fn on_find_match_should_find_every_pushed_word() {
         let mut words = Vec::new();
         for _ in 0..BENCH_LOOP_SIZE {
             words.push(create_random_str(BENCH_WORD_SIZE));
         }
 
-        let mut root = Node::new();
+        let mut root = Trie::new();
 
         for (i, w) in words.iter().enumerate() {
-            root.push(w, i as u32);
+            root.push(w, Token::from(i as u32));
         }
 
-        let start = Instant::now();
-
         for (i, w) in words.iter().enumerate() {
             if let Some(n) = root.find_match(w) {
-                assert_eq!(n, i as u32);
+                assert_eq!(n, Token::from(i as u32));
             } else {
                 assert!(false);
             }
         }
-
-        let duration = start.elapsed();
-
-        println!(
-            "Time elapsed in test_bench_find_match is: {:?} for one word in {} words of length {}, total: {:?}.",
-            duration / BENCH_LOOP_SIZE as u32,
-            words.len(),
-            BENCH_WORD_SIZE,
-            duration
-        );
-    }
-
-    #[test]
-    fn bench_find_prefix() {
-        let mut words = Vec::new();
-        for _ in 0..BENCH_LOOP_SIZE {
-            words.push(create_random_str(BENCH_WORD_SIZE));
-        }
-
-        let mut root = Node::new();
-
-        for (i, w) in words.iter().enumerate() {
-            root.push(w, i as u32);
-        }
-
-        let start = Instant::now();
-
-        for (_, w) in words.iter().enumerate() {
-            let _ = root.find_prefix(&w[0..BENCH_WORD_SIZE / 2 as usize]);
-        }
-
-        let duration = start.elapsed();
-
-        println!(
-            "Time elapsed in test_bench_find_prefix of size {} is: {:?} for one word in {} words of length {}, total: {:?}.",
-            BENCH_WORD_SIZE / 2,
-            duration / BENCH_LOOP_SIZE as u32,
-            words.len(),
-            BENCH_WORD_SIZE,
-            duration
-        );
     }
 
     #[test]
     fn on_find_prefix_case_insensitive_should_find_matches_when_case_insensitive() {
-        let mut root = Node::new();
-        vec![
+        let mut root = Trie::new();
+        [
             ("ALA", 0),
             ("noise", 1),
             ("ala", 2),
@@ -306,18 +1269,88 @@ mod tests {
             ("al", 8),
         ]
         .iter()
-        .for_each(|el| root.push(el.0, el.1));
-        let expected = HashSet::from_iter([0, 2, 3, 7, 8]);
+        .for_each(|el| root.push(el.0, Token::from(el.1)));
+        let expected = HashSet::from_iter([0, 2, 3, 7, 8].map(Token::from));
 
         let actual = root.find_prefix_case_insensitive("al");
 
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn on_find_prefix_case_insensitive_should_fold_non_ascii_words() {
+        let mut root = Trie::new();
+        [
+            ("Straße", 0),
+            ("STRASSE", 1),
+            ("strasbourg", 2),
+            ("ΣΟΦΟΣ", 3),
+            ("σοφος", 4),
+            ("σοφός", 5),
+            ("ﬁle", 6),
+            ("FILE", 7),
+            ("Żółć", 8),
+            ("żÓŁw", 9),
+            ("İstanbul", 10),
+        ]
+        .iter()
+        .for_each(|el| root.push(el.0, Token::from(el.1)));
+
+        let cases: [(&str, &[u32]); 9] = [
+            ("strass", &[0, 1]),
+            ("STRAẞE", &[0, 1]),
+            ("stras", &[0, 1, 2]),
+            ("straß", &[0, 1]),
+            ("σοφοσ", &[3, 4]),
+            ("ΣΟΦΟς", &[3, 4]),
+            ("fil", &[6, 7]),
+            ("ŻÓŁ", &[8, 9]),
+            ("i̇stan", &[10]),
+        ];
+        for (prefix, expected) in cases {
+            assert_eq!(
+                root.find_prefix_case_insensitive(prefix),
+                HashSet::from_iter(expected.iter().map(|n| Token::from(*n))),
+                "prefix {}",
+                prefix
+            );
+        }
+    }
+
+    #[test]
+    fn on_find_prefix_case_insensitive_should_match_prefix_inside_a_fold() {
+        let mut root = Trie::new();
+        root.push("ß", Token::from(0));
+        root.push("x", Token::from(1));
+
+        assert_eq!(
+            root.find_prefix_case_insensitive("s"),
+            HashSet::from([Token::from(0)])
+        );
+        assert_eq!(root.find_prefix_case_insensitive("sss"), HashSet::new());
+    }
+
+    #[test]
+    fn on_find_prefix_limited_should_give_up_on_more_than_max_words() {
+        let mut root = Trie::new();
+        for (i, w) in TEST_WORDS_PUSH.iter().enumerate() {
+            root.push(w, Token::from(i as u32));
+        }
+
+        assert_eq!(
+            root.find_prefix_limited("ab", 6),
+            Some(root.find_prefix("ab"))
+        );
+        assert_eq!(root.find_prefix_limited("ab", 5), None);
+        assert_eq!(root.find_prefix_limited("ole", 3).map(|n| n.len()), Some(3));
+        assert_eq!(root.find_prefix_limited("xyz", 0), Some(HashSet::new()));
+        assert_eq!(root.find_prefix_limited("a", 0), None);
+    }
+
     #[test]
     fn on_find_prefix_should_find_matches_when_case_sensitive() {
-        let mut root = Node::new();
-        vec![
+        let mut root = Trie::new();
+        [
             ("ALA", 0),
             ("noise", 1),
             ("ala", 2),
@@ -329,8 +1362,8 @@ mod tests {
             ("al", 8),
         ]
         .iter()
-        .for_each(|el| root.push(el.0, el.1));
-        let expected = HashSet::from_iter([2, 6, 7, 8]);
+        .for_each(|el| root.push(el.0, Token::from(el.1)));
+        let expected = HashSet::from_iter([2, 6, 7, 8].map(Token::from));
 
         let actual = root.find_prefix("al");
 
@@ -338,22 +1371,651 @@ mod tests {
     }
 
     #[test]
-    fn on_append_inner_should_retrieve_numbers_from_descendant_nodes() {
-        let mut root = Node::new();
-        vec![("inn", 0), ("in", 1), ("inner", 2), ("i", 3), ("innest", 4)]
+    fn on_find_fuzzy_should_find_words_within_edit_distance() {
+        let mut root = Trie::new();
+        [
+            ("kubernetes", 0),
+            ("kubernete", 1),
+            ("kubernetess", 2),
+            ("kebernetes", 3),
+            ("kubrenetes", 4),
+            ("docker", 5),
+            ("kube", 6),
+        ]
+        .iter()
+        .for_each(|el| root.push(el.0, Token::from(el.1)));
+
+        assert_eq!(
+            root.find_fuzzy("kubernetes", 0),
+            HashSet::from_iter([0].map(Token::from))
+        );
+        assert_eq!(
+            root.find_fuzzy("kubernetes", 1),
+            HashSet::from_iter([0, 1, 2, 3].map(Token::from))
+        );
+        assert_eq!(
+            root.find_fuzzy("kubranetes", 2),
+            HashSet::from_iter([0, 4].map(Token::from))
+        );
+        assert!(root.find_fuzzy("kubranetes", 1).contains(&Token::from(4)));
+        assert!(root.find_fuzzy("podman", 2).is_empty());
+    }
+
+    #[test]
+    fn on_find_prefix_fuzzy_should_find_words_starting_within_edit_distance() {
+        let mut root = Trie::new();
+        for (i, w) in ["connection", "connected", "collected", "refused", "timeout"]
             .iter()
-            .for_each(|(s, idx)| root.push(s, *idx));
-        let mut node = &root;
-        for char in "inn".chars() {
-            let Some(n) = node.nodes.get(&char) else {
-                return;
-            };
-            node = n
+            .enumerate()
+        {
+            root.push(w, Token::from(i as u32));
+        }
+
+        assert_eq!(root.find_prefix_fuzzy("conect", 0), HashSet::new());
+        assert_eq!(
+            root.find_prefix_fuzzy("conect", 1),
+            HashSet::from_iter([0, 1].map(Token::from))
+        );
+        assert_eq!(
+            root.find_prefix_fuzzy("conect", 2),
+            HashSet::from_iter([0, 1, 2].map(Token::from))
+        );
+        assert_eq!(
+            root.find_prefix_fuzzy("timeuot", 2),
+            HashSet::from([Token::from(4)])
+        );
+        assert_eq!(root.find_prefix_fuzzy("ab", 2).len(), 5);
+        assert!(root.find_prefix_fuzzy("disk", 1).is_empty());
+    }
+
+    #[test]
+    fn on_find_prefix_fuzzy_should_match_a_brute_force_search() {
+        fn distance(a: &[char], b: &[char]) -> usize {
+            let mut row: Vec<usize> = (0..=b.len()).collect();
+            for (i, ca) in a.iter().enumerate() {
+                let mut curr = vec![i + 1];
+                for (j, cb) in b.iter().enumerate() {
+                    curr.push(
+                        (curr[j] + 1)
+                            .min(row[j + 1] + 1)
+                            .min(row[j] + usize::from(ca != cb)),
+                    );
+                }
+                row = curr;
+            }
+            row[b.len()]
+        }
+
+        let mut root = Trie::new();
+        let words: Vec<String> = (0..500)
+            .map(|_| create_random_str(6).to_lowercase())
+            .collect();
+        for (i, w) in words.iter().enumerate() {
+            root.push(w, Token::from(i as u32));
+        }
+        for query in ["ab1", "x9zq", "k"] {
+            let query: Vec<char> = query.chars().collect();
+            for d in 0..=2u8 {
+                let expected: HashSet<Token> = words
+                    .iter()
+                    .filter(|w| {
+                        let w: Vec<char> = w.chars().collect();
+                        (0..=w.len()).any(|end| distance(&w[..end], &query) <= d as usize)
+                    })
+                    .filter_map(|w| root.find_match(w))
+                    .collect();
+                let s: String = query.iter().collect();
+                assert_eq!(root.find_prefix_fuzzy(&s, d), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn on_shrink_to_fit_should_keep_all_pushed_words() {
+        let mut root = Trie::new();
+        for (i, w) in TEST_WORDS_PUSH.iter().enumerate() {
+            root.push(w, Token::from(i as u32));
+        }
+
+        root.shrink_to_fit();
+
+        for (i, w) in TEST_WORDS_PUSH.iter().enumerate() {
+            assert_eq!(root.find_match(w), Some(Token::from(i as u32)));
+        }
+        assert_eq!(root.find_prefix(TEST_WORDS_PUSH[5]).len(), 6);
+    }
+
+    #[test]
+    fn on_value_mut_should_keep_payload_of_the_word_only() {
+        #[derive(Debug, Clone, Default, PartialEq)]
+        struct Seen {
+            last: u64,
+            times: u32,
+        }
+
+        let mut root: Trie<Seen> = Trie::default();
+        root.push("abacus", Token::from(1));
+        *root.value_mut("abacus").unwrap() = Seen { last: 10, times: 1 };
+        root.push("aba", Token::from(2));
+        root.push("abacusa", Token::from(3));
+        root.value_mut("abacusa").unwrap().times += 2;
+
+        assert_eq!(
+            root.value_mut("abacus").cloned(),
+            Some(Seen { last: 10, times: 1 })
+        );
+        assert_eq!(root.value_mut("aba").cloned(), Some(Seen::default()));
+        assert_eq!(root.value_mut("abac").cloned(), None);
+        assert!(root.value_mut("abacu").is_none());
+
+        assert!(root.remove("abacus", Token::from(1)));
+        assert_eq!(root.value_mut("abacus").cloned(), None);
+        root.shrink_to_fit();
+        assert_eq!(
+            root.value_mut("abacusa").cloned(),
+            Some(Seen { last: 0, times: 2 })
+        );
+        assert_eq!(
+            root.find_prefix_values("aba"),
+            vec![&Seen::default(), &Seen { last: 0, times: 2 }]
+        );
+        root.push("abacus", Token::from(4));
+        assert_eq!(root.value_mut("abacus").cloned(), Some(Seen::default()));
+    }
+
+    #[test]
+    fn on_push_bytes_should_keep_binary_words_next_to_utf8_words() {
+        let mut root = Trie::new();
+        root.push("żółw", Token::from(1));
+        root.push("źle", Token::from(2));
+        root.push_bytes(b"\xc5\x00bin", Token::from(3));
+        root.push_bytes(b"\xc5\x00bit\xff", Token::from(4));
+        root.push_bytes(b"ab\xe2\x82", Token::from(5));
+
+        assert_eq!(root.nodes[ROOT as usize].edges.len(), 4);
+        assert_eq!(root.find_match("żółw"), Some(Token::from(1)));
+        assert_eq!(
+            root.find_match_bytes(b"\xc5\x00bit\xff"),
+            Some(Token::from(4))
+        );
+        assert_eq!(root.find_match_bytes(b"\xc5\x00bi"), None);
+        assert_eq!(
+            root.find_prefix_bytes(b"\xc5\x00"),
+            HashSet::from_iter([3, 4].map(Token::from))
+        );
+        assert_eq!(root.find_prefix("ż"), HashSet::from([Token::from(1)]));
+        assert_eq!(root.find_prefix("ab"), HashSet::from([Token::from(5)]));
+        assert_eq!(
+            root.find_glob("\u{FFFD}\0bi*"),
+            HashSet::from_iter([3, 4].map(Token::from))
+        );
+        assert_eq!(root.find_fuzzy("zle", 1), HashSet::from([Token::from(2)]));
+        assert_eq!(
+            root.find_prefix_words("ab"),
+            vec![("ab\u{FFFD}".to_string(), Token::from(5))]
+        );
+
+        let mut buf = Vec::new();
+        root.write_to(&mut buf).unwrap();
+        let loaded = Trie::read_from(&mut buf.as_slice()).unwrap();
+        assert_eq!(loaded.find_match_bytes(b"ab\xe2\x82"), Some(Token::from(5)));
+
+        assert!(root.remove_bytes(b"\xc5\x00bin", Token::from(3)));
+        assert_eq!(
+            root.find_prefix_bytes(b"\xc5"),
+            HashSet::from([Token::from(4)])
+        );
+    }
+
+    #[test]
+    fn on_iter_words_should_yield_all_words_in_lexicographic_order() {
+        let mut root = Trie::new();
+        for (i, w) in TEST_WORDS_PUSH.iter().enumerate() {
+            root.push(w, Token::from(i as u32));
         }
+        root.push("żółw", Token::from(100));
+        root.remove(TEST_WORDS_PUSH[3], Token::from(3));
+
+        let mut expected: Vec<(String, Token)> = TEST_WORDS_PUSH
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != 3)
+            .map(|(i, w)| (w.to_string(), Token::from(i as u32)))
+            .chain([("żółw".to_string(), Token::from(100))])
+            .collect();
+        expected.sort();
+
+        assert_eq!(root.iter_words().collect::<Vec<_>>(), expected);
+        assert_eq!(Trie::new().iter_words().count(), 0);
+    }
+
+    #[test]
+    fn on_find_prefix_words_should_spell_words_under_the_prefix() {
+        let mut root = Trie::new();
+        for (i, w) in TEST_WORDS_PUSH.iter().enumerate() {
+            root.push(w, Token::from(i as u32));
+        }
+
+        for prefix in [
+            "",
+            "a",
+            "ab",
+            "abacu",
+            "abacus",
+            "ole",
+            "o",
+            "x",
+            "abx",
+            "abacusasax",
+        ] {
+            let expected: Vec<(String, Token)> = root
+                .iter_words()
+                .filter(|(w, _)| w.starts_with(prefix))
+                .collect();
+            assert_eq!(
+                root.find_prefix_words(prefix),
+                expected,
+                "prefix {}",
+                prefix
+            );
+        }
+        assert_eq!(
+            root.find_prefix_words("oleu"),
+            vec![("oleum".to_string(), Token::from(8))]
+        );
+    }
+
+    #[test]
+    fn on_stats_should_describe_the_shape_of_the_graph() {
+        let mut root = Trie::new();
+        assert_eq!(
+            root.stats(),
+            Stats {
+                nodes: 1,
+                max_depth: 0,
+                branching: 0.0,
+                bytes: root.heap_size()
+            }
+        );
+
+        for (w, n) in [("romane", 1), ("romanus", 2), ("rom", 3), ("rubens", 4)] {
+            root.push(w, Token::from(n));
+        }
+        root.remove("rubens", Token::from(4));
+        root.push("ruby", Token::from(5));
+
+        let stats = root.stats();
+        assert_eq!(stats.nodes, 7);
+        assert_eq!(stats.max_depth, 4);
+        assert_eq!(stats.branching, 6.0 / 4.0);
+        assert_eq!(stats.bytes, root.heap_size());
+    }
+
+    #[test]
+    fn on_push_should_split_and_compress_edges() {
+        let mut root = Trie::new();
+        root.push("romane", Token::from(1));
+        root.push("romanus", Token::from(2));
+        root.push("rom", Token::from(3));
+        root.push("rubens", Token::from(4));
+        root.push("ro", Token::from(5));
+
+        assert_eq!(labels(&root, ROOT), vec!["r"]);
+        let r = root.nodes[ROOT as usize].edges[0].node;
+        assert_eq!(labels(&root, r), vec!["o", "ubens"]);
+        for (w, n) in [
+            ("romane", 1),
+            ("romanus", 2),
+            ("rom", 3),
+            ("rubens", 4),
+            ("ro", 5),
+        ] {
+            assert_eq!(root.find_match(w), Some(Token::from(n)));
+        }
+        assert_eq!(root.find_match("roman"), None);
+        assert_eq!(root.find_match("r"), None);
+        assert_eq!(
+            root.find_prefix("roma"),
+            HashSet::from_iter([1, 2].map(Token::from))
+        );
+    }
+
+    #[test]
+    fn on_find_glob_should_find_words_matching_pattern() {
+        let mut root = Trie::new();
+        [
+            ("connection-refused", 0),
+            ("conn_refused", 1),
+            ("connection", 2),
+            ("error", 3),
+            ("errar", 4),
+            ("errors", 5),
+            ("refused", 6),
+        ]
+        .iter()
+        .for_each(|el| root.push(el.0, Token::from(el.1)));
+
+        let cases: [(&str, &[u32]); 8] = [
+            ("conn*refused", &[0, 1]),
+            ("err?r", &[3, 4]),
+            ("err?r*", &[3, 4, 5]),
+            ("*refused", &[0, 1, 6]),
+            ("**ref**used", &[0, 1, 6]),
+            ("error", &[3]),
+            ("e?", &[]),
+            ("*", &[0, 1, 2, 3, 4, 5, 6]),
+        ];
+        for (pattern, expected) in cases {
+            assert_eq!(
+                root.find_glob(pattern),
+                HashSet::from_iter(expected.iter().map(|n| Token::from(*n))),
+                "pattern {}",
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn on_find_suffix_should_find_words_ending_with_suffix() {
+        let mut root = Trie::new();
+        ["config.json", "data.json", "json", "data.yaml", "jsonl"]
+            .iter()
+            .enumerate()
+            .for_each(|(i, w)| root.push(w, Token::from(i as u32)));
+
+        assert_eq!(
+            root.find_suffix(".json"),
+            HashSet::from_iter([0, 1].map(Token::from))
+        );
+        assert_eq!(
+            root.find_suffix("json"),
+            HashSet::from_iter([0, 1, 2].map(Token::from))
+        );
+        assert!(root.find_suffix(".toml").is_empty());
+    }
+
+    #[test]
+    fn on_remove_should_prune_and_compress_the_graph() {
+        let mut root = Trie::new();
+        let words = ["test", "team", "toast", "te"];
+        words
+            .iter()
+            .enumerate()
+            .for_each(|(i, w)| root.push(w, Token::from(i as u32)));
+
+        assert!(!root.remove("test", Token::from(1)));
+        assert!(!root.remove("tes", Token::from(0)));
+        assert!(!root.remove("tests", Token::from(0)));
+        assert!(root.remove("test", Token::from(0)));
+        assert!(!root.remove("test", Token::from(0)));
+
+        assert_eq!(root.find_match("test"), None);
+        assert_eq!(root.find_match("team"), Some(Token::from(1)));
+        assert_eq!(root.find_match("te"), Some(Token::from(3)));
+
+        assert!(root.remove("te", Token::from(3)));
+        let t = root.nodes[ROOT as usize].edges[0].node;
+        assert_eq!(labels(&root, t), vec!["eam", "oast"]);
+
+        assert!(root.remove("team", Token::from(1)));
+        assert!(root.remove("toast", Token::from(2)));
+        assert!(labels(&root, ROOT).is_empty());
+        assert_eq!(root.free.len(), root.nodes.len() - 1);
+
+        root.push("tea", Token::from(5));
+        assert_eq!(root.free.len(), root.nodes.len() - 2);
+        assert_eq!(root.find_match("tea"), Some(Token::from(5)));
+    }
+
+    #[test]
+    fn on_remove_should_match_a_graph_built_without_the_word() {
+        let mut rng = thread_rng();
+        let words: Vec<String> = (0..2000)
+            .map(|_| create_random_str(rng.gen_range(1..6)))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let mut full = Trie::new();
+        let mut kept = Trie::new();
+        for (i, w) in words.iter().enumerate() {
+            full.push(w, Token::from(i as u32));
+            if i % 3 != 0 {
+                kept.push(w, Token::from(i as u32));
+            }
+        }
+        for (i, w) in words.iter().enumerate() {
+            if i % 3 == 0 {
+                full.remove(w, Token::from(i as u32));
+            }
+        }
+
+        let mut a = Vec::new();
+        let mut b = Vec::new();
+        full.write_to(&mut a).unwrap();
+        kept.write_to(&mut b).unwrap();
+        assert_eq!(a, b);
+
+        full.shrink_to_fit();
+        assert!(full.free.is_empty());
+        assert_eq!(full.nodes.len(), kept.nodes.len());
+        assert_eq!(full.labels.len(), kept.labels.len());
+    }
+
+    #[test]
+    fn on_find_prefix_top_should_return_most_frequent_words_first() {
+        let mut root = Trie::new();
+        let words = [
+            ("connect", 5),
+            ("connection", 40),
+            ("connected", 12),
+            ("connections", 40),
+            ("conf", 100),
+            ("cat", 1000),
+            ("co", 0),
+        ];
+        for (i, (w, count)) in words.iter().enumerate() {
+            root.push(w, Token::from(i as u32));
+            root.count(w, *count);
+        }
+        root.count("connec", 10);
+        root.count("missing", 10);
+
+        let top = |root: &Trie, prefix: &str, k: usize| -> Vec<(u32, u64)> {
+            root.find_prefix_top(prefix, k)
+                .into_iter()
+                .map(|(n, c)| (n.to_string().parse().unwrap(), c))
+                .collect()
+        };
+        assert_eq!(top(&root, "con", 3), vec![(4, 100), (1, 40), (3, 40)]);
+        assert_eq!(
+            top(&root, "connect", 10),
+            vec![(1, 40), (3, 40), (2, 12), (0, 5)]
+        );
+        assert_eq!(top(&root, "co", 10).last(), Some(&(6, 0)));
+        assert_eq!(top(&root, "c", 1), vec![(5, 1000)]);
+        assert!(top(&root, "con", 0).is_empty());
+        assert!(top(&root, "x", 3).is_empty());
+
+        assert!(root.remove("cat", Token::from(5)));
+        assert!(root.remove("conf", Token::from(4)));
+        assert_eq!(top(&root, "c", 1), vec![(1, 40)]);
+        assert_eq!(root.nodes[ROOT as usize].best, 40);
+    }
+
+    #[test]
+    fn on_find_prefix_top_should_match_a_full_sort() {
+        let mut rng = thread_rng();
+        let mut root = Trie::new();
+        let mut counts = std::collections::HashMap::new();
+        for i in 0..5000 {
+            let w = create_random_str(rng.gen_range(1..5));
+            let num = Token::from(i as u32);
+            if counts.contains_key(&w) {
+                continue;
+            }
+            let count = rng.gen_range(0..50);
+            root.push(&w, num);
+            root.count(&w, count);
+            counts.insert(w, (num, count));
+        }
+        root.shrink_to_fit();
+
+        for prefix in ["a", "B", "7", ""] {
+            let mut expected: Vec<(Token, u64)> = counts
+                .iter()
+                .filter(|(w, _)| w.starts_with(prefix))
+                .map(|(_, v)| *v)
+                .collect();
+            expected.sort_by_key(|e| Reverse(e.1));
+            expected.truncate(10);
+            let actual = root.find_prefix_top(prefix, 10);
+            assert_eq!(
+                actual.iter().map(|(_, c)| *c).collect::<Vec<_>>(),
+                expected.iter().map(|(_, c)| *c).collect::<Vec<_>>()
+            );
+            for (num, count) in actual {
+                assert!(counts
+                    .iter()
+                    .any(|(w, v)| w.starts_with(prefix) && *v == (num, count)));
+            }
+        }
+    }
+
+    #[test]
+    fn on_save_and_load_should_restore_the_graph() {
+        let path = "./save_load.trie";
+        let mut root = Trie::new();
+        for (i, w) in TEST_WORDS_PUSH.iter().enumerate() {
+            root.push(w, Token::from(i as u32));
+        }
+        root.push("żółw", Token::from(100));
+
+        root.save(path).unwrap();
+        let loaded = Trie::load(path).unwrap();
+        let _ = std::fs::remove_file(path);
+
+        for (i, w) in TEST_WORDS_PUSH.iter().enumerate() {
+            assert_eq!(loaded.find_match(w), Some(Token::from(i as u32)));
+        }
+        for w in TEST_WORDS_NOT_PUSH.iter() {
+            assert!(loaded.find_match(w).is_none());
+        }
+        assert_eq!(loaded.find_match("żółw"), Some(Token::from(100)));
+        assert_eq!(loaded.find_prefix("ab"), root.find_prefix("ab"));
+    }
+
+    #[test]
+    fn on_read_from_should_reject_malformed_input() {
+        let mut root = Trie::new();
+        for (i, w) in TEST_WORDS_PUSH.iter().enumerate() {
+            root.push(w, Token::from(i as u32));
+        }
+        let mut data = Vec::new();
+        root.write_to(&mut data).unwrap();
+
+        let mut truncated = data.clone();
+        truncated.pop();
+        let mut version = data.clone();
+        version[4] += 1;
+        for malformed in [&b"SCRD"[..], &truncated, &version] {
+            assert!(Trie::read_from(&mut &malformed[..]).is_err());
+        }
+        assert!(Trie::read_from(&mut data.as_slice()).is_ok());
+    }
+
+    #[test]
+    fn on_push_should_handle_multibyte_characters() {
+        let mut root = Trie::new();
+        root.push("zażółć", Token::from(1));
+        root.push("zażegnać", Token::from(2));
+        root.push("żółw", Token::from(3));
+
+        assert_eq!(root.find_match("zażółć"), Some(Token::from(1)));
+        assert_eq!(
+            root.find_prefix("zaż"),
+            HashSet::from_iter([1, 2].map(Token::from))
+        );
+        assert_eq!(
+            root.find_prefix_case_insensitive("ŻÓ"),
+            HashSet::from_iter([3].map(Token::from))
+        );
+    }
+
+    /// CharNode is the trie with a node per character the radix tree replaced,
+    /// kept as a baseline for memory and lookup benchmarks.
+    ///
+    #[derive(Default)]
+    struct CharNode {
+        num: Option<Token>,
+        nodes: std::collections::HashMap<char, Box<CharNode>>,
+    }
+
+    impl CharNode {
+        fn push(&mut self, s: &str, num: Token) {
+            let mut curr = self;
+            for c in s.chars() {
+                curr = curr.nodes.entry(c).or_default();
+            }
+            curr.num = Some(num);
+        }
+
+        fn find_match(&self, s: &str) -> Option<Token> {
+            let mut curr = self;
+            for c in s.chars() {
+                curr = curr.nodes.get(&c)?;
+            }
+            curr.num
+        }
+
+        fn heap_size(&self) -> usize {
+            self.nodes.capacity() * (std::mem::size_of::<(char, Box<CharNode>)>() + 1)
+                + self
+                    .nodes
+                    .values()
+                    .map(|n| std::mem::size_of::<CharNode>() + n.heap_size())
+                    .sum::<usize>()
+        }
+    }
+
+    #[test]
+    fn on_push_should_take_less_memory_than_a_char_trie() {
+        let words: Vec<String> = (0..BENCH_LOOP_SIZE)
+            .map(|_| create_random_str(BENCH_WORD_SIZE))
+            .collect();
+
+        let mut radix = Trie::new();
+        let mut chars = CharNode::default();
+        for (i, w) in words.iter().enumerate() {
+            radix.push(w, Token::from(i as u32));
+            chars.push(w, Token::from(i as u32));
+        }
+        radix.shrink_to_fit();
+        chars.nodes.shrink_to_fit();
+
+        for w in words.iter() {
+            assert!(chars.find_match(w).is_some());
+        }
+        for w in words.iter() {
+            assert!(radix.find_match(w).is_some());
+        }
+
+        assert!(radix.heap_size() * 4 < chars.heap_size());
+    }
+
+    #[test]
+    fn on_append_inner_should_retrieve_numbers_from_descendant_nodes() {
+        let mut root = Trie::new();
+        [("inn", 0), ("in", 1), ("inner", 2), ("i", 3), ("innest", 4)]
+            .iter()
+            .for_each(|(s, idx)| root.push(s, Token::from(*idx)));
+        let Walk::Node(node) = root.walk(b"inn") else {
+            assert!(false);
+            return;
+        };
 
         let mut actual = HashSet::new();
-        node.append_inner(&mut actual);
+        root.append_inner(node, &mut actual);
 
-        assert_eq!(HashSet::from_iter([2, 4]), actual);
+        assert_eq!(HashSet::from_iter([2, 4].map(Token::from)), actual);
     }
 }
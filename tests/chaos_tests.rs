@@ -0,0 +1,87 @@
+#![cfg(feature = "chaos")]
+
+use serde::{Deserialize, Serialize};
+use std::io::Result;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const ROUNDS: usize = 100;
+const REPOSITORY_LATENCY: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LogInput {
+    log: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Query {
+    words: Option<Vec<String>>,
+    from: u64,
+    to: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LogsOutput {
+    logs: Vec<String>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64
+}
+
+#[test]
+#[ignore]
+fn on_save_log_api_call_with_injected_failures_should_keep_only_logs_that_succeeded() -> Result<()>
+{
+    let from = now();
+    let mut saved = 0;
+    for i in 0..ROUNDS {
+        let res = ureq::post("http://localhost:8000/save")
+            .set("Content-Type", "application/json")
+            .send_json(&LogInput {
+                log: format!("chaos round {}", i),
+            });
+        match res {
+            Ok(resp) => {
+                assert_eq!(resp.status(), 200);
+                saved += 1;
+            }
+            Err(ureq::Error::Status(code, _)) => assert_eq!(code, 500),
+            Err(e) => panic!("transport error {}", e),
+        }
+    }
+    assert!(saved > 0 && saved < ROUNDS);
+
+    let logs: LogsOutput = ureq::post("http://localhost:8000/read")
+        .set("Content-Type", "application/json")
+        .send_json(&Query {
+            words: Some(vec!["chaos".to_string()]),
+            from,
+            to: now(),
+        })
+        .map_err(std::io::Error::other)?
+        .into_json()?;
+    assert_eq!(logs.logs.len(), saved);
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn on_read_log_api_call_should_be_delayed_by_repository_latency() -> Result<()> {
+    let start = Instant::now();
+    let resp = ureq::post("http://localhost:8000/read")
+        .set("Content-Type", "application/json")
+        .send_json(&Query {
+            words: None,
+            from: 0,
+            to: now(),
+        })
+        .map_err(std::io::Error::other)?;
+    assert_eq!(resp.status(), 200);
+    assert!(start.elapsed() >= REPOSITORY_LATENCY);
+
+    Ok(())
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::assertions_on_constants)]
+
 use rand::rngs::ThreadRng;
 use rand::Rng;
 use serde::Deserialize;
@@ -11,7 +13,6 @@ use std::time::Duration;
 use std::time::Instant;
 use std::time::SystemTime;
 use std::time::UNIX_EPOCH;
-use ureq;
 
 const WAIT_MS: u64 = 2;
 const ROUNDS: usize = 1000;
@@ -29,11 +30,96 @@ struct Query {
     to: u64,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct QueryPrefixes {
+    prefix: Option<Vec<String>>,
+    words: Option<Vec<String>>,
+    from: u64,
+    to: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct QueryLimit {
+    words: Option<Vec<String>>,
+    limit: Option<usize>,
+    from: u64,
+    to: u64,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct LogsOutput {
     logs: Vec<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct ErrorOutput {
+    code: String,
+    message: String,
+    #[serde(default)]
+    details: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedOutput {
+    id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IdsOutput {
+    logs: Vec<String>,
+    ids: Vec<String>,
+    timestamps: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LogOutput {
+    id: String,
+    timestamp: String,
+    log: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TimedLogInput {
+    log: String,
+    timestamp: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LogsInput {
+    logs: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct QueryPage {
+    limit: usize,
+    cursor: Option<String>,
+    from: u64,
+    to: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LogsPageOutput {
+    logs: Vec<String>,
+    next: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DictionaryEntry {
+    word: String,
+    num: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WordCount {
+    word: String,
+    count: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SuggestOutput {
+    words: Vec<WordCount>,
+}
+
 fn file_read_helper() -> Result<Vec<String>> {
     let file = File::open("assets/quotes.txt")?;
     let reader = BufReader::new(file);
@@ -61,7 +147,7 @@ fn mix_and_merge(rng: &mut ThreadRng, rounds: usize, data: &[String]) -> String
 fn on_create_log_api_call_should_respond_with_code_200() -> Result<()> {
     let path = "http://localhost:8000/save";
 
-    let status = ureq::post(&path)
+    let status = ureq::post(path)
         .set("Content-Type", "application/json")
         .send_json(&LogInput {
             log: "I am the log that is added in to application".to_string(),
@@ -77,6 +163,183 @@ fn on_create_log_api_call_should_respond_with_code_200() -> Result<()> {
     Ok(())
 }
 
+#[test]
+#[ignore]
+fn on_create_log_api_call_with_timestamp_should_save_log_at_the_event_time() -> Result<()> {
+    let path = "http://localhost:8000/save";
+
+    let event = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .saturating_sub(Duration::from_secs(24 * 3600));
+    let log = "shipped late log of yesterday";
+    let status = ureq::post(path)
+        .set("Content-Type", "application/json")
+        .send_json(&TimedLogInput {
+            log: log.to_string(),
+            timestamp: event.as_nanos() as u64,
+        });
+    match status {
+        Ok(resp) => assert_eq!(resp.status(), 200),
+        Err(e) => {
+            println!("err {}", e);
+            assert!(false);
+        }
+    };
+    sleep(Duration::from_millis(WAIT_MS));
+
+    let path = "http://localhost:8000/read";
+
+    let status = ureq::post(path)
+        .set("Content-Type", "application/json")
+        .send_json(&QueryLimit {
+            words: None,
+            limit: None,
+            from: event.as_nanos() as u64,
+            to: event.as_nanos() as u64,
+        });
+    match status {
+        Ok(resp) => {
+            assert_eq!(resp.status(), 200);
+            let output: LogsOutput = resp.into_json()?;
+            assert_eq!(output.logs, [log]);
+        }
+        Err(e) => {
+            println!("err {}", e);
+            assert!(false);
+        }
+    };
+
+    let status = ureq::post("http://localhost:8000/save")
+        .set("Content-Type", "application/json")
+        .send_json(&TimedLogInput {
+            log: log.to_string(),
+            timestamp: u64::MAX,
+        });
+    match status {
+        Err(ureq::Error::Status(code, resp)) => {
+            assert_eq!(code, 422);
+            let output: ErrorOutput = resp.into_json()?;
+            assert_eq!(output.code, "invalid_input");
+            assert_eq!(output.message, "Timestamp is out of range.");
+        }
+        _ => assert!(false),
+    };
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn on_create_log_api_call_should_respond_with_id_and_timestamp_the_log_is_read_by() -> Result<()> {
+    let event = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .saturating_sub(Duration::from_secs(48 * 3600));
+    let date = humantime::format_rfc3339_nanos(UNIX_EPOCH + event).to_string();
+    let log = "bookmarked log read back by its id";
+    let Ok(resp) = ureq::post("http://localhost:8000/save")
+        .set("Content-Type", "application/json")
+        .send_json(&TimedLogInput {
+            log: log.to_string(),
+            timestamp: event.as_nanos() as u64,
+        })
+    else {
+        assert!(false);
+        return Ok(());
+    };
+    let saved: SavedOutput = resp.into_json()?;
+    assert!(!saved.id.is_empty());
+
+    let Ok(resp) = ureq::get(&format!("http://localhost:8000/log/{}", saved.id)).call() else {
+        assert!(false);
+        return Ok(());
+    };
+    let output: LogOutput = resp.into_json()?;
+    assert_eq!(output.id, saved.id);
+    assert_eq!(output.timestamp, date);
+    assert_eq!(output.log, log);
+
+    let Ok(resp) = ureq::post("http://localhost:8000/read")
+        .set("Content-Type", "application/json")
+        .send_json(&QueryLimit {
+            words: None,
+            limit: None,
+            from: event.as_nanos() as u64,
+            to: event.as_nanos() as u64,
+        })
+    else {
+        assert!(false);
+        return Ok(());
+    };
+    let output: IdsOutput = resp.into_json()?;
+    assert_eq!(output.logs, [log]);
+    assert_eq!(output.ids, [saved.id]);
+    assert_eq!(output.timestamps, [date]);
+
+    match ureq::get("http://localhost:8000/log/999999999999").call() {
+        Err(ureq::Error::Status(code, _)) => assert_eq!(code, 404),
+        _ => assert!(false),
+    };
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn on_create_logs_batch_api_call_should_save_all_logs_in_order() -> Result<()> {
+    let path = "http://localhost:8000/save/batch";
+
+    let time_from = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let logs = [
+        "batched log number one",
+        "batched log number two",
+        "batched log number three",
+    ];
+    let status = ureq::post(path)
+        .set("Content-Type", "application/json")
+        .send_json(&LogsInput {
+            logs: logs.iter().map(|l| l.to_string()).collect(),
+        });
+    match status {
+        Ok(resp) => assert_eq!(resp.status(), 200),
+        Err(e) => {
+            println!("err {}", e);
+            assert!(false);
+        }
+    };
+    sleep(Duration::from_millis(WAIT_MS));
+    let time_to = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    let path = "http://localhost:8000/read";
+
+    let status = ureq::post(path)
+        .set("Content-Type", "application/json")
+        .send_json(&QueryLimit {
+            words: Some(vec!["batched".to_string()]),
+            limit: None,
+            from: time_from.as_nanos() as u64,
+            to: time_to.as_nanos() as u64,
+        });
+    match status {
+        Ok(resp) => {
+            assert_eq!(resp.status(), 200);
+            let output: LogsOutput = resp.into_json()?;
+            assert_eq!(output.logs, logs);
+        }
+        Err(e) => {
+            println!("err {}", e);
+            assert!(false);
+        }
+    };
+
+    Ok(())
+}
+
 #[test]
 #[ignore]
 fn on_read_log_api_call_match_words_and_prefix_should_use_multiple_query_params_and_respond_with_logs_matching_all_query_params(
@@ -97,7 +360,7 @@ fn on_read_log_api_call_match_words_and_prefix_should_use_multiple_query_params_
         "Not found four but prefix match",
         "Not found five",
     ] {
-        let status = ureq::post(&path)
+        let status = ureq::post(path)
             .set("Content-Type", "application/json")
             .send_json(&LogInput { log: l.to_string() });
         match status {
@@ -116,13 +379,13 @@ fn on_read_log_api_call_match_words_and_prefix_should_use_multiple_query_params_
 
     sleep(Duration::from_millis(10));
 
-    for l in vec![
+    for l in [
         "Outside of time range. This log is number one prefix match",
         "Outside of time range. This log is number two prefix match",
         "Outside of time range. This log is number three prefix match",
         "Outside of time range. This log is number four prefix match",
     ] {
-        let status = ureq::post(&path)
+        let status = ureq::post(path)
             .set("Content-Type", "application/json")
             .send_json(&LogInput { log: l.to_string() });
         match status {
@@ -137,7 +400,7 @@ fn on_read_log_api_call_match_words_and_prefix_should_use_multiple_query_params_
 
     let path = "http://localhost:8000/read";
 
-    let status = ureq::post(&path)
+    let status = ureq::post(path)
         .set("Content-Type", "application/json")
         .send_json(&Query {
             prefix: Some("pref".to_string()),
@@ -196,7 +459,7 @@ fn on_read_log_api_call_match_existing_word_should_respond_with_previously_added
         "Not found four",
         "Not found five",
     ] {
-        let status = ureq::post(&path)
+        let status = ureq::post(path)
             .set("Content-Type", "application/json")
             .send_json(&LogInput { log: l.to_string() });
         match status {
@@ -217,7 +480,7 @@ fn on_read_log_api_call_match_existing_word_should_respond_with_previously_added
 
     let path = "http://localhost:8000/read";
 
-    let status = ureq::post(&path)
+    let status = ureq::post(path)
         .set("Content-Type", "application/json")
         .send_json(&Query {
             prefix: None,
@@ -263,14 +526,14 @@ fn on_read_log_api_call_match_existing_word_should_respond_with_empty_message_wh
     let time_from = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default();
-    for l in vec![
+    for l in [
         "Not found one",
         "Not found two",
         "Not found three",
         "Not found four",
         "Not found five",
     ] {
-        let status = ureq::post(&path)
+        let status = ureq::post(path)
             .set("Content-Type", "application/json")
             .send_json(&LogInput { log: l.to_string() });
         match status {
@@ -291,7 +554,7 @@ fn on_read_log_api_call_match_existing_word_should_respond_with_empty_message_wh
 
     let path = "http://localhost:8000/read";
 
-    let status = ureq::post(&path)
+    let status = ureq::post(path)
         .set("Content-Type", "application/json")
         .send_json(&Query {
             prefix: None,
@@ -328,7 +591,7 @@ fn on_read_log_api_call_match_word_prefix_should_respond_with_messages_when_logs
     let time_from = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default();
-    for l in vec![
+    for l in [
         "This log is super funny should found",
         "This log is super fun should found",
         "This log is super fan",
@@ -337,7 +600,7 @@ fn on_read_log_api_call_match_word_prefix_should_respond_with_messages_when_logs
         "This log is super fixed",
         "This log is super fu should found",
     ] {
-        let status = ureq::post(&path)
+        let status = ureq::post(path)
             .set("Content-Type", "application/json")
             .send_json(&LogInput { log: l.to_string() });
         match status {
@@ -358,7 +621,7 @@ fn on_read_log_api_call_match_word_prefix_should_respond_with_messages_when_logs
 
     let path = "http://localhost:8000/read";
 
-    let status = ureq::post(&path)
+    let status = ureq::post(path)
         .set("Content-Type", "application/json")
         .send_json(&Query {
             prefix: Some("fu".to_string()),
@@ -390,6 +653,69 @@ fn on_read_log_api_call_match_word_prefix_should_respond_with_messages_when_logs
     Ok(())
 }
 
+#[test]
+#[ignore]
+fn on_read_log_api_call_match_many_prefixes_should_respond_with_messages_matching_any_prefix(
+) -> Result<()> {
+    let path = "http://localhost:8000/save";
+
+    let time_from = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    for l in [
+        "Connection refused should found",
+        "Request timeout should found",
+        "Request served",
+        "Connected to peer should found",
+        "Nothing happened",
+    ] {
+        let status = ureq::post(path)
+            .set("Content-Type", "application/json")
+            .send_json(&LogInput { log: l.to_string() });
+        match status {
+            Ok(resp) => assert_eq!(resp.status(), 200),
+            Err(e) => {
+                println!("err {}", e);
+                assert!(false);
+            }
+        };
+        sleep(Duration::from_millis(WAIT_MS));
+    }
+
+    let time_to = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    sleep(Duration::from_millis(100));
+
+    let path = "http://localhost:8000/read";
+
+    let status = ureq::post(path)
+        .set("Content-Type", "application/json")
+        .send_json(&QueryPrefixes {
+            prefix: Some(vec!["Conn".to_string(), "time".to_string()]),
+            words: None,
+            from: time_from.as_nanos() as u64,
+            to: time_to.as_nanos() as u64,
+        });
+    match status {
+        Ok(resp) => {
+            assert_eq!(resp.status(), 200);
+            let logs: LogsOutput = resp.into_json()?;
+            assert_eq!(logs.logs.len(), 3);
+            for log in logs.logs.iter() {
+                assert!(log.ends_with("should found"));
+            }
+        }
+        Err(e) => {
+            println!("err {}", e);
+            assert!(false);
+        }
+    };
+
+    Ok(())
+}
+
 #[test]
 #[ignore]
 fn on_read_log_api_call_match_word_prefix_should_respond_with_empty_response_when_prefix_is_not_matching(
@@ -399,7 +725,7 @@ fn on_read_log_api_call_match_word_prefix_should_respond_with_empty_response_whe
     let time_from = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default();
-    for l in vec![
+    for l in [
         "This log is super funny should found",
         "This log is super fun should found",
         "This log is super fan",
@@ -408,7 +734,7 @@ fn on_read_log_api_call_match_word_prefix_should_respond_with_empty_response_whe
         "This log is super fixed",
         "This log is super fu should found",
     ] {
-        let status = ureq::post(&path)
+        let status = ureq::post(path)
             .set("Content-Type", "application/json")
             .send_json(&LogInput { log: l.to_string() });
         match status {
@@ -429,7 +755,7 @@ fn on_read_log_api_call_match_word_prefix_should_respond_with_empty_response_whe
 
     let path = "http://localhost:8000/read";
 
-    let status = ureq::post(&path)
+    let status = ureq::post(path)
         .set("Content-Type", "application/json")
         .send_json(&Query {
             prefix: Some("xx".to_string()),
@@ -460,7 +786,7 @@ fn on_read_log_api_call_match_time_should_find_all_in_time_range() -> Result<()>
     let time_from = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default();
-    for l in vec![
+    for l in [
         "This log is super funny should found",
         "This log is super fun should found",
         "This log is super fan should found",
@@ -469,7 +795,7 @@ fn on_read_log_api_call_match_time_should_find_all_in_time_range() -> Result<()>
         "This log is super fixed should found",
         "This log is super fu should found",
     ] {
-        let status = ureq::post(&path)
+        let status = ureq::post(path)
             .set("Content-Type", "application/json")
             .send_json(&LogInput { log: l.to_string() });
         match status {
@@ -490,7 +816,7 @@ fn on_read_log_api_call_match_time_should_find_all_in_time_range() -> Result<()>
 
     let path = "http://localhost:8000/read";
 
-    let status = ureq::post(&path)
+    let status = ureq::post(path)
         .set("Content-Type", "application/json")
         .send_json(&Query {
             prefix: None,
@@ -524,28 +850,21 @@ fn on_read_log_api_call_match_time_should_find_all_in_time_range() -> Result<()>
 
 #[test]
 #[ignore]
-fn on_read_log_api_call_match_should_return_empty_result_for_time_rang_with_no_matching_logs(
-) -> Result<()> {
+fn on_read_log_api_call_with_limit_should_respond_with_oldest_matching_logs_first() -> Result<()> {
     let path = "http://localhost:8000/save";
 
     let time_from = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default();
-    sleep(Duration::from_millis(10));
-    let time_to = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default();
-    sleep(Duration::from_millis(10));
-    for l in vec![
-        "This log is super funny",
-        "This log is super fun",
-        "This log is super fan",
-        "This log is super fantastic",
-        "This log is super fonetic",
-        "This log is super fixed",
-        "This log is super fu",
-    ] {
-        let status = ureq::post(&path)
+    let logs = [
+        "limited log number one",
+        "other log number two",
+        "limited log number three",
+        "limited log number four",
+        "limited log number five",
+    ];
+    for l in logs {
+        let status = ureq::post(path)
             .set("Content-Type", "application/json")
             .send_json(&LogInput { log: l.to_string() });
         match status {
@@ -558,23 +877,27 @@ fn on_read_log_api_call_match_should_return_empty_result_for_time_rang_with_no_m
         sleep(Duration::from_millis(WAIT_MS));
     }
 
+    let time_to = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
     sleep(Duration::from_millis(100));
 
     let path = "http://localhost:8000/read";
 
-    let status = ureq::post(&path)
+    let status = ureq::post(path)
         .set("Content-Type", "application/json")
-        .send_json(&Query {
-            prefix: None,
-            words: None,
+        .send_json(&QueryLimit {
+            words: Some(vec!["limited".to_string()]),
+            limit: Some(3),
             from: time_from.as_nanos() as u64,
             to: time_to.as_nanos() as u64,
         });
     match status {
         Ok(resp) => {
             assert_eq!(resp.status(), 200);
-            let logs: LogsOutput = resp.into_json()?;
-            assert_eq!(logs.logs.len(), 0);
+            let output: LogsOutput = resp.into_json()?;
+            assert_eq!(output.logs, [logs[0], logs[2], logs[3]]);
         }
         Err(e) => {
             println!("err {}", e);
@@ -587,18 +910,23 @@ fn on_read_log_api_call_match_should_return_empty_result_for_time_rang_with_no_m
 
 #[test]
 #[ignore]
-fn integration_bench_create_log() -> Result<()> {
+fn on_read_log_api_call_with_cursor_should_page_through_all_logs_in_time_span() -> Result<()> {
     let path = "http://localhost:8000/save";
 
-    let mut rng = rand::thread_rng();
-    let logs = file_read_helper()?;
-
-    let start = Instant::now();
-    for _ in 0..ROUNDS {
-        let log = mix_and_merge(&mut rng, 5, &logs);
-        let status = ureq::post(&path)
+    let time_from = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let logs = [
+        "paged log number one",
+        "paged log number two",
+        "paged log number three",
+        "paged log number four",
+        "paged log number five",
+    ];
+    for l in logs {
+        let status = ureq::post(path)
             .set("Content-Type", "application/json")
-            .send_json(&LogInput { log: log });
+            .send_json(&LogInput { log: l.to_string() });
         match status {
             Ok(resp) => assert_eq!(resp.status(), 200),
             Err(e) => {
@@ -609,21 +937,150 @@ fn integration_bench_create_log() -> Result<()> {
         sleep(Duration::from_millis(WAIT_MS));
     }
 
-    let duration = start.elapsed();
+    let time_to = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
 
-    println!(
-        "create_log test took per request [ {:?} ms ], total [ {:?} ms ] for {} request",
-        (duration.as_millis() as u32 - (ROUNDS as u64 * WAIT_MS) as u32) / ROUNDS as u32,
-        duration.as_millis() as u32 - (ROUNDS as u64 * WAIT_MS) as u32,
-        ROUNDS
-    );
+    sleep(Duration::from_millis(100));
 
-    Ok(())
-}
+    let path = "http://localhost:8000/read";
 
-#[test]
-#[ignore]
-fn integration_bench_read_log() -> Result<()> {
+    let mut found = Vec::new();
+    let mut cursor = None;
+    loop {
+        let status = ureq::post(path)
+            .set("Content-Type", "application/json")
+            .send_json(&QueryPage {
+                limit: 2,
+                cursor: cursor.take(),
+                from: time_from.as_nanos() as u64,
+                to: time_to.as_nanos() as u64,
+            });
+        match status {
+            Ok(resp) => {
+                assert_eq!(resp.status(), 200);
+                let output: LogsPageOutput = resp.into_json()?;
+                assert!(output.logs.len() <= 2);
+                found.extend(output.logs);
+                match output.next {
+                    Some(next) => cursor = Some(next),
+                    None => break,
+                }
+            }
+            Err(e) => {
+                println!("err {}", e);
+                assert!(false);
+                break;
+            }
+        };
+    }
+    assert_eq!(found, logs);
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn on_read_log_api_call_match_should_return_empty_result_for_time_rang_with_no_matching_logs(
+) -> Result<()> {
+    let path = "http://localhost:8000/save";
+
+    let time_from = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    sleep(Duration::from_millis(10));
+    let time_to = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    sleep(Duration::from_millis(10));
+    for l in [
+        "This log is super funny",
+        "This log is super fun",
+        "This log is super fan",
+        "This log is super fantastic",
+        "This log is super fonetic",
+        "This log is super fixed",
+        "This log is super fu",
+    ] {
+        let status = ureq::post(path)
+            .set("Content-Type", "application/json")
+            .send_json(&LogInput { log: l.to_string() });
+        match status {
+            Ok(resp) => assert_eq!(resp.status(), 200),
+            Err(e) => {
+                println!("err {}", e);
+                assert!(false);
+            }
+        };
+        sleep(Duration::from_millis(WAIT_MS));
+    }
+
+    sleep(Duration::from_millis(100));
+
+    let path = "http://localhost:8000/read";
+
+    let status = ureq::post(path)
+        .set("Content-Type", "application/json")
+        .send_json(&Query {
+            prefix: None,
+            words: None,
+            from: time_from.as_nanos() as u64,
+            to: time_to.as_nanos() as u64,
+        });
+    match status {
+        Ok(resp) => {
+            assert_eq!(resp.status(), 200);
+            let logs: LogsOutput = resp.into_json()?;
+            assert_eq!(logs.logs.len(), 0);
+        }
+        Err(e) => {
+            println!("err {}", e);
+            assert!(false);
+        }
+    };
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn integration_bench_create_log() -> Result<()> {
+    let path = "http://localhost:8000/save";
+
+    let mut rng = rand::thread_rng();
+    let logs = file_read_helper()?;
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        let log = mix_and_merge(&mut rng, 5, &logs);
+        let status = ureq::post(path)
+            .set("Content-Type", "application/json")
+            .send_json(&LogInput { log });
+        match status {
+            Ok(resp) => assert_eq!(resp.status(), 200),
+            Err(e) => {
+                println!("err {}", e);
+                assert!(false);
+            }
+        };
+        sleep(Duration::from_millis(WAIT_MS));
+    }
+
+    let duration = start.elapsed();
+
+    println!(
+        "create_log test took per request [ {:?} ms ], total [ {:?} ms ] for {} request",
+        (duration.as_millis() as u32 - (ROUNDS as u64 * WAIT_MS) as u32) / ROUNDS as u32,
+        duration.as_millis() as u32 - (ROUNDS as u64 * WAIT_MS) as u32,
+        ROUNDS
+    );
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn integration_bench_read_log() -> Result<()> {
     let path = "http://localhost:8000/save";
 
     let mut rng = rand::thread_rng();
@@ -634,9 +1091,9 @@ fn integration_bench_read_log() -> Result<()> {
         .unwrap_or_default();
     for _ in 0..ROUNDS {
         let log = mix_and_merge(&mut rng, 5, &logs);
-        let status = ureq::post(&path)
+        let status = ureq::post(path)
             .set("Content-Type", "application/json")
-            .send_json(&LogInput { log: log });
+            .send_json(&LogInput { log });
         match status {
             Ok(resp) => assert_eq!(resp.status(), 200),
             Err(e) => {
@@ -657,7 +1114,7 @@ fn integration_bench_read_log() -> Result<()> {
 
     let start = Instant::now();
     for _ in 0..ROUNDS {
-        let status = ureq::post(&path)
+        let status = ureq::post(path)
             .set("Content-Type", "application/json")
             .send_json(&Query {
                 prefix: Some("th".to_string()),
@@ -692,3 +1149,719 @@ fn integration_bench_read_log() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+#[ignore]
+fn on_dictionary_lookup_api_call_should_resolve_words_and_numbers_both_ways() -> Result<()> {
+    let status = ureq::post("http://localhost:8000/save")
+        .set("Content-Type", "application/json")
+        .send_json(&LogInput {
+            log: "lookup reverse dictionary".to_string(),
+        });
+    match status {
+        Ok(resp) => assert_eq!(resp.status(), 200),
+        Err(e) => {
+            println!("err {}", e);
+            assert!(false);
+        }
+    };
+
+    let Ok(resp) = ureq::get("http://localhost:8000/dictionary/num/reverse").call() else {
+        assert!(false);
+        return Ok(());
+    };
+    let by_word: DictionaryEntry = resp.into_json()?;
+    assert_eq!(by_word.word, "reverse");
+
+    let path = format!("http://localhost:8000/dictionary/word/{}", by_word.num);
+    let Ok(resp) = ureq::get(&path).call() else {
+        assert!(false);
+        return Ok(());
+    };
+    let by_num: DictionaryEntry = resp.into_json()?;
+    assert_eq!(by_num.word, "reverse");
+    assert_eq!(by_num.num, by_word.num);
+
+    match ureq::get("http://localhost:8000/dictionary/num/never-saved-word").call() {
+        Err(ureq::Error::Status(code, _)) => assert_eq!(code, 404),
+        _ => assert!(false),
+    };
+
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphStats {
+    nodes: usize,
+    max_depth: usize,
+    branching: f64,
+    bytes: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct DictionaryStats {
+    words: usize,
+    graph: Option<GraphStats>,
+}
+
+#[test]
+#[ignore]
+fn on_dictionary_stats_api_call_should_describe_the_graph() -> Result<()> {
+    let status = ureq::post("http://localhost:8000/save")
+        .set("Content-Type", "application/json")
+        .send_json(&LogInput {
+            log: "statistics of the dictionary graph".to_string(),
+        });
+    match status {
+        Ok(resp) => assert_eq!(resp.status(), 200),
+        Err(e) => {
+            println!("err {}", e);
+            assert!(false);
+        }
+    };
+
+    let Ok(resp) = ureq::get("http://localhost:8000/dictionary/stats").call() else {
+        assert!(false);
+        return Ok(());
+    };
+    let stats: DictionaryStats = resp.into_json()?;
+    assert!(stats.words >= 5);
+    let Some(graph) = stats.graph else {
+        assert!(false);
+        return Ok(());
+    };
+    assert!(graph.nodes > stats.words);
+    assert!(graph.max_depth >= 1);
+    assert!(graph.branching >= 1.0);
+    assert!(graph.bytes > 0);
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn on_dictionary_export_and_import_api_call_should_refuse_import_in_to_non_empty_repository(
+) -> Result<()> {
+    let status = ureq::post("http://localhost:8000/save")
+        .set("Content-Type", "application/json")
+        .send_json(&LogInput {
+            log: "exported dictionary words".to_string(),
+        });
+    match status {
+        Ok(resp) => assert_eq!(resp.status(), 200),
+        Err(e) => {
+            println!("err {}", e);
+            assert!(false);
+        }
+    };
+
+    let Ok(resp) = ureq::get("http://localhost:8000/dictionary/export").call() else {
+        assert!(false);
+        return Ok(());
+    };
+    let mut schema = Vec::new();
+    resp.into_reader().read_to_end(&mut schema)?;
+    assert_eq!(&schema[..4], b"SCRD");
+
+    for url in [
+        "http://localhost:8000/dictionary/import",
+        "http://localhost:8000/dictionary/import?force=true",
+    ] {
+        match ureq::post(url).send_bytes(&schema) {
+            Err(ureq::Error::Status(code, _)) => assert_eq!(code, 409),
+            _ => assert!(false),
+        };
+    }
+
+    let Ok(resp) = ureq::get("http://localhost:8000/dictionary/num/exported").call() else {
+        assert!(false);
+        return Ok(());
+    };
+    assert_eq!(resp.status(), 200);
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn on_suggest_api_call_should_respond_with_most_frequent_words_under_prefix() -> Result<()> {
+    for log in [
+        "suggestible suggestion",
+        "suggestion suggested",
+        "suggestion",
+    ] {
+        let status = ureq::post("http://localhost:8000/save")
+            .set("Content-Type", "application/json")
+            .send_json(&LogInput {
+                log: log.to_string(),
+            });
+        match status {
+            Ok(resp) => assert_eq!(resp.status(), 200),
+            Err(e) => {
+                println!("err {}", e);
+                assert!(false);
+            }
+        };
+    }
+
+    let Ok(resp) = ureq::get("http://localhost:8000/suggest?prefix=suggest&k=2").call() else {
+        assert!(false);
+        return Ok(());
+    };
+    let output: SuggestOutput = resp.into_json()?;
+    assert_eq!(output.words.len(), 2);
+    assert_eq!(output.words[0].word, "suggestion");
+    assert!(output.words[0].count >= 3);
+    assert!(output.words[0].count >= output.words[1].count);
+
+    match ureq::get("http://localhost:8000/suggest?prefix=suggest&k=100000").call() {
+        Err(ureq::Error::Status(code, _)) => assert_eq!(code, 422),
+        _ => assert!(false),
+    };
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ExplainOutput {
+    words: Vec<DictionaryEntry>,
+    truncated: bool,
+}
+
+#[test]
+#[ignore]
+fn on_explain_api_call_should_respond_with_words_the_prefix_expands_to() -> Result<()> {
+    let status = ureq::post("http://localhost:8000/save")
+        .set("Content-Type", "application/json")
+        .send_json(&LogInput {
+            log: "unexplainable unexplained unexplanation".to_string(),
+        });
+    match status {
+        Ok(resp) => assert_eq!(resp.status(), 200),
+        Err(e) => {
+            println!("err {}", e);
+            assert!(false);
+        }
+    };
+
+    let Ok(resp) = ureq::get("http://localhost:8000/explain?prefix=unexplai").call() else {
+        assert!(false);
+        return Ok(());
+    };
+    let output: ExplainOutput = resp.into_json()?;
+    assert!(!output.truncated);
+    let words: Vec<&str> = output.words.iter().map(|e| e.word.as_str()).collect();
+    assert_eq!(words, ["unexplainable", "unexplained"]);
+
+    let Ok(resp) = ureq::get(&format!(
+        "http://localhost:8000/dictionary/num/{}",
+        output.words[0].word
+    ))
+    .call() else {
+        assert!(false);
+        return Ok(());
+    };
+    let entry: DictionaryEntry = resp.into_json()?;
+    assert_eq!(entry.num, output.words[0].num);
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn on_versioned_api_call_should_serve_v1_and_legacy_paths_marked_deprecated() -> Result<()> {
+    let log = "versioned api log";
+    let Ok(resp) = ureq::post("http://localhost:8000/v1/save")
+        .set("Content-Type", "application/json")
+        .send_json(&LogInput {
+            log: log.to_string(),
+        })
+    else {
+        assert!(false);
+        return Ok(());
+    };
+    assert_eq!(resp.header("Deprecation"), None);
+    let saved: SavedOutput = resp.into_json()?;
+
+    let Ok(resp) = ureq::get(&format!("http://localhost:8000/v1/log/{}", saved.id)).call() else {
+        assert!(false);
+        return Ok(());
+    };
+    assert_eq!(resp.header("Deprecation"), None);
+
+    let Ok(resp) = ureq::get(&format!("http://localhost:8000/log/{}", saved.id)).call() else {
+        assert!(false);
+        return Ok(());
+    };
+    assert_eq!(resp.header("Deprecation"), Some("true"));
+
+    match ureq::get("http://localhost:8000/v2/version").call() {
+        Err(ureq::Error::Status(code, _)) => assert_eq!(code, 404),
+        _ => assert!(false),
+    };
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn on_invalid_save_and_read_api_call_should_respond_with_400_naming_the_violation() -> Result<()> {
+    match ureq::post("http://localhost:8000/v1/save")
+        .set("Content-Type", "application/json")
+        .send_json(&LogInput {
+            log: "   ".to_string(),
+        }) {
+        Err(ureq::Error::Status(code, resp)) => {
+            assert_eq!(code, 400);
+            let output: ErrorOutput = resp.into_json()?;
+            assert_eq!(output.code, "validation_failed");
+            assert_eq!(
+                output.details,
+                Some(serde_json::json!({ "violation": "empty_log" }))
+            );
+        }
+        _ => assert!(false),
+    };
+
+    match ureq::post("http://localhost:8000/v1/read")
+        .set("Content-Type", "application/json")
+        .send_json(&QueryLimit {
+            words: None,
+            limit: None,
+            from: 2,
+            to: 1,
+        }) {
+        Err(ureq::Error::Status(code, resp)) => {
+            assert_eq!(code, 400);
+            let output: ErrorOutput = resp.into_json()?;
+            assert_eq!(
+                output.details,
+                Some(serde_json::json!({ "violation": "from_after_to" }))
+            );
+        }
+        _ => assert!(false),
+    };
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct QueryWords {
+    words: Option<Vec<String>>,
+}
+
+#[test]
+#[ignore]
+fn on_read_api_call_without_range_should_read_logs_up_to_now() -> Result<()> {
+    let log = "openended rangeless log";
+    let Ok(resp) = ureq::post("http://localhost:8000/v1/save")
+        .set("Content-Type", "application/json")
+        .send_json(&LogInput {
+            log: log.to_string(),
+        })
+    else {
+        assert!(false);
+        return Ok(());
+    };
+    assert_eq!(resp.status(), 200);
+    sleep(Duration::from_millis(WAIT_MS));
+
+    let Ok(resp) = ureq::post("http://localhost:8000/v1/read")
+        .set("Content-Type", "application/json")
+        .send_json(&QueryWords {
+            words: Some(vec!["rangeless".to_string()]),
+        })
+    else {
+        assert!(false);
+        return Ok(());
+    };
+    let output: LogsOutput = resp.into_json()?;
+    assert_eq!(output.logs, [log]);
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct QueryTimeExpr {
+    words: Option<Vec<String>>,
+    from: String,
+    to: String,
+}
+
+#[test]
+#[ignore]
+fn on_read_api_call_with_time_expressions_should_read_logs_in_relative_range() -> Result<()> {
+    let log = "relative expressive log";
+    let Ok(resp) = ureq::post("http://localhost:8000/v1/save")
+        .set("Content-Type", "application/json")
+        .send_json(&LogInput {
+            log: log.to_string(),
+        })
+    else {
+        assert!(false);
+        return Ok(());
+    };
+    assert_eq!(resp.status(), 200);
+    sleep(Duration::from_millis(WAIT_MS));
+
+    let Ok(resp) = ureq::post("http://localhost:8000/v1/read")
+        .set("Content-Type", "application/json")
+        .send_json(&QueryTimeExpr {
+            words: Some(vec!["expressive".to_string()]),
+            from: "now-15m".to_string(),
+            to: "now".to_string(),
+        })
+    else {
+        assert!(false);
+        return Ok(());
+    };
+    let output: LogsOutput = resp.into_json()?;
+    assert_eq!(output.logs, [log]);
+
+    let Ok(resp) = ureq::post("http://localhost:8000/v1/read")
+        .set("Content-Type", "application/json")
+        .send_json(&QueryTimeExpr {
+            words: Some(vec!["expressive".to_string()]),
+            from: "2000-01-01T00:00:00Z".to_string(),
+            to: "now-1h".to_string(),
+        })
+    else {
+        assert!(false);
+        return Ok(());
+    };
+    let output: LogsOutput = resp.into_json()?;
+    assert!(output.logs.is_empty());
+
+    match ureq::post("http://localhost:8000/v1/read")
+        .set("Content-Type", "application/json")
+        .send_json(&QueryTimeExpr {
+            words: None,
+            from: "yesterday".to_string(),
+            to: "now".to_string(),
+        }) {
+        Err(ureq::Error::Status(code, resp)) => {
+            assert_eq!(code, 400);
+            let output: ErrorOutput = resp.into_json()?;
+            assert_eq!(output.code, "bad_request");
+        }
+        _ => assert!(false),
+    };
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Meta {
+    total: Option<usize>,
+    truncated: bool,
+    took_ms: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MetaOutput {
+    logs: Vec<String>,
+    meta: Meta,
+}
+
+#[test]
+#[ignore]
+fn on_read_api_call_with_limit_should_respond_with_total_of_matching_logs_in_meta() -> Result<()> {
+    let time_from = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let logs = [
+        "metered log number one",
+        "metered log number two",
+        "metered log number three",
+    ];
+    for log in logs.iter() {
+        let Ok(resp) = ureq::post("http://localhost:8000/v1/save")
+            .set("Content-Type", "application/json")
+            .send_json(&LogInput {
+                log: log.to_string(),
+            })
+        else {
+            assert!(false);
+            return Ok(());
+        };
+        assert_eq!(resp.status(), 200);
+        sleep(Duration::from_millis(WAIT_MS));
+    }
+    let time_to = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    for (limit, expected, truncated) in [(Some(2), &logs[..2], true), (None, &logs[..], false)] {
+        let Ok(resp) = ureq::post("http://localhost:8000/v1/read")
+            .set("Content-Type", "application/json")
+            .send_json(&QueryLimit {
+                words: Some(vec!["metered".to_string()]),
+                limit,
+                from: time_from.as_nanos() as u64,
+                to: time_to.as_nanos() as u64,
+            })
+        else {
+            assert!(false);
+            return Ok(());
+        };
+        let output: MetaOutput = resp.into_json()?;
+        assert_eq!(output.logs, expected);
+        assert_eq!(output.meta.total, Some(3));
+        assert_eq!(output.meta.truncated, truncated);
+        assert!(output.meta.took_ms >= 0.0);
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct WordsOutput {
+    words: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct AdminStats {
+    version: String,
+    unsaved_words: usize,
+}
+
+fn admin(req: ureq::Request) -> ureq::Request {
+    let token = std::env::var("SCRIBE_ADMIN_TOKEN").unwrap_or_default();
+    req.set("Authorization", &format!("Bearer {}", token))
+}
+
+#[test]
+#[ignore]
+fn on_admin_api_calls_should_flush_and_rotate_the_dictionary_and_report_stats() -> Result<()> {
+    let Ok(resp) = ureq::post("http://localhost:8000/v1/save")
+        .set("Content-Type", "application/json")
+        .send_json(&LogInput {
+            log: "administered log".to_string(),
+        })
+    else {
+        assert!(false);
+        return Ok(());
+    };
+    assert_eq!(resp.status(), 200);
+
+    let Ok(resp) = admin(ureq::post("http://localhost:8000/admin/dictionary/flush")).call() else {
+        assert!(false);
+        return Ok(());
+    };
+    let _: WordsOutput = resp.into_json()?;
+
+    let Ok(resp) = admin(ureq::post("http://localhost:8000/admin/dictionary/rotate")).call() else {
+        assert!(false);
+        return Ok(());
+    };
+    let rotated: WordsOutput = resp.into_json()?;
+    assert!(rotated.words >= 2);
+
+    let Ok(resp) = admin(ureq::get("http://localhost:8000/admin/stats")).call() else {
+        assert!(false);
+        return Ok(());
+    };
+    let stats: AdminStats = resp.into_json()?;
+    assert!(!stats.version.is_empty());
+    assert_eq!(stats.unsaved_words, 0);
+
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorEnvelope {
+    code: String,
+    request_id: Option<String>,
+}
+
+#[test]
+#[ignore]
+fn on_failed_api_call_should_respond_with_the_request_id_of_the_client() -> Result<()> {
+    let resp = match ureq::post("http://localhost:8000/v1/save")
+        .set("Content-Type", "application/json")
+        .set("X-Request-Id", "integration-377")
+        .send_json(&LogInput {
+            log: " ".to_string(),
+        }) {
+        Err(ureq::Error::Status(400, resp)) => resp,
+        _ => {
+            assert!(false);
+            return Ok(());
+        }
+    };
+    assert_eq!(resp.header("X-Request-Id"), Some("integration-377"));
+    let envelope: ErrorEnvelope = resp.into_json()?;
+    assert_eq!(envelope.code, "validation_failed");
+    assert_eq!(envelope.request_id.as_deref(), Some("integration-377"));
+
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct VersionOutput {
+    version: String,
+    commit: String,
+    built_at: String,
+    backends: Vec<String>,
+    config_digest: String,
+}
+
+#[test]
+#[ignore]
+fn on_version_api_call_should_report_the_build_and_the_setup_digest() -> Result<()> {
+    let Ok(resp) = ureq::get("http://localhost:8000/v1/version").call() else {
+        assert!(false);
+        return Ok(());
+    };
+    let version: VersionOutput = resp.into_json()?;
+    assert!(version.version.starts_with("Scribe "));
+    assert!(!version.commit.is_empty());
+    assert!(version.built_at.ends_with('Z'));
+    assert!(version.backends.iter().any(|b| b == "SQLite"));
+    assert_eq!(version.config_digest.len(), 16);
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn on_gelf_api_call_should_save_the_message_with_its_fields() -> Result<()> {
+    let Ok(resp) = ureq::post("http://localhost:8000/gelf")
+        .set("Content-Type", "application/json")
+        .send_json(serde_json::json!({
+            "version": "1.1",
+            "host": "graylogged",
+            "short_message": "gelfshipped message",
+            "level": 6,
+            "_user_id": 7
+        }))
+    else {
+        assert!(false);
+        return Ok(());
+    };
+    assert_eq!(resp.status(), 202);
+    sleep(Duration::from_millis(WAIT_MS));
+
+    let Ok(resp) = ureq::post("http://localhost:8000/v1/read")
+        .set("Content-Type", "application/json")
+        .send_json(&QueryWords {
+            words: Some(vec!["gelfshipped".to_string()]),
+        })
+    else {
+        assert!(false);
+        return Ok(());
+    };
+    let output: LogsOutput = resp.into_json()?;
+    assert_eq!(
+        output.logs,
+        ["gelfshipped message host=graylogged level=6 user_id=7"]
+    );
+
+    match ureq::post("http://localhost:8000/gelf")
+        .set("Content-Type", "application/json")
+        .send_string(r#"{"version":"1.1","host":"graylogged"}"#)
+    {
+        Err(ureq::Error::Status(code, _)) => assert_eq!(code, 400),
+        _ => assert!(false),
+    };
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn on_import_api_call_should_save_lines_at_their_timestamps_and_report_rejected_ones() -> Result<()>
+{
+    let Ok(resp) = ureq::post("http://localhost:8000/v1/import?timestamps=true")
+        .set("Content-Type", "text/plain")
+        .send_string(
+            "2024-05-01T12:00:00Z backfilled first\nnot-a-time backfilled\n1714564801000000000 backfilled second",
+        )
+    else {
+        assert!(false);
+        return Ok(());
+    };
+    let report: serde_json::Value = resp.into_json()?;
+    assert_eq!(report["imported"], 2);
+    assert_eq!(report["rejected"], 1);
+    assert_eq!(report["errors"][0]["line"], 2);
+
+    let Ok(resp) = ureq::post("http://localhost:8000/v1/import")
+        .set("Content-Type", "application/x-ndjson")
+        .send_string("{\"log\":\"backfilled third\",\"timestamp\":\"2024-05-01T12:00:02Z\"}\n")
+    else {
+        assert!(false);
+        return Ok(());
+    };
+    let report: serde_json::Value = resp.into_json()?;
+    assert_eq!(report["imported"], 1);
+    sleep(Duration::from_millis(WAIT_MS));
+
+    let Ok(resp) = ureq::post("http://localhost:8000/v1/read")
+        .set("Content-Type", "application/json")
+        .send_json(serde_json::json!({
+            "words": ["backfilled"],
+            "from": "2024-05-01T12:00:00Z",
+            "to": "2024-05-01T12:00:03Z"
+        }))
+    else {
+        assert!(false);
+        return Ok(());
+    };
+    let output: LogsOutput = resp.into_json()?;
+    assert_eq!(output.logs.len(), 3);
+    assert!(output.logs.contains(&"backfilled second".to_string()));
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct QueryIgnoreCase {
+    prefix: String,
+    ignore_case: bool,
+}
+
+#[test]
+#[ignore]
+fn on_read_api_call_ignoring_case_should_match_prefixes_of_any_case() -> Result<()> {
+    let log = "Caseless LOGGER wrote MixedCase";
+    let Ok(resp) = ureq::post("http://localhost:8000/v1/save")
+        .set("Content-Type", "application/json")
+        .send_json(&LogInput {
+            log: log.to_string(),
+        })
+    else {
+        assert!(false);
+        return Ok(());
+    };
+    assert_eq!(resp.status(), 200);
+    sleep(Duration::from_millis(WAIT_MS));
+
+    let Ok(resp) = ureq::post("http://localhost:8000/v1/read")
+        .set("Content-Type", "application/json")
+        .send_json(&QueryIgnoreCase {
+            prefix: "caseles".to_string(),
+            ignore_case: true,
+        })
+    else {
+        assert!(false);
+        return Ok(());
+    };
+    let output: LogsOutput = resp.into_json()?;
+    assert_eq!(output.logs, [log]);
+
+    let Ok(resp) = ureq::post("http://localhost:8000/v1/read")
+        .set("Content-Type", "application/json")
+        .send_json(&QueryIgnoreCase {
+            prefix: "caseles".to_string(),
+            ignore_case: false,
+        })
+    else {
+        assert!(false);
+        return Ok(());
+    };
+    let output: LogsOutput = resp.into_json()?;
+    assert!(output.logs.is_empty());
+
+    Ok(())
+}